@@ -1,16 +1,18 @@
 //TODO: Would using Bevy's Hashmap be better for performance?
-use std::{collections::HashMap, path::{Path, PathBuf}};
+use std::{collections::HashMap, error::Error, path::{Path, PathBuf}};
 
 use bevy::prelude::*;
 use events::{ChangeContextEvent, ContextEventsPlugin};
 use karta_server::prelude::*;
 
-use crate::{node_plugin, prelude::{CurrentVault, DataEdge, DataEdgeBundle, DataNode, DataNodeBundle, Relation, Relations, ToBeDespawned, ViewNode}};
+use crate::{node_plugin, prelude::{CurrentVault, DataEdgeBundle, DataNode, DataNodeBundle, Relation, Relations, ToBeDespawned, ViewNode}};
 
 pub mod pe_index;
 pub mod events;
+pub mod positions;
 
 use pe_index::*;
+use positions::PendingPositionSaves;
 
 // -----------------------------------------------------------------
 // Plugin
@@ -22,6 +24,7 @@ impl Plugin for ContextPlugin {
             .add_plugins(ContextEventsPlugin)
             .insert_resource(CurrentContext::empty())
             .insert_resource(PathsToEntitiesIndex::new())
+            .init_resource::<PendingPositionSaves>()
 
             .add_systems(PreUpdate, (
                 change_context,
@@ -33,6 +36,7 @@ impl Plugin for ContextPlugin {
                 update_pe_index_on_viewnode_spawn,
                 update_relations_on_edge_spawn,
             ).chain())
+            .add_systems(Update, positions::save_pending_positions)
 
             .add_systems(PostUpdate, cleanup_to_be_despawned)
         ;
@@ -41,13 +45,33 @@ impl Plugin for ContextPlugin {
 
 // -----------------------------------------------------------------
 // Resources
+
+/// Default number of contexts kept in each of the undo and redo stacks.
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// Name of the file in the vault's karta folder where the context history is saved.
+const HISTORY_FILE_NAME: &str = "context_history.ron";
+
 #[derive(Resource)]
 pub struct CurrentContext {
     undo_stack: Vec<KartaContext>,
     redo_stack: Vec<KartaContext>,
     setting_from_undo_redo: bool,
 
+    /// Maximum length of each stack. The oldest contexts are dropped beyond it.
+    history_limit: usize,
+    /// Whether the history is saved into the vault, to be restored when it is reopened.
+    pub persist_history: bool,
+
+    context: Option<KartaContext>,
+}
+
+/// The navigation history as it is saved into the vault.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ContextHistory {
     context: Option<KartaContext>,
+    undo_stack: Vec<KartaContext>,
+    redo_stack: Vec<KartaContext>,
 }
 
 impl CurrentContext {
@@ -58,19 +82,180 @@ impl CurrentContext {
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
             setting_from_undo_redo: false,
+
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            persist_history: true,
+        }
+    }
+
+    pub fn history_limit(&self) -> usize {
+        self.history_limit
+    }
+
+    /// Sets the maximum length of the undo and redo stacks, dropping
+    /// the oldest contexts if they are already longer.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        Self::truncate_oldest(&mut self.undo_stack, limit);
+        Self::truncate_oldest(&mut self.redo_stack, limit);
+    }
+
+    fn truncate_oldest(stack: &mut Vec<KartaContext>, limit: usize) {
+        if stack.len() > limit {
+            stack.drain(..stack.len() - limit);
+        }
+    }
+
+    fn push_undo(&mut self, ctx: KartaContext) {
+        self.undo_stack.push(ctx);
+        Self::truncate_oldest(&mut self.undo_stack, self.history_limit);
+    }
+
+    fn push_redo(&mut self, ctx: KartaContext) {
+        self.redo_stack.push(ctx);
+        Self::truncate_oldest(&mut self.redo_stack, self.history_limit);
+    }
+
+    fn history_filepath(vault: &KartaVault) -> PathBuf {
+        vault.karta_dirpath().join(HISTORY_FILE_NAME)
+    }
+
+    /// Saves the undo and redo stacks into the vault.
+    pub fn save_history(&self, vault: &KartaVault) -> Result<(), Box<dyn Error>> {
+        let history = ContextHistory {
+            context: self.context.clone(),
+            undo_stack: self.undo_stack.clone(),
+            redo_stack: self.redo_stack.clone(),
+        };
+        let ron = ron::to_string(&history)?;
+        std::fs::create_dir_all(vault.karta_dirpath())?;
+        write_atomically(&Self::history_filepath(vault), ron)?;
+        Ok(())
+    }
+
+    /// Replaces the current context and the undo and redo stacks with the ones
+    /// saved in the vault, as they were when it was saved. The history isn't
+    /// touched otherwise, so undo and redo continue from where they left off.
+    /// A vault without saved history leaves everything empty.
+    pub fn load_history(&mut self, vault: &KartaVault) -> Result<(), Box<dyn Error>> {
+        self.context = None;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+
+        let path = Self::history_filepath(vault);
+        if !path.exists() {
+            return Ok(());
         }
+
+        let history: ContextHistory = ron::from_str(&std::fs::read_to_string(path)?)?;
+        self.context = history.context;
+        self.undo_stack = history.undo_stack;
+        self.redo_stack = history.redo_stack;
+        self.set_history_limit(self.history_limit);
+        Ok(())
     }
 
-    pub fn set(&mut self, ctx_path: NodePath){
+    /// Sets up the context for a newly opened vault. The saved history is
+    /// restored if it is persisted, otherwise or if there is none, the root
+    /// is opened. The history of the previous vault is never carried over.
+    pub fn open_vault(&mut self, vault: Option<&KartaVault>) {
+        let mut restored = false;
+        if self.persist_history {
+            if let Some(vault) = vault {
+                match self.load_history(vault) {
+                    Ok(()) => restored = true,
+                    Err(err) => error!("Failed to load context history: {}", err),
+                }
+            }
+        }
+        if !restored {
+            self.context = None;
+            self.undo_stack.clear();
+            self.redo_stack.clear();
+        }
+        // Navigating would push the restored context and clear the redo stack, so
+        // the root is only opened if there was nothing to restore
+        if self.path().is_none() {
+            self.navigate_to(NodePath::root());
+        }
+    }
+
+    /// Changes the context. All changes of context should go through here so that
+    /// the previous context is pushed onto the undo stack and the redo stack is cleared.
+    pub fn navigate_to(&mut self, ctx_path: NodePath){
+        // Contexts set by undo or redo manage the stacks themselves
+        if !self.setting_from_undo_redo {
+            if let Some(previous) = self.context.take() {
+                self.push_undo(previous);
+            }
+            self.redo_stack.clear();
+        }
+        self.setting_from_undo_redo = false;
+
         self.context = Some(KartaContext {
             path: ctx_path,
         });
     }
+
+    /// Path of the focal node of the current context, if there is one.
+    pub fn path(&self) -> Option<&NodePath> {
+        self.context.as_ref().map(|ctx| &ctx.path)
+    }
+
+    /// Parent of the focal node, if it exists in the graph of the vault.
+    pub fn parent_in(&self, vault: &CurrentVault) -> Option<NodePath> {
+        let graph = vault.graph.as_ref()?;
+        self.path()?
+            .parent()
+            .filter(|parent| graph.open_node(parent).is_ok())
+    }
+
+    /// Paths of the nodes that the focal node contains.
+    pub fn children_in(&self, vault: &CurrentVault) -> Vec<NodePath> {
+        let (graph, path) = match (vault.graph.as_ref(), self.path()) {
+            (Some(graph), Some(path)) => (graph, path),
+            _ => return Vec::new(),
+        };
+
+        graph
+            .open_node_connections(path)
+            .into_iter()
+            .filter(|(_, edge)| edge.contains() && edge.source() == path)
+            .map(|(node, _)| node.path())
+            .collect()
+    }
+
+    /// Returns to the previous context. Does nothing if there is no history.
+    pub fn undo_context(&mut self) {
+        let previous = match self.undo_stack.pop() {
+            Some(previous) => previous,
+            None => return,
+        };
+        if let Some(current) = self.context.take() {
+            self.push_redo(current);
+        }
+        self.setting_from_undo_redo = true;
+        self.navigate_to(previous.path);
+    }
+
+    /// Goes forward to the context that was last undone.
+    pub fn redo_context(&mut self) {
+        let next = match self.redo_stack.pop() {
+            Some(next) => next,
+            None => return,
+        };
+        if let Some(current) = self.context.take() {
+            self.push_undo(current);
+        }
+        self.setting_from_undo_redo = true;
+        self.navigate_to(next.path);
+    }
 }
 
 
 
 
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct KartaContext {
     path: NodePath, 
 }
@@ -99,6 +284,27 @@ fn on_context_change(
     open_nodes: Query<Entity, With<DataNode>>,
     pe_index: Res<PathsToEntitiesIndex>,
 ){
+    if context.persist_history {
+        if let Some(karta_vault) = &vault.vault {
+            if let Err(err) = context.save_history(karta_vault) {
+                error!("Failed to save context history: {}", err);
+            }
+        }
+    }
+
+    // Stacking order and pins of the nodes, if the context has been saved
+    let saved_nodes: HashMap<NodePath, karta_server::prelude::ViewNode> = match (&vault.vault, context.path()) {
+        (Some(karta_vault), Some(path)) => match ContextDb::new(karta_vault).get_context(path) {
+            Ok(Some(saved)) => saved.nodes.into_iter().map(|n| (n.path.clone(), n)).collect(),
+            Ok(None) => HashMap::new(),
+            Err(err) => {
+                error!("Failed to load saved context: {}", err);
+                HashMap::new()
+            }
+        },
+        _ => HashMap::new(),
+    };
+
     let graph = match &mut vault.graph {
         Some(graph) => graph,
         None => return, // TODO: Better error handling here
@@ -128,22 +334,12 @@ fn on_context_change(
     };
     
     let nodepath = node.path().clone();
-    let name = node.name().to_string();
     let ctx_rt_e: Option<Entity>;
     
     graph.index_node_context(&nodepath);
 
     if !pe_index.0.contains_key(&nodepath){
-        ctx_rt_e = Some(commands.spawn(DataNodeBundle{
-            name: Name::new(name),
-            data_node: DataNode {
-                path: nodepath,
-                created_time: node.created_time().clone(),
-                modified_time: node.modified_time().clone(),
-            },
-            data_node_type: node_plugin::DataNodeType(node.ntype_name()),
-            attributes: node_plugin::Attributes(node.attributes().clone()),
-        }).id());
+        ctx_rt_e = Some(commands.spawn(DataNodeBundle::from_node(&node)).id());
     } else {
         ctx_rt_e = match pe_index.get_data(&nodepath) {
             Some(e) => Some(e),
@@ -172,7 +368,6 @@ fn on_context_change(
         println!("Node: {:#?}", node);
         let (node, edge) = node;
         let node_path = node.path().clone();
-        let name = node.name().to_string();
 
         if pe_index.0.contains_key(&node_path) {
             let rt_e = pe_index.get_data(&node_path);
@@ -190,26 +385,18 @@ fn on_context_change(
             continue;
         }
 
-        commands.spawn(DataNodeBundle{
-            name: Name::new(name),
-            data_node: DataNode {
-                path: node_path,
-                created_time: node.created_time().clone(),
-                modified_time: node.modified_time().clone(),
-            },
-            data_node_type: node_plugin::DataNodeType(node.ntype_name()),
-            attributes: node_plugin::Attributes(node.attributes().clone()),
-        });
+        let data_e = commands.spawn(DataNodeBundle::from_node(node)).id();
+        if let Some(saved) = saved_nodes.get(&node_path) {
+            commands.entity(data_e).insert((
+                node_plugin::SavedZIndex(saved.z_index),
+                node_plugin::SavedPosition(Vec2::new(saved.x, saved.y)),
+            ));
+            if saved.pinned {
+                commands.entity(data_e).insert(node_plugin::SavedPin);
+            }
+        }
 
-        commands.spawn(DataEdgeBundle {
-            data_edge: DataEdge {
-                source: edge.source().clone(),
-                target: edge.target().clone(),
-                created_time: edge.created_time().clone(),
-                modified_time: edge.modified_time().clone(),
-            },
-            attributes: node_plugin::Attributes(edge.attributes().clone()),
-        });
+        commands.spawn(DataEdgeBundle::from_edge(edge));
     }
 }
 
@@ -218,9 +405,7 @@ fn change_context(
     mut context: ResMut<CurrentContext>,
 ){
     for event in event.read() {
-        context.context = Some(KartaContext {
-            path: event.new_ctx.clone(),
-        });
+        context.navigate_to(event.new_ctx.clone());
     }
 }
 
@@ -237,4 +422,138 @@ fn cleanup_to_be_despawned(
         pe_index.remove(&data.path);
         commands.entity(e).despawn_recursive();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use super::*;
+
+    #[test]
+    fn undo_stack_drops_oldest_context_beyond_limit() {
+        let mut context = CurrentContext::empty();
+        let paths: Vec<NodePath> = (0..=DEFAULT_HISTORY_LIMIT + 5)
+            .map(|i| NodePath::from(format!("node_{}", i).as_str()))
+            .collect();
+
+        for path in paths.iter() {
+            context.navigate_to(path.clone());
+        }
+
+        assert_eq!(context.undo_stack.len(), DEFAULT_HISTORY_LIMIT);
+        assert_eq!(
+            context.undo_stack.first().unwrap().path,
+            paths[5],
+            "Oldest contexts should be evicted"
+        );
+
+        context.undo_context();
+        assert_eq!(
+            context.path(),
+            Some(&paths[DEFAULT_HISTORY_LIMIT + 4]),
+            "Most recent context should still be reachable"
+        );
+    }
+
+    #[test]
+    fn loaded_history_can_still_be_redone() {
+        let dir = std::env::temp_dir().join("bevy_karta_client_test_loaded_history");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let vault = KartaVault::new(dir.clone());
+
+        let mut context = CurrentContext::empty();
+        context.navigate_to(NodePath::from("first"));
+        context.navigate_to(NodePath::from("second"));
+        context.navigate_to(NodePath::from("third"));
+        context.undo_context();
+        context.save_history(&vault).unwrap();
+
+        let mut loaded = CurrentContext::empty();
+        loaded.load_history(&vault).unwrap();
+        assert_eq!(loaded.path(), Some(&NodePath::from("second")), "Open context should be restored");
+        assert_eq!(loaded.undo_stack, context.undo_stack);
+        assert_eq!(loaded.redo_stack, context.redo_stack);
+
+        loaded.redo_context();
+        assert_eq!(loaded.path(), Some(&NodePath::from("third")), "Redo should survive loading");
+        loaded.undo_context();
+        loaded.undo_context();
+        assert_eq!(loaded.path(), Some(&NodePath::from("first")));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reopened_vault_continues_its_history() {
+        let dir = std::env::temp_dir().join("bevy_karta_client_test_reopened_vault");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let vault = KartaVault::new(dir.clone());
+
+        // A vault without history opens at the root
+        let mut context = CurrentContext::empty();
+        context.open_vault(Some(&vault));
+        assert_eq!(context.path(), Some(&NodePath::root()));
+        assert_eq!(context.undo_stack.len(), 0);
+
+        context.navigate_to(NodePath::from("first"));
+        context.navigate_to(NodePath::from("second"));
+        context.undo_context();
+        context.save_history(&vault).unwrap();
+
+        let mut reopened = CurrentContext::empty();
+        reopened.open_vault(Some(&vault));
+        assert_eq!(reopened.path(), Some(&NodePath::from("first")));
+        assert_eq!(reopened.undo_stack, context.undo_stack, "Opening should not push onto the undo stack");
+        assert_eq!(reopened.redo_stack, context.redo_stack, "Opening should not clear the redo stack");
+
+        let mut forgetful = CurrentContext::empty();
+        forgetful.persist_history = false;
+        forgetful.open_vault(Some(&vault));
+        assert_eq!(forgetful.path(), Some(&NodePath::root()), "Unpersisted history should not be loaded");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn switching_vaults_without_persistence_starts_at_the_root() {
+        let dir = std::env::temp_dir().join("bevy_karta_client_test_switching_vaults");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        let vault_a = KartaVault::new(dir.join("a"));
+        let vault_b = KartaVault::new(dir.join("b"));
+
+        let mut context = CurrentContext::empty();
+        context.persist_history = false;
+        context.open_vault(Some(&vault_a));
+        context.navigate_to(NodePath::from("first"));
+        context.navigate_to(NodePath::from("second"));
+        context.undo_context();
+
+        context.open_vault(Some(&vault_b));
+        assert_eq!(context.path(), Some(&NodePath::root()), "New vault should open at the root");
+        assert_eq!(context.undo_stack.len(), 0, "Undo should not lead back into the old vault");
+        assert_eq!(context.redo_stack.len(), 0, "Redo should not lead back into the old vault");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn each_navigation_pushes_the_previous_context() {
+        let mut context = CurrentContext::empty();
+
+        context.navigate_to(NodePath::from("first"));
+        context.navigate_to(NodePath::from("second"));
+        context.navigate_to(NodePath::from("third"));
+
+        assert_eq!(context.undo_stack.len(), 2);
+        assert_eq!(context.path(), Some(&NodePath::from("third")));
+
+        context.undo_context();
+        assert_eq!(context.path(), Some(&NodePath::from("second")));
+        assert_eq!(context.redo_stack.len(), 1);
+    }
+}
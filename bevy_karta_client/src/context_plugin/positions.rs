@@ -0,0 +1,143 @@
+// Positions of nodes waiting to be written to the saved context, along with
+// whether the nodes are pinned in place. Moving or pinning nodes queues them, and the queue is written once there have been no
+// new moves for a moment, so that a run of drags writes the context only once.
+// If the write fails, the positions stay queued and the write is retried.
+
+use std::{collections::HashMap, error::Error};
+
+use bevy::prelude::*;
+use karta_server::prelude::{Context, ContextDb, NodePath};
+
+use crate::prelude::CurrentVault;
+
+/// Seconds to wait after the latest queued position before writing.
+pub const POSITION_SAVE_DELAY: f32 = 0.5;
+
+#[derive(Resource)]
+pub struct PendingPositionSaves {
+    /// Latest position and pin of each moved node, by the focal node of the
+    /// context it was moved in. Positions are relative to the focal node.
+    contexts: HashMap<NodePath, HashMap<NodePath, (Vec2, bool)>>,
+    delay: Timer,
+}
+
+impl Default for PendingPositionSaves {
+    fn default() -> Self {
+        PendingPositionSaves {
+            contexts: HashMap::new(),
+            delay: Timer::from_seconds(POSITION_SAVE_DELAY, TimerMode::Once),
+        }
+    }
+}
+
+impl PendingPositionSaves {
+    /// Queues the position and pin of a node in the context of the focal node, replacing
+    /// any queued for it before. Restarts the wait before the queue is written.
+    pub fn queue(&mut self, focal: NodePath, path: NodePath, position: Vec2, pinned: bool) {
+        self.contexts.entry(focal).or_default().insert(path, (position, pinned));
+        self.delay.reset();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
+    /// Writes the positions queued for the context into it. Nodes that aren't
+    /// in the context yet are added to it.
+    pub fn apply_to(&self, context: &mut Context) {
+        let positions = match self.contexts.get(&context.focal) {
+            Some(positions) => positions,
+            None => return,
+        };
+        for (path, (position, pinned)) in positions.iter() {
+            let index = match context.nodes.iter().position(|node| node.path == *path) {
+                Some(index) => index,
+                None => {
+                    context.nodes.push(karta_server::prelude::ViewNode::new(path.clone(), 0.0, 0.0));
+                    context.nodes.len() - 1
+                }
+            };
+            let node = &mut context.nodes[index];
+            node.x = position.x;
+            node.y = position.y;
+            node.pinned = *pinned;
+        }
+    }
+
+    fn save(&self, db: &ContextDb, focal: &NodePath) -> Result<(), Box<dyn Error>> {
+        let mut saved = db
+            .get_context(focal)?
+            .unwrap_or_else(|| Context::new(focal.clone()));
+        self.apply_to(&mut saved);
+        db.save_context(&saved)
+    }
+}
+
+pub(crate) fn save_pending_positions(
+    time: Res<Time>,
+    vault: Res<CurrentVault>,
+    mut pending: ResMut<PendingPositionSaves>,
+){
+    if pending.is_empty() {
+        return;
+    }
+
+    pending.delay.tick(time.delta());
+    if !pending.delay.finished() {
+        return;
+    }
+
+    // Without a vault the positions are kept until there is one to write them to
+    let db = match &vault.vault {
+        Some(karta_vault) => ContextDb::new(karta_vault),
+        None => {
+            pending.delay.reset();
+            return;
+        }
+    };
+
+    let focals: Vec<NodePath> = pending.contexts.keys().cloned().collect();
+    for focal in focals {
+        match pending.save(&db, &focal) {
+            Ok(()) => {
+                pending.contexts.remove(&focal);
+            }
+            Err(err) => {
+                warn!("Failed to save node positions in {}, retrying: {}", focal.alias(), err);
+                pending.delay.reset();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use super::*;
+
+    #[test]
+    fn queued_positions_update_and_extend_the_context() {
+        let focal = NodePath::from("focal");
+        let moved = NodePath::from("focal/moved");
+        let added = NodePath::from("focal/added");
+
+        let mut context = Context::new(focal.clone());
+        context.nodes.push(karta_server::prelude::ViewNode::new(moved.clone(), 0.0, 0.0));
+
+        let mut pending = PendingPositionSaves::default();
+        pending.queue(focal.clone(), moved.clone(), Vec2::new(1.0, 1.0), false);
+        pending.queue(focal.clone(), moved.clone(), Vec2::new(10.0, -5.0), true);
+        pending.queue(focal.clone(), added.clone(), Vec2::new(3.0, 4.0), false);
+        pending.queue(NodePath::from("other"), NodePath::from("other/node"), Vec2::ZERO, false);
+        pending.apply_to(&mut context);
+
+        assert_eq!(context.nodes.len(), 2, "Positions queued for other contexts should be left out");
+        let node = context.nodes.iter().find(|node| node.path == moved).unwrap();
+        assert_eq!((node.x, node.y), (10.0, -5.0), "Latest queued position should win");
+        assert_eq!(node.pinned, true);
+        let node = context.nodes.iter().find(|node| node.path == added).unwrap();
+        assert_eq!((node.x, node.y), (3.0, 4.0));
+        assert_eq!(node.pinned, false);
+    }
+}
@@ -16,6 +16,9 @@ pub struct DataEdgePlugin;
 pub struct DataEdge {
     pub source: NodePath,
     pub target: NodePath,
+    /// Whether the edge is the parent-child edge between a directory and
+    /// one of its children, as opposed to an associative edge.
+    pub contains: bool,
     pub created_time: SysTime,
     pub modified_time: SysTime,
 }
@@ -64,3 +67,18 @@ pub struct DataEdgeBundle {
     pub data_edge: DataEdge,
     pub attributes: Attributes,
 }
+
+impl DataEdgeBundle {
+    pub fn from_edge(edge: &Edge) -> Self {
+        DataEdgeBundle {
+            data_edge: DataEdge {
+                source: edge.source().clone(),
+                target: edge.target().clone(),
+                contains: edge.contains(),
+                created_time: edge.created_time().clone(),
+                modified_time: edge.modified_time().clone(),
+            },
+            attributes: Attributes(edge.attributes().clone()),
+        }
+    }
+}
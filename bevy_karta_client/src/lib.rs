@@ -17,4 +17,8 @@ pub mod prelude {
     
     pub use crate::node_plugin::*;
     pub use crate::edge_plugin::*;
+
+    pub use karta_server::prelude::{
+        read_text_preview, write_atomically, NodeType, TextPreview, NODE_COLOR_ATTR,
+    };
 }
\ No newline at end of file
@@ -1,6 +1,10 @@
+use std::error::Error;
+
 use bevy::prelude::*;
 use karta_server::prelude::*;
 
+use crate::prelude::{CurrentVault, DataEdgeBundle};
+
 // pub struct DataNodePlugin;
 
 // impl Plugin for DataNodePlugin {
@@ -16,6 +20,7 @@ pub struct ToBeDespawned;
 #[derive(Component)]
 pub struct DataNode {
     pub path: NodePath,
+    pub persistent: bool,
     pub created_time: SysTime,
     pub modified_time: SysTime,
 }
@@ -26,6 +31,42 @@ pub struct DataNodeType(pub NodeType);
 #[derive(Component, Deref)]
 pub struct Attributes(pub Vec<Attribute>);
 
+impl Attributes {
+    pub fn get(&self, name: &str) -> Option<&Attribute> {
+        self.0.iter().find(|attr| attr.name == name)
+    }
+
+    /// Numeric attributes are read as floats regardless of how they are stored.
+    pub fn get_float(&self, name: &str) -> Option<f32> {
+        match self.get(name)?.value {
+            AttrValue::Float(value) => Some(value),
+            AttrValue::UInt(value) => Some(value as f32),
+            AttrValue::String(_) => None,
+        }
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        match &self.get(name)?.value {
+            AttrValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Stacking order of a node in the saved context it was opened in.
+/// Higher is drawn on top.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SavedZIndex(pub f32);
+
+/// Position of a node in the saved context it was opened in, relative to the
+/// focal node.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SavedPosition(pub Vec2);
+
+/// Marks a node that is pinned in place in the saved context it was opened in.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SavedPin;
+
 #[derive(Bundle)]
 /// Bevy 0.15 TODO: convert to use required components
 pub struct DataNodeBundle {
@@ -35,6 +76,49 @@ pub struct DataNodeBundle {
     pub attributes: Attributes,
 }
 
+impl DataNodeBundle {
+    pub fn from_node(node: &Node) -> Self {
+        DataNodeBundle {
+            name: Name::new(node.name()),
+            data_node: DataNode {
+                path: node.path(),
+                persistent: node.persistent(),
+                created_time: node.created_time().clone(),
+                modified_time: node.modified_time().clone(),
+            },
+            data_node_type: DataNodeType(node.ntype_name()),
+            attributes: Attributes(node.attributes().clone()),
+        }
+    }
+}
+
+/// Creates a virtual node with the given name under the parent, and spawns its
+/// data node along with the contains edge from the parent. Errs if the name is
+/// invalid or the parent already has a child with the name.
+pub fn create_child_node(
+    commands: &mut Commands,
+    vault: &mut CurrentVault,
+    parent: &NodePath,
+    name: &str,
+) -> Result<Entity, Box<dyn Error>> {
+    let graph = vault.graph.as_mut().ok_or("No vault is open")?;
+
+    NodePath::validate_name(name)?;
+    let path = parent.join(name);
+    if graph.open_node(&path).is_ok() {
+        return Err(format!("{} already exists", path.alias()).into());
+    }
+
+    let node = graph.create_node_by_path(&path, None)?;
+    let entity = commands.spawn(DataNodeBundle::from_node(&node)).id();
+
+    if let Ok(edge) = graph.get_edge_strict(parent, &path) {
+        commands.spawn(DataEdgeBundle::from_edge(&edge));
+    }
+
+    Ok(entity)
+}
+
 #[derive(Component)]
 pub struct ViewNode {
     pub path: Option<NodePath>,
@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 
 use std::fs::{self, File};
-use std::io::{self, BufRead, Write};
+use std::io::{self, BufRead};
 
 use bevy::prelude::*;
 use directories::ProjectDirs;
@@ -51,7 +51,7 @@ impl VaultOfVaults {
     pub fn create_vault(&mut self, cur: &mut CurrentVault) -> io::Result<()> {
         let search_path = PathBuf::from(self.search_input.clone());
 
-        if self.vaults.contains(&KartaVault::new(search_path.clone())) {
+        if self.vaults.iter().any(|vault| vault.vault_dirpath() == search_path) {
             return Ok(());
         }
 
@@ -88,14 +88,32 @@ impl VaultOfVaults {
         self.config_dir().join("vaults.txt")
     }
 
+    /// Sets the directory to keep the db of the vault in, or puts it back in
+    /// the vault if None. Takes effect the next time the vault is opened.
+    pub fn set_storage_override(&mut self, vault_path: &PathBuf, storage: Option<PathBuf>) -> io::Result<()> {
+        let vault = self
+            .vaults
+            .iter_mut()
+            .find(|vault| vault.vault_dirpath() == *vault_path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No such vault"))?;
+        *vault = KartaVault::new(vault_path.clone()).with_storage_override(storage);
+        self.save_vaults_to_config()
+    }
+
+    /// Each vault is on its own line. A storage override follows the path of
+    /// the vault, separated by a tab.
+    /// The file is replaced atomically, so a crash while saving can't lose the list.
     pub fn save_vaults_to_config(&self) -> io::Result<()> {
         fs::create_dir_all(self.config_dir())?;
-        let mut file = File::create(self.config_file_path())?;
 
+        let mut contents = String::new();
         for vault in &self.vaults {
-            writeln!(file, "{}", vault.path.display())?;
+            match vault.storage_override() {
+                Some(storage) => contents.push_str(&format!("{}\t{}\n", vault.vault_dirpath().display(), storage.display())),
+                None => contents.push_str(&format!("{}\n", vault.vault_dirpath().display())),
+            }
         }
-        Ok(())
+        write_atomically(&self.config_file_path(), contents)
     }
 
     pub fn load_vaults_from_config(&mut self) -> io::Result<()> {
@@ -109,9 +127,13 @@ impl VaultOfVaults {
 
         self.vaults.clear();
         for line in reader.lines() {
-            let path = PathBuf::from(line?);
+            let line = line?;
+            let (path, storage) = match line.split_once('\t') {
+                Some((path, storage)) => (PathBuf::from(path), Some(PathBuf::from(storage))),
+                None => (PathBuf::from(line), None),
+            };
             if path.exists() && path.is_dir() {
-                self.vaults.push(KartaVault::new(path));
+                self.vaults.push(KartaVault::new(path).with_storage_override(storage));
             }
         }
         Ok(())
@@ -138,17 +160,14 @@ impl CurrentVault {
     }
 
     pub fn set_vault(&mut self, vault: KartaVault) {
-        println!("Setting vault to be: {:?}", vault.path);
-        let name = vault.path.file_stem();
+        let path = vault.vault_dirpath();
+        info!("Setting vault to be: {:?}", path);
+        let name = path.file_stem();
         let name: &str = match name {
             Some(name) => name.to_str().unwrap(),
             None => return,
         };
-        self.graph = Some(GraphCommands::new(
-            name,
-            vault.path.clone(),
-            Some(vault.path.clone()),
-        ));
+        self.graph = Some(GraphCommands::open_vault(name, &vault));
         self.vault = Some(vault);
     }
 }
@@ -157,16 +176,5 @@ fn set_context_on_vault_change(
     mut ctx: ResMut<CurrentContext>,
     vault: Res<CurrentVault>,
 ){
-    ctx.set(NodePath::root());
-}
-
-#[derive(Clone, PartialEq)]
-pub struct KartaVault {
-    path: PathBuf,
-}
-
-impl KartaVault {
-    pub fn new(path: PathBuf) -> Self {
-        KartaVault { path }
-    }
+    ctx.open_vault(vault.vault.as_ref());
 }
@@ -1,11 +1,11 @@
 use std::any::TypeId;
 
 use bevy::{
-    app::{App, Plugin, PreStartup}, core::Name, ecs::{
+    app::{App, Plugin, PreStartup}, ecs::{
         component::Component,
         system::{Commands, Res, ResMut, Resource, SystemId, SystemState},
         world::World,
-    }, hierarchy::DespawnRecursiveExt, transform::components::Transform, utils::HashMap
+    }, hierarchy::DespawnRecursiveExt, utils::HashMap
 };
 
 use crate::prelude::pointer::InputData;
@@ -118,6 +118,4 @@ fn register_default_systems(mut world: &mut World) {
     let (mut entity_systems, mut component_systems) = system_state.get_mut(&mut world);
 
     entity_systems.insert("Delete".to_string(), del);
-    component_systems.insert::<Transform>("DOLOROMS".to_string(), del);
-    component_systems.insert::<Name>("tROFL".to_string(), del);
 }
@@ -26,6 +26,7 @@ pub struct GraphEntity;
 #[derive(Component, Default)]
 pub struct Pins {
     /// If true, the node will not be moved by the graph simulation.
+    /// Saved in the context along with the position of the node.
     pub position: bool,
     /// If true, the node will not be despawned even if its corresponding entity is despawned.
     pub presence: bool,
@@ -2,9 +2,11 @@ use bevy::prelude::{Plugin, App};
 
 use self::node_events::*;
 use self::edges::*;
+use self::ui_events::*;
 
 pub mod node_events;
 pub mod edges;
+pub mod ui_events;
 
 pub struct EventPlugin;
 
@@ -17,10 +19,15 @@ impl Plugin for EventPlugin {
             .add_event::<NodeHoverEvent>()
             .add_event::<NodeHoverStopEvent>()
             .add_event::<MoveNodesEvent>()
+            .add_event::<NodeDragEndEvent>()
 
             // Edge Events
             .add_event::<EdgeClickEvent>()
 
+            // Ui Events
+            .add_event::<RectangleSelectionEvent>()
+            .add_event::<RectangleSelectionEndEvent>()
+
         ;
     }
 }
\ No newline at end of file
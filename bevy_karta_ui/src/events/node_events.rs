@@ -15,6 +15,17 @@ impl From<ListenerInput<Pointer<Drag>>> for MoveNodesEvent {
     }
 }
 
+/// Sent when the user lets go of dragged nodes.
+#[derive(Event)]
+pub struct NodeDragEndEvent;
+
+// Implementation required by bevy_mod_picking
+impl From<ListenerInput<Pointer<DragEnd>>> for NodeDragEndEvent {
+    fn from(_event: ListenerInput<Pointer<DragEnd>>) -> Self {
+        NodeDragEndEvent
+    }
+}
+
 // The main input event for nodes. 
 #[derive(Event, Debug)]
 pub struct NodeClickEvent {
@@ -1,3 +1,19 @@
+use bevy::prelude::{Event, Vec2};
 
 #[derive(Event)]
 pub struct OpenCreateNodeMenuEvent;
+
+/// Sent every frame while the user drags a selection rectangle over empty space.
+/// The corners are in world coordinates.
+#[derive(Event, Debug)]
+pub struct RectangleSelectionEvent {
+    pub start: Vec2,
+    pub end: Vec2,
+}
+
+/// Sent when the user lets go of a selection rectangle.
+#[derive(Event, Debug)]
+pub struct RectangleSelectionEndEvent {
+    pub start: Vec2,
+    pub end: Vec2,
+}
@@ -0,0 +1,102 @@
+// Keyboard bindings and the systems that act on them.
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_karta_client::prelude::{CurrentContext, CurrentVault, ViewNode};
+use bevy_mod_picking::selection::PickSelection;
+
+/// Actions that can be bound to a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyAction {
+    UndoContext,
+    RedoContext,
+    /// Move the context to the parent of the focal node.
+    ContextToParent,
+    /// Move the context to the selected child of the focal node.
+    ContextToChild,
+    /// Pin the selected nodes in place, or unpin them.
+    TogglePin,
+    /// Pause or resume the force simulation.
+    ToggleSimulation,
+}
+
+/// Resource mapping actions to the keys that trigger them.
+#[derive(Resource, Debug, Clone)]
+pub struct KeyMap {
+    bindings: HashMap<KeyAction, KeyCode>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let mut bindings = HashMap::new();
+        bindings.insert(KeyAction::UndoContext, KeyCode::KeyQ);
+        bindings.insert(KeyAction::RedoContext, KeyCode::KeyE);
+        bindings.insert(KeyAction::ContextToParent, KeyCode::Backspace);
+        bindings.insert(KeyAction::ContextToChild, KeyCode::Enter);
+        bindings.insert(KeyAction::TogglePin, KeyCode::KeyP);
+        bindings.insert(KeyAction::ToggleSimulation, KeyCode::KeyF);
+        KeyMap { bindings }
+    }
+}
+
+impl KeyMap {
+    /// Binds an action to a key, replacing its previous binding.
+    pub fn bind(&mut self, action: KeyAction, key: KeyCode) {
+        self.bindings.insert(action, key);
+    }
+
+    pub fn key(&self, action: KeyAction) -> Option<KeyCode> {
+        self.bindings.get(&action).copied()
+    }
+
+    pub fn just_pressed(&self, action: KeyAction, keys: &ButtonInput<KeyCode>) -> bool {
+        match self.key(action) {
+            Some(key) => keys.just_pressed(key),
+            None => false,
+        }
+    }
+}
+
+pub fn undo_redo_context(
+    keymap: Res<KeyMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut context: ResMut<CurrentContext>,
+){
+    if keymap.just_pressed(KeyAction::UndoContext, &keys) {
+        context.undo_context();
+    } else if keymap.just_pressed(KeyAction::RedoContext, &keys) {
+        context.redo_context();
+    }
+}
+
+/// Moves the context up to the parent of the focal node, or down into
+/// a selected child of it. Goes through the undo stack like any other
+/// change of context.
+pub fn navigate_context(
+    keymap: Res<KeyMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    vault: Res<CurrentVault>,
+    mut context: ResMut<CurrentContext>,
+    nodes: Query<(&ViewNode, &PickSelection)>,
+){
+    let to_parent = keymap.just_pressed(KeyAction::ContextToParent, &keys);
+    let to_child = keymap.just_pressed(KeyAction::ContextToChild, &keys);
+    if !to_parent && !to_child {
+        return;
+    }
+
+    let new_ctx = if to_parent {
+        context.parent_in(&vault)
+    } else {
+        let children = context.children_in(&vault);
+
+        nodes
+            .iter()
+            .filter(|(_, selection)| selection.is_selected)
+            .filter_map(|(node, _)| node.path.clone())
+            .find(|path| children.contains(path))
+    };
+
+    if let Some(new_ctx) = new_ctx {
+        context.navigate_to(new_ctx);
+    }
+}
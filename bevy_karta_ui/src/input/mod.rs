@@ -1,7 +1,11 @@
-use bevy::prelude::{Plugin, PreUpdate, App};
+use bevy::prelude::{Plugin, PreUpdate, App, IntoSystemConfigs};
+
+use self::keymap::{navigate_context, undo_redo_context, KeyMap};
+use crate::ui::create_node::no_name_prompt;
 
 use self::pointer::{handle_node_click, handle_node_hover, handle_node_press, update_cursor_info, InputData};
 
+pub mod keymap;
 pub mod pointer;
 
 pub struct InputPlugin;
@@ -10,6 +14,7 @@ impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
         app
             .insert_resource(InputData::default())
+            .insert_resource(KeyMap::default())
             
             .add_systems(PreUpdate, (
                 update_cursor_info,
@@ -17,6 +22,10 @@ impl Plugin for InputPlugin {
                 handle_node_press,
                 handle_node_hover,
             ))
+            .add_systems(PreUpdate, (
+                undo_redo_context,
+                navigate_context,
+            ).run_if(no_name_prompt))
 
             
             // Add the update when the ui for input map editing is in place.
@@ -1,9 +1,11 @@
-use bevy::prelude::{Plugin, App};
+use bevy::prelude::{Plugin, App, Startup, Update};
 
-use self::{view::EdgeDefaults, theme::ThemePlugin};
+use self::{view::EdgeDefaults, theme::ThemePlugin, node_colors::{load_node_type_colors, NodeTypeColors}, simulation::{load_simulation_settings, save_simulation_settings, SimulationSettings}};
 
 mod view;
 pub mod theme;
+pub mod node_colors;
+pub mod simulation;
 
 pub struct SettingsPlugin;
 
@@ -12,6 +14,10 @@ impl Plugin for SettingsPlugin {
         app
             .add_plugins(ThemePlugin)
             .insert_resource(EdgeDefaults::default())
+            .insert_resource(NodeTypeColors::default())
+            .insert_resource(SimulationSettings::default())
+            .add_systems(Startup, (load_node_type_colors, load_simulation_settings))
+            .add_systems(Update, save_simulation_settings)
         ;
     }
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+// Colors of nodes by their type, for nodes that don't set their own color
+// through their attributes. The defaults can be overridden per type in a
+// settings file in the config directory of the app.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_karta_client::prelude::VaultOfVaults;
+
+use super::theme::NODE_BASE_COLOR;
+
+/// Name of the settings file in the config directory. The file is a RON map
+/// of node type names to hex colors, such as { "Directory": "#3355aa" }.
+pub const NODE_TYPE_COLORS_FILE_NAME: &str = "node_type_colors.ron";
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct NodeTypeColors {
+    colors: HashMap<String, Color>,
+}
+
+impl Default for NodeTypeColors {
+    fn default() -> Self {
+        let colors = [
+            ("Directory", Color::srgb(0.15, 0.25, 0.55)),
+            ("File", Color::srgb(0.3, 0.3, 0.35)),
+            ("Text", Color::srgb(0.35, 0.35, 0.45)),
+            ("Image", Color::srgb(0.15, 0.45, 0.2)),
+            ("Audio", Color::srgb(0.55, 0.4, 0.1)),
+            ("Video", Color::srgb(0.4, 0.15, 0.5)),
+        ];
+        NodeTypeColors {
+            colors: colors
+                .into_iter()
+                .map(|(ntype, color)| (ntype.to_string(), color))
+                .collect(),
+        }
+    }
+}
+
+impl NodeTypeColors {
+    pub fn get(&self, ntype: &str) -> Option<Color> {
+        self.colors.get(ntype).copied()
+    }
+
+    pub fn set(&mut self, ntype: &str, color: Color) {
+        self.colors.insert(ntype.to_string(), color);
+    }
+
+    /// The color to draw a node with. A color set on the node itself comes
+    /// first, then the color of its type, then the base color of all nodes.
+    pub fn resolve(&self, explicit: Option<Color>, ntype: Option<&str>) -> Color {
+        explicit
+            .or_else(|| ntype.and_then(|ntype| self.get(ntype)))
+            .unwrap_or(NODE_BASE_COLOR)
+    }
+
+    /// Overrides the colors of the types listed in the RON map. Colors that
+    /// aren't valid hex are skipped with a warning.
+    pub fn apply_overrides(&mut self, ron: &str) -> Result<(), ron::error::SpannedError> {
+        let overrides: HashMap<String, String> = ron::from_str(ron)?;
+        for (ntype, hex) in overrides {
+            match Srgba::hex(hex.trim()) {
+                Ok(color) => self.set(&ntype, color.into()),
+                Err(_) => warn!("Invalid color {} for node type {}", hex, ntype),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the overrides from the settings file, if there is one.
+pub(super) fn load_node_type_colors(
+    vaults: Option<Res<VaultOfVaults>>,
+    mut colors: ResMut<NodeTypeColors>,
+) {
+    let path = match vaults {
+        Some(vaults) => vaults.config_dir().join(NODE_TYPE_COLORS_FILE_NAME),
+        None => return,
+    };
+    if !path.exists() {
+        return;
+    }
+
+    let loaded = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|ron| colors.apply_overrides(&ron).map_err(|e| e.to_string()));
+    if let Err(e) = loaded {
+        error!("Failed to load node type colors from {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_color_comes_before_type_color_before_default() {
+        let colors = NodeTypeColors::default();
+        let explicit = Color::srgb(1.0, 0.0, 1.0);
+        let directory = colors.get("Directory").unwrap();
+
+        assert_eq!(colors.resolve(Some(explicit), Some("Directory")), explicit);
+        assert_eq!(colors.resolve(None, Some("Directory")), directory);
+        assert_eq!(colors.resolve(None, Some("Unknown")), NODE_BASE_COLOR);
+        assert_eq!(colors.resolve(None, None), NODE_BASE_COLOR);
+    }
+
+    #[test]
+    fn overrides_replace_and_add_type_colors() {
+        let mut colors = NodeTypeColors::default();
+        let overrides = r##"{ "Directory": "#ff0000", "Custom": "00ff00", "Image": "not a color" }"##;
+        assert!(colors.apply_overrides(overrides).is_ok());
+
+        assert_eq!(colors.get("Directory"), Some(Color::srgb(1.0, 0.0, 0.0)));
+        assert_eq!(colors.get("Custom"), Some(Color::srgb(0.0, 1.0, 0.0)));
+        assert_eq!(
+            colors.get("Image"),
+            NodeTypeColors::default().get("Image"),
+            "Invalid colors should be skipped"
+        );
+
+        assert!(colors.apply_overrides("not ron").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+// Parameters of the force simulation that lays out the nodes. The simulation
+// reads them every tick, so edits show up right away. They are saved to a
+// settings file in the config directory of the app whenever they change.
+
+use bevy::prelude::*;
+use bevy_karta_client::prelude::{write_atomically, VaultOfVaults};
+use serde::{Deserialize, Serialize};
+
+/// Name of the settings file in the config directory. Fields missing from the
+/// file keep their defaults.
+pub const SIMULATION_SETTINGS_FILE_NAME: &str = "simulation_settings.ron";
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationSettings {
+    /// Strength of the force pushing every pair of nodes apart.
+    pub repulsion_strength: f32,
+    /// Resting length of the springs along edges.
+    pub link_distance: f32,
+    /// Stiffness of the springs along edges.
+    pub link_stiffness: f32,
+    /// Strength of the pull of every node towards the origin. Zero turns it off.
+    pub center_gravity: f32,
+    /// Share of the force on a node that is turned into movement.
+    pub damping: f32,
+    /// Forces are clamped to this length.
+    pub force_upper_limit: f32,
+    /// Forces shorter than this are ignored.
+    pub force_lower_limit: f32,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        SimulationSettings {
+            repulsion_strength: 20000.0,
+            link_distance: 200.0,
+            link_stiffness: 0.85,
+            center_gravity: 0.0,
+            damping: 0.85,
+            force_upper_limit: 350.0,
+            force_lower_limit: 0.5,
+        }
+    }
+}
+
+fn settings_file(vaults: Option<Res<VaultOfVaults>>) -> Option<std::path::PathBuf> {
+    vaults.map(|vaults| vaults.config_dir().join(SIMULATION_SETTINGS_FILE_NAME))
+}
+
+/// Reads the settings from the settings file, if there is one.
+pub(super) fn load_simulation_settings(
+    vaults: Option<Res<VaultOfVaults>>,
+    mut settings: ResMut<SimulationSettings>,
+) {
+    let path = match settings_file(vaults) {
+        Some(path) if path.exists() => path,
+        _ => return,
+    };
+
+    let loaded = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|ron| ron::from_str::<SimulationSettings>(&ron).map_err(|e| e.to_string()));
+    match loaded {
+        Ok(loaded) => *settings = loaded,
+        Err(e) => error!("Failed to load simulation settings from {}: {}", path.display(), e),
+    }
+}
+
+/// Writes the settings to the settings file after they have been edited.
+pub(super) fn save_simulation_settings(
+    vaults: Option<Res<VaultOfVaults>>,
+    settings: Res<SimulationSettings>,
+) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+    let path = match settings_file(vaults) {
+        Some(path) => path,
+        None => return,
+    };
+
+    // The config directory doesn't exist before anything has been saved to it
+    let saved = ron::ser::to_string_pretty(&*settings, ron::ser::PrettyConfig::default())
+        .map_err(|e| e.to_string())
+        .and_then(|ron| {
+            std::fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+            write_atomically(&path, ron).map_err(|e| e.to_string())
+        });
+    if let Err(e) = saved {
+        error!("Failed to save simulation settings to {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip_and_missing_fields_keep_defaults() {
+        let settings = SimulationSettings {
+            repulsion_strength: 5000.0,
+            center_gravity: 0.1,
+            ..default()
+        };
+        let ron = ron::to_string(&settings).unwrap();
+        assert_eq!(ron::from_str::<SimulationSettings>(&ron).unwrap(), settings);
+
+        let partial: SimulationSettings = ron::from_str("(link_distance: 120.0)").unwrap();
+        assert_eq!(partial.link_distance, 120.0);
+        assert_eq!(partial.repulsion_strength, SimulationSettings::default().repulsion_strength);
+    }
+}
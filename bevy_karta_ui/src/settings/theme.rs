@@ -19,6 +19,14 @@ impl Plugin for ThemePlugin {
 // cumbersome, so the resources won't be used until this has been 
 // addressed. 
 
+// Nodes
+// ------------------------------------------------------------------
+// Used when a node doesn't set its own color or radius through its attributes.
+pub const NODE_BASE_COLOR: Color = Color::srgb(0.3, 0.0, 0.0);
+pub const NODE_BASE_RADIUS: f32 = 35.0;
+pub const TEXT_NODE_COLOR: Color = Color::srgb(0.12, 0.12, 0.16);
+pub const TEXT_NODE_TEXT_COLOR: Color = Color::srgb(0.85, 0.85, 0.85);
+
 // Outlines
 // ------------------------------------------------------------------
 pub const OUTLINE_BASE_COLOR: Color = Color::srgba(0.2, 0.2, 0.4, 1.0);
@@ -58,7 +66,7 @@ impl Default for OutlinesTheme {
 // pub const EDGE_FOCAL_BASE_HOVER_COLOR: Color = Color::rgba(0.73, 0.22, 0.0, 0.73);
 // pub const EDGE_FOCAL_PARENT_COLOR: Color = Color::rgba(0.57, 0.0, 0.0, 0.5);
 // pub const EDGE_FOCAL_PARENT_HOVER_COLOR: Color = Color::rgba(0.67, 0.0, 0.0, 0.73);
-pub const EDGE_BASE_COLOR: Color = Color::rgb(0.2, 0.2, 0.3);
-pub const EDGE_BASE_HOVER_COLOR: Color = Color::rgb(0.3, 0.3, 0.4);
-pub const EDGE_PARENT_COLOR: Color = Color::rgb(0.67, 0.21, 0.0);
-pub const EDGE_PARENT_HOVER_COLOR: Color = Color::rgb(0.93, 0.22, 0.0);
\ No newline at end of file
+pub const EDGE_BASE_COLOR: Color = Color::srgb(0.2, 0.2, 0.3);
+pub const EDGE_BASE_HOVER_COLOR: Color = Color::srgb(0.3, 0.3, 0.4);
+pub const EDGE_PARENT_COLOR: Color = Color::srgb(0.67, 0.21, 0.0);
+pub const EDGE_PARENT_HOVER_COLOR: Color = Color::srgb(0.93, 0.22, 0.0);
\ No newline at end of file
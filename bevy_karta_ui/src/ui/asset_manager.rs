@@ -4,13 +4,17 @@
 
 
 use bevy::{
-    ecs::{system::{Resource, ResMut, Query}, event::EventReader, entity::Entity, query::Without}, 
-    asset::{Handle, AssetEvent, Assets}, 
-    render::texture::Image, sprite::{Sprite, Mesh2dHandle}, transform::components::Transform, math::{Vec2, Vec3}, 
-    hierarchy::Children, 
+    ecs::{system::{Commands, Resource, Res, ResMut, Query}, event::EventReader, entity::Entity, query::{With, Without}}, 
+    asset::{Handle, AssetEvent, AssetServer, Assets, LoadState}, 
+    render::{mesh::Mesh, texture::Image}, sprite::{ColorMaterial, MaterialMesh2dBundle, Sprite, SpriteBundle, Mesh2dHandle}, 
+    transform::components::Transform, math::{self, Vec2, Vec3}, 
+    hierarchy::Children, prelude::default,
 };
+use bevy_karta_client::prelude::ViewNode;
 use bevy_prototype_lyon::{shapes, entity::Path, geometry::GeometryBuilder, draw::Stroke};
 
+use crate::settings::theme::{NODE_BASE_COLOR, NODE_BASE_RADIUS};
+
 use super::nodes::{ViewNodeShape, NodeOutline, NodeLabel};
 
 #[derive(Resource)]
@@ -128,3 +132,34 @@ pub fn on_image_load(
         }
     }
 }
+
+/// Image nodes whose image fails to load are shown as base nodes instead.
+pub fn fallback_failed_image_nodes(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    mut image_tracker: ResMut<ImageLoadTracker>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+
+    nodes: Query<(Entity, &Handle<Image>, &Transform), (With<ViewNode>, Without<NodeOutline>, Without<NodeLabel>)>,
+) {
+    for (entity, image, transform) in nodes.iter() {
+        if !matches!(server.load_state(image.id()), LoadState::Failed(_)) {
+            continue;
+        }
+
+        image_tracker.remove_image(image.clone_weak());
+
+        commands.entity(entity)
+            .remove::<SpriteBundle>()
+            .insert((
+                ViewNodeShape::Circle(NODE_BASE_RADIUS),
+                MaterialMesh2dBundle {
+                    mesh: meshes.add(math::primitives::Circle::new(NODE_BASE_RADIUS)).into(),
+                    material: materials.add(ColorMaterial::from(NODE_BASE_COLOR)),
+                    transform: *transform,
+                    ..default()
+                },
+            ));
+    }
+}
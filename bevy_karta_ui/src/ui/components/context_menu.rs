@@ -13,8 +13,10 @@ use bevy_mod_picking::{
     selection::NoDeselect,
 };
 
+use bevy_karta_client::prelude::ViewNode;
+
 use crate::{
-    events::{edges::EdgeClickEvent, node_events::NodeClickEvent}, prelude::context_commands::{ContextComponentSystems, ContextEntitySystems, ContextSystem}, ui::nodes::ViewNode
+    events::{edges::EdgeClickEvent, node_events::NodeClickEvent}, prelude::context_commands::{ContextComponentSystems, ContextEntitySystems, ContextSystem},
 };
 
 use super::popup::*;
@@ -46,6 +48,7 @@ pub fn despawn_context_menus_on_any_click(
 pub fn spawn_node_context_menu(
     mut mouse_event: EventReader<NodeClickEvent>, 
     view_nodes: Query<&ViewNode>,
+    parents: Query<&Parent>,
     mut commands: Commands,
     window: Query<&Window>,
     mut menus: Query<(Entity, &PopupGroup), With<Popup>>,
@@ -68,10 +71,14 @@ pub fn spawn_node_context_menu(
         return
     }
     
+    // The click may land on a child of the node, such as its outline
     let viewtarget = ev.target.unwrap();
     let target = match view_nodes.get(viewtarget){
-        Ok(node) => node.get_target(),
-        Err(_) => return,
+        Ok(_) => viewtarget,
+        Err(_) => match parents.get(viewtarget) {
+            Ok(parent) if view_nodes.contains(parent.get()) => parent.get(),
+            _ => return,
+        },
     };
 
     let window = window.single();
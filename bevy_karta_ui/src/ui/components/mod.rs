@@ -0,0 +1,4 @@
+// Generic ui elements that are built on by the rest of the ui.
+
+pub(crate) mod context_menu;
+pub(crate) mod popup;
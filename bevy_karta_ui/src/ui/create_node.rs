@@ -0,0 +1,228 @@
+// Creating nodes from the ui. The "Create child" entry in the context menu of a
+// node opens a prompt for the name of the new node, and the node is created
+// under the clicked node and placed where the menu was used.
+
+use bevy::{
+    input::{keyboard::{Key, KeyboardInput}, ButtonState},
+    prelude::*,
+};
+use bevy_karta_client::prelude::{create_child_node, CurrentVault, ViewNode};
+
+use crate::{
+    prelude::{context_commands::ContextComponentSystems, pointer::InputData},
+    settings::theme::TEXT_NODE_TEXT_COLOR,
+};
+
+use super::{
+    components::popup::{spawn_popup_root, Popup, PopupGroup},
+    nodes::TargetPosition,
+};
+
+pub struct CreateNodePlugin;
+
+impl Plugin for CreateNodePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Startup, register_create_child_system)
+            .add_systems(Update, edit_name_prompt)
+        ;
+    }
+}
+
+/// Prompt for the name of a new child node. Lives on the root of the popup.
+#[derive(Component, Debug)]
+pub struct NamePrompt {
+    /// The view node to create the child under.
+    parent: Entity,
+    /// World position to place the new node at.
+    position: Vec2,
+    name: String,
+    /// Why the latest attempt to create the node failed, if it did.
+    error: Option<String>,
+}
+
+impl NamePrompt {
+    fn label(&self) -> String {
+        match &self.error {
+            Some(error) => format!("Name: {}_\n{}", self.name, error),
+            None => format!("Name: {}_", self.name),
+        }
+    }
+}
+
+#[derive(Component)]
+struct NamePromptText;
+
+/// What a key press does to the name being typed.
+#[derive(Debug, PartialEq)]
+enum PromptAction {
+    Edit,
+    Confirm,
+    Cancel,
+    Ignore,
+}
+
+fn apply_key(name: &mut String, key: &Key) -> PromptAction {
+    match key {
+        Key::Character(chars) => {
+            name.extend(chars.chars().filter(|c| !c.is_control()));
+            PromptAction::Edit
+        }
+        Key::Space => {
+            name.push(' ');
+            PromptAction::Edit
+        }
+        Key::Backspace => {
+            name.pop();
+            PromptAction::Edit
+        }
+        Key::Enter => PromptAction::Confirm,
+        Key::Escape => PromptAction::Cancel,
+        _ => PromptAction::Ignore,
+    }
+}
+
+/// Run condition for the keyboard shortcuts, so that typing a name doesn't trigger them.
+pub fn no_name_prompt(prompts: Query<(), With<NamePrompt>>) -> bool {
+    prompts.is_empty()
+}
+
+fn register_create_child_system(world: &mut World) {
+    let create_child = world.register_system(open_create_child_prompt);
+    world
+        .resource_mut::<ContextComponentSystems>()
+        .insert::<ViewNode>("Create child".to_string(), create_child);
+}
+
+/// Opens the name prompt for a child of the node that the context menu was opened on.
+fn open_create_child_prompt(
+    mut commands: Commands,
+    input_data: Res<InputData>,
+    view_nodes: Query<&ViewNode>,
+    parents: Query<&Parent>,
+    menus: Query<(Entity, &PopupGroup), With<Popup>>,
+    window: Query<&Window>,
+){
+    // The click may have landed on a child of the node, such as its outline
+    let parent = match input_data.latest_click_entity() {
+        Some(target) if view_nodes.contains(target) => target,
+        Some(target) => match parents.get(target) {
+            Ok(parent) if view_nodes.contains(parent.get()) => parent.get(),
+            _ => return,
+        },
+        None => return,
+    };
+
+    let cursor = match window.get_single().ok().and_then(|window| window.cursor_position()) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    let prompt = NamePrompt {
+        parent,
+        position: input_data.cursor_world_current_position(),
+        name: String::new(),
+        error: None,
+    };
+
+    let text = commands.spawn((
+        TextBundle::from_section(
+            prompt.label(),
+            TextStyle {
+                font_size: 16.0,
+                color: TEXT_NODE_TEXT_COLOR,
+                ..default()
+            },
+        ),
+        NamePromptText,
+    )).id();
+
+    let root = spawn_popup_root(&mut commands, &menus, PopupGroup::ModalStrong, cursor, Vec2::new(240.0, 50.0));
+    commands.entity(root).insert(prompt).push_children(&[text]);
+}
+
+/// Edits the name in the open prompt as the user types. Enter creates the node,
+/// escape closes the prompt. If the node can't be created, the prompt stays open
+/// with the reason, so that the name can be fixed.
+fn edit_name_prompt(
+    mut commands: Commands,
+    mut ev_keys: EventReader<KeyboardInput>,
+    mut vault: ResMut<CurrentVault>,
+    mut prompts: Query<(Entity, &mut NamePrompt, &Children)>,
+    mut texts: Query<&mut Text, With<NamePromptText>>,
+    view_nodes: Query<&ViewNode>,
+){
+    let Ok((prompt_e, mut prompt, children)) = prompts.get_single_mut() else {
+        ev_keys.clear();
+        return
+    };
+
+    for ev in ev_keys.read() {
+        if ev.state != ButtonState::Pressed {
+            continue
+        }
+
+        match apply_key(&mut prompt.name, &ev.logical_key) {
+            PromptAction::Edit => prompt.error = None,
+            PromptAction::Ignore => continue,
+            PromptAction::Cancel => {
+                commands.entity(prompt_e).despawn_recursive();
+                return
+            }
+            PromptAction::Confirm => {
+                let parent_path = match view_nodes.get(prompt.parent).ok().and_then(|node| node.path.clone()) {
+                    Some(path) => path,
+                    None => {
+                        commands.entity(prompt_e).despawn_recursive();
+                        return
+                    }
+                };
+
+                // Writing to the graph isn't a change of vault, which would reset the context
+                match create_child_node(&mut commands, vault.bypass_change_detection(), &parent_path, &prompt.name) {
+                    Ok(entity) => {
+                        commands.entity(entity).insert(TargetPosition { position: prompt.position });
+                        commands.entity(prompt_e).despawn_recursive();
+                        return
+                    }
+                    Err(err) => prompt.error = Some(err.to_string()),
+                }
+            }
+        }
+    }
+
+    if !prompt.is_changed() {
+        return
+    }
+    for child in children.iter() {
+        if let Ok(mut text) = texts.get_mut(*child) {
+            text.sections[0].value = prompt.label();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typing_edits_the_name_until_confirmed() {
+        let mut name = String::new();
+
+        let keys = [
+            Key::Character("a".into()),
+            Key::Character("b".into()),
+            Key::Backspace,
+            Key::Space,
+            Key::Character("c".into()),
+            Key::Shift,
+        ];
+        let actions: Vec<PromptAction> = keys.iter().map(|key| apply_key(&mut name, key)).collect();
+
+        assert_eq!(name, "a c");
+        assert_eq!(actions.last(), Some(&PromptAction::Ignore), "Modifiers shouldn't edit the name");
+        assert_eq!(apply_key(&mut name, &Key::Enter), PromptAction::Confirm);
+        assert_eq!(apply_key(&mut name, &Key::Escape), PromptAction::Cancel);
+        assert_eq!(name, "a c");
+    }
+}
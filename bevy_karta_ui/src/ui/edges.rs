@@ -6,72 +6,90 @@
 // use crate::{settings::theme::*, events::edges::EdgeClickEvent, prelude::theme::EDGE_PARENT_COLOR};
 // use super::{nodes::ViewNode, graph_cam::ViewData};
 
-use bevy::prelude::*;
+use bevy::{prelude::*, render::view::RenderLayers};
 use bevy_karta_client::prelude::*;
+use bevy_prototype_lyon::{shapes, prelude::{GeometryBuilder, Path, ShapeBundle, Stroke}};
 use pe_index::PathsToEntitiesIndex;
 
+use crate::settings::theme::{EDGE_BASE_COLOR, EDGE_PARENT_COLOR};
+
+use super::graph_cam::ViewData;
+
+/// Width of the line of a contains edge. Associative edges are drawn thinner,
+/// so that the hierarchy stands out.
+const PARENT_EDGE_WIDTH: f32 = 8.0;
+const EDGE_WIDTH: f32 = 4.0;
 
 pub struct EdgeUiPlugin;
 
 impl Plugin for EdgeUiPlugin {
     fn build(&self, app: &mut App) {
-        // let material_plugin = Material2dPlugin::<EdgeMaterial>::default();
         app
-            .add_systems(PostUpdate, update_and_draw_edges)
+            .add_systems(PostUpdate, add_edge_ui)
+            .add_systems(PostUpdate, update_edges.after(add_edge_ui))
 
             // .add_systems(PostUpdate, visualise_edge_transforms)
         ;
     }
 }
 
-pub fn update_and_draw_edges(
-    mut edges: Query<(Entity, &DataEdge), Without<ViewNode>>,
-    nodes: Query<&Transform, With<ViewNode>>,
-    pe_index: Res<PathsToEntitiesIndex>,
-    mut gizmos: Gizmos,
+/// Gives new data edges a line to be drawn with. The line is hidden until
+/// both of its ends have been spawned.
+pub fn add_edge_ui(
+    new_edges: Query<(Entity, &DataEdge), Added<DataEdge>>,
+    mut commands: Commands,
+    mut view_data: ResMut<ViewData>,
 ){
-    for (_edge, data) in edges.iter_mut() {
-        let source_entity = match pe_index.get_view(&data.source){
-            Some(entity) => entity,
-            None => {
-                // println!("Failed to find entity for edge source");
-                continue
-            },
-        };
-        let target_entity = match pe_index.get_view(&data.target){
-            Some(entity) => entity,
-            None => {
-                // println!("Failed to find entity for edge target");
-                continue
-            },
-        };
-        let start = match nodes.get(source_entity) {
-            Ok(node) => node,
-            Err(_) => {
-                // println!("Failed to find node for edge source");
-                // commands.entity(edge).despawn_recursive();
-                continue
-            },
-        };
-        let end = match nodes.get(target_entity){
-            Ok(node) => node,
-            Err(_) => {
-                // println!("Failed to find node for edge target");
-                // commands.entity(edge).despawn_recursive();
-                continue
-            },
+    for (entity, data) in new_edges.iter() {
+        let line = shapes::Line(Vec2::ZERO, Vec2::ZERO);
+
+        let stroke = match data.contains {
+            true => Stroke::new(EDGE_PARENT_COLOR, PARENT_EDGE_WIDTH),
+            false => Stroke::new(EDGE_BASE_COLOR, EDGE_WIDTH),
         };
 
+        commands.entity(entity).insert((
+            RenderLayers::layer(31),
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&line),
+                spatial: SpatialBundle {
+                    transform: Transform::from_translation(
+                        Vec3::new(0.0, 0.0, view_data.get_z_for_edge())
+                    ),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                ..default()
+            },
+            stroke,
+        ));
+    }
+}
 
-        // Check that all positions are valid
-        if !start.translation.x.is_finite() || !start.translation.y.is_finite() || !end.translation.x.is_finite() || !end.translation.y.is_finite() {
-            // commands.entity(edge).despawn_recursive();
+/// Redraws the lines of the edges between the current positions of their nodes.
+/// Edges with an end that isn't spawned as a view node are hidden.
+pub fn update_edges(
+    mut edges: Query<(&DataEdge, &mut Path, &mut Visibility), Without<ViewNode>>,
+    nodes: Query<&Transform, With<ViewNode>>,
+    pe_index: Res<PathsToEntitiesIndex>,
+){
+    for (data, mut path, mut visibility) in edges.iter_mut() {
+        let ends = [&data.source, &data.target].map(|end| {
+            pe_index
+                .get_view(end)
+                .and_then(|entity| nodes.get(entity).ok())
+                .map(|tform| tform.translation.xy())
+                // Check that the position is valid
+                .filter(|pos| pos.is_finite())
+        });
+
+        let [Some(start), Some(end)] = ends else {
+            *visibility = Visibility::Hidden;
             continue
-        }
-
-        let color = LinearRgba::BLUE;
-        gizmos.line_2d(start.translation.xy(), end.translation.xy(), color);
+        };
 
+        *path = GeometryBuilder::build_as(&shapes::Line(start, end));
+        *visibility = Visibility::Inherited;
     }
 }
 
@@ -59,6 +59,14 @@ impl ViewData {
         self.top_z = self.top_z + self.increment;
         self.top_z
     }
+
+    /// Z for a node with a stacking order saved in its context. Nodes spawned
+    /// afterwards without a saved order still go on top of it.
+    pub fn get_z_for_saved_node(&mut self, z_index: f32) -> f32 {
+        let z = 1.0 + z_index * self.increment;
+        self.top_z = self.top_z.max(z);
+        z
+    }
 }
 
 #[derive(Component)]
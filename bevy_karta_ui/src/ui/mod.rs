@@ -8,18 +8,22 @@ use bevy_prototype_lyon::prelude::*;
 use edges::EdgeUiPlugin;
 
 use self::{
+    components::{context_menu::{self, *}, popup},
     nodes::NodesUiPlugin, // edges::EdgeUiPlugin, 
-    grid::InfiniteGrid2DPlugin, graph_cam::GraphCamera, asset_manager::{ImageLoadTracker, on_image_load},
+    grid::InfiniteGrid2DPlugin, graph_cam::GraphCamera, asset_manager::{ImageLoadTracker, on_image_load, fallback_failed_image_nodes},
 };
 
 use super::events::{node_events::NodeClickEvent, edges::EdgeClickEvent};
 
 // Building blocks of specific components
 
+pub(crate) mod components;
 pub(crate) mod grid;
 pub(crate) mod ui_base_panel;
 pub(crate) mod nodes;
 pub(crate) mod edges;
+pub(crate) mod selection;
+pub(crate) mod create_node;
 pub(crate) mod graph_cam;
 pub(crate) mod simulation;
 pub(crate) mod asset_manager;
@@ -40,7 +44,7 @@ impl Plugin for KartaUiPlugin {
 
             .insert_resource(ImageLoadTracker::new())
 
-            // .add_event::<ContextMenuSpawnEvent>()
+            .add_event::<ContextMenuSpawnEvent>()
 
             // Resources
             .add_systems(PreStartup, require_markers_for_raycasting)
@@ -52,29 +56,29 @@ impl Plugin for KartaUiPlugin {
 
             .add_plugins(NodesUiPlugin)
             .add_plugins(EdgeUiPlugin)
+            .add_plugins(selection::SelectionPlugin)
+            .add_plugins(create_node::CreateNodePlugin)
             // .add_plugins(InfiniteGrid2DPlugin)
             
             // Element Systems
-            // .add_systems(PostUpdate, popup::popup_position_system.after(UiSystem::Layout))
+            .add_systems(PostUpdate, popup::popup_position_system.after(UiSystem::Layout))
                         
+            .add_systems(
+                Update, 
+                (
+                    context_menu::despawn_context_menus_on_any_click,
+                    spawn_node_context_menu.run_if(on_event::<NodeClickEvent>()),
+                    // spawn_edge_context_menu.run_if(on_event::<EdgeClickEvent>())
+                ).chain()
+            )
+
+            .add_systems(PostUpdate, add_component_systems_to_context_menu
+                .run_if(on_event::<ContextMenuSpawnEvent>())
+            ) 
             
-            // .add_systems(Update, context_menu::despawn_context_menus_on_any_click)
-            // .add_systems(
-            //     Update, 
-            //     (
-            //         spawn_node_context_menu.run_if(on_event::<NodeClickEvent>()),
-            //         spawn_edge_context_menu.run_if(on_event::<EdgeClickEvent>())
-            //     )
-            //     // .after(context_menu::despawn_context_menus_on_any_click)
-            //     // .before(add_component_systems_to_context_menu)
-            // )
-
-            // .add_systems(PostUpdate, add_component_systems_to_context_menu
-            //     .run_if(on_event::<ContextMenuSpawnEvent>())
-            // ) 
-            
-            // .add_systems(PostUpdate, context_menu_button_system)
+            .add_systems(PostUpdate, context_menu_button_system)
             .add_systems(PostUpdate, on_image_load)
+            .add_systems(PostUpdate, fallback_failed_image_nodes)
 
             .add_plugins(curve_material::CurveMaterialPlugin)
         ;
@@ -1,18 +1,23 @@
 use std::{time::Duration, path::PathBuf};
 
 use bevy::{prelude::*, text::Text2dBounds, sprite::Anchor, render::view::RenderLayers, window::PrimaryWindow};
-use bevy_karta_client::prelude::{DataNode, ViewNode};
+use bevy_karta_client::prelude::{
+    pe_index::PathsToEntitiesIndex, positions::PendingPositionSaves,
+    Attributes, CurrentContext, CurrentVault, DataNode, DataNodeType, SavedPin, SavedPosition, SavedZIndex, ViewNode,
+};
 use bevy_mod_picking::{prelude::*, backends::raycast::RaycastPickable, backend::{PointerHits, HitData}};
 use bevy_prototype_lyon::{shapes, prelude::{GeometryBuilder, ShapeBundle, Stroke, StrokeOptions}};
 use bevy_tweening::{Tween, EaseFunction, lens::TransformPositionLens, Animator, TweenCompleted, TweeningPlugin};
 
 use crate::{
-    events::node_events::*, settings::theme::*, prelude::{GraphEntity, Pins, pointer::InputData}, 
+    events::node_events::*, settings::{theme::*, node_colors::NodeTypeColors}, prelude::{GraphEntity, Pins, pointer::InputData, keymap::{KeyAction, KeyMap}}, 
 };
 
-use self::node_ui_types::add_base_node_ui;
+use self::node_ui_types::{
+    add_base_node_ui, add_image_node_ui, add_text_node_ui, is_image_path, text_preview,
+};
 
-use super::graph_cam::ViewData;
+use super::{asset_manager::ImageLoadTracker, graph_cam::ViewData};
 
 mod node_ui_types;
 
@@ -30,7 +35,9 @@ impl Plugin for NodesUiPlugin {
             .add_systems(PreUpdate, node_picking.in_set(picking_core::PickSet::Backend))
 
             .add_systems(Update, move_node_selection)
-            .add_systems(Update, change_current_context_on_c)
+            .add_systems(Update, queue_dragged_node_positions.after(move_node_selection))
+            .add_systems(Update, toggle_pin_on_selected_nodes.run_if(super::create_node::no_name_prompt))
+            .add_systems(Update, change_current_context_on_c.run_if(super::create_node::no_name_prompt))
             .add_systems(Update, toggle_outline_on_node_select)
 
             .add_systems(PostUpdate, add_node_ui)
@@ -69,6 +76,26 @@ impl GraphStartingPositions {
         println!("Setting spawn position to {:#?}", pos);
         self.position = pos;
     }
+
+    /// Spawn position of the node with the given index among the nodes spawned together.
+    /// Nodes are laid out on a spiral around the spawn position, so that they don't
+    /// overlap and the same nodes always end up in the same places.
+    pub fn get_pos_for_index(&self, index: usize) -> Vec2 {
+        spiral_position(self.position, index)
+    }
+}
+
+/// Distance between neighbouring nodes in the spawn spiral.
+const SPAWN_SPACING: f32 = 80.0;
+
+/// Position of the nth point of a sunflower spiral around the center. Consecutive points
+/// are a golden angle apart, which spreads them evenly however many there are.
+fn spiral_position(center: Vec2, index: usize) -> Vec2 {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+    let n = index as f32 + 1.0;
+    let distance = SPAWN_SPACING * n.sqrt();
+    let angle = n * golden_angle;
+    center + Vec2::new(angle.cos(), angle.sin()) * distance
 }
 
 // Component Definitions
@@ -149,8 +176,17 @@ impl Default for ViewNodeScale {
 // TODO: Convert to One-Shot System
 // Is that even possible? This function requires input parameters. 
 pub fn add_node_ui(
-    new_nodes: Query<(Entity, &DataNode, Option<&Name>, Option<&TargetPosition>), Added<DataNode>>,
+    new_nodes: Query<
+        (
+            Entity, &DataNode, Option<&DataNodeType>, Option<&Name>,
+            Option<&Attributes>, Option<&TargetPosition>, Option<&SavedZIndex>,
+            Option<&SavedPosition>, Option<&SavedPin>,
+        ),
+        Added<DataNode>,
+    >,
     spawn: Res<GraphStartingPositions>,
+    vault: Option<Res<CurrentVault>>,
+    type_colors: Res<NodeTypeColors>,
 
     mut commands: Commands,
 
@@ -158,9 +194,23 @@ pub fn add_node_ui(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut view_data: ResMut<ViewData>,
+    mut image_tracker: ResMut<ImageLoadTracker>,
     // systems: Res<UiNodeSystemsIndex>,
 ){
-    for (entity, data, name, tpos) in new_nodes.iter(){
+    // Sorted by path so that the same nodes get the same spawn positions every time
+    let mut new_nodes: Vec<_> = new_nodes.iter().collect();
+    new_nodes.sort_by_key(|(_, data, ..)| data.path.alias());
+
+    for (index, (entity, data, ntype, name, attributes, tpos, saved_z, saved_pos, saved_pin)) in new_nodes.into_iter().enumerate(){
+
+        // Nodes of the saved context go back to where they were, relative to the
+        // focal node. Only the rest are laid out on the spiral.
+        let saved_pos = saved_pos.map(|saved| TargetPosition { position: spawn.get_pos() + saved.0 });
+        let tpos = saved_pos.as_ref().or(tpos);
+        let pins = match saved_pin {
+            Some(_) => Pins::new_pinpos(),
+            None => Pins::default(),
+        };
 
         // println!("Node type: {:#?}", data.ntype);
 
@@ -178,7 +228,7 @@ pub fn add_node_ui(
                 path: Some(data.path.clone()),
                 data: Some(entity),
             },
-            Pins::default(),
+            pins,
             Velocity2D::default(),
 
             PickableBundle {
@@ -191,6 +241,7 @@ pub fn add_node_ui(
             RaycastPickable,
             
             On::<Pointer<Drag>>::send_event::<MoveNodesEvent>(),
+            On::<Pointer<DragEnd>>::send_event::<NodeDragEndEvent>(),
             On::<Pointer<Click>>::send_event::<NodeClickEvent>(),
             On::<Pointer<Down>>::send_event::<NodePressEvent>(),
             On::<Pointer<Over>>::send_event::<NodeHoverEvent>(),
@@ -204,23 +255,35 @@ pub fn add_node_ui(
             }),
         );
 
-        // match data.ntype {
-        //     NodeTypes::FileImage => {
-        //         add_image_node_ui(
-        //             entity, &data, spawn.get_pos(), tpos,
-        //             &mut commands, &mut server, &mut view_data
-        //         )
-        //     },
-        //     _ => add_base_node_ui(
-        //         entity, &data, spawn.get_pos(), tpos,
-        //         &mut commands, &mut meshes, &mut materials, &mut view_data
-        //     ),
-        // }
-
-        add_base_node_ui(
-            node, data, name, spawn.get_pos(), tpos,
-            &mut commands, &mut meshes, &mut materials, &mut view_data
-        )
+        let node_z = match saved_z {
+            Some(saved_z) => view_data.get_z_for_saved_node(saved_z.0),
+            None => view_data.get_z_for_node(),
+        };
+
+        // Images are resolved through the vault, so that they can be loaded from disk.
+        // Physical files have a type by their content, such as Image or Text.
+        let file_path = vault
+            .as_ref()
+            .and_then(|vault| vault.vault.as_ref())
+            .map(|vault| data.path.full(&vault.vault_dirpath()))
+            .filter(|path| path.is_file());
+
+        if let Some(image_path) = file_path.clone().filter(|path| is_image_path(path)) {
+            add_image_node_ui(
+                node, name, image_path, spawn.get_pos_for_index(index), tpos,
+                &mut commands, &server, &mut image_tracker, node_z
+            );
+        } else if let Some(preview) = file_path.as_deref().and_then(|path| text_preview(path, ntype)) {
+            add_text_node_ui(
+                node, name, &preview, spawn.get_pos_for_index(index), tpos,
+                &mut commands, &mut meshes, &mut materials, node_z
+            );
+        } else {
+            add_base_node_ui(
+                node, data, ntype, name, attributes, &type_colors, spawn.get_pos_for_index(index), tpos,
+                &mut commands, &mut meshes, &mut materials, node_z
+            );
+        }
 
     }
 }
@@ -277,16 +340,41 @@ pub fn add_node_base_outline(
     top_z: &f32,
 ){
     
-    let outline_width = 10.0;
-    let outline_width_hovered = 12.0;
-    
     let outline_shape = shapes::Circle {
-        radius: radius + outline_width / 2.,
+        radius: radius + OUTLINE_WIDTH / 2.,
         center: Vec2::from((0.0, 0.0)),
     };
 
-    let outline_path = GeometryBuilder::build_as(&outline_shape);
-    
+    spawn_node_outline(commands, parent, GeometryBuilder::build_as(&outline_shape), top_z);
+}
+
+/// Rectangular version of the node outline, for nodes that aren't circles.
+pub fn add_node_rect_outline(
+    commands: &mut Commands,
+    parent: &Entity,
+    size: Vec2,
+    top_z: &f32,
+){
+    let outline_shape = shapes::Rectangle {
+        extents: size + Vec2::new(OUTLINE_WIDTH, OUTLINE_WIDTH),
+        origin: shapes::RectangleOrigin::Center,
+    };
+
+    spawn_node_outline(commands, parent, GeometryBuilder::build_as(&outline_shape), top_z);
+}
+
+const OUTLINE_WIDTH: f32 = 10.0;
+const OUTLINE_WIDTH_HOVERED: f32 = 12.0;
+
+fn spawn_node_outline(
+    commands: &mut Commands,
+    parent: &Entity,
+    outline_path: bevy_prototype_lyon::entity::Path,
+    top_z: &f32,
+){
+    let outline_width = OUTLINE_WIDTH;
+    let outline_width_hovered = OUTLINE_WIDTH_HOVERED;
+
     let node_outline = commands.spawn((
         RenderLayers::layer(31),
         ShapeBundle {
@@ -298,7 +386,7 @@ pub fn add_node_base_outline(
             ..default()
         },
         Stroke::new(
-            OUTLINE_BASE_COLOR, 10.0
+            OUTLINE_BASE_COLOR, outline_width
             // Color::rgba(0.0, 0.0, 0.0, 0.0), 10.0
         ),
         NodeOutline,
@@ -390,6 +478,89 @@ pub fn move_node_selection(
     }
 }
 
+/// Queues the positions and pins of the nodes to be saved in the current context.
+/// The positions are saved relative to the focal node.
+fn queue_node_placements<'a>(
+    context: &CurrentContext,
+    pe_index: &PathsToEntitiesIndex,
+    pending: &mut PendingPositionSaves,
+    position_of: impl Fn(Entity) -> Option<Vec2>,
+    nodes: impl Iterator<Item = (&'a ViewNode, Vec2, bool)>,
+){
+    let focal = match context.path() {
+        Some(focal) => focal,
+        None => return,
+    };
+    let origin = pe_index
+        .get_view(focal)
+        .and_then(position_of)
+        .unwrap_or(Vec2::ZERO);
+
+    for (node, position, pinned) in nodes {
+        let path = match &node.path {
+            Some(path) => path,
+            None => continue,
+        };
+        pending.queue(focal.clone(), path.clone(), position - origin, pinned);
+    }
+}
+
+/// Queues the positions of the dragged nodes to be saved when the drag ends.
+pub fn queue_dragged_node_positions(
+    mut ev_drag_end: EventReader<NodeDragEndEvent>,
+    context: Res<CurrentContext>,
+    pe_index: Res<PathsToEntitiesIndex>,
+    mut pending: ResMut<PendingPositionSaves>,
+    query: Query<(&ViewNode, &Transform, &PickSelection, &Pins)>,
+){
+    if ev_drag_end.is_empty() {
+        return
+    }
+    ev_drag_end.clear();
+
+    queue_node_placements(
+        &context,
+        &pe_index,
+        &mut pending,
+        |entity| query.get(entity).ok().map(|(_, transform, ..)| transform.translation.xy()),
+        query
+            .iter()
+            .filter(|(.., selection, _)| selection.is_selected)
+            .map(|(node, transform, _, pins)| (node, transform.translation.xy(), pins.position)),
+    );
+}
+
+/// Pins the selected nodes in place, or unpins them if they were pinned. The
+/// pins are saved in the current context along with the positions of the nodes.
+pub fn toggle_pin_on_selected_nodes(
+    keymap: Res<KeyMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    context: Res<CurrentContext>,
+    pe_index: Res<PathsToEntitiesIndex>,
+    mut pending: ResMut<PendingPositionSaves>,
+    mut query: Query<(&ViewNode, &Transform, &PickSelection, &mut Pins)>,
+){
+    if !keymap.just_pressed(KeyAction::TogglePin, &keys) {
+        return
+    }
+
+    for (_, _, selection, mut pins) in query.iter_mut() {
+        if !selection.is_selected {continue};
+        pins.position = !pins.position;
+    }
+
+    queue_node_placements(
+        &context,
+        &pe_index,
+        &mut pending,
+        |entity| query.get(entity).ok().map(|(_, transform, ..)| transform.translation.xy()),
+        query
+            .iter()
+            .filter(|(.., selection, _)| selection.is_selected)
+            .map(|(node, transform, _, pins)| (node, transform.translation.xy(), pins.position)),
+    );
+}
+
 pub fn toggle_outline_on_node_select(
     nodes: Query<(&PickSelection, &Children), Changed<PickSelection>>,
     mut outlines: Query<&mut Visibility, With<NodeOutline>>,
@@ -757,4 +928,25 @@ fn visualise_selected (
 // }
 
 
-                    
\ No newline at end of file
+                    
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_positions_are_deterministic_and_apart() {
+        let spawn = GraphStartingPositions {
+            position: Vec2::new(100.0, -50.0),
+        };
+
+        let positions: Vec<Vec2> = (0..20).map(|i| spawn.get_pos_for_index(i)).collect();
+        let again: Vec<Vec2> = (0..20).map(|i| spawn.get_pos_for_index(i)).collect();
+        assert_eq!(positions, again, "Same indices should give the same positions");
+
+        for (i, a) in positions.iter().enumerate() {
+            for b in positions.iter().skip(i + 1) {
+                assert!(a.distance(*b) > 40.0, "Nodes should not spawn on top of each other");
+            }
+        }
+    }
+}
@@ -1,6 +1,10 @@
 use bevy::{
     asset::{AssetServer, Assets, Handle},
-    color::Color,
+    hierarchy::BuildChildren,
+    render::view::RenderLayers,
+    sprite::Anchor,
+    text::{Text, Text2dBounds, Text2dBundle, TextStyle},
+    color::{Color, Srgba},
     core::Name,
     ecs::{entity::Entity, system::Commands},
     math::{self, Vec2, Vec3},
@@ -9,15 +13,22 @@ use bevy::{
     sprite::{ColorMaterial, MaterialMesh2dBundle, Sprite, SpriteBundle},
     transform::components::Transform,
 };
-use bevy_karta_client::prelude::DataNode;
-use rand::Rng;
+use std::path::{Path, PathBuf};
+
+use bevy_karta_client::prelude::{
+    read_text_preview, Attributes, DataNode, DataNodeType, NodeType, NODE_COLOR_ATTR,
+};
 
 use crate::{
     prelude::GraphEntity,
-    ui::{graph_cam::ViewData, nodes::ViewNodeShape},
+    settings::{
+        node_colors::NodeTypeColors,
+        theme::{NODE_BASE_RADIUS, TEXT_NODE_COLOR, TEXT_NODE_TEXT_COLOR},
+    },
+    ui::{asset_manager::ImageLoadTracker, nodes::ViewNodeShape},
 };
 
-use super::{add_node_base_outline, add_node_label, TargetPosition};
+use super::{add_node_base_outline, add_node_label, add_node_rect_outline, TargetPosition};
 
 // TODO: Convert back to using one-shot systems in 0.13
 // #[derive(Resource)]
@@ -42,6 +53,31 @@ use super::{add_node_base_outline, add_node_label, TargetPosition};
 
 // }
 
+/// Parses a hex color such as "#ff8800" or "ff8800cc". None if the string is malformed.
+pub fn parse_hex_color(hex: &str) -> Option<Color> {
+    Srgba::hex(hex.trim()).ok().map(Color::from)
+}
+
+/// The color of the node from its color attribute, or the color of its type.
+fn node_color(
+    attributes: Option<&Attributes>,
+    ntype: Option<&DataNodeType>,
+    type_colors: &NodeTypeColors,
+) -> Color {
+    let explicit = attributes
+        .and_then(|attrs| attrs.get_string(NODE_COLOR_ATTR))
+        .and_then(parse_hex_color);
+    type_colors.resolve(explicit, ntype.map(|ntype| ntype.name()))
+}
+
+/// The radius of the node from its "radius" attribute, or the default radius.
+fn node_radius(attributes: Option<&Attributes>) -> f32 {
+    attributes
+        .and_then(|attrs| attrs.get_float("radius"))
+        .filter(|radius| radius.is_finite() && *radius > 0.0)
+        .unwrap_or(NODE_BASE_RADIUS)
+}
+
 // BASE NODE
 // ----------------------------------------------------------------
 // For the node types that don't have a specific ui
@@ -49,7 +85,10 @@ use super::{add_node_base_outline, add_node_label, TargetPosition};
 pub fn add_base_node_ui(
     entity: Entity,
     data: &DataNode,
+    ntype: Option<&DataNodeType>,
     name: Option<&Name>,
+    attributes: Option<&Attributes>,
+    type_colors: &NodeTypeColors,
     spawn_pos: Vec2,
     tpos: Option<&TargetPosition>,
 
@@ -57,28 +96,22 @@ pub fn add_base_node_ui(
 
     meshes: &mut Assets<Mesh>,
     materials: &mut Assets<ColorMaterial>,
-    view_data: &mut ViewData,
+    node_z: f32,
 ) {
-    // Positions are slightly randomized to avoid nodes being spawned on top of each other
-    let mut rng = rand::thread_rng();
-    let label_pos = Vec2::new(40.0, 0.0);
-    let radius = 35.0;
+    let radius = node_radius(attributes);
+    let label_pos = Vec2::new(radius + 5.0, 0.0);
 
     let node_pos: Vec2 = match tpos {
         Some(pos) => pos.position,
-        None => Vec2::new(
-            spawn_pos.x + rng.gen_range(-10.0..10.0),
-            spawn_pos.y + rng.gen_range(-10.0..10.0),
-        ),
+        None => spawn_pos,
     };
 
-    let node_z = view_data.get_z_for_node();
 
     println!("z depth for base node ui: {}", node_z);
 
     commands.entity(entity).insert((MaterialMesh2dBundle {
         mesh: meshes.add(math::primitives::Circle::new(radius)).into(),
-        material: materials.add(ColorMaterial::from(Color::rgb(0.3, 0.0, 0.0))),
+        material: materials.add(ColorMaterial::from(node_color(attributes, ntype, type_colors))),
         transform: Transform::from_translation(Vec3::new(node_pos.x, node_pos.y, node_z)),
         ..default()
     },));
@@ -98,88 +131,205 @@ pub fn add_base_node_ui(
 // IMAGE NODE
 // ----------------------------------------------------------------
 
-// pub fn add_image_node_ui(
-//     entity: Entity,
-//     data: &GraphEntity,
-//     spawn_pos: Vec2,
-//     tpos: Option<&TargetPosition>,
+/// Extensions of the image formats that the ui can load.
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "bmp", "tga", "webp"];
 
-//     mut commands: &mut Commands,
+pub fn is_image_path(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+        None => false,
+    }
+}
 
-//     server: &mut AssetServer,
-//     view_data: &mut ViewData,
-// ){
-//     let mut rng = rand::thread_rng();
-
-//     let full_path = &data.path;
-//     println!("Adding image node ui: {:#?}", full_path);
-
-//     let accepted_image_formats = vec!["png", "jpg", "jpeg", "gif", "bmp", "tga", "tif", "tiff", "webp", "ico",];
-//     match full_path.extension() {
-//         Some(ext) => {
-//             if !accepted_image_formats.contains(&ext.to_str().unwrap()) {
-//                 return;
-//             }
-//             println!("Found extension: {}", ext.to_string_lossy());
-//         },
-//         None => return,
-//     }
+/// Spawns the image of the node as a sprite. The dimensions of the image are not
+/// known until it is loaded, so the sprite is resized in on_image_load() in asset_manager.rs.
+/// If the image fails to load, fallback_failed_image_nodes() turns the node into a base node.
+pub fn add_image_node_ui(
+    entity: Entity,
+    name: Option<&Name>,
+    image_path: PathBuf,
+    spawn_pos: Vec2,
+    tpos: Option<&TargetPosition>,
 
-//     // let metadata = full_path.metadata().unwrap();
-//     // println!("Metadata: {:#?}", metadata);
-
-//     let image: Handle<Image> = server.load(full_path.clone());
-
-//     let node_pos: Vec2 = match tpos {
-//         Some(pos) => pos.position,
-//         None => {
-//             Vec2::new(
-//                 spawn_pos.x + rng.gen_range(-10.0..10.0),
-//                 spawn_pos.y + rng.gen_range(-10.0..10.0),
-//             )
-//         },
-//     };
-
-//     let node_z = view_data.get_z_for_node();
-//     let size = Vec2::new(60.0, 40.0);
-
-//     commands.entity(entity).insert((
-
-//         // The actual dimensions of the image are not known until it is loaded.
-//         // The dimensions are set in on_image_load() in asset_manager.rs
-//         ViewNodeShape::Rectangle(size),
-//         SpriteBundle {
-//             texture: image,
-//             sprite: Sprite {
-//                 //anchor: bevy::sprite::Anchor::Center,
-//                 ..default()
-//             },
-//             transform: Transform {
-//                 translation: Vec3::new(
-//                     node_pos.x,
-//                     node_pos.y,
-//                     node_z,
-//                 ),
-//                 ..default()
-//             },
-//             ..default()
-//         },
-//     ));
-
-//     let pos = Vec2::new(-size.x / 2.0, -size.y / 2.0);
-
-//     add_node_label(&mut commands, &entity, &data.path, pos, &node_z);
-//     // add_node_rect_outline(&mut commands, &ev.entity, size, &view_data.top_z);
-//     add_node_base_outline(&mut commands, &entity, size.x, &node_z);
+    mut commands: &mut Commands,
 
-// }
+    server: &AssetServer,
+    image_tracker: &mut ImageLoadTracker,
+    node_z: f32,
+) {
+    let image: Handle<Image> = server.load(image_path);
+    image_tracker.add_image(image.clone_weak());
+
+    let node_pos: Vec2 = match tpos {
+        Some(pos) => pos.position,
+        None => spawn_pos,
+    };
+
+    let size = Vec2::new(60.0, 40.0);
+
+    commands.entity(entity).insert((
+        ViewNodeShape::Rectangle(size),
+        SpriteBundle {
+            texture: image,
+            sprite: Sprite {
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_translation(Vec3::new(node_pos.x, node_pos.y, node_z)),
+            ..default()
+        },
+    ));
+
+    if let Some(name) = name {
+        let label_pos = Vec2::new(-size.x / 2.0, size.y / 2.0 + 10.0);
+        add_node_label(&mut commands, &entity, name, label_pos, &node_z);
+    }
+    add_node_rect_outline(&mut commands, &entity, size, &node_z);
+}
 
 // TEXT NODE
 // ----------------------------------------------------------------
 
+/// At most this many bytes are read from a file for its preview,
+/// so that large files are never loaded in full.
+const TEXT_PREVIEW_BYTES: u64 = 512;
+
+/// Characters shown in the preview before it is cut off with an ellipsis.
+const TEXT_PREVIEW_CHARS: usize = 200;
+
+/// The beginning of the text of a text node's file, read the same way the
+/// server reads previews. None if the node isn't a text node or the file
+/// can't be read as text.
+pub fn text_preview(path: &Path, ntype: Option<&DataNodeType>) -> Option<String> {
+    if ntype.map(|ntype| **ntype != NodeType::text()).unwrap_or(true) {
+        return None;
+    }
+
+    let preview = read_text_preview(path, TEXT_PREVIEW_BYTES).ok()?;
+    if preview.is_binary {
+        return None;
+    }
+
+    let mut text: String = preview.text.chars().take(TEXT_PREVIEW_CHARS).collect();
+    if text.len() < preview.text.len() || preview.truncated {
+        text.push('…');
+    }
+    Some(text)
+}
+
+/// Spawns a card with the beginning of the text of the node's file.
+pub fn add_text_node_ui(
+    entity: Entity,
+    name: Option<&Name>,
+    preview: &str,
+    spawn_pos: Vec2,
+    tpos: Option<&TargetPosition>,
+
+    mut commands: &mut Commands,
+
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    node_z: f32,
+) {
+    let node_pos: Vec2 = match tpos {
+        Some(pos) => pos.position,
+        None => spawn_pos,
+    };
+
+    let size = Vec2::new(160.0, 100.0);
+    let padding = 8.0;
+
+    // Text is rendered large and scaled down, like the labels, to keep it sharp
+    let text_scale = 0.2;
+
+    commands.entity(entity).insert((
+        ViewNodeShape::Rectangle(size),
+        MaterialMesh2dBundle {
+            mesh: meshes.add(math::primitives::Rectangle::new(size.x, size.y)).into(),
+            material: materials.add(ColorMaterial::from(TEXT_NODE_COLOR)),
+            transform: Transform::from_translation(Vec3::new(node_pos.x, node_pos.y, node_z)),
+            ..default()
+        },
+    ));
+
+    let text = commands
+        .spawn((
+            RenderLayers::layer(31),
+            Text2dBundle {
+                text: Text::from_section(
+                    preview,
+                    TextStyle {
+                        font_size: 50.0,
+                        color: TEXT_NODE_TEXT_COLOR,
+                        ..default()
+                    },
+                ),
+                text_anchor: Anchor::TopLeft,
+                text_2d_bounds: Text2dBounds {
+                    size: (size - Vec2::splat(padding * 2.0)) / text_scale,
+                },
+                transform: Transform {
+                    translation: Vec3::new(-size.x / 2.0 + padding, size.y / 2.0 - padding, 0.001),
+                    scale: Vec3::new(text_scale, text_scale, 1.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+    commands.entity(entity).push_children(&[text]);
+
+    if let Some(name) = name {
+        let label_pos = Vec2::new(-size.x / 2.0, size.y / 2.0 + 10.0);
+        add_node_label(&mut commands, &entity, name, label_pos, &node_z);
+    }
+    add_node_rect_outline(&mut commands, &entity, size, &node_z);
+}
+
 // SVG NODE
 // ----------------------------------------------------------------
 
 pub fn add_svg_node_ui() {}
 
 pub fn debug_sprite_picking() {}
+
+#[cfg(test)]
+mod tests {
+    use karta_server::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn color_set_through_the_server_comes_before_type_color() {
+        let root = std::env::temp_dir().join("bevy_karta_ui_test_node_color");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let mut graph = GraphCommands::new("node_color", root.clone(), Some(root.clone()));
+
+        let colored = NodePath::from("colored");
+        let plain = NodePath::from("plain");
+        for path in [&colored, &plain] {
+            graph.create_node_by_path(path, Some(NodeType::dir())).unwrap();
+        }
+        graph
+            .insert_node_attrs(&colored, vec![Attribute::new_string(NODE_COLOR_ATTR.to_string(), "#ff0000".to_string())])
+            .unwrap();
+        // Malformed, so the type color is used
+        let malformed = Attribute::new_string(NODE_COLOR_ATTR.to_string(), "#00gg00".to_string());
+        graph.insert_node_attrs(&plain, vec![malformed]).unwrap();
+
+        let type_colors = NodeTypeColors::default();
+        let color_of = |path: &NodePath| {
+            let node = graph.open_node(path).unwrap();
+            let attributes = Attributes(node.attributes());
+            let ntype = DataNodeType(node.ntype_name());
+            node_color(Some(&attributes), Some(&ntype), &type_colors)
+        };
+
+        assert_eq!(color_of(&colored), Color::srgb(1.0, 0.0, 0.0));
+        assert_eq!(color_of(&plain), type_colors.get("Directory").unwrap());
+
+        drop(graph);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,132 @@
+// Rectangle selection. Dragging with the left mouse button over empty space
+// draws a rectangle, and the nodes inside it are selected when it's let go.
+// Holding shift adds to the current selection instead of replacing it.
+
+use bevy::prelude::*;
+use bevy_karta_client::prelude::ViewNode;
+use bevy_mod_picking::prelude::PickSelection;
+
+use crate::{
+    events::ui_events::{RectangleSelectionEndEvent, RectangleSelectionEvent},
+    prelude::pointer::InputData,
+};
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Update, drag_selection_rectangle)
+            .add_systems(Update, draw_selection_rectangle.after(drag_selection_rectangle))
+            .add_systems(Update, select_nodes_in_rectangle.after(drag_selection_rectangle))
+        ;
+    }
+}
+
+const SELECTION_RECTANGLE_COLOR: Color = Color::srgba(0.4, 0.4, 0.6, 0.8);
+
+/// Returns the items whose positions are inside the rectangle spanned by the
+/// two corners, in any order. Positions on the edges count as inside.
+pub fn contained_in_rectangle<T: Copy>(
+    start: Vec2,
+    end: Vec2,
+    positions: impl IntoIterator<Item = (T, Vec2)>,
+) -> Vec<T> {
+    let rect = Rect::from_corners(start, end);
+    positions
+        .into_iter()
+        .filter(|(_, position)| rect.contains(*position))
+        .map(|(item, _)| item)
+        .collect()
+}
+
+/// Starts a selection rectangle when the left mouse button is pressed over
+/// empty space, and ends it when the button is released.
+fn drag_selection_rectangle(
+    mouse: Res<ButtonInput<MouseButton>>,
+    input_data: Res<InputData>,
+    mut start: Local<Option<Vec2>>,
+    mut ev_drag: EventWriter<RectangleSelectionEvent>,
+    mut ev_end: EventWriter<RectangleSelectionEndEvent>,
+){
+    let cursor = input_data.cursor_world_current_position();
+
+    if mouse.just_pressed(MouseButton::Left) && input_data.latest_hover_entity().is_none() {
+        *start = Some(cursor);
+    }
+
+    let Some(rect_start) = *start else {
+        return
+    };
+
+    if mouse.pressed(MouseButton::Left) {
+        ev_drag.send(RectangleSelectionEvent { start: rect_start, end: cursor });
+    } else {
+        ev_end.send(RectangleSelectionEndEvent { start: rect_start, end: cursor });
+        *start = None;
+    }
+}
+
+fn draw_selection_rectangle(
+    mut ev_drag: EventReader<RectangleSelectionEvent>,
+    mut gizmos: Gizmos,
+){
+    if let Some(ev) = ev_drag.read().last() {
+        let rect = Rect::from_corners(ev.start, ev.end);
+        gizmos.rect_2d(rect.center(), 0.0, rect.size(), SELECTION_RECTANGLE_COLOR);
+    }
+}
+
+/// Selects the nodes inside the rectangle. Without shift held down, the
+/// nodes outside of it are deselected. The selection is kept in PickSelection,
+/// the same as for nodes selected by clicking.
+fn select_nodes_in_rectangle(
+    mut ev_end: EventReader<RectangleSelectionEndEvent>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut nodes: Query<(Entity, &Transform, &mut PickSelection), With<ViewNode>>,
+){
+    let Some(ev) = ev_end.read().last() else {
+        return
+    };
+
+    let additive = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    let contained = contained_in_rectangle(
+        ev.start,
+        ev.end,
+        nodes.iter().map(|(entity, transform, _)| (entity, transform.translation.xy())),
+    );
+
+    for (entity, _, mut selection) in nodes.iter_mut() {
+        if contained.contains(&entity) {
+            selection.is_selected = true;
+        } else if !additive && selection.is_selected {
+            selection.is_selected = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_inside_the_rectangle_are_contained() {
+        let positions = [
+            (0, Vec2::new(0.0, 0.0)),
+            (1, Vec2::new(50.0, 50.0)),
+            (2, Vec2::new(100.0, 100.0)),
+            (3, Vec2::new(-10.0, 50.0)),
+            (4, Vec2::new(50.0, 150.0)),
+        ];
+
+        let contained = contained_in_rectangle(Vec2::ZERO, Vec2::new(100.0, 100.0), positions);
+        assert_eq!(contained, vec![0, 1, 2], "Positions on the edges should be contained");
+
+        let reversed = contained_in_rectangle(Vec2::new(100.0, 100.0), Vec2::ZERO, positions);
+        assert_eq!(reversed, contained, "Order of the corners shouldn't matter");
+
+        let empty = contained_in_rectangle(Vec2::new(200.0, 200.0), Vec2::new(300.0, 300.0), positions);
+        assert!(empty.is_empty());
+    }
+}
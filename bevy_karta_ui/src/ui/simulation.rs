@@ -4,9 +4,13 @@ use bevy::{prelude::*, utils::HashMap};
 use bevy_karta_client::prelude::{pe_index::PathsToEntitiesIndex, Attributes, DataEdge, ViewNode};
 use bevy_mod_picking::selection::PickSelection;
 
-use crate::prelude::Pins;
+use crate::{
+    events::node_events::MoveNodesEvent,
+    prelude::{keymap::{KeyAction, KeyMap}, Pins},
+    settings::simulation::SimulationSettings,
+};
 
-use super::nodes::{TargetPosition, Velocity2D};
+use super::{create_node::no_name_prompt, nodes::{TargetPosition, Velocity2D}};
 
 pub struct GraphSimPlugin;
 
@@ -15,49 +19,106 @@ impl Plugin for GraphSimPlugin {
         app
             //.add_systems(Update, simulation.before(move_node_selection))
 
-            .insert_resource(GraphSimSettings::default())
+            .insert_resource(SimulationState::default())
+            .add_systems(Update, (
+                toggle_simulation.run_if(no_name_prompt),
+                resume_simulation_on_change,
+            ))
             .add_systems(Update, (
                 repulsion_constraints,
-                edge_spring_constraints
-            ).chain())
-            .add_systems(PostUpdate, apply_forces)
+                edge_spring_constraints,
+                center_gravity_constraints,
+            ).chain().run_if(simulation_running))
+            .add_systems(PostUpdate, apply_forces.run_if(simulation_running))
         ;
     }
 
 }
 
-const _FORCE_UPPER_LIMIT: f32 = 50.0;
-const _FORCE_LOWER_LIMIT: f32 = 0.5;
-const _DAMPING_FACTOR: f32 = 0.95;
-const _SIMULATION_STEPS: usize = 8;
+/// Total movement of all nodes in a tick, below which the tick counts as calm.
+const SETTLE_THRESHOLD: f32 = 1.0;
+/// Number of calm ticks in a row after which the simulation is considered settled.
+const SETTLE_TICKS: usize = 30;
 
-// Should the simulation settings be a part of the sim root node?
+/// Whether the simulation is running. The simulation pauses itself once the
+/// nodes have settled, and resumes when nodes are added or moved.
 #[derive(Debug, Resource)]
-pub struct GraphSimSettings {
-    pub force_upper_limit: f32,
-    pub force_lower_limit: f32,
-    pub damping_factor: f32,
-    pub simulation_steps: usize,
+pub struct SimulationState {
+    pub running: bool,
+    calm_ticks: usize,
 }
 
-impl Default for GraphSimSettings {
+impl Default for SimulationState {
     fn default() -> Self {
-        GraphSimSettings {
-            force_upper_limit: 350.0,
-            force_lower_limit: 0.5,
-            damping_factor: 0.85,
-            simulation_steps: 8,
+        SimulationState {
+            running: true,
+            calm_ticks: 0,
+        }
+    }
+}
+
+impl SimulationState {
+    /// Records the total movement of the nodes in a tick. Pauses the simulation
+    /// and returns true if the nodes have settled.
+    pub fn record_movement(&mut self, movement: f32) -> bool {
+        if movement < SETTLE_THRESHOLD {
+            self.calm_ticks += 1;
+        } else {
+            self.calm_ticks = 0;
         }
+
+        let settled = self.calm_ticks >= SETTLE_TICKS;
+        if settled {
+            self.running = false;
+        }
+        settled
+    }
+
+    pub fn resume(&mut self) {
+        self.running = true;
+        self.calm_ticks = 0;
+    }
+}
+
+fn simulation_running(state: Res<SimulationState>) -> bool {
+    state.running
+}
+
+fn toggle_simulation(
+    keymap: Res<KeyMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut state: ResMut<SimulationState>,
+){
+    if !keymap.just_pressed(KeyAction::ToggleSimulation, &keys) {
+        return
+    }
+    match state.running {
+        true => state.running = false,
+        false => state.resume(),
+    }
+}
+
+fn resume_simulation_on_change(
+    added: Query<(), Added<ViewNode>>,
+    mut ev_move: EventReader<MoveNodesEvent>,
+    mut state: ResMut<SimulationState>,
+){
+    let moved = !ev_move.is_empty();
+    ev_move.clear();
+
+    if !state.running && (moved || !added.is_empty()) {
+        state.resume();
     }
 }
 
 // Constraint: Edge Spring
 // ----------------------------------------------------------------
 /// This constraint treats edges like springs, and applies a force to each node.
-/// For now, the attributes that will be read will be hard-coded (k and length).
+/// For now, the resting length and stiffness are read from the simulation settings.
 /// In the future, the resting length and stiffness values will be inputs to the node.
 pub fn edge_spring_constraints (
     // _forces: Query<(&ViewNode, &mut NodeForce)>,
+    sim_settings: Res<SimulationSettings>,
     mut nodes: Query<(Entity, &ViewNode, &Transform, &mut Velocity2D)>,
     edges: Query<(&DataEdge, &Attributes)>,
     pe_index: Res<PathsToEntitiesIndex>,
@@ -91,11 +152,11 @@ pub fn edge_spring_constraints (
         // distance between the two positions
         let dist = diff.length() + 0.0001;
         
-        let len = 200.0;
+        let len = sim_settings.link_distance;
         
         let displacement = dist - len;
         
-        let attractive_force = 0.85 * displacement;      
+        let attractive_force = sim_settings.link_stiffness * displacement;      
             
         match nodes.get_mut(source_entity){
             Ok(mut node) => {
@@ -113,6 +174,23 @@ pub fn edge_spring_constraints (
     } 
 }
 
+// Constraint: Center Gravity
+// ----------------------------------------------------------------
+/// Pulls every node towards the origin, in proportion to its distance from it.
+/// Keeps unconnected nodes from drifting away. Off when the strength is zero.
+pub fn center_gravity_constraints (
+    sim_settings: Res<SimulationSettings>,
+    mut nodes: Query<(&Transform, &mut Velocity2D), With<ViewNode>>,
+){
+    if sim_settings.center_gravity == 0.0 {
+        return
+    }
+
+    for (pos, mut vel) in nodes.iter_mut(){
+        vel.velocity -= pos.translation.truncate() * sim_settings.center_gravity;
+    }
+}
+
 // Constraint: Repulsion
 // ----------------------------------------------------------------
 // This constraint applies a repulsive force to each node, based on the distance between them.
@@ -120,6 +198,7 @@ pub fn edge_spring_constraints (
 
 // Same current restrictions and future plans as for the edge spring constraints apply here. 
 pub fn repulsion_constraints (
+    sim_settings: Res<SimulationSettings>,
     mut nodes: Query<(Entity, &ViewNode, &Transform, &mut Velocity2D)>,
 ){
     let mut forces: HashMap<Entity, Vec2> = HashMap::new();
@@ -142,7 +221,7 @@ pub fn repulsion_constraints (
             //     continue
             // }
              
-            let repulsive_force = 20000.0 / dist.powf(1.25);
+            let repulsive_force = sim_settings.repulsion_strength / dist.powf(1.25);
 
             *forces.entry(node_a).or_insert(Vec2::ZERO) += diff / dist * repulsive_force;
             *forces.entry(node_b).or_insert(Vec2::ZERO) -= diff / dist * repulsive_force;
@@ -156,27 +235,31 @@ pub fn repulsion_constraints (
     }
 }
 
-/// System that applies the forces calculated by force nodes
+/// System that applies the forces calculated by force nodes. Nodes that are
+/// pinned in place or selected are left where they are, and their forces are dropped.
 fn apply_forces(
-    sim_settings: Res<GraphSimSettings>,
+    sim_settings: Res<SimulationSettings>,
     time: Res<Time>,
+    mut state: ResMut<SimulationState>,
     mut nodes: Query<
         (Entity, &ViewNode, &mut Transform, &mut Velocity2D, &Pins, &PickSelection), 
         Without<TargetPosition>
     >,
-    mut _gizmos: Gizmos,
-    keys: Res<ButtonInput<KeyCode>>,
+    // mut _gizmos: Gizmos,
+    // keys: Res<ButtonInput<KeyCode>>,
 ) {
     // if !keys.pressed(KeyCode::Space) {
     //     return
     // }
+    let mut movement = 0.0;
+
     //for step in 0..sim_settings.simulation_steps {
         for (_node, _view, mut pos, mut vel, pins, pick) in nodes.iter_mut() {
 
-            if pick.is_selected || pins.position {continue};
-
             let mut force = vel.velocity;
             vel.velocity = Vec2::ZERO;
+
+            if pick.is_selected || pins.position {continue};
                 
             if force.length() < sim_settings.force_lower_limit {
                 continue
@@ -186,7 +269,7 @@ fn apply_forces(
                 force = force.normalize() * sim_settings.force_upper_limit;
             }
             
-            force = force * sim_settings.damping_factor * time.delta().as_secs_f32();
+            force = force * sim_settings.damping * time.delta().as_secs_f32();
             
             // Lines for debugging the forces
             // _gizmos.line_2d(
@@ -197,9 +280,126 @@ fn apply_forces(
                 
             pos.translation.x += force.x;
             pos.translation.y += force.y;
+            movement += force.length();
 
 
         }
     //}
+
+    state.record_movement(movement);
 }
 
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn pinned_node_stays_in_place_while_unpinned_node_moves() {
+        let mut world = World::new();
+        world.insert_resource(SimulationSettings::default());
+        world.insert_resource(SimulationState::default());
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(16));
+        world.insert_resource(time);
+
+        let mut spawn_node = |pins: Pins| {
+            world.spawn((
+                ViewNode { path: None, data: None },
+                Transform::default(),
+                Velocity2D { velocity: Vec2::new(100.0, 50.0) },
+                pins,
+                PickSelection::default(),
+            )).id()
+        };
+        let pinned = spawn_node(Pins::new_pinpos());
+        let unpinned = spawn_node(Pins::default());
+
+        world.run_system_once(apply_forces);
+
+        let position = |entity: Entity| world.get::<Transform>(entity).unwrap().translation;
+        assert_eq!(position(pinned), Vec3::ZERO, "Pinned node should not move");
+        assert_ne!(position(unpinned), Vec3::ZERO, "Unpinned node should move");
+        assert_eq!(
+            world.get::<Velocity2D>(pinned).unwrap().velocity,
+            Vec2::ZERO,
+            "Forces on a pinned node shouldn't build up"
+        );
+    }
+
+    /// Distance between two nodes pushed apart by repulsion after a fixed number of ticks.
+    fn spread_after_ticks(settings: SimulationSettings, ticks: usize) -> f32 {
+        let mut world = World::new();
+        world.insert_resource(settings);
+        world.insert_resource(SimulationState::default());
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(16));
+        world.insert_resource(time);
+
+        let mut spawn_node = |x: f32| {
+            world.spawn((
+                ViewNode { path: None, data: None },
+                Transform::from_xyz(x, 0.0, 0.0),
+                Velocity2D { velocity: Vec2::ZERO },
+                Pins::default(),
+                PickSelection::default(),
+            )).id()
+        };
+        let a = spawn_node(0.0);
+        let b = spawn_node(10.0);
+
+        for _ in 0..ticks {
+            world.run_system_once(repulsion_constraints);
+            world.run_system_once(apply_forces);
+        }
+
+        let position = |entity: Entity| world.get::<Transform>(entity).unwrap().translation;
+        position(a).distance(position(b))
+    }
+
+    #[test]
+    fn stronger_repulsion_spreads_nodes_further() {
+        let ticks = 60;
+        let default_spread = spread_after_ticks(SimulationSettings::default(), ticks);
+        let strong_spread = spread_after_ticks(
+            SimulationSettings { repulsion_strength: 80000.0, ..default() },
+            ticks,
+        );
+
+        assert!(default_spread > 10.0, "Repulsion should push the nodes apart");
+        assert!(
+            strong_spread > default_spread + 1.0,
+            "Stronger repulsion should spread the nodes further: {} vs {}", strong_spread, default_spread
+        );
+    }
+
+    #[test]
+    fn simulation_pauses_once_movement_stays_below_threshold() {
+        let mut state = SimulationState::default();
+
+        // Movement halves every tick, from well above the threshold
+        let movements = (0..100).map(|tick| 1000.0 * 0.5_f32.powi(tick));
+        let mut paused_at = None;
+        for (tick, movement) in movements.enumerate() {
+            if state.record_movement(movement) {
+                paused_at = Some(tick);
+                break
+            }
+        }
+
+        let first_calm_tick = 10; // 1000 * 0.5^10 < 1.0
+        assert_eq!(paused_at, Some(first_calm_tick + SETTLE_TICKS - 1));
+        assert_eq!(state.running, false);
+
+        state.resume();
+        assert_eq!(state.running, true);
+        for _ in 0..SETTLE_TICKS - 1 {
+            assert_eq!(state.record_movement(0.0), false, "Calm ticks should be counted from the resume");
+        }
+        assert_eq!(state.record_movement(SETTLE_THRESHOLD * 2.0), false);
+        assert_eq!(state.running, true, "A busy tick should reset the calm ticks");
+    }
+}
@@ -0,0 +1,235 @@
+// Command line arguments of the server binary, and the logging they set up.
+
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_HOST: &str = "0.0.0.0";
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// Environment variable the vault path is read from when it isn't given as an argument.
+pub const VAULT_ENV_VAR: &str = "KARTA_VAULT";
+
+pub const USAGE: &str = "Usage: karta_server [VAULT_PATH] [--host HOST] [--port PORT] [--storage DIR] [--headless] [-v | -vv]";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerArgs {
+    /// The vault to open. If not given, the user is prompted for one.
+    pub vault: Option<PathBuf>,
+    /// Directory to keep the db in instead of the vault.
+    pub storage: Option<PathBuf>,
+    pub host: String,
+    pub port: u16,
+    /// Never prompt for the vault, as when running as a service.
+    pub headless: bool,
+    /// How much to log. 0 leaves it to RUST_LOG, -v is debug and -vv is trace.
+    pub verbosity: u8,
+}
+
+impl Default for ServerArgs {
+    fn default() -> Self {
+        ServerArgs {
+            vault: None,
+            storage: None,
+            host: DEFAULT_HOST.to_string(),
+            port: DEFAULT_PORT,
+            headless: false,
+            verbosity: 0,
+        }
+    }
+}
+
+impl ServerArgs {
+    /// Parses the arguments, not including the name of the binary.
+    pub fn parse<I>(args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut parsed = ServerArgs::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--host" => {
+                    parsed.host = args.next().ok_or("--host requires a value")?;
+                }
+                "--storage" => {
+                    let storage = args.next().ok_or("--storage requires a value")?;
+                    parsed.storage = Some(PathBuf::from(storage));
+                }
+                "--headless" => parsed.headless = true,
+                "--port" => {
+                    let port = args.next().ok_or("--port requires a value")?;
+                    parsed.port = port
+                        .parse()
+                        .map_err(|_| format!("Invalid port: {}", port))?;
+                }
+                // -v, -vv, -vvv and so on, each v raising the verbosity
+                flag if flag.strip_prefix('-').is_some_and(|vs| !vs.is_empty() && vs.chars().all(|c| c == 'v')) => {
+                    let count = (flag.len() - 1).min(u8::MAX as usize) as u8;
+                    parsed.verbosity = parsed.verbosity.saturating_add(count);
+                }
+                flag if flag.starts_with('-') => {
+                    return Err(format!("Unknown flag: {}", flag));
+                }
+                path => {
+                    if parsed.vault.is_some() {
+                        return Err(format!("Unexpected argument: {}", path));
+                    }
+                    parsed.vault = Some(PathBuf::from(path));
+                }
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// The address to bind the server to.
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// The log filter for a verbosity given on the command line. Without one,
+/// RUST_LOG decides, and info is logged if it isn't set either.
+pub fn log_filter(verbosity: u8) -> tracing_subscriber::EnvFilter {
+    use tracing_subscriber::EnvFilter;
+    match verbosity {
+        0 => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        1 => EnvFilter::new("info,karta_server=debug"),
+        _ => EnvFilter::new("debug,karta_server=trace"),
+    }
+}
+
+/// Sets up logging to stdout at the given verbosity. An app that embeds the
+/// server may have set up its own logging already, in which case that is kept
+/// and false is returned. Safe to call more than once.
+pub fn init_logging(verbosity: u8) -> bool {
+    tracing_subscriber::fmt()
+        .with_env_filter(log_filter(verbosity))
+        .try_init()
+        .is_ok()
+}
+
+/// Where the vault to open comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VaultSource {
+    Path(PathBuf),
+    Prompt,
+}
+
+/// Picks the vault from the argument, then from the environment variable. The
+/// user is only prompted if neither is set and the session is interactive, so
+/// that a server without a terminal fails clearly instead of waiting for input.
+pub fn select_vault_source(
+    arg: Option<&PathBuf>,
+    env: Option<String>,
+    interactive: bool,
+) -> Result<VaultSource, String> {
+    if let Some(path) = arg {
+        return Ok(VaultSource::Path(path.clone()));
+    }
+    if let Some(path) = env.filter(|path| !path.trim().is_empty()) {
+        return Ok(VaultSource::Path(PathBuf::from(path)));
+    }
+    if interactive {
+        return Ok(VaultSource::Prompt);
+    }
+    Err(format!(
+        "No vault given. Pass its path as an argument or set {}.",
+        VAULT_ENV_VAR
+    ))
+}
+
+/// Checks that a vault path given on the command line can be opened.
+pub fn resolve_vault_path(path: &Path) -> Result<PathBuf, String> {
+    if !path.is_dir() {
+        return Err(format!("{} is not a directory", path.display()));
+    }
+    path.canonicalize()
+        .map_err(|e| format!("Could not open {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn vault_path_and_bind_flags_are_parsed() {
+        let parsed = ServerArgs::parse(args(&["/some/vault", "--port", "4000", "--host", "127.0.0.1"]));
+        assert_eq!(
+            parsed,
+            Ok(ServerArgs {
+                vault: Some(PathBuf::from("/some/vault")),
+                storage: None,
+                host: "127.0.0.1".to_string(),
+                port: 4000,
+                headless: false,
+                verbosity: 0,
+            })
+        );
+        assert_eq!(parsed.unwrap().addr(), "127.0.0.1:4000");
+
+        assert_eq!(ServerArgs::parse(args(&[])), Ok(ServerArgs::default()));
+
+        let parsed = ServerArgs::parse(args(&["/some/vault", "--storage", "/fast/disk"])).unwrap();
+        assert_eq!(parsed.storage, Some(PathBuf::from("/fast/disk")));
+    }
+
+    #[test]
+    fn verbosity_flags_raise_the_log_level() {
+        assert_eq!(ServerArgs::parse(args(&["-v"])).unwrap().verbosity, 1);
+        assert_eq!(ServerArgs::parse(args(&["-vv", "/some/vault"])).unwrap().verbosity, 2);
+        assert_eq!(ServerArgs::parse(args(&["-v", "-v"])).unwrap().verbosity, 2);
+        assert_eq!(ServerArgs::parse(args(&["-vvv"])).unwrap().verbosity, 3);
+        assert_eq!(ServerArgs::parse(args(&["-vvv"])).unwrap().vault, None, "Flags should not become the vault");
+        assert_eq!(ServerArgs::parse(args(&["vv"])).unwrap().verbosity, 0, "Paths should not be read as flags");
+
+        assert_eq!(log_filter(1).to_string().contains("karta_server=debug"), true);
+        assert_eq!(log_filter(3).to_string().contains("karta_server=trace"), true);
+
+        // The first call may or may not win, depending on the other tests
+        init_logging(0);
+        assert_eq!(init_logging(2), false, "Logging should only be set up once");
+    }
+
+    #[test]
+    fn vault_argument_takes_precedence_over_environment() {
+        let arg = PathBuf::from("/from/arg");
+        let env = Some("/from/env".to_string());
+
+        assert_eq!(
+            select_vault_source(Some(&arg), env.clone(), false),
+            Ok(VaultSource::Path(arg.clone()))
+        );
+        assert_eq!(
+            select_vault_source(None, env, false),
+            Ok(VaultSource::Path(PathBuf::from("/from/env")))
+        );
+        assert_eq!(select_vault_source(None, None, true), Ok(VaultSource::Prompt));
+        assert_eq!(
+            select_vault_source(None, Some("".to_string()), false).is_err(),
+            true,
+            "Headless server without a vault should fail"
+        );
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        assert_eq!(ServerArgs::parse(args(&["--port", "not_a_port"])).is_err(), true);
+        assert_eq!(ServerArgs::parse(args(&["--port"])).is_err(), true);
+        assert_eq!(ServerArgs::parse(args(&["--storage"])).is_err(), true);
+        assert_eq!(ServerArgs::parse(args(&["--verbose"])).is_err(), true);
+        assert_eq!(ServerArgs::parse(args(&["-x"])).is_err(), true, "Unknown short flags should be rejected");
+        assert_eq!(ServerArgs::parse(args(&["-vx"])).is_err(), true);
+        assert_eq!(ServerArgs::parse(args(&["-"])).is_err(), true);
+        assert_eq!(ServerArgs::parse(args(&["first", "second"])).is_err(), true);
+
+        let missing = resolve_vault_path(&PathBuf::from("/definitely/not/a/vault"));
+        assert_eq!(missing.is_err(), true);
+    }
+}
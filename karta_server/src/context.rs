@@ -0,0 +1,423 @@
+// Saved contexts. A context is the arrangement of nodes around a focal node,
+// as the user has laid it out. Contexts are stored as RON files in the vault's
+// karta folder, separately from the graph db.
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::{elements::node_path::NodePath, fs_reader, vault::KartaVault};
+
+/// Extension of the files that contexts are saved in.
+pub const CONTEXT_FILE_EXTENSION: &str = "ctx";
+
+/// A node as it is placed in a context. The position is relative to the focal node.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ViewNode {
+    pub path: NodePath,
+    pub x: f32,
+    pub y: f32,
+    /// Stacking order of the node. Nodes with a higher index are drawn on top.
+    #[serde(default)]
+    pub z_index: f32,
+    /// Pinned nodes stay where the user placed them instead of being moved
+    /// by the layout of the client.
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl ViewNode {
+    pub fn new(path: NodePath, x: f32, y: f32) -> Self {
+        ViewNode { path, x, y, z_index: 0.0, pinned: false }
+    }
+}
+
+/// How a context is displayed, apart from the placement of its nodes.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ContextSettings {
+    /// RGBA. If not set, the client's default background is used.
+    #[serde(default)]
+    pub background_color: Option<[f32; 4]>,
+    #[serde(default = "default_zoom")]
+    pub default_zoom: f32,
+    /// Position of the camera relative to the focal node.
+    #[serde(default)]
+    pub camera_position: [f32; 2],
+}
+
+fn default_zoom() -> f32 {
+    1.0
+}
+
+impl Default for ContextSettings {
+    fn default() -> Self {
+        ContextSettings {
+            background_color: None,
+            default_zoom: default_zoom(),
+            camera_position: [0.0, 0.0],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Context {
+    pub focal: NodePath,
+    pub nodes: Vec<ViewNode>,
+    /// Contexts saved before settings existed get the defaults.
+    #[serde(default)]
+    pub settings: ContextSettings,
+}
+
+impl Context {
+    pub fn new(focal: NodePath) -> Self {
+        Context {
+            focal,
+            nodes: Vec::new(),
+            settings: ContextSettings::default(),
+        }
+    }
+
+    /// Rewrites the z-indices of the given nodes, listed from front to back, so that
+    /// they stack in that order on top of the nodes that aren't listed. Errs with
+    /// the paths that aren't in the context, in which case nothing is changed.
+    pub fn reorder(&mut self, front_to_back: &[NodePath]) -> Result<(), Vec<NodePath>> {
+        let unknown: Vec<NodePath> = front_to_back
+            .iter()
+            .filter(|path| !self.nodes.iter().any(|node| node.path == **path))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(unknown);
+        }
+
+        let base = self
+            .nodes
+            .iter()
+            .filter(|node| !front_to_back.contains(&node.path))
+            .map(|node| node.z_index)
+            .fold(0.0, f32::max);
+
+        for node in self.nodes.iter_mut() {
+            if let Some(i) = front_to_back.iter().position(|path| *path == node.path) {
+                node.z_index = base + (front_to_back.len() - i) as f32;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads and writes the saved contexts of a vault.
+pub struct ContextDb {
+    dirpath: PathBuf,
+}
+
+impl ContextDb {
+    pub fn new(vault: &KartaVault) -> Self {
+        ContextDb {
+            dirpath: vault.karta_dirpath().join("contexts"),
+        }
+    }
+
+    pub fn dirpath(&self) -> &PathBuf {
+        &self.dirpath
+    }
+
+    /// Path of the file the context of the focal node is saved in. Named by a hash
+    /// of the focal node's alias.
+    pub fn context_file_path(&self, focal: &NodePath) -> PathBuf {
+        self.dirpath
+            .join(format!("{:016x}.{}", focal.stable_hash(), CONTEXT_FILE_EXTENSION))
+    }
+
+    /// Saves the context, replacing any previously saved context of the same focal node.
+    /// The file is replaced atomically, so a crash never leaves it half written.
+    pub fn save_context(&self, context: &Context) -> Result<(), Box<dyn Error>> {
+        let ron = ron::to_string(context)?;
+        std::fs::create_dir_all(&self.dirpath)?;
+        fs_reader::write_atomically(&self.context_file_path(&context.focal), ron)?;
+        Ok(())
+    }
+
+    /// The saved context of the focal node, if there is one.
+    pub fn get_context(&self, focal: &NodePath) -> Result<Option<Context>, Box<dyn Error>> {
+        let filepath = self.context_file_path(focal);
+        if !filepath.exists() {
+            return Ok(None);
+        }
+
+        self.get_context_file(&filepath).map(Some)
+    }
+
+    /// Reads the context saved in a context file.
+    pub fn get_context_file(&self, filepath: &Path) -> Result<Context, Box<dyn Error>> {
+        let context: Context = ron::from_str(&std::fs::read_to_string(filepath)?)?;
+        Ok(context)
+    }
+
+    /// Deletes the saved context of the focal node. Returns whether there was one.
+    pub fn delete_context(&self, focal: &NodePath) -> Result<bool, Box<dyn Error>> {
+        let filepath = self.context_file_path(focal);
+        if !filepath.exists() {
+            return Ok(false);
+        }
+
+        std::fs::remove_file(filepath)?;
+        Ok(true)
+    }
+
+    /// All saved contexts of the vault. Files that can't be read are skipped.
+    pub fn all_contexts(&self) -> Result<Vec<Context>, Box<dyn Error>> {
+        if !self.dirpath.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut contexts = Vec::new();
+        for entry in std::fs::read_dir(&self.dirpath)?.flatten() {
+            let filepath = entry.path();
+            if filepath.extension().is_none_or(|ext| ext != CONTEXT_FILE_EXTENSION) {
+                continue;
+            }
+            match self.get_context_file(&filepath) {
+                Ok(context) => contexts.push(context),
+                Err(e) => tracing::warn!("Skipping context {}: {}", filepath.display(), e),
+            }
+        }
+        Ok(contexts)
+    }
+
+    /// The focal nodes of the saved contexts the node is placed in.
+    pub fn contexts_containing(&self, path: &NodePath) -> Result<Vec<NodePath>, Box<dyn Error>> {
+        let mut focals: Vec<NodePath> = self
+            .all_contexts()?
+            .into_iter()
+            .filter(|context| context.nodes.iter().any(|node| node.path == *path))
+            .map(|context| context.focal)
+            .collect();
+        focals.sort_by_key(|focal| focal.alias());
+        Ok(focals)
+    }
+
+    /// Removes the node from every saved context it is placed in. Returns the
+    /// focal nodes of the contexts that changed.
+    pub fn remove_node_from_contexts(&self, path: &NodePath) -> Result<Vec<NodePath>, Box<dyn Error>> {
+        let changed = self.contexts_containing(path)?;
+        for focal in changed.iter() {
+            if let Some(mut context) = self.get_context(focal)? {
+                context.nodes.retain(|node| node.path != *path);
+                self.save_context(&context)?;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Follows a node that was renamed or moved. The saved contexts of the node
+    /// and its descendants are re-keyed to their new focal paths, and the node
+    /// and its descendants are renamed wherever they are placed. Returns the
+    /// focal nodes of the contexts that changed, at their new paths.
+    ///
+    /// Errs without changing anything if a context is already saved at one of
+    /// the new focal paths. Re-keyed contexts are saved before their old files
+    /// are removed, so a failed save doesn't lose them.
+    pub fn rename_path(&self, old: &NodePath, new: &NodePath) -> Result<Vec<NodePath>, Box<dyn Error>> {
+        let moved = |path: &NodePath| -> Option<NodePath> {
+            path.relative_to(old)
+                .map(|relative| new.join(&relative.buf().to_string_lossy()))
+        };
+
+        // Paired with the focal path they were saved under
+        let mut updated: Vec<(NodePath, Context)> = Vec::new();
+        for mut context in self.all_contexts()? {
+            let old_focal = context.focal.clone();
+            let mut modified = false;
+            if let Some(focal) = moved(&context.focal) {
+                context.focal = focal;
+                modified = true;
+            }
+            for node in context.nodes.iter_mut() {
+                if let Some(path) = moved(&node.path) {
+                    node.path = path;
+                    modified = true;
+                }
+            }
+            if modified {
+                updated.push((old_focal, context));
+            }
+        }
+
+        let vacated: Vec<&NodePath> = updated
+            .iter()
+            .filter(|(old_focal, context)| context.focal != *old_focal)
+            .map(|(old_focal, _)| old_focal)
+            .collect();
+        for (old_focal, context) in updated.iter() {
+            let taken = context.focal != *old_focal
+                && !vacated.contains(&&context.focal)
+                && self.context_file_path(&context.focal).exists();
+            if taken {
+                return Err(format!("A context is already saved for {}", context.focal.alias()).into());
+            }
+        }
+
+        for (_, context) in updated.iter() {
+            self.save_context(context)?;
+        }
+        for (old_focal, context) in updated.iter() {
+            let resaved = updated.iter().any(|(_, other)| other.focal == *old_focal);
+            if context.focal != *old_focal && !resaved {
+                self.delete_context(old_focal)?;
+            }
+        }
+
+        let mut changed: Vec<NodePath> = updated.into_iter().map(|(_, context)| context.focal).collect();
+        changed.sort_by_key(|focal| focal.alias());
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use crate::{graph_traits::graph_core::GraphCore, utils::utils::TestContext};
+
+    use super::*;
+
+    #[test]
+    fn saved_context_can_be_read_back() {
+        let func_name = "saved_context_can_be_read_back";
+        let ctx = TestContext::new(func_name);
+        let db = ContextDb::new(&KartaVault::new(ctx.graph.user_root_dirpath()));
+
+        let focal = NodePath::from("focal");
+        assert_eq!(db.get_context(&focal).unwrap(), None);
+
+        let mut context = Context::new(focal.clone());
+        context.nodes.push(ViewNode::new(NodePath::from("focal/child"), 10.0, -20.0));
+        db.save_context(&context).unwrap();
+
+        assert_eq!(db.get_context(&focal).unwrap(), Some(context));
+        assert_eq!(db.get_context(&NodePath::from("other")).unwrap(), None);
+    }
+
+    #[test]
+    fn context_file_name_is_uuid_with_ctx_extension() {
+        let func_name = "context_file_name_is_uuid_with_ctx_extension";
+        let ctx = TestContext::new(func_name);
+        let db = ContextDb::new(&KartaVault::new(ctx.graph.user_root_dirpath()));
+
+        // Nodes have no uuid, so the stable hash of the focal path names the file instead
+        let focal = NodePath::from("focal");
+        let filepath = db.context_file_path(&focal);
+        assert_eq!(filepath.parent(), Some(db.dirpath().as_path()));
+        assert_eq!(filepath.extension().unwrap(), CONTEXT_FILE_EXTENSION);
+
+        let stem = filepath.file_stem().unwrap().to_str().unwrap().to_string();
+        assert_eq!(stem.len(), 16);
+        assert_eq!(stem.chars().all(|c| c.is_ascii_hexdigit()), true, "{} should be a hex hash", stem);
+        assert_eq!(db.context_file_path(&focal), filepath, "Name should be stable");
+        assert_ne!(db.context_file_path(&NodePath::from("other")), filepath);
+
+        let context = Context::new(focal.clone());
+        db.save_context(&context).unwrap();
+        assert_eq!(filepath.exists(), true, "Context should be saved at its file path");
+        assert_eq!(db.get_context_file(&filepath).unwrap(), context);
+        assert_eq!(db.get_context(&focal).unwrap(), Some(context));
+    }
+
+    #[test]
+    fn contexts_containing_a_node_are_found() {
+        let func_name = "contexts_containing_a_node_are_found";
+        let ctx = TestContext::new(func_name);
+        let db = ContextDb::new(&KartaVault::new(ctx.graph.user_root_dirpath()));
+
+        let node = NodePath::from("node");
+        let focals = vec![NodePath::from("first"), NodePath::from("second")];
+        for focal in focals.iter() {
+            let mut context = Context::new(focal.clone());
+            context.nodes.push(ViewNode::new(node.clone(), 0.0, 0.0));
+            db.save_context(&context).unwrap();
+        }
+        db.save_context(&Context::new(NodePath::from("third"))).unwrap();
+
+        assert_eq!(db.contexts_containing(&node).unwrap(), focals);
+        assert_eq!(db.contexts_containing(&NodePath::from("elsewhere")).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn renamed_subtree_is_followed_by_its_contexts() {
+        let func_name = "renamed_subtree_is_followed_by_its_contexts";
+        let ctx = TestContext::new(func_name);
+        let db = ContextDb::new(&KartaVault::new(ctx.graph.user_root_dirpath()));
+
+        let old = NodePath::from("old");
+        let child = NodePath::from("old/child");
+        let mut own = Context::new(old.clone());
+        own.nodes.push(ViewNode::new(child.clone(), 5.0, 5.0));
+        db.save_context(&own).unwrap();
+        db.save_context(&Context::new(child.clone())).unwrap();
+
+        let mut elsewhere = Context::new(NodePath::from("elsewhere"));
+        elsewhere.nodes.push(ViewNode::new(old.clone(), 1.0, 2.0));
+        elsewhere.nodes.push(ViewNode::new(NodePath::from("older"), 3.0, 4.0));
+        db.save_context(&elsewhere).unwrap();
+
+        let new = NodePath::from("new");
+        let new_child = NodePath::from("new/child");
+        let changed = db.rename_path(&old, &new).unwrap();
+        assert_eq!(changed, vec![NodePath::from("elsewhere"), new.clone(), new_child.clone()]);
+
+        assert_eq!(db.get_context(&old).unwrap(), None, "Old file should be gone");
+        assert_eq!(db.get_context(&child).unwrap(), None);
+        let renamed = db.get_context(&new).unwrap().expect("Context should be re-keyed");
+        assert_eq!(renamed.nodes[0].path, new_child);
+        assert_eq!(renamed.nodes[0].x, 5.0, "Positions should be kept");
+        assert_eq!(db.get_context(&new_child).unwrap().is_some(), true);
+
+        let elsewhere = db.get_context(&NodePath::from("elsewhere")).unwrap().unwrap();
+        assert_eq!(elsewhere.nodes[0].path, new);
+        assert_eq!(elsewhere.nodes[1].path, NodePath::from("older"), "Only whole components match");
+    }
+
+    #[test]
+    fn renaming_onto_a_saved_context_changes_nothing() {
+        let func_name = "renaming_onto_a_saved_context_changes_nothing";
+        let ctx = TestContext::new(func_name);
+        let db = ContextDb::new(&KartaVault::new(ctx.graph.user_root_dirpath()));
+
+        let old = NodePath::from("old");
+        let new = NodePath::from("new");
+        let mut own = Context::new(old.clone());
+        own.nodes.push(ViewNode::new(NodePath::from("old/child"), 5.0, 5.0));
+        db.save_context(&own).unwrap();
+        let mut existing = Context::new(new.clone());
+        existing.nodes.push(ViewNode::new(NodePath::from("other"), 1.0, 1.0));
+        db.save_context(&existing).unwrap();
+
+        assert_eq!(db.rename_path(&old, &new).is_err(), true, "Saved context should not be overwritten");
+        assert_eq!(db.get_context(&old).unwrap(), Some(own), "Renamed context should be kept");
+        assert_eq!(db.get_context(&new).unwrap(), Some(existing));
+    }
+
+    #[test]
+    fn reordering_rewrites_z_indices_front_to_back() {
+        let mut context = Context::new(NodePath::from("focal"));
+        let paths: Vec<NodePath> = ["a", "b", "c"]
+            .iter()
+            .map(|name| NodePath::from(format!("focal/{}", name).as_str()))
+            .collect();
+        for path in paths.iter() {
+            context.nodes.push(ViewNode::new(path.clone(), 0.0, 0.0));
+        }
+
+        let order = vec![paths[2].clone(), paths[0].clone(), paths[1].clone()];
+        assert_eq!(context.reorder(&order).is_ok(), true);
+
+        let z = |path: &NodePath| context.nodes.iter().find(|n| n.path == *path).unwrap().z_index;
+        assert_eq!(z(&paths[2]) > z(&paths[0]), true, "First in order should be in front");
+        assert_eq!(z(&paths[0]) > z(&paths[1]), true);
+
+        let unknown = context.reorder(&[NodePath::from("elsewhere")]);
+        assert_eq!(unknown, Err(vec![NodePath::from("elsewhere")]));
+    }
+}
@@ -10,7 +10,7 @@ pub struct Attribute {
 pub enum AttrValue {
     Float(f32),
     String(String),
-    UInt(u32),
+    UInt(u64),
 }
 
 impl Into<DbValue> for AttrValue {
@@ -18,7 +18,7 @@ impl Into<DbValue> for AttrValue {
         match self {
             AttrValue::Float(f) => DbValue::F64(f.into()),
             AttrValue::String(s) => DbValue::String(s),
-            AttrValue::UInt(u) => DbValue::U64(u.into()),
+            AttrValue::UInt(u) => DbValue::U64(u),
         }
     }
 }
@@ -33,7 +33,7 @@ impl Attribute {
         Self { name, value: AttrValue::String(value) }
     }
 
-    pub fn new_uint(name: String, value: u32) -> Self {
+    pub fn new_uint(name: String, value: u64) -> Self {
         Self { name, value: AttrValue::UInt(value) }
     }
 
@@ -84,7 +84,7 @@ impl Into<Attribute> for DbKeyValue {
             value: match self.value {
                 DbValue::F64(f) => AttrValue::Float(f.to_f64() as f32),
                 DbValue::String(s) => AttrValue::String(s),
-                DbValue::U64(u) => AttrValue::UInt(u as u32),
+                DbValue::U64(u) => AttrValue::UInt(u),
                 _ => panic!("Invalid attribute value"),
             }
         }
@@ -158,7 +158,7 @@ impl TryFrom<&DbKeyValue> for Attribute {
         let name = value.key.to_string();
         let value = match &value.value {
             DbValue::F64(f) => AttrValue::Float(f.to_f64() as f32),
-            DbValue::U64(u) => AttrValue::UInt(*u as u32),
+            DbValue::U64(u) => AttrValue::UInt(*u),
             DbValue::String(s) => AttrValue::String(s.clone()),
             _ => return Err(format!("Unsupported DbValue type for attribute: {:?}", value.value)),
         };
@@ -167,8 +167,15 @@ impl TryFrom<&DbKeyValue> for Attribute {
     }
 }
 
+/// Hex color, such as "#ff8800", that clients draw the node with. Set by the user.
+pub const NODE_COLOR_ATTR: &str = "color";
+
+/// Name of the attribute that stores the backing file of a text node,
+/// relative to the karta folder of the vault.
+pub const TEXT_FILE_ATTR: &str = "text_file";
+
 /// A list of reserved node attribute names that cannot be set by the user directly.
-pub const RESERVED_NODE_ATTRS: [&str; 12] = [
+pub const RESERVED_NODE_ATTRS: [&str; 13] = [
     "path", // The full path of the node, name included. Implemented as an alias, but still reserved.
     "name", // The name of the node, without the path. Maybe allows for different characters?
 
@@ -177,12 +184,14 @@ pub const RESERVED_NODE_ATTRS: [&str; 12] = [
 
     "created_time", // The time when the node was created.
     "modified_time", // The time when the node was last modified.
+    "version", // Counts the changes to the attributes of the node, for detecting conflicting edits.
+
+    "persistent", // Whether a virtual node is kept even if it has no edges and isn't in any context.
 
     "preview", // Connects a file to a preview file, or stores it in this attribute in base64 for example. 
 
     "scale", // The absolute scaling of the node, in case it is needed. Vec of 2 f32s
     "rotation", // The absolute rotation of the node, in case it is needed. 
-    "color", // The absolute color of the node. Vec of 4 f32s
     "pins", // The absolute state pins of the node. 
 
     // Reserved names with an underscore at the end are prefixes. 
@@ -192,8 +201,9 @@ pub const RESERVED_NODE_ATTRS: [&str; 12] = [
 
 /// A list of reserved edge attribute names that cannot be set by the user directly.
 /// Note that they are optional, so default behavior is when they are not set.
-pub const RESERVED_EDGE_ATTRS: [&str; 22] = [
+pub const RESERVED_EDGE_ATTRS: [&str; 23] = [
     "contains", // Physical parent_child relationship
+    "directed", // Whether the edge points from source to target. Edges are directed if it's not set.
 
     "text", // Text that is displayed on the edge, additional description
 
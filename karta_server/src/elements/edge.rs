@@ -4,7 +4,18 @@ use agdb::{DbElement, DbError, DbId, DbKeyValue, DbUserValue, DbValue, QueryId};
 
 use crate::elements::attribute::RESERVED_EDGE_ATTRS;
 
-use super::{attribute::Attribute, node_path::NodePath, SysTime};
+use super::{attribute::{AttrValue, Attribute}, node_path::NodePath, SysTime};
+
+/// Type of the associative edges created from links in the content of a node.
+pub const LINK_EDGE_TYPE: &str = "link";
+
+/// Type of the associative edges created when a node is added to the context
+/// of a focal node that it isn't otherwise connected to.
+pub const CONTEXT_EDGE_TYPE: &str = "context";
+
+fn default_directed() -> bool {
+    true
+}
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Edge {
@@ -12,6 +23,10 @@ pub struct Edge {
     source: NodePath,
     target: NodePath,
     contains: bool,
+    /// Undirected edges connect their nodes both ways and are drawn without
+    /// arrowheads. The source and target are still stored, but only for lookup.
+    #[serde(default = "default_directed")]
+    directed: bool,
     created_time: SysTime,
     modified_time: SysTime,
     attributes: Vec<Attribute>,
@@ -25,12 +40,30 @@ impl Edge {
             source: source.clone(),
             target: target.clone(),
             contains: false,
+            directed: true,
             attributes: Vec::new(),
             created_time: now.clone(),
             modified_time: now,
         }
     }
 
+    /// An associative edge with a semantic type, such as "references".
+    /// The type is stored as the "type" attribute of the edge.
+    pub fn new_typed(source: &NodePath, target: &NodePath, edge_type: &str) -> Self {
+        let mut edge = Edge::new(source, target);
+        edge.attributes
+            .push(Attribute::new_string("type".to_string(), edge_type.to_string()));
+        edge
+    }
+
+    /// An associative edge that connects the nodes both ways.
+    pub fn new_undirected(source: &NodePath, target: &NodePath) -> Self {
+        Self {
+            directed: false,
+            ..Edge::new(source, target)
+        }
+    }
+
     pub fn new_cont(source: &NodePath, target: &NodePath) -> Self {
         let attrs: Vec<Attribute> = vec![
             Attribute::new_contains()
@@ -41,12 +74,30 @@ impl Edge {
             source: source.clone(),
             target: target.clone(),
             contains: true,
+            directed: true,
             attributes: attrs,
             created_time: now.clone(),
             modified_time: now,
         }
     }
 
+    /// Copy of the edge between different nodes, to be inserted as a new edge.
+    pub(crate) fn relocated(&self, source: &NodePath, target: &NodePath) -> Self {
+        // Loaded edges keep the contains flag but not the attribute it is stored as
+        let mut attributes = self.attributes.clone();
+        if self.contains && !attributes.iter().any(|attr| attr.name == "contains") {
+            attributes.push(Attribute::new_contains());
+        }
+
+        Self {
+            db_id: None,
+            source: source.clone(),
+            target: target.clone(),
+            attributes,
+            ..self.clone()
+        }
+    }
+
     pub fn db_id(&self) -> Option<DbId> {
         self.db_id
     }
@@ -63,6 +114,19 @@ impl Edge {
         self.contains
     }
 
+    pub fn directed(&self) -> bool {
+        self.directed
+    }
+
+    /// The node at the other end of the edge from the given one.
+    pub fn other_end(&self, path: &NodePath) -> &NodePath {
+        if self.source == *path {
+            &self.target
+        } else {
+            &self.source
+        }
+    }
+
     pub fn created_time(&self) -> SysTime {
         self.created_time.clone()
     }
@@ -74,6 +138,14 @@ impl Edge {
     pub fn attributes(&self) -> &Vec<Attribute> {
         &self.attributes
     }
+
+    /// The semantic type of an associative edge, such as "references".
+    pub fn edge_type(&self) -> Option<&str> {
+        self.attributes.iter().find_map(|attr| match &attr.value {
+            AttrValue::String(value) if attr.name == "type" => Some(value.as_str()),
+            _ => None,
+        })
+    }
 } 
 
 impl DbUserValue for Edge {
@@ -104,6 +176,7 @@ impl DbUserValue for Edge {
         let mut values = Vec::new();
         values.push(DbKeyValue::from(("source", self.source.clone())));
         values.push(DbKeyValue::from(("target", self.target.clone())));
+        values.push(DbKeyValue::from(("directed", self.directed as u64)));
         values.push(DbKeyValue::from(("created_time", self.created_time.clone())));
         values.push(DbKeyValue::from(("modified_time", self.modified_time.clone())));
 
@@ -127,11 +200,17 @@ impl TryFrom<DbElement> for Edge {
         let source = value.values.iter().find(|v| v.key == "source".into());
         let target = value.values.iter().find(|v| v.key == "target".into());
         let contains = value.values.iter().find(|v| v.key == "contains".into());
+        // Edges stored before the flag existed are directed
+        let directed = value
+            .values
+            .iter()
+            .find(|v| v.key == "directed".into())
+            .map_or(true, |v| v.value.to_u64().map_or(true, |directed| directed != 0));
         let created_time = value.values.iter().find(|v| v.key == "created_time".into());
         let modified_time = value.values.iter().find(|v| v.key == "modified_time".into());
 
-        println!("source: {:?}", source);
-        println!("target: {:?}", target);
+        tracing::trace!("source: {:?}", source);
+        tracing::trace!("target: {:?}", target);
 
         if source.is_none() || target.is_none() {
             return Err(DbError::from("Edge must have source and target"));
@@ -139,7 +218,7 @@ impl TryFrom<DbElement> for Edge {
 
 
         let attrs: Vec<Attribute> = rest.iter().map(|attr| {
-            println!("Creating attribute - {:#?}", attr);
+            tracing::trace!("Creating attribute - {:#?}", attr);
 
             Attribute::try_from(*attr).unwrap()
         }).collect();
@@ -149,6 +228,7 @@ impl TryFrom<DbElement> for Edge {
             source: NodePath::try_from(source.unwrap().value.clone())?,
             target: NodePath::try_from(target.unwrap().value.clone())?,
             contains: contains.is_some(),
+            directed,
             created_time: SysTime::try_from(created_time.unwrap().value.clone())?,
             modified_time: SysTime::try_from(modified_time.unwrap().value.clone())?,
             attributes: attrs,
@@ -7,12 +7,27 @@ pub (crate) mod node_path;
 pub (crate) mod nodetype;
 pub (crate) mod edge;
 pub (crate) mod attribute;
+#[deny(warnings)]
+pub (crate) mod schema;
 
 
 
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct SysTime(SystemTime);
 
+impl SysTime {
+    /// Seconds since the unix epoch.
+    pub fn as_secs(&self) -> u64 {
+        self.0.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+    }
+}
+
+impl From<SystemTime> for SysTime {
+    fn from(time: SystemTime) -> Self {
+        SysTime(time)
+    }
+}
+
 impl From<SysTime> for DbValue {
     fn from(time: SysTime) -> Self {
         time.0.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs().into()
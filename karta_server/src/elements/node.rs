@@ -27,10 +27,19 @@ pub struct Node {
     ntype: NodeType,
     nphys: NodePhysicality,
     alive: bool, 
+    /// A persistent virtual node stays in the database even if it has no edges
+    /// and isn't in any context.
+    #[serde(default)]
+    persistent: bool,
 
     created_time: SysTime,
     modified_time: SysTime,
 
+    /// Incremented whenever the attributes of the node change, so that a
+    /// client can tell if its copy of the node is out of date.
+    #[serde(default)]
+    version: u64,
+
     attributes: Vec<Attribute>,
 }
 
@@ -48,8 +57,10 @@ impl DbUserValue for Node {
         keys.push(DbValue::from("ntype"));
         keys.push(DbValue::from("nphys"));
         keys.push(DbValue::from("alive"));
+        keys.push(DbValue::from("persistent"));
         keys.push(DbValue::from("created_time"));
         keys.push(DbValue::from("modified_time"));
+        keys.push(DbValue::from("version"));
 
         // Why on earth does this function not have a self parameter?
         // for attribute in &self.attributes {
@@ -71,8 +82,10 @@ impl DbUserValue for Node {
         values.push(DbKeyValue::from(("ntype", self.ntype.clone())));
         values.push(DbKeyValue::from(("nphys", self.nphys.clone())));
         values.push(DbKeyValue::from(("alive", self.alive)));
+        values.push(DbKeyValue::from(("persistent", self.persistent)));
         values.push(DbKeyValue::from(("created_time", self.created_time.clone())));
         values.push(DbKeyValue::from(("modified_time", self.modified_time.clone())));
+        values.push(DbKeyValue::from(("version", self.version)));
 
         for attr in &self.attributes {
             values.push(attr.into());
@@ -95,9 +108,12 @@ impl Node {
             ntype,
             nphys,
             alive: true,
+            persistent: false,
             created_time: now.clone(),
             modified_time: now,
 
+            version: 0,
+
             attributes: Vec::new(),
         }
     }
@@ -108,11 +124,39 @@ impl Node {
         self.modified_time = SysTime(SystemTime::now());
     }
 
+    /// Sets the creation and modification times, such as those of the file of a physical node.
+    pub(crate) fn set_times(&mut self, created: SystemTime, modified: SystemTime) {
+        self.created_time = SysTime(created);
+        self.modified_time = SysTime(modified);
+    }
+
     /// Insert a vector of attibutes into the node. Not for library use. 
-    /// Though perhaps not even this crate needs this function.
+    /// Attributes that the node already has are replaced.
     pub(crate) fn insert_attributes(&mut self, attributes: Vec<Attribute>) {
-        unimplemented!();
+        for attribute in attributes {
+            match self.attributes.iter_mut().find(|a| a.name == attribute.name) {
+                Some(existing) => *existing = attribute,
+                None => self.attributes.push(attribute),
+            }
+        }
+    }
+
+    /// Removes the attributes with the given names, if the node has them. Not for library use.
+    pub(crate) fn remove_attributes(&mut self, names: &[&str]) {
+        self.attributes.retain(|a| !names.contains(&a.name.as_str()));
     }
+
+    /// Copy of the node at a different path, to be inserted as a new virtual node.
+    /// Used when importing bundles, where the bytes of physical files aren't available.
+    pub(crate) fn relocated(&self, path: &NodePath) -> Self {
+        Node {
+            db_id: None,
+            path: path.clone(),
+            nphys: NodePhysicality::Virtual,
+            ..self.clone()
+        }
+    }
+
     // Getters
     
     pub fn id(&self) -> Option<DbId> {
@@ -136,6 +180,14 @@ impl Node {
         self.nphys.clone()
     }
 
+    pub fn persistent(&self) -> bool {
+        self.persistent
+    }
+
+    pub fn set_persistent(&mut self, persistent: bool) {
+        self.persistent = persistent;
+    }
+
     pub fn created_time(&self) -> SysTime {
         self.created_time.clone()
     }
@@ -144,6 +196,10 @@ impl Node {
         self.modified_time.clone()
     }
 
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     pub fn attributes(&self) -> Vec<Attribute> {
         self.attributes.clone()
     }
@@ -155,15 +211,21 @@ impl TryFrom<DbElement> for Node {
     fn try_from(value: DbElement) -> Result<Self, Self::Error> {
         // let fixed: [&str; 6] = ["path", "ntype", "nphys", "alive", "created_time", "modified_time"];
         let fixed = super::attribute::RESERVED_NODE_ATTRS;
-        let rest = value.values.iter().filter(|v|!fixed.contains(&v.key.string().unwrap().as_str())).collect::<Vec<_>>();
+        // Alive is stored with the node but isn't a reserved attribute name
+        let rest = value.values.iter().filter(|v| {
+            let key = v.key.string().unwrap().as_str();
+            !fixed.contains(&key) && key != "alive"
+        }).collect::<Vec<_>>();
 
         let db_id = value.id;
         let path = value.values.iter().find(|v| v.key == "path".into());
         let ntype = value.values.iter().find(|v| v.key == "ntype".into());
         let nphys = value.values.iter().find(|v| v.key == "nphys".into());
         let alive = value.values.iter().find(|v| v.key == "alive".into());
+        let persistent = value.values.iter().find(|v| v.key == "persistent".into());
         let created_time = value.values.iter().find(|v| v.key == "created_time".into());
         let modified_time = value.values.iter().find(|v| v.key == "modified_time".into());
+        let version = value.values.iter().find(|v| v.key == "version".into());
 
         let attrs: Vec<Attribute> = rest.iter().map(|v| {
             Attribute::try_from(*v).unwrap()
@@ -175,11 +237,43 @@ impl TryFrom<DbElement> for Node {
             ntype: NodeType::try_from(ntype.unwrap().value.clone())?,
             nphys: NodePhysicality::try_from(nphys.unwrap().value.clone())?,
             alive: alive.unwrap().value.to_bool().unwrap(),
+            // Nodes stored before the flag existed are not persistent.
+            persistent: persistent.map_or(false, |p| p.value.to_bool().unwrap_or(false)),
             created_time: SysTime::try_from(created_time.unwrap().value.clone())?,
             modified_time: SysTime::try_from(modified_time.unwrap().value.clone())?,
+            // Nodes stored before versioning start from the first version.
+            version: version.map_or(0, |v| v.value.to_u64().unwrap_or(0)),
             attributes: attrs,
         };
 
         Ok(node)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nodes_serialized_before_persistence_and_versions_still_deserialize() {
+        let node = Node::new(&NodePath::from("old"), NodeType::other());
+        let mut json = serde_json::to_value(&node).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("persistent");
+        fields.remove("version");
+
+        let read: Node = serde_json::from_value(json).unwrap();
+        assert_eq!(read.persistent(), false);
+        assert_eq!(read.version(), 0);
+        assert_eq!(read.path(), node.path());
+    }
+
+    #[test]
+    fn db_keys_cover_every_stored_field() {
+        let node = Node::new(&NodePath::from("node"), NodeType::other());
+        let keys = Node::db_keys();
+        for value in node.to_db_values() {
+            assert_eq!(keys.contains(&value.key), true, "{:?} should be a key", value.key);
+        }
+    }
+}
@@ -1,15 +1,55 @@
-use std::path::PathBuf;
+use std::{fmt, path::{Component, Path, PathBuf}};
 
 use agdb::{DbError, DbValue};
 
+use crate::vault::KARTA_DIR_NAME;
+
 use super::nodetype::ARCHETYPES;
 
+/// Names that can't be given to a node.
+const RESERVED_NAMES: [&str; 3] = [KARTA_DIR_NAME, ".", ".."];
+
+/// Reason why a name can't be given to a node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameError {
+    Empty,
+    OnlyWhitespace,
+    ContainsSeparator,
+    Reserved(String),
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Empty => write!(f, "Name cannot be empty"),
+            NameError::OnlyWhitespace => write!(f, "Name cannot be only whitespace"),
+            NameError::ContainsSeparator => write!(f, "Name cannot contain path separators"),
+            NameError::Reserved(name) => write!(f, "Name {} is reserved", name),
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
 /// Newtype wrapper for the node path. Acts as the main struct for
 /// creating and modifying node paths, turning them into db aliases/strings and
 /// back. Path includes the name of the node itself.
 #[derive(Debug, Clone, Hash, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "DeserializedNodePath")]
 pub struct NodePath(PathBuf);
 
+/// A NodePath as it was sent or saved. Normalized like any other path when it
+/// is turned into a NodePath, so that it can't point outside the vault either.
+#[derive(serde::Deserialize)]
+#[serde(rename = "NodePath")]
+struct DeserializedNodePath(PathBuf);
+
+impl From<DeserializedNodePath> for NodePath {
+    fn from(path: DeserializedNodePath) -> Self {
+        NodePath(NodePath::normalize(&path.0))
+    }
+}
+
 impl NodePath {
     /// Get NodePath of the root node. Note that this is not
     /// the user_root, which must be accessed through the graph.
@@ -24,17 +64,43 @@ impl NodePath {
 
     /// Create a new NodePath from a pathbuf relative to the user_root.
     /// Supplying an empty pathbuf will create a NodePath to the userroot.
+    /// A leading slash is ignored, so "/a/b" is the same path as "a/b".
     pub fn new(path: PathBuf) -> Self {
-        if path.to_str().unwrap().is_empty() {
+        let path = Self::normalize(&path);
+        if path.as_os_str().is_empty() {
             return NodePath::user_root();
         }
         let root = NodePath::user_root().buf().clone();
         return NodePath(root.join(path));
     }
 
+    /// Drops the leading slash, trailing slashes and "." components of a path,
+    /// and resolves "..", so that every way of writing the same path gives the
+    /// same NodePath. A ".." never goes above the start of the path, so that a
+    /// NodePath can't point outside the vault.
+    fn normalize(path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::new();
+        Self::push_normalized(&mut normalized, path);
+        normalized
+    }
+
+    fn push_normalized(base: &mut PathBuf, path: &Path) {
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => base.push(part),
+                Component::ParentDir => {
+                    base.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Joins a path onto this one. Like other ways of building a NodePath, ".."
+    /// is resolved and can't go above the root.
     pub fn join(&self, path: &str) -> Self {
         let mut path_buf = self.0.clone();
-        path_buf.push(path);
+        Self::push_normalized(&mut path_buf, Path::new(path));
         NodePath(path_buf)
     }
 
@@ -74,17 +140,20 @@ impl NodePath {
         alias
     }
 
+    /// FNV-1a hash of the alias, for naming files after a node. Unlike std's
+    /// DefaultHasher, it stays the same across builds.
+    pub fn stable_hash(&self) -> u64 {
+        self.alias()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
     // Turn alias (root/path) into NodePath
     pub fn from_alias(alias: &str) -> Self {
-        let buf = PathBuf::from(alias);
-
-        // Remove root/ prefix from path
-        let newbuf = match buf.strip_prefix("/") {
-            Ok(buf) => PathBuf::from(buf),
-            Err(_) => buf,
-        };
-
-        NodePath(newbuf)
+        // Removes the root/ prefix along with anything else new() would drop
+        NodePath(Self::normalize(Path::new(alias)))
     }
 
     pub fn atype(name: &str) -> Self {
@@ -129,6 +198,39 @@ impl NodePath {
             None => None,
         }
     }
+
+    /// Checks whether a name can be used for a single node. Shared by everything
+    /// that creates or renames nodes, so that they can't produce broken paths.
+    pub fn validate_name(name: &str) -> Result<(), NameError> {
+        if name.is_empty() {
+            return Err(NameError::Empty);
+        }
+        if name.trim().is_empty() {
+            return Err(NameError::OnlyWhitespace);
+        }
+        if name.contains('/') || name.contains('\\') {
+            return Err(NameError::ContainsSeparator);
+        }
+        if RESERVED_NAMES.contains(&name) {
+            return Err(NameError::Reserved(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Whether the other path is below this one. Compares whole components,
+    /// so "a/b" is not an ancestor of "a/bc". A path is not its own ancestor.
+    pub fn is_ancestor_of(&self, other: &NodePath) -> bool {
+        self != other && other.0.starts_with(&self.0)
+    }
+
+    /// The part of this path below the base. None if the base is neither this path
+    /// nor one of its ancestors. Equal paths give an empty path.
+    pub fn relative_to(&self, base: &NodePath) -> Option<NodePath> {
+        self.0
+            .strip_prefix(&base.0)
+            .ok()
+            .map(|relative| NodePath(relative.to_path_buf()))
+    }
 }
 
 impl From<String> for NodePath {
@@ -161,5 +263,88 @@ impl From<NodePath> for DbValue {
 mod tests {
     use super::*;
 
-    
-}
\ No newline at end of file
+    #[test]
+    fn ancestry_is_checked_by_whole_components() {
+        let parent = NodePath::from("a");
+        let child = NodePath::from("a/b");
+        let sibling = NodePath::from("ab");
+
+        assert_eq!(parent.is_ancestor_of(&child), true, "Parent is an ancestor");
+        assert_eq!(NodePath::user_root().is_ancestor_of(&child), true, "User root is an ancestor");
+        assert_eq!(NodePath::root().is_ancestor_of(&child), true, "Root is an ancestor");
+        assert_eq!(child.is_ancestor_of(&parent), false, "Descendant is not an ancestor");
+        assert_eq!(parent.is_ancestor_of(&sibling), false, "Sibling is not an ancestor");
+        assert_eq!(parent.is_ancestor_of(&parent), false, "Path is not its own ancestor");
+    }
+
+    #[test]
+    fn relative_path_is_the_suffix_below_the_base() {
+        let base = NodePath::from("a");
+        let deep = NodePath::from("a/b/c");
+
+        assert_eq!(deep.relative_to(&base), Some(NodePath(PathBuf::from("b/c"))));
+        assert_eq!(base.relative_to(&base), Some(NodePath(PathBuf::from(""))));
+        assert_eq!(base.relative_to(&deep), None, "Ancestor is not below its descendant");
+        assert_eq!(NodePath::from("ab").relative_to(&base), None, "Sibling is not below");
+    }
+
+    #[test]
+    fn validating_name_rejects_broken_names() {
+        assert_eq!(NodePath::validate_name(""), Err(NameError::Empty));
+        assert_eq!(NodePath::validate_name("  \t"), Err(NameError::OnlyWhitespace));
+        assert_eq!(NodePath::validate_name("a/b"), Err(NameError::ContainsSeparator));
+        assert_eq!(NodePath::validate_name("a\\b"), Err(NameError::ContainsSeparator));
+        for reserved in [".karta", ".", ".."] {
+            assert_eq!(
+                NodePath::validate_name(reserved),
+                Err(NameError::Reserved(reserved.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn every_way_of_writing_a_path_gives_the_same_path() {
+        let expected = NodePath::from("vault/x");
+        let written = [
+            NodePath::from("/vault/x"),
+            NodePath::from("vault/x/".to_string()),
+            NodePath::from("./vault/x"),
+            NodePath::new("vault/x".into()),
+            NodePath::new("/vault/x".into()),
+            "vault/x".into(),
+            NodePath::from_alias("/user_root/vault/x"),
+            NodePath::from_alias("user_root/vault/x/"),
+        ];
+
+        for path in written.iter() {
+            assert_eq!(path, &expected);
+            assert_eq!(path.alias(), "/user_root/vault/x");
+        }
+
+        assert_eq!(NodePath::from("a/../b"), NodePath::from("b"));
+        assert_eq!(NodePath::from("a/./b/.."), NodePath::from("a"));
+
+        assert_eq!(NodePath::from("/"), NodePath::user_root());
+        assert_eq!(NodePath::from_alias("/"), NodePath::root());
+        assert_eq!(NodePath::root().alias(), "/");
+    }
+
+    #[test]
+    fn parent_dirs_cannot_lead_outside_the_vault() {
+        let root = PathBuf::from("/vault");
+
+        assert_eq!(NodePath::from("../secret"), NodePath::from("secret"));
+        assert_eq!(NodePath::from("../../secret").full(&root), PathBuf::from("/vault/secret"));
+        assert_eq!(NodePath::from_alias("/user_root/../../secret").full(&root), PathBuf::from("/vault/secret"));
+        assert_eq!(NodePath::from("a").join("../../..").full(&root), root);
+        assert_eq!(NodePath::from("a").join("/etc/passwd"), NodePath::from("a/etc/passwd"));
+
+        let sent: NodePath = serde_json::from_str("\"user_root/../../etc\"").unwrap();
+        assert_eq!(sent, NodePath::root().join("etc"));
+    }
+
+    #[test]
+    fn validating_name_accepts_unicode() {
+        assert_eq!(NodePath::validate_name("kartta ✨ 地図"), Ok(()));
+    }
+}
@@ -121,6 +121,45 @@ impl NodeType {
         }
     }
 
+    /// Node with text content. Physical text nodes are text files, virtual
+    /// ones store their content in a file in the karta folder.
+    pub fn text() -> Self {
+        Self {
+            type_name: "Text".to_string(),
+        }
+    }
+
+    pub fn image() -> Self {
+        Self {
+            type_name: "Image".to_string(),
+        }
+    }
+
+    pub fn audio() -> Self {
+        Self {
+            type_name: "Audio".to_string(),
+        }
+    }
+
+    pub fn video() -> Self {
+        Self {
+            type_name: "Video".to_string(),
+        }
+    }
+
+    /// The node type of a file with the given MIME type. Only the category
+    /// matters, so "image/png" and "image/webp" are both images. Files of
+    /// other categories are plain files.
+    pub fn from_mime(mime: &str) -> Self {
+        match mime.split('/').next().unwrap_or("") {
+            "image" => NodeType::image(),
+            "audio" => NodeType::audio(),
+            "video" => NodeType::video(),
+            "text" => NodeType::text(),
+            _ => NodeType::file(),
+        }
+    }
+
     pub fn other() -> Self {
         Self {
             type_name: "Other".to_string(),
@@ -132,6 +171,30 @@ impl NodeType {
     }
 }
 
+/// Why a node can't be given a type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeTypeError {
+    /// The type says directory when the node is a file on disk, or the other way around.
+    ContradictsFileSystem { ntype: NodeType, is_dir: bool },
+    Archetype,
+}
+
+impl std::fmt::Display for NodeTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeTypeError::ContradictsFileSystem { ntype, is_dir } => write!(
+                f,
+                "Type {} contradicts the {} on disk",
+                ntype.name(),
+                if *is_dir { "directory" } else { "file" }
+            ),
+            NodeTypeError::Archetype => write!(f, "The type of archetype nodes cannot be changed"),
+        }
+    }
+}
+
+impl std::error::Error for NodeTypeError {}
+
 impl TryFrom<agdb::DbValue> for NodeType {
     type Error = agdb::DbError;
 
@@ -0,0 +1,169 @@
+use std::{collections::HashMap, fmt};
+
+use super::{
+    attribute::{AttrValue, Attribute},
+    nodetype::NodeType,
+};
+
+/// The kind of value an attribute is expected to hold.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum AttrKind {
+    Float,
+    String,
+    UInt,
+}
+
+impl AttrKind {
+    pub fn matches(&self, value: &AttrValue) -> bool {
+        matches!(
+            (self, value),
+            (AttrKind::Float, AttrValue::Float(_))
+                | (AttrKind::String, AttrValue::String(_))
+                | (AttrKind::UInt, AttrValue::UInt(_))
+        )
+    }
+}
+
+/// Declares the attributes that nodes of a type are expected to have.
+/// Attributes that aren't declared are still allowed.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct NodeTypeSchema {
+    ntype: NodeType,
+    attributes: HashMap<String, AttrKind>,
+}
+
+impl NodeTypeSchema {
+    pub fn new(ntype: NodeType) -> Self {
+        NodeTypeSchema {
+            ntype,
+            attributes: HashMap::new(),
+        }
+    }
+
+    /// Builder style declaration of an attribute.
+    pub fn with_attribute(mut self, name: &str, kind: AttrKind) -> Self {
+        self.attributes.insert(name.to_string(), kind);
+        self
+    }
+
+    pub fn ntype(&self) -> &NodeType {
+        &self.ntype
+    }
+
+    pub fn attribute_kind(&self, name: &str) -> Option<AttrKind> {
+        self.attributes.get(name).copied()
+    }
+}
+
+/// An attribute whose value doesn't match the schema of its node type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaViolation {
+    pub attribute: String,
+    pub expected: AttrKind,
+    pub found: AttrValue,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Attribute {} should be {:?}, found {:?}",
+            self.attribute, self.expected, self.found
+        )
+    }
+}
+
+/// What to do when attributes don't match the schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SchemaMode {
+    /// Log the violations and insert the attributes anyway.
+    #[default]
+    Warn,
+    /// Refuse to insert the attributes.
+    Error,
+}
+
+/// The attribute schemas of node types. Node types without a schema
+/// accept any attributes.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: HashMap<String, NodeTypeSchema>,
+    mode: SchemaMode,
+}
+
+impl SchemaRegistry {
+    /// Registers a schema, replacing any previous schema of the same node type.
+    pub fn register(&mut self, schema: NodeTypeSchema) {
+        self.schemas.insert(schema.ntype().name().to_string(), schema);
+    }
+
+    pub fn schema(&self, ntype: &NodeType) -> Option<&NodeTypeSchema> {
+        self.schemas.get(ntype.name())
+    }
+
+    pub fn mode(&self) -> SchemaMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: SchemaMode) {
+        self.mode = mode;
+    }
+
+    /// Lists the attributes that don't match the schema of the node type.
+    pub fn validate(&self, ntype: &NodeType, attrs: &[Attribute]) -> Vec<SchemaViolation> {
+        let schema = match self.schema(ntype) {
+            Some(schema) => schema,
+            None => return Vec::new(),
+        };
+
+        attrs
+            .iter()
+            .filter_map(|attr| {
+                let expected = schema.attribute_kind(&attr.name)?;
+                if expected.matches(&attr.value) {
+                    None
+                } else {
+                    Some(SchemaViolation {
+                        attribute: attr.name.clone(),
+                        expected,
+                        found: attr.value.clone(),
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrongly_typed_attribute_violates_schema() {
+        let mut registry = SchemaRegistry::default();
+        registry.register(
+            NodeTypeSchema::new(NodeType::new("Image".to_string()))
+                .with_attribute("width", AttrKind::UInt)
+                .with_attribute("height", AttrKind::UInt),
+        );
+
+        let attrs = vec![
+            Attribute::new_uint("width".to_string(), 640),
+            Attribute::new_string("height".to_string(), "tall".to_string()),
+            Attribute::new_string("caption".to_string(), "undeclared".to_string()),
+        ];
+
+        let violations = registry.validate(&NodeType::new("Image".to_string()), &attrs);
+        assert_eq!(
+            violations,
+            vec![SchemaViolation {
+                attribute: "height".to_string(),
+                expected: AttrKind::UInt,
+                found: AttrValue::String("tall".to_string()),
+            }]
+        );
+
+        let untyped = registry.validate(&NodeType::other(), &attrs);
+        assert_eq!(untyped.is_empty(), true, "Types without a schema accept anything");
+    }
+}
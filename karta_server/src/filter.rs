@@ -1,4 +1,48 @@
 // Main file of the Filter struct and its implementations.
 
 // Filter is a struct that can be passed to functions in the Graph struct
-// to limit the search to a certain part of the graph.
\ No newline at end of file
+// to limit the search to a certain part of the graph.
+
+use crate::elements::{nodetype::NodeType, SysTime};
+
+/// Conditions that nodes must match to be included in search results.
+/// Times are in unix seconds. Unset fields don't filter anything.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Filter {
+    pub modified_after: Option<u64>,
+    pub modified_before: Option<u64>,
+    pub created_after: Option<u64>,
+    /// Names of the allowed node types. Empty allows all types.
+    #[serde(default)]
+    pub ntypes: Vec<String>,
+}
+
+impl Filter {
+    /// Whether the given creation and modification times pass the filter.
+    pub fn matches_times(&self, created: &SysTime, modified: &SysTime) -> bool {
+        let created = created.as_secs();
+        let modified = modified.as_secs();
+
+        if let Some(after) = self.modified_after {
+            if modified < after {
+                return false;
+            }
+        }
+        if let Some(before) = self.modified_before {
+            if modified > before {
+                return false;
+            }
+        }
+        if let Some(after) = self.created_after {
+            if created < after {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether the node type is one of the allowed types.
+    pub fn matches_ntype(&self, ntype: &NodeType) -> bool {
+        self.ntypes.is_empty() || self.ntypes.iter().any(|name| name == ntype.name())
+    }
+}
@@ -0,0 +1,407 @@
+// Reading of files in the vault. Files are classified into node types by their
+// extension, which is fast. Their content is only looked at if the extension
+// doesn't tell what they are and sniffing is enabled.
+
+use std::{
+    fs::{File, Metadata},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use ignore::{gitignore::Gitignore, WalkBuilder, WalkState};
+
+use crate::{
+    elements::{attribute::Attribute, nodetype::NodeType},
+    vault::KARTA_DIR_NAME,
+};
+
+/// File at the vault root listing, in gitignore syntax, the files and
+/// directories that shouldn't become nodes, such as node_modules or target.
+pub const IGNORE_FILE_NAME: &str = ".kartaignore";
+
+/// How many bytes from the start of a file are read to sniff its type.
+const SNIFF_BYTES: u64 = 8192;
+
+/// Attribute holding the size of a file in bytes, set when the file is indexed.
+pub const FILE_SIZE_ATTR: &str = "size";
+
+/// MIME type given to files whose type can't be determined.
+pub const UNKNOWN_MIME_TYPE: &str = "application/octet-stream";
+
+/// Infers the MIME type of a file from its extension.
+/// Falls back to application/octet-stream.
+pub fn mime_type_for(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    match ext.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("bmp") => "image/bmp",
+        Some("ico") => "image/x-icon",
+        Some("mp3") => "audio/mpeg",
+        Some("wav") => "audio/wav",
+        Some("ogg") => "audio/ogg",
+        Some("flac") => "audio/flac",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mov") => "video/quicktime",
+        Some("mkv") => "video/x-matroska",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        _ => UNKNOWN_MIME_TYPE,
+    }
+}
+
+/// Infers the MIME type of a file from its first bytes, such as the signature
+/// of a PNG. None if the file can't be read or its type isn't recognized.
+pub fn sniff_mime_type(path: &Path) -> Option<&'static str> {
+    let mut start = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(SNIFF_BYTES)
+        .read_to_end(&mut start)
+        .ok()?;
+
+    infer::get(&start).map(|kind| kind.mime_type())
+}
+
+/// The node type of a physical file. Classified by extension, unless the
+/// extension is missing or unknown and sniffing is enabled, in which case
+/// the content of the file is sniffed.
+pub fn file_node_type(path: &Path, sniff: bool) -> NodeType {
+    let mime = match mime_type_for(path) {
+        UNKNOWN_MIME_TYPE if sniff => sniff_mime_type(path).unwrap_or(UNKNOWN_MIME_TYPE),
+        mime => mime,
+    };
+    NodeType::from_mime(mime)
+}
+
+/// Attributes of a file that are read from its metadata, currently just its size.
+/// Directories and paths whose metadata can't be read get none.
+pub fn file_metadata_attributes(path: &Path) -> Vec<Attribute> {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.is_file() => {
+            vec![Attribute::new_uint(FILE_SIZE_ATTR.to_string(), meta.len())]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Creation and modification times of a file or directory, in that order. Not
+/// every platform supports creation times, so the modification time stands in
+/// for it, and the current time for a missing modification time.
+pub fn file_times(meta: &Metadata) -> (SystemTime, SystemTime) {
+    let modified = meta.modified().unwrap_or_else(|_| SystemTime::now());
+    let created = meta.created().unwrap_or(modified);
+    (created, modified)
+}
+
+/// Copies a file, or a directory with everything in it, to a path that
+/// doesn't exist yet.
+pub fn copy_recursively(from: &Path, to: &Path) -> std::io::Result<()> {
+    if !from.is_dir() {
+        return std::fs::copy(from, to).map(|_| ());
+    }
+    std::fs::create_dir(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Replaces the contents of a file so that it's never left half written. The
+/// contents go to a temporary file next to it first, which is then renamed over
+/// it, so a crash leaves either the old or the new contents.
+pub fn write_atomically(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(contents.as_ref())?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| std::fs::rename(&temp_path, path)) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// The ignore patterns of the vault. They are read from the file every time
+/// instead of being cached, so edits to it apply to the next walk.
+pub fn load_ignore(root: &Path) -> Gitignore {
+    let (ignore, error) = Gitignore::new(root.join(IGNORE_FILE_NAME));
+    if let Some(error) = error {
+        if root.join(IGNORE_FILE_NAME).exists() {
+            tracing::warn!("Error reading {}: {}", IGNORE_FILE_NAME, error);
+        }
+    }
+    ignore
+}
+
+/// Whether the entry should be left out of the graph, either because it is
+/// the .karta directory or because it matches the ignore patterns.
+pub fn is_ignored(ignore: &Gitignore, path: &Path, is_dir: bool) -> bool {
+    if path.file_name().is_some_and(|name| name == KARTA_DIR_NAME) {
+        return true;
+    }
+    // Paths outside the vault, such as the vault itself, can't be matched
+    if !path.starts_with(ignore.path()) || path == ignore.path() {
+        return false;
+    }
+    ignore.matched_path_or_any_parents(path, is_dir).is_ignore()
+}
+
+/// Full paths of all files and directories in the vault, not including the
+/// vault itself. Ignored directories aren't descended into. The tree is walked
+/// on multiple threads, so the order of the paths is not deterministic.
+pub fn get_all_paths(root: &Path) -> Vec<PathBuf> {
+    let ignore = load_ignore(root);
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+            !is_ignored(&ignore, entry.path(), is_dir)
+        })
+        .build_parallel();
+
+    let paths: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+    walker.run(|| {
+        let paths = &paths;
+        // Each thread collects its own paths, so the lock is only taken once per thread
+        let mut found = Collector { paths, found: Vec::new() };
+        Box::new(move |entry| {
+            if let Ok(entry) = entry {
+                if entry.depth() > 0 {
+                    found.found.push(entry.into_path());
+                }
+            }
+            WalkState::Continue
+        })
+    });
+
+    paths.into_inner().unwrap()
+}
+
+/// Paths found by one thread of a walk. Handed over to the shared list when
+/// the thread is done.
+struct Collector<'a> {
+    paths: &'a Mutex<Vec<PathBuf>>,
+    found: Vec<PathBuf>,
+}
+
+impl Drop for Collector<'_> {
+    fn drop(&mut self) {
+        self.paths.lock().unwrap().append(&mut self.found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use std::{collections::HashSet, io::Write};
+
+    use crate::{
+        elements::{attribute::AttrValue, node::Node, node_path::NodePath},
+        graph_traits::{graph_core::GraphCore, graph_node::GraphNode},
+        utils::utils::TestContext,
+    };
+
+    use super::*;
+
+    const PNG_BYTES: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    #[test]
+    fn extensionless_png_is_sniffed_as_an_image() {
+        let func_name = "extensionless_png_is_sniffed_as_an_image";
+        let ctx = TestContext::new(func_name);
+
+        let path = ctx.graph.user_root_dirpath().join("picture");
+        File::create(&path).unwrap().write_all(&PNG_BYTES).unwrap();
+
+        assert_eq!(file_node_type(&path, false), NodeType::file(), "Sniffing is opt-in");
+        assert_eq!(file_node_type(&path, true), NodeType::image());
+    }
+
+    #[test]
+    fn video_extensions_map_to_the_video_type() {
+        for name in ["clip.mp4", "clip.mov", "clip.webm", "clip.MKV"] {
+            let mime = mime_type_for(Path::new(name));
+            assert_eq!(mime.starts_with("video/"), true, "{} should be a video", name);
+            assert_eq!(NodeType::from_mime(mime), NodeType::video());
+        }
+        assert_eq!(file_node_type(Path::new("clip.mp4"), false), NodeType::video());
+    }
+
+    #[test]
+    fn kartaignore_excludes_matching_paths() {
+        let func_name = "kartaignore_excludes_matching_paths";
+        let ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        std::fs::create_dir_all(root.join("node_modules/package")).unwrap();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        File::create(root.join("node_modules/package/index.js")).unwrap();
+        File::create(root.join("src/main.rs")).unwrap();
+        File::create(root.join("src/debug.log")).unwrap();
+
+        let paths = get_all_paths(&root);
+        assert_eq!(paths.contains(&root.join("node_modules/package/index.js")), true);
+        assert_eq!(
+            paths.iter().any(|path| path.ends_with(KARTA_DIR_NAME)),
+            false,
+            ".karta should never be walked"
+        );
+
+        std::fs::write(root.join(IGNORE_FILE_NAME), "node_modules/\n*.log\n").unwrap();
+
+        let paths = get_all_paths(&root);
+        assert_eq!(
+            paths.iter().any(|path| path.starts_with(root.join("node_modules"))),
+            false,
+            "Ignored directory and its contents should be skipped"
+        );
+        assert_eq!(paths.contains(&root.join("src/debug.log")), false);
+        assert_eq!(paths.contains(&root.join("src/main.rs")), true);
+    }
+
+    #[test]
+    fn parallel_walk_finds_the_same_paths_as_a_sequential_one() {
+        let func_name = "parallel_walk_finds_the_same_paths_as_a_sequential_one";
+        let ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        for i in 0..10 {
+            for j in 0..10 {
+                let dir = root.join(format!("dir_{}/sub_{}", i, j));
+                std::fs::create_dir_all(&dir).unwrap();
+                for k in 0..5 {
+                    File::create(dir.join(format!("file_{}.txt", k))).unwrap();
+                }
+            }
+        }
+
+        let mut sequential: HashSet<PathBuf> = HashSet::new();
+        let mut dirs = vec![root.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(&dir).unwrap().flatten() {
+                if entry.file_name() == KARTA_DIR_NAME {
+                    continue;
+                }
+                if entry.path().is_dir() {
+                    dirs.push(entry.path());
+                }
+                sequential.insert(entry.path());
+            }
+        }
+
+        let parallel = get_all_paths(&root);
+        assert_eq!(parallel.len(), sequential.len(), "Walk should not find duplicates");
+        assert_eq!(parallel.into_iter().collect::<HashSet<_>>(), sequential);
+    }
+
+    #[test]
+    fn indexed_file_carries_its_size() {
+        let func_name = "indexed_file_carries_its_size";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs/notes.txt"), "hello karta").unwrap();
+
+        let node = ctx.graph.create_node_by_path(&NodePath::from("docs/notes.txt"), None).unwrap();
+        let size = |node: &Node| {
+            node.attributes().into_iter().find(|attr| attr.name == FILE_SIZE_ATTR).map(|attr| attr.value)
+        };
+        assert_eq!(size(&node), Some(AttrValue::UInt(11)));
+
+        let reopened = ctx.graph.open_node(&NodePath::from("docs/notes.txt")).unwrap();
+        assert_eq!(size(&reopened), Some(AttrValue::UInt(11)), "Size should be stored in the db");
+
+        let dir = ctx.graph.open_node(&NodePath::from("docs")).unwrap();
+        assert_eq!(dir.attributes().iter().any(|attr| attr.name == FILE_SIZE_ATTR), false);
+
+        // Sizes past 4 GiB are kept as they are. The file is sparse, so nothing is written.
+        let large = root.join("docs/large.bin");
+        let big = u32::MAX as u64 + 1;
+        std::fs::File::create(&large).unwrap().set_len(big).unwrap();
+        assert_eq!(
+            file_metadata_attributes(&large),
+            vec![Attribute::new_uint(FILE_SIZE_ATTR.to_string(), big)]
+        );
+    }
+
+    #[test]
+    fn indexed_file_carries_its_file_system_times() {
+        let func_name = "indexed_file_carries_its_file_system_times";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        let touched = SystemTime::now() - std::time::Duration::from_secs(60 * 60 * 24 * 3);
+        File::create(root.join("old.txt")).unwrap().set_modified(touched).unwrap();
+
+        let node = ctx.graph.create_node_by_path(&NodePath::from("old.txt"), None).unwrap();
+        let reopened = ctx.graph.open_node(&NodePath::from("old.txt")).unwrap();
+
+        // Times are stored in whole seconds
+        let touched_secs = touched.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+        for node in [node, reopened] {
+            let modified = node.modified_time().as_secs();
+            assert_eq!(modified.abs_diff(touched_secs) <= 1, true, "{} vs {}", modified, touched_secs);
+            assert_eq!(node.created_time().as_secs() >= modified, true, "File was created after the touched time");
+        }
+
+        let virtual_node = ctx.graph.create_node_by_path(&NodePath::from("idea"), None).unwrap();
+        assert_eq!(virtual_node.modified_time().as_secs() > touched_secs + 60, true, "Virtual nodes are new");
+    }
+
+    #[test]
+    fn extension_takes_precedence_over_content() {
+        let func_name = "extension_takes_precedence_over_content";
+        let ctx = TestContext::new(func_name);
+
+        let path = ctx.graph.user_root_dirpath().join("notes.txt");
+        File::create(&path).unwrap().write_all(&PNG_BYTES).unwrap();
+
+        assert_eq!(file_node_type(&path, true), NodeType::text());
+    }
+
+    #[test]
+    fn atomic_write_leaves_old_or_new_contents() {
+        let func_name = "atomic_write_leaves_old_or_new_contents";
+        let ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        let path = root.join("config.txt");
+        std::fs::write(&path, "old").unwrap();
+
+        write_atomically(&path, "new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(root.join(".config.txt.tmp").exists(), false, "Temporary file should be renamed");
+
+        // A directory in the way of the temporary file makes the write fail
+        std::fs::create_dir(root.join(".config.txt.tmp")).unwrap();
+        assert_eq!(write_atomically(&path, "newer").is_err(), true);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new", "Failed write should keep the old contents");
+    }
+}
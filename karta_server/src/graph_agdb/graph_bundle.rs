@@ -0,0 +1,175 @@
+use std::{collections::HashSet, error::Error};
+
+use agdb::{QueryBuilder, QueryType};
+
+use crate::{
+    context::{Context, ContextDb},
+    graph_traits::{
+        graph_bundle::{ExportBundle, GraphBundle, ImportResult, BUNDLE_VERSION},
+        graph_edge::GraphEdge,
+        graph_node::GraphNode,
+    },
+    vault::KartaVault,
+};
+
+use super::{edge::Edge, node_path::NodePath, GraphAgdb};
+
+impl GraphBundle for GraphAgdb {
+    fn export_subtree(&self, root: &NodePath) -> Result<ExportBundle, Box<dyn Error>> {
+        let mut nodes = vec![self.open_node(root)?];
+        nodes.extend(self.get_all_descendants(root)?);
+
+        let paths: Vec<NodePath> = nodes.iter().map(|node| node.path()).collect();
+
+        let edges = self.get_edges_between_nodes(&paths)?;
+
+        let context_db = ContextDb::new(&KartaVault::new(self.root_path.clone()));
+        let mut contexts = Vec::new();
+        for path in paths.iter() {
+            if let Some(mut context) = context_db.get_context(path)? {
+                context.nodes.retain(|node| paths.contains(&node.path));
+                contexts.push(context);
+            }
+        }
+
+        Ok(ExportBundle {
+            version: BUNDLE_VERSION,
+            nodes,
+            edges,
+            contexts,
+        })
+    }
+
+    fn import_bundle(
+        &mut self,
+        bundle: ExportBundle,
+        target_parent: &NodePath,
+        rename_on_conflict: bool,
+    ) -> Result<ImportResult, Box<dyn Error>> {
+        if bundle.version != BUNDLE_VERSION {
+            return Err(format!("Unsupported bundle version {}", bundle.version).into());
+        }
+
+        let old_root = match bundle.nodes.first() {
+            Some(node) => node.path(),
+            None => return Err("Bundle has no nodes".into()),
+        };
+
+        self.open_node(target_parent)
+            .map_err(|_| format!("Target parent {} does not exist", target_parent.alias()))?;
+
+        // Find a free path for the root of the bundle
+        let mut new_root = target_parent.join(&old_root.name());
+        if self.open_node(&new_root).is_ok() {
+            if !rename_on_conflict {
+                return Err(format!("Node {} already exists", new_root.alias()).into());
+            }
+            new_root = target_parent.join(&self.generate_unique_name(target_parent, &old_root.name()));
+        }
+
+        let rebase = |path: &NodePath| -> Result<NodePath, Box<dyn Error>> {
+            let relative = path.relative_to(&old_root).ok_or_else(|| {
+                format!("Node {} is not a part of the bundle", path.alias())
+            })?;
+            if relative.buf().as_os_str().is_empty() {
+                Ok(new_root.clone())
+            } else {
+                let relative = relative
+                    .buf()
+                    .to_str()
+                    .ok_or_else(|| format!("Path of {} is not valid UTF-8", path.alias()))?;
+                Ok(new_root.join(relative))
+            }
+        };
+
+        // Resolve and check everything before inserting anything, so that a
+        // broken bundle is refused as a whole.
+        let mut nodes = Vec::new();
+        let mut paths: HashSet<NodePath> = HashSet::new();
+        for node in bundle.nodes.iter() {
+            let path = rebase(&node.path())?;
+            NodePath::validate_name(&path.name())
+                .map_err(|e| format!("Invalid name in bundle {}: {}", node.path().alias(), e))?;
+            if !paths.insert(path.clone()) {
+                return Err(format!("Bundle has more than one node at {}", node.path().alias()).into());
+            }
+            if self.open_node(&path).is_ok() {
+                return Err(format!("Node {} already exists", path.alias()).into());
+            }
+            nodes.push(node.relocated(&path));
+        }
+        let mut edges = Vec::new();
+        for edge in bundle.edges.iter() {
+            let source = rebase(edge.source())?;
+            let target = rebase(edge.target())?;
+            for endpoint in [&source, &target] {
+                if !paths.contains(endpoint) {
+                    return Err(format!("Edge endpoint {} is not a node of the bundle", endpoint.alias()).into());
+                }
+            }
+            edges.push(edge.relocated(&source, &target));
+        }
+        let mut contexts = Vec::new();
+        for context in bundle.contexts.iter() {
+            let focal = rebase(&context.focal)?;
+            if !paths.contains(&focal) {
+                return Err(format!("Context of {} is not for a node of the bundle", context.focal.alias()).into());
+            }
+            let mut relocated = Context { focal, ..context.clone() };
+            for node in relocated.nodes.iter_mut() {
+                node.path = rebase(&node.path)?;
+            }
+            contexts.push(relocated);
+        }
+
+        let mut queries: Vec<QueryType> = Vec::new();
+        for node in nodes.iter() {
+            queries.push(
+                QueryBuilder::insert()
+                    .nodes()
+                    .aliases(node.path().alias())
+                    .values(node)
+                    .query()
+                    .into(),
+            );
+        }
+        for edge in edges.iter() {
+            queries.push(
+                QueryBuilder::insert()
+                    .edges()
+                    .from(edge.source().alias())
+                    .to(edge.target().alias())
+                    .values_uniform(edge)
+                    .query()
+                    .into(),
+            );
+        }
+        queries.push(
+            QueryBuilder::insert()
+                .edges()
+                .from(target_parent.alias())
+                .to(new_root.alias())
+                .values_uniform(&Edge::new_cont(target_parent, &new_root))
+                .query()
+                .into(),
+        );
+        self.exec_batch(queries)?;
+
+        // Contexts are files outside the db, so they are saved once the nodes
+        // are in. The nodes are imported even if a context can't be saved.
+        let context_db = ContextDb::new(&KartaVault::new(self.root_path.clone()));
+        for context in contexts.iter() {
+            if let Err(e) = context_db.save_context(context) {
+                tracing::warn!("Context of {} could not be imported: {}", context.focal.alias(), e);
+            }
+        }
+
+        let paths: Vec<NodePath> = nodes.iter().map(|node| node.path()).collect();
+        self.update_readable_files(&paths);
+
+        Ok(ImportResult {
+            root: new_root,
+            nodes: paths,
+        })
+    }
+}
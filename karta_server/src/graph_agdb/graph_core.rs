@@ -1,13 +1,15 @@
-use std::{error::Error, path::{self, PathBuf}};
+use std::{collections::{HashMap, HashSet}, error::Error, path::{self, PathBuf}};
 
-use agdb::QueryBuilder;
+use agdb::{DbId, QueryBuilder};
 
 use crate::{
     elements::nodetype::NodeType,
-    graph_traits::{self, graph_core::GraphCore, graph_node::GraphNode},
+    fs_reader,
+    graph_traits::{self, graph_core::{ContextIndex, GraphCore, IntegrityIssue, VacuumStats, DEFAULT_DIR_NODE_LIMIT}, graph_node::GraphNode},
+    vault::KARTA_DIR_NAME,
 };
 
-use super::{node::Node, node_path::NodePath, nodetype::ARCHETYPES, GraphAgdb, StoragePath};
+use super::{edge::Edge, node::Node, node_path::NodePath, nodetype::ARCHETYPES, GraphAgdb, GraphDb, StoragePath};
 
 /// Implementation block for the Graph struct itself.
 /// Includes constructors and utility functions.
@@ -18,7 +20,7 @@ impl GraphCore for GraphAgdb {
 
     fn user_root_dirpath(&self) -> PathBuf {
         let path = self.root_path.clone();
-        println!("root_path: {:?}", path);
+        tracing::debug!("root_path: {:?}", path);
         path
     }
 
@@ -50,43 +52,7 @@ impl GraphCore for GraphAgdb {
             Some(path) => graph_traits::StoragePath::Custom(path),
             None => graph_traits::StoragePath::Default,
         };
-        let storage_path = match storage_enum.clone() {
-            StoragePath::Custom(path) => path,
-            StoragePath::Default => {
-                directories::ProjectDirs::from("com", "teodosin_labs", "karta_server")
-                    .unwrap()
-                    .data_dir()
-                    .to_path_buf()
-            }
-        };
-        let storage_dir = storage_path.join(".kartaVault");
-
-
-        // Create the path if it doesn't exist
-        if !storage_dir.exists() {
-            std::fs::create_dir_all(&storage_dir).expect("Failed to create storage path");
-        }
-        
-        let db_path = storage_dir.join(format!("{}.agdb", name));
-
-        // Check if the database already exists
-        let open_existing = db_path.exists();
-
-        let db = agdb::Db::new(db_path.to_str().unwrap()).expect("Failed to create new db");
-
-        let mut giraphe = GraphAgdb {
-            name: name.to_string(),
-            db,
-            root_path: root_path.into(),
-            storage_path: storage_enum,
-            maintain_readable_files: false,
-        };
-
-        if !open_existing {
-            giraphe.init_archetype_nodes();
-        }
-
-        return giraphe;
+        GraphAgdb::new_with_storage(name, root_path, storage_enum)
     }
 
     /// Create the initial archetype nodes for the graph. Includes
@@ -97,57 +63,65 @@ impl GraphCore for GraphAgdb {
     fn init_archetype_nodes(&mut self) {
         let archetypes = ARCHETYPES;
 
-        println!("Length of archetypes {}", archetypes.len());
+        tracing::trace!("Length of archetypes {}", archetypes.len());
 
         archetypes.iter().for_each(|at| {
-            println!("{}", at);
+            tracing::trace!("{}", at);
         });
 
         archetypes.iter().for_each(|atype| {
             let atype_path = NodePath::atype(*atype);
-            println!("Atypepath {:?}", atype_path);
+            tracing::trace!("Atypepath {:?}", atype_path);
 
-            println!("Creating archetype node: {}", atype_path.alias());
+            tracing::debug!("Creating archetype node: {}", atype_path.alias());
 
             let ntype = if atype_path == NodePath::root() {
-                println!("Root node in question");
+                tracing::trace!("Root node in question");
                 NodeType::root_type()
             } else {
-                println!("Archetype node in question");
+                tracing::trace!("Archetype node in question");
                 NodeType::archetype_type()
             };
 
-            let node: Node = Node::new(&atype_path, ntype);
+            // Archetypes of an existing db are kept as they are
+            if self.open_node(&atype_path).is_err() {
+                let node: Node = Node::new(&atype_path, ntype);
 
-            println!("alias is {}", atype_path.alias());
+                tracing::trace!("alias is {}", atype_path.alias());
 
-            let query = self.db.exec_mut(
-                &QueryBuilder::insert()
-                    .nodes()
-                    .aliases(atype_path.alias())
-                    .values(&node)
-                    .query(),
-            );
+                let query = self.db.exec_mut(
+                    &QueryBuilder::insert()
+                        .nodes()
+                        .aliases(atype_path.alias())
+                        .values(&node)
+                        .query(),
+                );
 
-            match query {
-                Ok(_) => {
-                    println!("Created archetype node: {}", atype_path.alias());
-                }
-                Err(ref err) => {
-                    panic!("Failed to create archetype node: {}", err);
-                    println!("Failed to create archetype node: {}", err);
+                match query {
+                    Ok(_) => {
+                        tracing::debug!("Created archetype node: {}", atype_path.alias());
+                    }
+                    Err(ref err) => {
+                        tracing::error!("Failed to create archetype node: {}", err);
+                        panic!("Failed to create archetype node: {}", err);
+                    }
                 }
             }
 
-            if atype_path != NodePath::root() {
-                println!(
+            let contained = self
+                .adjacent_edges(&NodePath::root(), true)
+                .map_or(false, |edges| {
+                    edges.iter().any(|edge| edge.contains() && *edge.target() == atype_path)
+                });
+            if atype_path != NodePath::root() && !contained {
+                tracing::trace!(
                     "autoparent: parent {:?} to child {:?}",
                     &NodePath::root(),
                     &atype_path
                 );
                 self.autoparent_nodes(&NodePath::root(), &atype_path);
-            } else {
-                println!("Root node, no autoparenting");
+            } else if atype_path == NodePath::root() {
+                tracing::trace!("Root node, no autoparenting");
             }
         });
     }
@@ -180,18 +154,18 @@ impl GraphCore for GraphAgdb {
             is_dir = full_path.is_dir();
         }
 
-        println!("Indexing node: {}", node_alias);
-        println!("Is phys: {} is dir: {}", is_phys, is_dir);
+        tracing::debug!("Indexing node: {}", node_alias);
+        tracing::trace!("Is phys: {} is dir: {}", is_phys, is_dir);
 
         // Handle the case where the node is already in the db
         let node = self.db.exec(&QueryBuilder::select().ids(node_alias.clone()).query());
         if node.is_ok() {
-            println!("Node already exists");
+            tracing::trace!("Node already exists");
             return Err("Node already exists".into())
         }
 
         if is_phys {
-            println!("Indexing node: {}", node_alias);
+            tracing::trace!("Indexing node: {}", node_alias);
             if is_dir {
                 return self.create_node_by_path(path, Some(NodeType::dir()))
             } else {
@@ -204,7 +178,8 @@ impl GraphCore for GraphAgdb {
     }
 
     /// Syncs the node's and its relationships in the db with the file system.
-    fn index_node_context(&mut self, path: &NodePath) {
+    #[tracing::instrument(level = "debug", skip_all, fields(path = %path.alias()))]
+    fn index_node_context(&mut self, path: &NodePath) -> ContextIndex {
         let full_path = path.full(&self.root_path);
         let mut node_alias: String;
 
@@ -236,22 +211,48 @@ impl GraphCore for GraphAgdb {
 
         // If the path is a directory, we must check for its contents in the 
         // file system and index them.
+        let mut context_index = ContextIndex::default();
         if is_dir {
             let children = full_path.read_dir().unwrap();
+            let ignore = fs_reader::load_ignore(&self.user_root_dirpath());
+            let mut child_paths: Vec<NodePath> = Vec::new();
             children.into_iter().for_each(|child| {
                 match child {
                     Ok(child) => {
                         let path = child.path();
-                        let child_path = NodePath::from_dir_path(&self.user_root_dirpath(), &path);
-                        println!("Indexing child: {:?}", child_path);
-
-                        self.index_single_node(&child_path);
+                        if fs_reader::is_ignored(&ignore, &path, path.is_dir()) {
+                            return;
+                        }
+                        child_paths.push(NodePath::from_dir_path(&self.user_root_dirpath(), &path));
                     },
                     Err(err) => {
-                        println!("Error reading directory: {}", err);
+                        tracing::warn!("Error reading directory: {}", err);
                     }
                 }
             });
+
+            // Sorted so that the same children are indexed every time
+            // when there are more of them than the limit.
+            child_paths.sort_by_key(|p| p.alias());
+            context_index.total_children = child_paths.len();
+            if child_paths.len() > self.dir_node_limit {
+                tracing::info!(
+                    "Indexing {} of the {} children of {}",
+                    self.dir_node_limit,
+                    child_paths.len(),
+                    path.alias()
+                );
+                child_paths.truncate(self.dir_node_limit);
+                context_index.truncated = true;
+            }
+
+            // Look up the children that are already indexed all at once, instead
+            // of one query per child.
+            let indexed = self.indexed_connections(path);
+            for child_path in child_paths.iter().filter(|p| !indexed.contains(p)) {
+                tracing::trace!("Indexing child: {:?}", child_path);
+                self.index_single_node(child_path);
+            }
         }
 
         // TODO: Check an existing node's relationships to find nodes that need updating 
@@ -259,6 +260,8 @@ impl GraphCore for GraphAgdb {
         // More code here pls
         // More pls
         // Pls?
+
+        context_index
     }
 
     fn cleanup_dead_nodes(&mut self) {
@@ -267,6 +270,245 @@ impl GraphCore for GraphAgdb {
 
     fn maintain_readable_files(&mut self, maintain: bool) {
         self.maintain_readable_files = maintain;
+        self.write_readable_files();
+    }
+
+    fn sniff_file_types(&mut self, sniff: bool) {
+        self.sniff_file_types = sniff;
+    }
+
+    fn dir_node_limit(&mut self, limit: usize) {
+        self.dir_node_limit = limit;
+    }
+
+    fn vacuum(&mut self) -> Result<VacuumStats, Box<dyn Error>> {
+        let size_before = self.db.size();
+
+        let aliases = self.db.exec(&QueryBuilder::select().aliases().query())?;
+        let ids: Vec<DbId> = aliases.elements.iter().map(|elem| elem.id).collect();
+        let counts = self.db.exec(&QueryBuilder::select().edge_count().ids(ids).query())?;
+
+        let mut orphans: Vec<(DbId, NodePath)> = Vec::new();
+        for (alias, count) in aliases.elements.iter().zip(counts.elements.iter()) {
+            let edge_count = count.values.first().and_then(|v| v.value.to_u64().ok());
+            if edge_count != Some(0) {
+                continue;
+            }
+
+            let path = match alias.values.first().map(|v| v.value.to_string()) {
+                Some(alias) => NodePath::from_alias(&alias),
+                None => continue,
+            };
+            if path == NodePath::root() || path.is_atype() {
+                continue;
+            }
+            if self.open_node(&path).map_or(true, |node| node.persistent()) {
+                continue;
+            }
+            orphans.push((alias.id, path));
+        }
+
+        let mut removed: Vec<DbId> = orphans.iter().map(|(id, _)| *id).collect();
+
+        // Agdb removes the edges of a node along with it, so every edge still
+        // connects two nodes. Its stored source or target can still be stale,
+        // which integrity_check reports as dangling. Contains edges are kept,
+        // as they place their nodes in the tree.
+        let known: HashSet<NodePath> = aliases
+            .elements
+            .iter()
+            .filter(|alias| !removed.contains(&alias.id))
+            .filter_map(|alias| alias.values.first().map(|v| NodePath::from_alias(&v.value.to_string())))
+            .collect();
+        let dangling: Vec<DbId> = self
+            .dangling_edges(&known.iter().collect())?
+            .into_iter()
+            .filter(|(_, edge)| !edge.contains())
+            .map(|(id, _)| id)
+            .collect();
+        let removed_edges = dangling.len();
+        removed.extend(dangling);
+
+        if !removed.is_empty() {
+            self.db.exec_mut(&QueryBuilder::remove().ids(removed).query())?;
+        }
+        self.db.optimize_storage()?;
+
+        let removed_nodes: Vec<NodePath> = orphans.into_iter().map(|(_, path)| path).collect();
+        self.update_readable_files(&removed_nodes);
+
+        Ok(VacuumStats {
+            removed_nodes,
+            removed_edges,
+            size_before,
+            size_after: self.db.size(),
+        })
+    }
+
+    fn integrity_check(&self) -> Vec<IntegrityIssue> {
+        let aliases = match self.db.exec(&QueryBuilder::select().aliases().query()) {
+            Ok(aliases) => aliases,
+            Err(e) => {
+                tracing::warn!("Failed to read aliases: {}", e);
+                return Vec::new();
+            }
+        };
+        let paths: HashMap<DbId, NodePath> = aliases
+            .elements
+            .iter()
+            .filter_map(|elem| {
+                let alias = elem.values.first()?.value.to_string();
+                Some((elem.id, NodePath::from_alias(&alias)))
+            })
+            .collect();
+        let known: HashSet<&NodePath> = paths.values().collect();
+
+        let mut parents: HashMap<DbId, Vec<NodePath>> = HashMap::new();
+        let mut issues: Vec<IntegrityIssue> = match self.dangling_edges(&known) {
+            Ok(dangling) => dangling
+                .into_iter()
+                .map(|(_, edge)| IntegrityIssue::DanglingEdge {
+                    source: edge.source().clone(),
+                    target: edge.target().clone(),
+                })
+                .collect(),
+            Err(e) => {
+                tracing::warn!("Failed to read edges: {}", e);
+                Vec::new()
+            }
+        };
+
+        for (id, path) in paths.iter() {
+            let outgoing = self.db.exec(
+                &QueryBuilder::select()
+                    .ids(
+                        QueryBuilder::search()
+                            .from(*id)
+                            .where_()
+                            .edge()
+                            .and()
+                            .distance(agdb::CountComparison::Equal(1))
+                            .query(),
+                    )
+                    .query(),
+            );
+            let outgoing = match outgoing {
+                Ok(outgoing) => outgoing.elements,
+                Err(_) => continue,
+            };
+
+            for elem in outgoing {
+                let target = elem.to;
+                let edge = match Edge::try_from(elem) {
+                    Ok(edge) => edge,
+                    Err(_) => continue,
+                };
+                if let (true, Some(target)) = (edge.contains(), target) {
+                    parents.entry(target).or_default().push(path.clone());
+                }
+            }
+        }
+
+        let mut sorted: Vec<(&DbId, &NodePath)> = paths.iter().collect();
+        sorted.sort_by_key(|(_, path)| path.alias());
+        for (id, path) in sorted {
+            if *path == NodePath::root() {
+                continue;
+            }
+            let mut node_parents = match parents.get(id) {
+                None => {
+                    issues.push(IntegrityIssue::MissingParent(path.clone()));
+                    continue;
+                }
+                Some(node_parents) => node_parents.clone(),
+            };
+            node_parents.sort_by_key(|parent| parent.alias());
+
+            // The same parent twice is a duplicated edge, not a second parent
+            let edge_count = node_parents.len();
+            node_parents.dedup();
+            if node_parents.len() < edge_count {
+                for parent in node_parents.iter() {
+                    if parents[id].iter().filter(|p| *p == parent).count() > 1 {
+                        issues.push(IntegrityIssue::DuplicateEdge {
+                            source: parent.clone(),
+                            target: path.clone(),
+                        });
+                    }
+                }
+            }
+            if node_parents.len() > 1 {
+                issues.push(IntegrityIssue::MultipleParents {
+                    path: path.clone(),
+                    parents: node_parents,
+                });
+            }
+        }
+
+        issues
+    }
+
+    fn repair_missing_parents(&mut self) -> Result<Vec<NodePath>, Box<dyn Error>> {
+        let mut repaired = Vec::new();
+
+        for issue in self.integrity_check() {
+            let path = match issue {
+                IntegrityIssue::MissingParent(path) => path,
+                _ => continue,
+            };
+
+            let parent = path
+                .parent()
+                .filter(|parent| self.open_node(parent).is_ok())
+                .unwrap_or_else(NodePath::user_root);
+            self.autoparent_nodes(&parent, &path)?;
+            repaired.push(path);
+        }
+
+        Ok(repaired)
+    }
+
+    fn repair_duplicate_edges(&mut self) -> Result<Vec<NodePath>, Box<dyn Error>> {
+        let mut repaired = Vec::new();
+
+        for issue in self.integrity_check() {
+            let (source, target) = match issue {
+                IntegrityIssue::DuplicateEdge { source, target } => (source, target),
+                _ => continue,
+            };
+
+            let source_id = self.open_node(&source)?.id();
+            let target_id = self
+                .open_node(&target)?
+                .id()
+                .ok_or_else(|| format!("Node {} has no id", target.alias()))?;
+            let incoming = self.db.exec(
+                &QueryBuilder::select()
+                    .ids(
+                        QueryBuilder::search()
+                            .to(target_id)
+                            .where_()
+                            .edge()
+                            .and()
+                            .distance(agdb::CountComparison::Equal(1))
+                            .query(),
+                    )
+                    .query(),
+            )?;
+            let duplicates: Vec<DbId> = incoming
+                .elements
+                .into_iter()
+                .filter(|elem| elem.from == source_id)
+                .filter(|elem| Edge::try_from(elem.clone()).is_ok_and(|edge| edge.contains()))
+                .map(|elem| elem.id)
+                .skip(1)
+                .collect();
+
+            self.db.exec_mut(&QueryBuilder::remove().ids(duplicates).query())?;
+            repaired.push(target);
+        }
+
+        Ok(repaired)
     }
 
     fn get_all_aliases(&self) -> Vec<String> {
@@ -280,9 +522,169 @@ impl GraphCore for GraphAgdb {
                 all
             },
             Err(err) => {
-                println!("Error: {}", err);
+                tracing::error!("Error: {}", err);
                 vec![]
             }
         }
     }
+
+    fn get_node_count(&self) -> usize {
+        match self.db.exec(&QueryBuilder::select().node_count().query()) {
+            Ok(count) => count
+                .elements
+                .first()
+                .and_then(|elem| elem.values.first())
+                .map_or(0, |kv| kv.value.to_u64().unwrap_or(0) as usize),
+            Err(err) => {
+                tracing::warn!("Failed to count nodes: {}", err);
+                0
+            }
+        }
+    }
+
+    /// Counts the ids of the edges, without reading any of their values.
+    fn get_edge_count(&self) -> usize {
+        let edges = self
+            .db
+            .exec(&QueryBuilder::search().elements().where_().edge().query());
+        match edges {
+            Ok(edges) => edges.elements.len(),
+            Err(err) => {
+                tracing::warn!("Failed to count edges: {}", err);
+                0
+            }
+        }
+    }
+
+    fn get_node_count_by_type(&self) -> HashMap<String, usize> {
+        let ntypes = self.db.exec(
+            &QueryBuilder::select()
+                .values(vec!["ntype".into()])
+                .ids(QueryBuilder::search().elements().where_().node().query())
+                .query(),
+        );
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        match ntypes {
+            Ok(ntypes) => {
+                for kv in ntypes.elements.iter().filter_map(|elem| elem.values.first()) {
+                    if let Ok(ntype) = NodeType::try_from(kv.value.clone()) {
+                        *counts.entry(ntype.name().to_string()).or_insert(0) += 1;
+                    }
+                }
+            }
+            Err(err) => tracing::warn!("Failed to count node types: {}", err),
+        }
+        counts
+    }
+}
+
+impl GraphAgdb {
+    /// Edges whose stored source or target isn't the path of any known node,
+    /// such as after an interrupted move, along with their ids.
+    fn dangling_edges(&self, known: &HashSet<&NodePath>) -> Result<Vec<(DbId, Edge)>, Box<dyn Error>> {
+        let edges = self.db.exec(
+            &QueryBuilder::select()
+                .ids(QueryBuilder::search().elements().where_().edge().query())
+                .query(),
+        )?;
+
+        Ok(edges
+            .elements
+            .into_iter()
+            .filter_map(|elem| {
+                let id = elem.id;
+                Edge::try_from(elem).ok().map(|edge| (id, edge))
+            })
+            .filter(|(_, edge)| !known.contains(edge.source()) || !known.contains(edge.target()))
+            .collect())
+    }
+
+    /// Paths of the nodes in the db that are directly connected to the node,
+    /// resolved in a single query. Empty if the node isn't in the db.
+    pub(crate) fn indexed_connections(&self, path: &NodePath) -> HashSet<NodePath> {
+        let search = self.db.exec(
+            &QueryBuilder::select()
+                .ids(
+                    QueryBuilder::search()
+                        .from(path.alias())
+                        .where_()
+                        .distance(agdb::CountComparison::Equal(2))
+                        .query(),
+                )
+                .query(),
+        );
+
+        match search {
+            Ok(result) => result
+                .elements
+                .into_iter()
+                .filter_map(|elem| Node::try_from(elem).ok())
+                .map(|node| node.path())
+                .collect(),
+            Err(_) => HashSet::new(),
+        }
+    }
+}
+
+impl GraphAgdb {
+    /// Constructor with the storage given directly, which also allows keeping
+    /// the db in memory. See GraphCore::new.
+    pub fn new_with_storage(name: &str, root_path: PathBuf, storage: StoragePath) -> Self {
+        let storage_path = match storage.clone() {
+            StoragePath::Custom(path) => Some(path),
+            StoragePath::Default => Some(
+                directories::ProjectDirs::from("com", "teodosin_labs", "karta_server")
+                    .unwrap()
+                    .data_dir()
+                    .to_path_buf(),
+            ),
+            StoragePath::Memory => None,
+        };
+
+        let (db, open_existing) = match storage_path {
+            Some(storage_path) => {
+                let storage_dir = storage_path.join(KARTA_DIR_NAME);
+
+                // Create the path if it doesn't exist
+                if !storage_dir.exists() {
+                    std::fs::create_dir_all(&storage_dir).expect("Failed to create storage path");
+                }
+
+                let db_path = storage_dir.join(format!("{}.agdb", name));
+
+                // Check if the database already exists
+                let open_existing = db_path.exists();
+
+                let db = agdb::Db::new(db_path.to_str().unwrap()).expect("Failed to create new db");
+                (GraphDb::File(db), open_existing)
+            }
+            None => {
+                let db = agdb::DbMemory::new(name).expect("Failed to create new db");
+                (GraphDb::Mem(db), false)
+            }
+        };
+
+        let mut giraphe = GraphAgdb {
+
+            name: name.to_string(),
+            db,
+            root_path: root_path.into(),
+            storage_path: storage,
+            maintain_readable_files: false,
+            sniff_file_types: false,
+            dir_node_limit: DEFAULT_DIR_NODE_LIMIT,
+        };
+
+        // Also run for an existing db, to restore any archetypes that are
+        // missing or detached from the root.
+        giraphe.init_archetype_nodes();
+        if open_existing {
+            // Only reported here, repairs are up to the user
+            for issue in giraphe.integrity_check() {
+                tracing::warn!("Integrity issue: {:?}", issue);
+            }
+        }
+
+        return giraphe;
+    }
 }
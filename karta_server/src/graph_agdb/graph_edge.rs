@@ -1,10 +1,10 @@
-use std::{error::Error, path::PathBuf};
+use std::{collections::HashSet, error::Error, path::PathBuf};
 
-use agdb::{DbElement, QueryBuilder};
+use agdb::{Comparison, CountComparison, DbElement, DbId, QueryBuilder, QueryType};
 
-use crate::{elements, graph_traits::graph_edge::GraphEdge};
+use crate::{elements, graph_traits::{graph_edge::GraphEdge, graph_node::GraphNode}};
 
-use super::{attribute::{Attribute, RESERVED_EDGE_ATTRS}, edge::Edge, node_path::NodePath, GraphAgdb, StoragePath};
+use super::{replace_value, attribute::{Attribute, RESERVED_EDGE_ATTRS}, edge::Edge, node::Node, node_path::NodePath, GraphAgdb, StoragePath};
 
 impl GraphEdge for GraphAgdb {
     fn get_edge_strict(
@@ -48,11 +48,11 @@ impl GraphEdge for GraphAgdb {
         }
         let data_query = data_query.unwrap();
         let data_elem = data_query.elements.first().unwrap();
-        println!("Edge element: {:#?}", data_elem);
+        tracing::trace!("Edge element: {:#?}", data_elem);
 
         let edge = Edge::try_from(data_elem.clone());
 
-        println!("Edge: {:#?}", edge);
+        tracing::trace!("Edge: {:#?}", edge);
 
         match edge {
             Ok(edge) => {
@@ -69,25 +69,360 @@ impl GraphEdge for GraphAgdb {
         source_path: &NodePath,
         target_path: &NodePath,
     ) -> Result<(), Box<dyn Error>> {
-        let alias = source_path.alias();
-        let child_alias = target_path.alias();
+        self.check_edge_ends(source_path, target_path)?;
 
-        todo!()
+        let edge = Edge::new(source_path, target_path);
+        self.db.exec_mut(
+            &QueryBuilder::insert()
+                .edges()
+                .from(source_path.alias())
+                .to(target_path.alias())
+                .values_uniform(&edge)
+                .query(),
+        )?;
+        self.update_readable_files(&[source_path.clone()]);
+        Ok(())
+    }
+
+    fn create_typed_edge(
+        &mut self,
+        source_path: &NodePath,
+        target_path: &NodePath,
+        edge_type: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check_edge_ends(source_path, target_path)?;
+
+        let edge = Edge::new_typed(source_path, target_path, edge_type);
+        self.db.exec_mut(
+            &QueryBuilder::insert()
+                .edges()
+                .from(source_path.alias())
+                .to(target_path.alias())
+                .values_uniform(&edge)
+                .query(),
+        )?;
+        self.update_readable_files(&[source_path.clone()]);
+        Ok(())
+    }
+
+    fn create_undirected_edge(
+        &mut self,
+        source_path: &NodePath,
+        target_path: &NodePath,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check_edge_ends(source_path, target_path)?;
+
+        let edge = Edge::new_undirected(source_path, target_path);
+        self.db.exec_mut(
+            &QueryBuilder::insert()
+                .edges()
+                .from(source_path.alias())
+                .to(target_path.alias())
+                .values_uniform(&edge)
+                .query(),
+        )?;
+        self.update_readable_files(&[source_path.clone()]);
+        Ok(())
+    }
+
+    fn get_edges_by_type(&self, edge_type: &str) -> Result<Vec<Edge>, Box<dyn Error>> {
+        let edges = self.db.exec(
+            &QueryBuilder::select()
+                .ids(
+                    QueryBuilder::search()
+                        .from(NodePath::root().alias())
+                        .where_()
+                        .edge()
+                        .and()
+                        .key("type")
+                        .value(Comparison::Equal(edge_type.into()))
+                        .query(),
+                )
+                .query(),
+        )?;
+
+        let edges = edges
+            .elements
+            .into_iter()
+            .filter_map(|elem| Edge::try_from(elem).ok())
+            .collect();
+        Ok(edges)
+    }
+
+    fn get_edges_between_nodes(&self, paths: &[NodePath]) -> Result<Vec<Edge>, Box<dyn Error>> {
+        let mut edges: Vec<Edge> = Vec::new();
+        for path in paths.iter() {
+            let outgoing = self.db.exec(
+                &QueryBuilder::search()
+                    .from(path.alias())
+                    .where_()
+                    .edge()
+                    .and()
+                    .distance(CountComparison::Equal(1))
+                    .query(),
+            )?;
+
+            let ids: Vec<DbId> = outgoing.elements.iter().map(|e| e.id).collect();
+            if ids.is_empty() {
+                continue;
+            }
+
+            let outgoing = self
+                .db
+                .exec(&QueryBuilder::select().values(vec![]).ids(ids).query())?;
+
+            edges.extend(
+                outgoing
+                    .elements
+                    .into_iter()
+                    .filter_map(|elem| Edge::try_from(elem).ok())
+                    .filter(|edge| paths.contains(edge.target())),
+            );
+        }
+
+        Ok(edges)
+    }
+
+    fn get_edges_between_nodes_filtered(
+        &self,
+        paths: &[NodePath],
+        include_contains: bool,
+        edge_type: Option<&str>,
+    ) -> Result<Vec<Edge>, Box<dyn Error>> {
+        let edges = self
+            .get_edges_between_nodes(paths)?
+            .into_iter()
+            .filter(|edge| include_contains || !edge.contains())
+            .filter(|edge| edge_type.is_none() || edge.edge_type() == edge_type)
+            .collect();
+
+        Ok(edges)
+    }
+
+    fn get_incoming_edges(
+        &self,
+        target: &NodePath,
+        exclude_contains: bool,
+    ) -> Result<Vec<(Node, Edge)>, Box<dyn Error>> {
+        self.open_node(target)?;
+
+        let incoming = self.db.exec(
+            &QueryBuilder::select()
+                .ids(
+                    QueryBuilder::search()
+                        .to(target.alias())
+                        .where_()
+                        .edge()
+                        .and()
+                        .distance(CountComparison::Equal(1))
+                        .query(),
+                )
+                .query(),
+        )?;
+
+        // Undirected edges stored with the target as their source
+        let outgoing = self.db.exec(
+            &QueryBuilder::select()
+                .ids(
+                    QueryBuilder::search()
+                        .from(target.alias())
+                        .where_()
+                        .edge()
+                        .and()
+                        .distance(CountComparison::Equal(1))
+                        .query(),
+                )
+                .query(),
+        )?;
+        let undirected = outgoing
+            .elements
+            .into_iter()
+            .filter_map(|elem| Edge::try_from(elem).ok())
+            .filter(|edge| !edge.directed());
+
+        let mut connections = Vec::new();
+        let edges = incoming.elements.into_iter().filter_map(|elem| Edge::try_from(elem).ok());
+        for edge in edges.chain(undirected) {
+            if exclude_contains && edge.contains() {
+                continue;
+            }
+            let node = self.open_node(edge.other_end(target))?;
+            connections.push((node, edge));
+        }
+
+        Ok(connections)
+    }
+
+    fn set_link_edges(
+        &mut self,
+        source: &NodePath,
+        targets: &[NodePath],
+    ) -> Result<(), Box<dyn Error>> {
+        self.open_node(source)?;
+
+        // Targets that aren't in the db yet are inserted along with their
+        // missing ancestors, in the same transaction as the edges.
+        let mut queries: Vec<QueryType> = Vec::new();
+        let mut created: Vec<NodePath> = Vec::new();
+        for target in targets.iter() {
+            let mut missing = Vec::new();
+            let mut path = target.clone();
+            while !created.contains(&path) && self.open_node(&path).is_err() {
+                let parent = path
+                    .parent()
+                    .ok_or_else(|| format!("Node {} has no parent", path.alias()))?;
+                missing.push(path);
+                path = parent;
+            }
+            for path in missing.into_iter().rev() {
+                let parent = path.parent().unwrap();
+                queries.push(
+                    QueryBuilder::insert()
+                        .nodes()
+                        .aliases(path.alias())
+                        .values(&self.node_for_path(&path, None))
+                        .query()
+                        .into(),
+                );
+                queries.push(
+                    QueryBuilder::insert()
+                        .edges()
+                        .from(parent.alias())
+                        .to(path.alias())
+                        .values_uniform(&Edge::new_cont(&parent, &path))
+                        .query()
+                        .into(),
+                );
+                created.push(path);
+            }
+        }
+
+        let outgoing = self.db.exec(
+            &QueryBuilder::select()
+                .ids(
+                    QueryBuilder::search()
+                        .from(source.alias())
+                        .where_()
+                        .edge()
+                        .and()
+                        .distance(CountComparison::Equal(1))
+                        .query(),
+                )
+                .query(),
+        )?;
+        let links: Vec<Edge> = outgoing
+            .elements
+            .into_iter()
+            .filter_map(|elem| Edge::try_from(elem).ok())
+            .filter(|edge| edge.edge_type() == Some(elements::edge::LINK_EDGE_TYPE))
+            .collect();
+
+        let stale: Vec<DbId> = links
+            .iter()
+            .filter(|edge| !targets.contains(edge.target()))
+            .filter_map(|edge| edge.db_id())
+            .collect();
+        if !stale.is_empty() {
+            queries.push(QueryBuilder::remove().ids(stale).query().into());
+        }
+
+        for target in targets.iter() {
+            if target == source || links.iter().any(|edge| edge.target() == target) {
+                continue;
+            }
+
+            queries.push(
+                QueryBuilder::insert()
+                    .edges()
+                    .from(source.alias())
+                    .to(target.alias())
+                    .values_uniform(&Edge::new_typed(source, target, elements::edge::LINK_EDGE_TYPE))
+                    .query()
+                    .into(),
+            );
+        }
+        self.exec_batch(queries)?;
+
+        created.push(source.clone());
+        self.update_readable_files(&created);
+        Ok(())
     }
 
     /// Changes the parent directory of a node. If the node is physical, it will be moved in the file system.
     /// If the node is virtual, the parent will be changed in the db.
     /// Note that due to the implementation, all children of the node will have to be reindexed, recursively.
+    #[tracing::instrument(level = "debug", skip_all, fields(node = %node_path.alias(), parent = %new_parent_path.alias()))]
     fn reparent_node(
-        &self,
+        &mut self,
         node_path: &NodePath,
         new_parent_path: &NodePath,
     ) -> Result<(), Box<dyn Error>> {
-        // Check if node is in database at all
-        let alias = node_path.alias();
-        let existing = self.db.exec(&QueryBuilder::select().ids(alias).query());
-        
-        todo!()
+        if node_path.is_atype() {
+            return Err("Archetype nodes cannot be reparented".into());
+        }
+
+        // Moving a node under itself or its descendant would create a cycle
+        if node_path == new_parent_path || node_path.is_ancestor_of(new_parent_path) {
+            return Err(format!(
+                "Cannot reparent {} under itself or its descendant {}",
+                node_path.alias(),
+                new_parent_path.alias()
+            )
+            .into());
+        }
+
+        let node = self.open_node(node_path)?;
+        let node_id = node.id().ok_or_else(|| format!("Node {} has no id", node_path.alias()))?;
+        self.open_node(new_parent_path)?;
+
+        let new_path = new_parent_path.join(&node_path.name());
+        if self.open_node(&new_path).is_ok() {
+            return Err(format!("Node already exists at {}", new_path.alias()).into());
+        }
+
+        // Physical nodes are moved in the file system first, so that nothing
+        // changes in the db if the move fails.
+        let physical = node_path.full(&self.root_path).exists();
+        if physical {
+            let new_parent_full = new_parent_path.full(&self.root_path);
+            if !new_parent_full.is_dir() {
+                return Err("Physical nodes can only be moved into directories".into());
+            }
+            self.move_in_file_system(node_path, &new_path)?;
+        }
+
+        // The writes of the move are collected and run in one transaction, so
+        // that the subtree is never left half moved. The file system move is
+        // undone if they fail.
+        let moved = self.subtree_path_rewrites(node, node_path, &new_path).and_then(|rewrites| {
+            let mut queries: Vec<QueryType> = rewrites;
+            // Detach from every old parent. A node should only have one, but a
+            // duplicate contains edge would otherwise leave it with two.
+            let old_contains = self.contains_edge_ids(node_id)?;
+            if !old_contains.is_empty() {
+                queries.push(QueryBuilder::remove().ids(old_contains).query().into());
+            }
+            queries.push(
+                QueryBuilder::insert()
+                    .edges()
+                    .from(new_parent_path.alias())
+                    .to(new_path.alias())
+                    .values_uniform(&Edge::new_cont(new_parent_path, &new_path))
+                    .query()
+                    .into(),
+            );
+            self.exec_batch(queries)
+        });
+
+        if let Err(e) = moved {
+            if physical {
+                self.move_in_file_system(&new_path, node_path)?;
+            }
+            return Err(e);
+        }
+
+        self.move_readable_files(node_path, &new_path);
+        Ok(())
     }
 
     /// Moves an edge and all its attributes to a new source and target. Parent edges can't be reconnected this way,
@@ -106,8 +441,14 @@ impl GraphEdge for GraphAgdb {
     }
 
     /// Delete an edge from the graph. Edges with the attribute "contains" refer to the parent-child relationship
-    /// between nodes and will be ignored. All other attributes will be cleared from them instead.
-    fn delete_edge(&self, edge: Edge) -> Result<(), Box<dyn Error>> {
+    /// between nodes and can't be deleted this way. The edge must have been read from the db.
+    fn delete_edge(&mut self, edge: Edge) -> Result<(), Box<dyn Error>> {
+        if edge.contains() {
+            return Err("Contains edges can't be deleted, reparent the node instead".into());
+        }
+        let id = edge.db_id().ok_or("Edge has not been read from the db")?;
+
+        self.db.exec_mut(&QueryBuilder::remove().ids(id).query())?;
         Ok(())
     }
 
@@ -142,4 +483,260 @@ impl GraphEdge for GraphAgdb {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl GraphAgdb {
+    /// Fails if either end of an edge to be created isn't in the db.
+    fn check_edge_ends(&self, source_path: &NodePath, target_path: &NodePath) -> Result<(), Box<dyn Error>> {
+        let ends = [source_path.clone(), target_path.clone()];
+        let found = self.get_nodes_by_paths(&ends)?;
+        for path in ends.iter() {
+            if !found.contains_key(path) {
+                return Err(format!("Node {} does not exist", path.alias()).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the file or directory of a physical node. Does nothing to the db.
+    pub(crate) fn move_in_file_system(
+        &self,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> Result<(), Box<dyn Error>> {
+        std::fs::rename(old_path.full(&self.root_path), new_path.full(&self.root_path))?;
+        Ok(())
+    }
+
+    /// Changes the path of a node and all its descendants in the db, along with
+    /// the endpoints of their edges. The contains edge from the parent is kept
+    /// and follows the node. Either the whole subtree is rewritten or nothing is.
+    pub(crate) fn rewrite_subtree_paths(
+        &mut self,
+        node: Node,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> Result<(), Box<dyn Error>> {
+        let rewrites = self.subtree_path_rewrites(node, old_path, new_path)?;
+        self.exec_batch(rewrites)?;
+
+        self.move_readable_files(old_path, new_path);
+        Ok(())
+    }
+
+    /// The writes that move a node and all its descendants to a new path, without
+    /// running them. Only reads the db, so that the writes can be run together.
+    pub(crate) fn subtree_path_rewrites(
+        &self,
+        node: Node,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> Result<Vec<QueryType>, Box<dyn Error>> {
+        let descendants = self.get_all_descendants(old_path)?;
+
+        let mut queries: Vec<QueryType> = Vec::new();
+        for moved in std::iter::once(node).chain(descendants) {
+            let moved_path = moved.path();
+            let relative = moved_path
+                .relative_to(old_path)
+                .ok_or_else(|| format!("{} is not under {}", moved_path.alias(), old_path.alias()))?;
+            let updated = if relative.buf().as_os_str().is_empty() {
+                new_path.clone()
+            } else {
+                let relative = relative
+                    .buf()
+                    .to_str()
+                    .ok_or_else(|| format!("Path of {} is not valid UTF-8", moved_path.alias()))?;
+                new_path.join(relative)
+            };
+            let id = moved
+                .id()
+                .ok_or_else(|| format!("Node {} has no id", moved_path.alias()))?;
+
+            queries.push(QueryBuilder::insert().aliases(updated.alias()).ids(id).query().into());
+            queries.extend(replace_value(vec![id], "path", updated.clone().into()));
+            queries.extend(self.edge_endpoint_rewrites(id, &moved_path, &updated)?);
+        }
+        Ok(queries)
+    }
+
+    /// Recomputes the path of a node by following its contains edges up to the
+    /// root, and rewrites its alias and path attribute to match. For repairing a
+    /// node whose stored path drifted from its place in the tree, such as after
+    /// an interrupted move. Returns the corrected path.
+    pub fn reindex_path(&mut self, id: DbId) -> Result<NodePath, Box<dyn Error>> {
+        let node = self.node_by_id(id)?;
+
+        let mut names: Vec<String> = Vec::new();
+        let mut current = node.clone();
+        let mut visited: HashSet<DbId> = HashSet::from([id]);
+        while let Some(parent_id) = self.contains_parent_id(current.id().unwrap())? {
+            if !visited.insert(parent_id) {
+                return Err(format!("Contains edges above {} form a cycle", node.path().alias()).into());
+            }
+            names.push(current.path().name());
+            current = self.node_by_id(parent_id)?;
+        }
+        if current.path() != NodePath::root() {
+            return Err(format!("Node {} is not connected to the root", node.path().alias()).into());
+        }
+
+        let path = names
+            .iter()
+            .rev()
+            .fold(NodePath::root(), |path, name| path.join(name));
+        if path == node.path() {
+            return Ok(path);
+        }
+
+        let mut queries: Vec<QueryType> =
+            vec![QueryBuilder::insert().aliases(path.alias()).ids(id).query().into()];
+        queries.extend(replace_value(vec![id], "path", path.clone().into()));
+        queries.extend(self.edge_endpoint_rewrites(id, &node.path(), &path)?);
+        self.exec_batch(queries)?;
+
+        self.move_readable_files(&node.path(), &path);
+        Ok(path)
+    }
+
+    /// The edges going out of the node, or coming into it.
+    pub(crate) fn adjacent_edges(
+        &self,
+        path: &NodePath,
+        outgoing: bool,
+    ) -> Result<Vec<Edge>, Box<dyn Error>> {
+        let search = if outgoing {
+            QueryBuilder::search()
+                .from(path.alias())
+                .where_()
+                .edge()
+                .and()
+                .distance(CountComparison::Equal(1))
+                .query()
+        } else {
+            QueryBuilder::search()
+                .to(path.alias())
+                .where_()
+                .edge()
+                .and()
+                .distance(CountComparison::Equal(1))
+                .query()
+        };
+        let edges = self.db.exec(&QueryBuilder::select().ids(search).query())?;
+
+        Ok(edges
+            .elements
+            .into_iter()
+            .filter_map(|elem| Edge::try_from(elem).ok())
+            .collect())
+    }
+
+    fn node_by_id(&self, id: DbId) -> Result<Node, Box<dyn Error>> {
+        let node = self.db.exec(&QueryBuilder::select().ids(id).query())?;
+        let elem = node.elements.into_iter().next().ok_or("Node not found")?;
+        Ok(Node::try_from(elem)?)
+    }
+
+    /// The ids of the contains edges coming into the given node.
+    pub(crate) fn contains_edge_ids(&self, id: DbId) -> Result<Vec<DbId>, Box<dyn Error>> {
+        let incoming = self.db.exec(
+            &QueryBuilder::select()
+                .ids(
+                    QueryBuilder::search()
+                        .to(id)
+                        .where_()
+                        .edge()
+                        .and()
+                        .distance(CountComparison::Equal(1))
+                        .query(),
+                )
+                .query(),
+        )?;
+
+        Ok(incoming
+            .elements
+            .into_iter()
+            .filter(|elem| Edge::try_from(elem.clone()).is_ok_and(|edge| edge.contains()))
+            .map(|elem| elem.id)
+            .collect())
+    }
+
+    /// The id of the node that has a contains edge to the given node, if any.
+    pub(crate) fn contains_parent_id(&self, id: DbId) -> Result<Option<DbId>, Box<dyn Error>> {
+        let incoming = self.db.exec(
+            &QueryBuilder::select()
+                .ids(
+                    QueryBuilder::search()
+                        .to(id)
+                        .where_()
+                        .edge()
+                        .and()
+                        .distance(CountComparison::Equal(1))
+                        .query(),
+                )
+                .query(),
+        )?;
+
+        let parent = incoming.elements.into_iter().find_map(|elem| {
+            let from = elem.from;
+            Edge::try_from(elem).ok().filter(|edge| edge.contains()).and(from)
+        });
+        Ok(parent)
+    }
+
+    /// The writes that update the source and target values of the edges
+    /// connected to a node after the node's path has changed.
+    fn edge_endpoint_rewrites(
+        &self,
+        id: DbId,
+        old_path: &NodePath,
+        new_path: &NodePath,
+    ) -> Result<Vec<QueryType>, Box<dyn Error>> {
+        let mut queries: Vec<QueryType> = Vec::new();
+        let outgoing = self.db.exec(
+            &QueryBuilder::search()
+                .from(id)
+                .where_()
+                .distance(CountComparison::Equal(1))
+                .query(),
+        )?;
+        let incoming = self.db.exec(
+            &QueryBuilder::search()
+                .to(id)
+                .where_()
+                .distance(CountComparison::Equal(1))
+                .query(),
+        )?;
+
+        for (edges, key) in [(outgoing, "source"), (incoming, "target")] {
+            let ids: Vec<DbId> = edges.elements.iter().map(|e| e.id).collect();
+            if ids.is_empty() {
+                continue;
+            }
+
+            let edges = self.db.exec(
+                &QueryBuilder::select()
+                    .values(vec![key.into()])
+                    .ids(ids)
+                    .query(),
+            )?;
+
+            let stale: Vec<DbId> = edges
+                .elements
+                .iter()
+                .filter(|e| {
+                    e.values
+                        .iter()
+                        .any(|kv| kv.value == old_path.alias().into())
+                })
+                .map(|e| e.id)
+                .collect();
+
+            if !stale.is_empty() {
+                queries.extend(replace_value(stale, key, new_path.clone().into()));
+            }
+        }
+
+        Ok(queries)
+    }
+}
@@ -1,20 +1,363 @@
-use std::{error::Error, path::PathBuf, vec};
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    error::Error,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    time::SystemTime,
+    vec,
+};
 
-use agdb::{DbElement, DbId, QueryBuilder};
+use agdb::{Comparison, DbElement, DbId, DbValue, QueryBuilder, QueryType};
 
 use crate::{
-    elements::{self, edge::Edge, nodetype::NodeType},
-    graph_traits::graph_node::GraphNode,
+    elements::{self, edge::Edge, nodetype::{NodeType, NodeTypeError}, SysTime},
+    filter::Filter,
+    fs_reader,
+    graph_traits::{
+        graph_edge::GraphEdge,
+        graph_node::{GraphNode, MergePreference, SearchResult},
+    },
     prelude::GraphCore,
+    vault::KARTA_DIR_NAME,
 };
 
 use super::{
-    attribute::{Attribute, RelativePosition, RESERVED_NODE_ATTRS},
+    attribute::{AttrValue, Attribute, RelativePosition, RESERVED_NODE_ATTRS, TEXT_FILE_ATTR},
     node::Node,
     node_path::NodePath,
-    GraphAgdb, StoragePath,
+    replace_value, GraphAgdb, StoragePath,
 };
 
+/// A node or file that may be among the most recently modified. The type of an
+/// unindexed file is only read once it is known to be in the results.
+struct RecentCandidate {
+    path: NodePath,
+    alias: String,
+    ntype: Option<NodeType>,
+    indexed: bool,
+    created_time: SysTime,
+    modified_time: SysTime,
+}
+
+impl RecentCandidate {
+    /// Newest first, with nodes modified in the same second sorted by path.
+    fn order(a: &Self, b: &Self) -> Ordering {
+        b.modified_time
+            .as_secs()
+            .cmp(&a.modified_time.as_secs())
+            .then_with(|| a.alias.cmp(&b.alias))
+    }
+}
+
+impl GraphAgdb {
+    /// Opens many nodes at once, keyed by their paths. Paths without a node are
+    /// left out of the map. All the nodes are selected in one query, unless some
+    /// of them are missing, in which case agdb rejects the query and the rest
+    /// are selected one at a time.
+    pub fn get_nodes_by_paths(&self, paths: &[NodePath]) -> Result<HashMap<NodePath, Node>, Box<dyn Error>> {
+        let mut nodes = HashMap::new();
+        if paths.is_empty() {
+            return Ok(nodes);
+        }
+
+        let aliases: Vec<String> = paths.iter().map(|path| path.alias()).collect();
+        let elements = match self.db.exec(&QueryBuilder::select().ids(aliases).query()) {
+            Ok(found) => found.elements,
+            Err(_) => paths
+                .iter()
+                .filter_map(|path| self.db.exec(&QueryBuilder::select().ids(path.alias()).query()).ok())
+                .flat_map(|found| found.elements)
+                .collect(),
+        };
+
+        for element in elements {
+            let node = Node::try_from(element)?;
+            nodes.insert(node.path(), node);
+        }
+        Ok(nodes)
+    }
+
+    /// The indexed nodes whose attribute of the given name passes all of the
+    /// comparisons, sorted by path. Values are compared as agdb compares them,
+    /// so a value of another type than the one compared to never passes both
+    /// a lower and an upper bound.
+    fn find_by_attribute_comparisons(
+        &self,
+        name: &str,
+        comparisons: Vec<Comparison>,
+    ) -> Result<Vec<Node>, Box<dyn Error>> {
+        let mut condition = QueryBuilder::search()
+            .from(NodePath::root().alias())
+            .where_()
+            .node();
+        for comparison in comparisons {
+            condition = condition.and().key(name).value(comparison);
+        }
+
+        let found = self
+            .db
+            .exec(&QueryBuilder::select().ids(condition.query()).query())?;
+
+        let mut nodes: Vec<Node> = found
+            .elements
+            .into_iter()
+            .filter_map(|elem| Node::try_from(elem).ok())
+            .filter(|node| !node.path().is_atype())
+            .collect();
+        nodes.sort_by_key(|node| node.path().alias());
+        Ok(nodes)
+    }
+
+    /// A name under the parent that no node has yet. If the name is taken, a
+    /// number is added to its stem, so "notes.txt" becomes "notes_2.txt".
+    pub(crate) fn generate_unique_name(&self, parent: &NodePath, name: &str) -> String {
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+            _ => (name, None),
+        };
+
+        let mut candidate = name.to_string();
+        let mut suffix = 2;
+        while self.open_node(&parent.join(&candidate)).is_ok() {
+            candidate = match extension {
+                Some(extension) => format!("{}_{}.{}", stem, suffix, extension),
+                None => format!("{}_{}", stem, suffix),
+            };
+            suffix += 1;
+        }
+        candidate
+    }
+
+    /// The attributes that a copy of the node gets. Reserved attributes, which
+    /// describe the node itself, are left out, and so is the backing file of a
+    /// text node, which the copy would otherwise share with the original.
+    fn copied_attributes(node: &Node) -> Vec<Attribute> {
+        node.attributes()
+            .into_iter()
+            .filter(|attr| !RESERVED_NODE_ATTRS.contains(&attr.name.as_str()))
+            .filter(|attr| attr.name != TEXT_FILE_ATTR)
+            .collect()
+    }
+
+    /// A new node for the path, not yet inserted. Files and directories in the
+    /// file system get their type, size and times from it, other paths get the
+    /// given type, or Other if none is given.
+    pub(crate) fn node_for_path(&self, path: &NodePath, ntype: Option<NodeType>) -> Node {
+        let full_path = path.full(&self.root_path);
+
+        // Determine type of node. If not specified, it's an Other node.
+        let mut ntype = match ntype {
+            Some(ntype) => ntype,
+            None => NodeType::other(),
+        };
+
+        // Check if the node is physical in the file system.
+        let is_file = full_path.exists() && !full_path.is_dir();
+        let is_dir = full_path.is_dir();
+
+        if is_file {
+            ntype = fs_reader::file_node_type(&full_path, self.sniff_file_types);
+        } else if is_dir {
+            ntype = NodeType::new("Directory".to_string());
+        }
+
+        let mut node = Node::new(path, ntype);
+        if is_file {
+            node.insert_attributes(fs_reader::file_metadata_attributes(&full_path));
+        }
+        if is_file || is_dir {
+            if let Ok(meta) = std::fs::metadata(&full_path) {
+                let (created, modified) = fs_reader::file_times(&meta);
+                node.set_times(created, modified);
+            }
+        }
+        node
+    }
+
+    /// Inserts deleted nodes back as they were, with their attributes, flags
+    /// and versions, along with the edges they had. Edges to nodes that are
+    /// gone by now are left out. Everything is inserted in one batch, so the
+    /// nodes are either all restored or none of them are.
+    pub(crate) fn restore_nodes(&mut self, nodes: &[Node], edges: &[Edge]) -> Result<(), Box<dyn Error>> {
+        let paths: Vec<NodePath> = nodes.iter().map(|node| node.path()).collect();
+        for path in paths.iter() {
+            if self.open_node(path).is_ok() {
+                return Err(format!("Node {} already exists", path.alias()).into());
+            }
+        }
+
+        let mut queries: Vec<QueryType> = Vec::new();
+        for node in nodes.iter() {
+            queries.push(
+                QueryBuilder::insert()
+                    .nodes()
+                    .aliases(node.path().alias())
+                    .values(node)
+                    .query()
+                    .into(),
+            );
+        }
+        for edge in edges.iter() {
+            let ends_exist = [edge.source(), edge.target()]
+                .iter()
+                .all(|end| paths.contains(end) || self.open_node(end).is_ok());
+            if !ends_exist {
+                continue;
+            }
+            queries.push(
+                QueryBuilder::insert()
+                    .edges()
+                    .from(edge.source().alias())
+                    .to(edge.target().alias())
+                    .values_uniform(&edge.relocated(edge.source(), edge.target()))
+                    .query()
+                    .into(),
+            );
+        }
+        self.exec_batch(queries)?;
+
+        self.update_readable_files(&paths);
+        Ok(())
+    }
+
+    /// The writes that merge the absorbed node into the kept one, without
+    /// running them. Checks that the nodes can be merged and only reads the db,
+    /// so that the whole merge can be run as one transaction.
+    pub(crate) fn merge_writes(
+        &self,
+        keep: &NodePath,
+        absorb: &NodePath,
+        preference: MergePreference,
+    ) -> Result<Vec<QueryType>, Box<dyn Error>> {
+        if keep == absorb {
+            return Err("Cannot merge a node into itself".into());
+        }
+        if keep.is_ancestor_of(absorb) || absorb.is_ancestor_of(keep) {
+            return Err("Cannot merge a node with its ancestor".into());
+        }
+        for path in [keep, absorb] {
+            if path.is_atype() {
+                return Err("Archetype nodes cannot be merged".into());
+            }
+            self.open_node(path)?;
+            if path.full(&self.root_path).exists() {
+                return Err(format!("{} is physical and cannot be merged", path.alias()).into());
+            }
+        }
+        let kept = self.open_node(keep)?;
+        let keep_id = kept.id().ok_or_else(|| format!("Node {} has no id", keep.alias()))?;
+
+        let outgoing = self.adjacent_edges(absorb, true)?;
+        let incoming = self.adjacent_edges(absorb, false)?;
+
+        // Children of the absorbed node move under the kept one, along with
+        // their own descendants
+        let mut queries: Vec<QueryType> = Vec::new();
+        for edge in outgoing.iter().filter(|edge| edge.contains()) {
+            let child = edge.target();
+            let moved = keep.join(&child.name());
+            if self.open_node(&moved).is_ok() {
+                return Err(format!("{} already has a child named {}", keep.alias(), child.name()).into());
+            }
+            queries.extend(self.subtree_path_rewrites(self.open_node(child)?, child, &moved)?);
+            queries.push(
+                QueryBuilder::insert()
+                    .edges()
+                    .from(keep.alias())
+                    .to(moved.alias())
+                    .values_uniform(&Edge::new_cont(keep, &moved))
+                    .query()
+                    .into(),
+            );
+        }
+
+        // Edge ends below the absorbed node have moved by the time the edges
+        // are inserted
+        let rebase = |path: &NodePath| -> NodePath {
+            match path.relative_to(absorb) {
+                Some(relative) if !relative.buf().as_os_str().is_empty() => {
+                    keep.join(&relative.buf().to_string_lossy())
+                }
+                _ => path.clone(),
+            }
+        };
+
+        // Associative edges, leaving out the ones between the two nodes and
+        // the ones the kept node already has
+        let kept_outgoing = self.adjacent_edges(keep, true)?;
+        let kept_incoming = self.adjacent_edges(keep, false)?;
+        let mut inserted: Vec<(NodePath, NodePath)> = Vec::new();
+        let outgoing = outgoing
+            .iter()
+            .filter(|edge| !edge.contains())
+            .map(|edge| (edge, keep.clone(), rebase(edge.target())));
+        let incoming = incoming
+            .iter()
+            .filter(|edge| !edge.contains())
+            .map(|edge| (edge, rebase(edge.source()), keep.clone()));
+        for (edge, source, target) in outgoing.chain(incoming) {
+            if source == target {
+                continue;
+            }
+            let exists = if &source == keep {
+                kept_outgoing.iter().any(|existing| existing.target() == &target)
+            } else {
+                kept_incoming.iter().any(|existing| existing.source() == &source)
+            };
+            if exists || inserted.contains(&(source.clone(), target.clone())) {
+                continue;
+            }
+            queries.push(
+                QueryBuilder::insert()
+                    .edges()
+                    .from(source.alias())
+                    .to(target.alias())
+                    .values_uniform(&edge.relocated(&source, &target))
+                    .query()
+                    .into(),
+            );
+            inserted.push((source, target));
+        }
+
+        let kept_attrs = kept.attributes();
+        let absorbed_attrs: Vec<Attribute> = self
+            .open_node(absorb)?
+            .attributes()
+            .into_iter()
+            .filter(|attr| !RESERVED_NODE_ATTRS.contains(&attr.name.as_str()))
+            .filter(|attr| {
+                preference == MergePreference::Absorbed
+                    || !kept_attrs.iter().any(|kept| kept.name == attr.name)
+            })
+            .collect();
+        if !absorbed_attrs.is_empty() {
+            let (replaced, added): (Vec<Attribute>, Vec<Attribute>) = absorbed_attrs
+                .into_iter()
+                .partition(|attr| kept_attrs.iter().any(|kept| kept.name == attr.name));
+            for attr in replaced {
+                queries.extend(replace_value(vec![keep_id], &attr.name, attr.value.into()));
+            }
+            if !added.is_empty() {
+                let values: Vec<agdb::DbKeyValue> = added.iter().map(|attr| attr.into()).collect();
+                queries.push(QueryBuilder::insert().values(vec![values]).ids(keep_id).query().into());
+            }
+            queries.extend(replace_value(vec![keep_id], "version", (kept.version() + 1).into()));
+        }
+
+        // Removing the node removes its remaining edges along with it
+        queries.push(QueryBuilder::remove().ids(absorb.alias()).query().into());
+        Ok(queries)
+    }
+
+    /// The writes that increment the version of the node, to be run in the same
+    /// batch as the change to its attributes.
+    fn version_bump_writes(node: &Node) -> Result<[QueryType; 2], Box<dyn Error>> {
+        let id = node.id().ok_or_else(|| format!("Node {} has no id", node.path().alias()))?;
+        Ok(replace_value(vec![id], "version", (node.version() + 1).into()))
+    }
+}
+
 impl GraphNode for GraphAgdb {
     fn open_node(&self, path: &NodePath) -> Result<Node, Box<dyn Error>> {
         let alias = path.alias();
@@ -35,6 +378,27 @@ impl GraphNode for GraphAgdb {
         }
     }
 
+    fn open_node_by_id(&self, id: DbId) -> Result<Node, Box<dyn Error>> {
+        // Edges have negative ids
+        if id.0 <= 0 {
+            return Err(format!("{} is not the id of a node", id.0).into());
+        }
+        let result = self
+            .db
+            .exec(&QueryBuilder::select().ids(id).query())
+            .map_err(|_| format!("Could not open node {}", id.0))?;
+        let element = result
+            .elements
+            .first()
+            .ok_or_else(|| format!("Could not open node {}", id.0))?
+            .clone();
+        if !element.values.iter().any(|v| v.key == "path".into()) {
+            return Err(format!("{} is not the id of a node", id.0).into());
+        }
+        Node::try_from(element).map_err(|_| format!("{} is not the id of a node", id.0).into())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, fields(path = %path.alias()))]
     fn open_node_connections(&self, path: &NodePath) -> Vec<(Node, Edge)> {
         // Step 1: Check if the node is a physical node in the file system.
         // Step 2: Check if the node exists in the db.
@@ -55,7 +419,7 @@ impl GraphNode for GraphAgdb {
         let mut edge_ids: Vec<DbId> = Vec::new();
 
         // Links from node
-        println!("Searching for links from node {}", as_str);
+        tracing::trace!("Searching for links from node {}", as_str);
         let links = self.db.exec(
             &QueryBuilder::search()
                 .from(path.alias())
@@ -72,7 +436,7 @@ impl GraphNode for GraphAgdb {
                         edge_ids.push(elem.id);
                     } else if elem.id.0 > 0 {
                         // Is node
-                        println!("Link: {:?}", elem);
+                        tracing::trace!("Link: {:?}", elem);
                         node_ids.push(elem.id);
                     }
                 }
@@ -97,11 +461,11 @@ impl GraphNode for GraphAgdb {
                         edge_ids.push(elem.id);
                     } else if elem.id.0 > 0 {
                         // Is node
-                        println!("Backlink: {:?}", elem);
+                        tracing::trace!("Backlink: {:?}", elem);
                         let balias = self
                             .db
                             .exec(&QueryBuilder::select().aliases().ids(elem.id).query());
-                        println!("balias: {:?}", balias);
+                        tracing::trace!("balias: {:?}", balias);
                         node_ids.push(elem.id);
                     }
                 }
@@ -129,7 +493,7 @@ impl GraphNode for GraphAgdb {
             .filter_map(|node| {
                 let node = Node::try_from(node.clone()).unwrap();
 
-                println!("Returning node {:?}", node.path());
+                tracing::trace!("Returning node {:?}", node.path());
                 // Ignore the original node
                 if node.path() == *path {
                     return None;
@@ -148,7 +512,7 @@ impl GraphNode for GraphAgdb {
                     .unwrap();
                 let edge = Edge::try_from(edge.clone()).unwrap();
 
-                println!("Nodes: {:?}", node.path());
+                tracing::trace!("Nodes: {:?}", node.path());
                 Some((node, edge))
             })
             .collect();
@@ -156,50 +520,327 @@ impl GraphNode for GraphAgdb {
         connections
     }
 
-    fn create_node_by_path(
-        &mut self,
+    fn connections_hash(&self, path: &NodePath) -> u64 {
+        // The same searches as open_node_connections, but the raw elements are
+        // hashed instead of being paired into nodes and edges
+        let mut elements: Vec<DbElement> = Vec::new();
+        for search in [
+            QueryBuilder::search()
+                .from(path.alias())
+                .where_()
+                .distance(agdb::CountComparison::LessThanOrEqual(2))
+                .query(),
+            QueryBuilder::search()
+                .to(path.alias())
+                .where_()
+                .distance(agdb::CountComparison::LessThanOrEqual(2))
+                .query(),
+        ] {
+            if let Ok(result) = self.db.exec(&QueryBuilder::select().values(vec![]).ids(search).query()) {
+                elements.extend(result.elements);
+            }
+        }
+        elements.sort();
+        elements.dedup();
+
+        let mut hasher = DefaultHasher::new();
+        for element in elements.iter() {
+            element.id.0.hash(&mut hasher);
+            element.from.map(|id| id.0).hash(&mut hasher);
+            element.to.map(|id| id.0).hash(&mut hasher);
+            for kv in element.values.iter() {
+                kv.key.hash(&mut hasher);
+                kv.value.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    fn get_all_descendants(&self, path: &NodePath) -> Result<Vec<Node>, Box<dyn Error>> {
+        // Only follow contains edges, and nodes so that the search can get past
+        // the starting node.
+        let search = self.db.exec(
+            &QueryBuilder::select()
+                .ids(
+                    QueryBuilder::search()
+                        .from(path.alias())
+                        .where_()
+                        .node()
+                        .and()
+                        .not()
+                        .ids(path.alias())
+                        .and()
+                        .beyond()
+                        .where_()
+                        .keys(vec!["contains".into()])
+                        .or()
+                        .node()
+                        .query(),
+                )
+                .query(),
+        )?;
+
+        let descendants = search
+            .elements
+            .into_iter()
+            .filter_map(|elem| Node::try_from(elem).ok())
+            .collect();
+
+        Ok(descendants)
+    }
+
+    fn is_ancestor_of(&self, ancestor: &NodePath, descendant: &NodePath) -> bool {
+        match self.get_all_descendants(ancestor) {
+            Ok(descendants) => descendants.iter().any(|node| node.path() == *descendant),
+            Err(_) => false,
+        }
+    }
+
+    fn get_descendants_to_depth(
+        &self,
         path: &NodePath,
-        ntype: Option<NodeType>,
-    ) -> Result<Node, Box<dyn Error>> {
-        let full_path = path.full(&self.root_path);
-        let alias = path.alias();
+        max_depth: usize,
+    ) -> Result<Vec<Node>, Box<dyn Error>> {
+        let node = self.open_node(path)?;
+        if max_depth == 0 {
+            return Ok(vec![node]);
+        }
 
-        // Check if the node already exists in the db.
-        // If it does, don't insert it, and return an error.
-        // Possibly redundant, unless used for updating an existing node.
-        let existing = self
-            .db
-            .exec(&QueryBuilder::select().ids(alias.clone()).query());
+        // Every level is two steps away: one for the edge, one for the node.
+        let search = self.db.exec(
+            &QueryBuilder::select()
+                .ids(
+                    QueryBuilder::search()
+                        .from(path.alias())
+                        .where_()
+                        .node()
+                        .and()
+                        .not()
+                        .ids(path.alias())
+                        .and()
+                        .distance(agdb::CountComparison::LessThanOrEqual(2 * max_depth as u64))
+                        .and()
+                        .beyond()
+                        .where_()
+                        .keys(vec!["contains".into()])
+                        .or()
+                        .node()
+                        .query(),
+                )
+                .query(),
+        )?;
+
+        let mut nodes = vec![node];
+        nodes.extend(
+            search
+                .elements
+                .into_iter()
+                .filter_map(|elem| Node::try_from(elem).ok()),
+        );
 
-        match existing {
-            Ok(_) => {
-                // return Err("node already exists".into());
+        Ok(nodes)
+    }
+
+    fn find_by_attribute(&self, name: &str, value: &AttrValue) -> Result<Vec<Node>, Box<dyn Error>> {
+        let value: DbValue = value.clone().into();
+        self.find_by_attribute_comparisons(name, vec![Comparison::Equal(value)])
+    }
+
+    fn find_by_attribute_range(&self, name: &str, min: f64, max: f64) -> Result<Vec<Node>, Box<dyn Error>> {
+        if min.is_nan() || max.is_nan() {
+            return Err("Range bounds must be numbers".into());
+        }
+
+        let mut nodes = self.find_by_attribute_comparisons(
+            name,
+            vec![
+                Comparison::GreaterThanOrEqual(min.into()),
+                Comparison::LessThanOrEqual(max.into()),
+            ],
+        )?;
+
+        // Uints are stored as u64 and don't compare with floats, so they get bounds of their own
+        let (uint_min, uint_max) = (min.max(0.0).ceil(), max.floor());
+        if uint_max >= 0.0 && uint_min <= uint_max {
+            nodes.extend(self.find_by_attribute_comparisons(
+                name,
+                vec![
+                    Comparison::GreaterThanOrEqual((uint_min as u64).into()),
+                    Comparison::LessThanOrEqual((uint_max as u64).into()),
+                ],
+            )?);
+            nodes.sort_by_key(|node| node.path().alias());
+        }
+        Ok(nodes)
+    }
+
+    fn recent_nodes(&self, limit: usize) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Only the values that the results need are read
+        let indexed = self.db.exec(
+            &QueryBuilder::select()
+                .values(vec![
+                    "path".into(),
+                    "ntype".into(),
+                    "created_time".into(),
+                    "modified_time".into(),
+                ])
+                .ids(QueryBuilder::search().from(NodePath::root().alias()).where_().node().query())
+                .query(),
+        )?;
+
+        let mut candidates: Vec<RecentCandidate> = Vec::new();
+        let mut seen: HashSet<NodePath> = HashSet::new();
+        for elem in indexed.elements {
+            let value = |key: &str| {
+                elem.values
+                    .iter()
+                    .find(|kv| kv.key == key.into())
+                    .map(|kv| kv.value.clone())
+                    .ok_or_else(|| format!("Node {} has no {}", elem.id.0, key))
+            };
+            let path = NodePath::try_from(value("path")?)?;
+            seen.insert(path.clone());
+            if path.is_atype() {
+                continue;
             }
-            Err(_e) => {
-                // Node doesn't exist, proceed to insertion
+            candidates.push(RecentCandidate {
+                alias: path.alias(),
+                path,
+                ntype: Some(NodeType::try_from(value("ntype")?)?),
+                indexed: true,
+                created_time: SysTime::try_from(value("created_time")?)?,
+                modified_time: SysTime::try_from(value("modified_time")?)?,
+            });
+        }
+
+        // Files and directories that aren't in the db yet
+        for entry_path in fs_reader::get_all_paths(&self.root_path) {
+            let path = NodePath::from_dir_path(&self.root_path, &entry_path);
+            if seen.contains(&path) {
+                continue;
             }
+            let meta = match std::fs::metadata(&entry_path) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            let (created, modified) = fs_reader::file_times(&meta);
+            candidates.push(RecentCandidate {
+                alias: path.alias(),
+                path,
+                ntype: if meta.is_dir() { Some(NodeType::dir()) } else { None },
+                indexed: false,
+                created_time: SysTime::from(created),
+                modified_time: SysTime::from(modified),
+            });
         }
 
-        // Determine type of node. If not specified, it's an Other node.
-        let mut ntype = match ntype {
-            Some(ntype) => ntype,
-            None => NodeType::other(),
-        };
+        // Only the newest are sorted
+        if candidates.len() > limit {
+            candidates.select_nth_unstable_by(limit - 1, RecentCandidate::order);
+            candidates.truncate(limit);
+        }
+        candidates.sort_by(RecentCandidate::order);
+
+        Ok(candidates
+            .into_iter()
+            .map(|candidate| SearchResult {
+                ntype: candidate.ntype.unwrap_or_else(|| {
+                    fs_reader::file_node_type(&candidate.path.full(&self.root_path), self.sniff_file_types)
+                }),
+                path: candidate.path,
+                indexed: candidate.indexed,
+                created_time: candidate.created_time,
+                modified_time: candidate.modified_time,
+            })
+            .collect())
+    }
 
-        // Check if the node is physical in the file system.
-        // If it is, check if it exists in the db.
-        let is_file = full_path.exists() && !full_path.is_dir();
-        let is_dir = full_path.is_dir();
+    fn search_nodes(
+        &self,
+        query: &str,
+        filter: &Filter,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        let query = query.to_lowercase();
+        let matches_query = |path: &NodePath| path.alias().to_lowercase().contains(&query);
+
+        // Indexed nodes
+        let all_nodes = self.db.exec(
+            &QueryBuilder::select()
+                .ids(QueryBuilder::search().from(NodePath::root().alias()).where_().node().query())
+                .query(),
+        )?;
+
+        let mut results: Vec<SearchResult> = all_nodes
+            .elements
+            .into_iter()
+            .filter_map(|elem| Node::try_from(elem).ok())
+            .filter(|node| !node.path().is_atype() && matches_query(&node.path()))
+            .map(|node| SearchResult {
+                path: node.path(),
+                ntype: node.ntype_name(),
+                indexed: true,
+                created_time: node.created_time(),
+                modified_time: node.modified_time(),
+            })
+            .collect();
+        let indexed: HashSet<NodePath> = results.iter().map(|r| r.path.clone()).collect();
 
-        if is_file {
-            ntype = NodeType::new("File".to_string());
-        } else if is_dir {
-            ntype = NodeType::new("Directory".to_string());
+        // Files and directories that aren't in the db yet
+        for entry_path in fs_reader::get_all_paths(&self.root_path) {
+            let path = NodePath::from_dir_path(&self.root_path, &entry_path);
+            if !matches_query(&path) || indexed.contains(&path) {
+                continue;
+            }
+            let meta = match std::fs::metadata(&entry_path) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+
+            let (created, modified) = fs_reader::file_times(&meta);
+
+            results.push(SearchResult {
+                path,
+                ntype: if meta.is_dir() {
+                    NodeType::dir()
+                } else {
+                    fs_reader::file_node_type(&entry_path, self.sniff_file_types)
+                },
+                indexed: false,
+                created_time: SysTime::from(created),
+                modified_time: SysTime::from(modified),
+            });
+        }
+
+        results.retain(|r| {
+            filter.matches_ntype(&r.ntype) && filter.matches_times(&r.created_time, &r.modified_time)
+        });
+        results.sort_by(|a, b| a.path.alias().cmp(&b.path.alias()));
+        results.truncate(limit);
+
+        Ok(results)
+    }
+
+    fn create_node_by_path(
+        &mut self,
+        path: &NodePath,
+        ntype: Option<NodeType>,
+    ) -> Result<Node, Box<dyn Error>> {
+        let alias = path.alias();
+
+        // An existing node is returned as it is. Inserting it again would reset
+        // its version, persistent flag and times.
+        if let Ok(existing) = self.open_node(path) {
+            return Ok(existing);
         }
 
-        let node = Node::new(&path.clone(), ntype);
+        let node = self.node_for_path(path, ntype);
 
-        println!("Creating node: {:?}", node.path());
+        tracing::debug!("Creating node: {:?}", node.path());
 
         let nodeqr = self.db.exec_mut(
             &QueryBuilder::insert()
@@ -213,23 +854,31 @@ impl GraphNode for GraphAgdb {
             Ok(nodeqr) => {
                 let node_elem = &nodeqr.elements[0];
                 let nid = node_elem.id;
+                self.update_readable_files(&[path.clone()]);
                 // If parent is not root, check if the parent node already exists in the db.
-                // If not, call this function recursively.
+                // If not, call this function recursively. Existing ancestors are left as
+                // they are, so that creating a child doesn't reset its parent.
                 let parent_path = path.parent();
                 match parent_path {
                     Some(parent_path) => {
                         if parent_path.parent().is_some() {
-                            println!("About to insert parent node: {:?}", parent_path);
-
-                            let n = self.create_node_by_path(&parent_path, Some(NodeType::other()));
-
-                            match n {
-                                Ok(n) => {
-                                    let parent_path = n.path();
-                                    self.autoparent_nodes(&parent_path, &path);
+                            let parent = match self.open_node(&parent_path) {
+                                Ok(parent) => Ok(parent),
+                                Err(_) => {
+                                    tracing::trace!("About to insert parent node: {:?}", parent_path);
+                                    self.create_node_by_path(&parent_path, Some(NodeType::other()))
+                                }
+                            };
+
+                            match parent {
+                                Ok(parent) => {
+                                    // A node that already existed keeps its contains edge
+                                    if self.contains_parent_id(nid)?.is_none() {
+                                        self.autoparent_nodes(&parent.path(), &path);
+                                    }
                                 }
                                 Err(e) => {
-                                    println!("Failed to insert parent node: {}", e);
+                                    tracing::error!("Failed to insert parent node: {}", e);
                                 }
                             }
                         }
@@ -243,7 +892,7 @@ impl GraphNode for GraphAgdb {
                 }
             }
             Err(e) => {
-                println!("Failed to insert node: {}", e);
+                tracing::error!("Failed to insert node: {}", e);
                 Err(e.into())
             }
         }
@@ -258,6 +907,8 @@ impl GraphNode for GraphAgdb {
         name: &str,
         ntype: Option<NodeType>,
     ) -> Result<Node, Box<dyn Error>> {
+        NodePath::validate_name(name)?;
+
         let parent_path = parent_path.unwrap_or_else(|| NodePath::new("".into()));
 
         let rel_path = if parent_path.buf().as_os_str().is_empty() {
@@ -281,6 +932,7 @@ impl GraphNode for GraphAgdb {
     /// those files would constantly be at a risk of getting reindexed, so this
     /// should probably still be implemented, unless we want to just mark nodes as deleted
     /// but never actually delete them, which seems like a smelly solution to me.
+    #[tracing::instrument(level = "debug", skip_all, fields(count = paths.len()))]
     fn delete_nodes(
         &mut self,
         paths: &Vec<NodePath>,
@@ -297,7 +949,10 @@ impl GraphNode for GraphAgdb {
             .exec_mut(&QueryBuilder::remove().ids(aliases).query());
 
         match query {
-            Ok(query) => Ok(()),
+            Ok(query) => {
+                self.update_readable_files(paths);
+                Ok(())
+            }
             Err(e) => Err(e.into()),
         }
     }
@@ -328,7 +983,7 @@ impl GraphNode for GraphAgdb {
                 return Ok(vec);
             }
             Err(e) => {
-                println!("Failed to get attributes: {}", e);
+                tracing::warn!("Failed to get attributes: {}", e);
                 return Err(e.to_string().into());
             }
         }
@@ -342,16 +997,7 @@ impl GraphNode for GraphAgdb {
         use RESERVED_NODE_ATTRS;
 
         // Check if the node exists. If it doesn't, errrrrrrr
-        let alias = path.alias();
-        let node = self
-            .db
-            .exec(&QueryBuilder::select().ids(alias.clone()).query());
-        match node {
-            Ok(node) => {}
-            Err(e) => {
-                return Err(e.into());
-            }
-        }
+        let node = self.open_node(path)?;
 
         // Error if attributes is empty
         if attrs.is_empty() {
@@ -369,34 +1015,36 @@ impl GraphNode for GraphAgdb {
                 }
                 return true;
             })
-            .map(|attr| {
-                let attr = (attr.name.clone(), attr.value.clone()).into();
-                return attr;
-            })
-            .collect::<Vec<agdb::DbKeyValue>>();
+            .cloned()
+            .collect::<Vec<Attribute>>();
 
         // Error if filtered attrs is empty
         if filtered_attrs.is_empty() {
             return Err("All insertion requests were for protected attributes".into());
         }
 
-        let added = self.db.exec_mut(
-            &QueryBuilder::insert()
-                .values(vec![filtered_attrs])
-                .ids(alias)
-                .query(),
-        );
+        // Overwritten values are replaced so that the batch can be undone in full
+        let id = node.id().ok_or_else(|| format!("Node {} has no id", path.alias()))?;
+        let existing = node.attributes();
+        let (replaced, added): (Vec<Attribute>, Vec<Attribute>) = filtered_attrs
+            .into_iter()
+            .partition(|attr| existing.iter().any(|old| old.name == attr.name));
+        let mut queries: Vec<QueryType> = Vec::new();
+        for attr in replaced {
+            queries.extend(replace_value(vec![id], &attr.name, attr.value.into()));
+        }
+        if !added.is_empty() {
+            let values: Vec<agdb::DbKeyValue> = added.iter().map(|attr| attr.into()).collect();
+            queries.push(QueryBuilder::insert().values(vec![values]).ids(id).query().into());
+        }
+        queries.extend(Self::version_bump_writes(&node)?);
 
-        println!("Added: {:?}", added);
+        let added = self.exec_batch(queries);
+        tracing::trace!("Added: {:?}", added);
+        added?;
 
-        match added {
-            query_result => {
-                return Ok(());
-            }
-            query_error => {
-                return Err("Failed to insert attribute".into());
-            }
-        }
+        self.update_readable_files(&[path.clone()]);
+        Ok(())
     }
 
     fn delete_node_attrs(
@@ -421,21 +1069,207 @@ impl GraphNode for GraphAgdb {
             return Err("All deletion requests were for protected attributes".into());
         }
 
-        let node = self.db.exec_mut(
-            &QueryBuilder::remove()
-                .values(filtered_attrs)
+        let node = self.open_node(path)?;
+        let mut queries: Vec<QueryType> = vec![QueryBuilder::remove()
+            .values(filtered_attrs)
+            .ids(path.alias())
+            .query()
+            .into()];
+        queries.extend(Self::version_bump_writes(&node)?);
+
+        self.exec_batch(queries)?;
+        self.update_readable_files(&[path.clone()]);
+        Ok(())
+    }
+
+    fn set_node_persistent(&mut self, path: &NodePath, persistent: bool) -> Result<(), Box<dyn Error>> {
+        // Errs if the node doesn't exist
+        self.open_node(path)?;
+
+        self.db.exec_mut(
+            &QueryBuilder::insert()
+                .values(vec![vec![("persistent", persistent).into()]])
                 .ids(path.alias())
                 .query(),
-        );
+        )?;
 
-        match node {
-            Ok(node) => Ok(()),
-            Err(e) => Err(e.into()),
+        Ok(())
+    }
+
+    fn set_node_type(&mut self, path: &NodePath, ntype: NodeType) -> Result<(), Box<dyn Error>> {
+        if path.is_atype() {
+            return Err(Box::new(NodeTypeError::Archetype));
+        }
+        self.open_node(path)?;
+
+        let full_path = path.full(&self.root_path);
+        if full_path.exists() {
+            let is_dir = full_path.is_dir();
+            if is_dir != (ntype == NodeType::dir()) {
+                return Err(Box::new(NodeTypeError::ContradictsFileSystem { ntype, is_dir }));
+            }
+        }
+
+        self.db.exec_mut(
+            &QueryBuilder::insert()
+                .values(vec![vec![
+                    ("ntype", ntype).into(),
+                    ("modified_time", SysTime::from(SystemTime::now())).into(),
+                ]])
+                .ids(path.alias())
+                .query(),
+        )?;
+
+        self.update_readable_files(&[path.clone()]);
+        Ok(())
+    }
+
+    fn rename_node(&mut self, path: &NodePath, new_name: &str) -> Result<NodePath, Box<dyn Error>> {
+        NodePath::validate_name(new_name)?;
+        if path.is_atype() {
+            return Err("Archetype nodes cannot be renamed".into());
+        }
+        if path.name() == new_name {
+            return Ok(path.clone());
         }
+
+        let node = self.open_node(path)?;
+        let parent = path
+            .parent()
+            .ok_or_else(|| format!("Node {} has no parent to rename it in", path.alias()))?;
+
+        let new_path = parent.join(&self.generate_unique_name(&parent, new_name));
+
+        // As with reparenting, the file system goes first and is moved back
+        // if the db can't be updated
+        let physical = path.full(&self.root_path).exists();
+        if physical {
+            if new_path.full(&self.root_path).exists() {
+                return Err(format!("{} already exists on disk", new_path.alias()).into());
+            }
+            self.move_in_file_system(path, &new_path)?;
+        }
+
+        if let Err(e) = self.rewrite_subtree_paths(node, path, &new_path) {
+            if physical {
+                self.move_in_file_system(&new_path, path)?;
+            }
+            return Err(e);
+        }
+
+        Ok(new_path)
+    }
+
+    fn duplicate_node(&mut self, path: &NodePath) -> Result<Node, Box<dyn Error>> {
+        if path.is_atype() {
+            return Err("Archetype nodes cannot be duplicated".into());
+        }
+        let node = self.open_node(path)?;
+        let parent = path
+            .parent()
+            .ok_or_else(|| format!("Node {} has no parent to duplicate it in", path.alias()))?;
+        let descendants = self.get_all_descendants(path)?;
+
+        let copy_path = parent.join(&self.generate_unique_name(&parent, &path.name()));
+
+        // The file system goes first, so that the copies are indexed as physical
+        let full_path = path.full(&self.root_path);
+        if full_path.exists() {
+            let copy_full_path = copy_path.full(&self.root_path);
+            if copy_full_path.exists() {
+                return Err(format!("{} already exists on disk", copy_path.alias()).into());
+            }
+            fs_reader::copy_recursively(&full_path, &copy_full_path)?;
+        }
+
+        // All the copies are inserted in one batch, so that a failure doesn't
+        // leave a partial subtree behind
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut edges: Vec<Edge> = Vec::new();
+        for original in std::iter::once(node).chain(descendants) {
+            let relative = match original.path().relative_to(path) {
+                Some(relative) => relative,
+                None => continue,
+            };
+            let (copied, copied_parent) = if relative.buf().as_os_str().is_empty() {
+                (copy_path.clone(), parent.clone())
+            } else {
+                let copied = copy_path.join(&relative.buf().to_string_lossy());
+                let copied_parent = copied
+                    .parent()
+                    .ok_or_else(|| format!("Node {} has no parent", copied.alias()))?;
+                (copied, copied_parent)
+            };
+
+            let mut copy = self.node_for_path(&copied, Some(original.ntype_name()));
+            copy.insert_attributes(Self::copied_attributes(&original));
+            copy.set_persistent(original.persistent());
+            edges.push(Edge::new_cont(&copied_parent, &copied));
+            nodes.push(copy);
+        }
+
+        let mut queries: Vec<QueryType> = Vec::new();
+        for copy in nodes.iter() {
+            queries.push(
+                QueryBuilder::insert()
+                    .nodes()
+                    .aliases(copy.path().alias())
+                    .values(copy)
+                    .query()
+                    .into(),
+            );
+        }
+        for edge in edges.iter() {
+            queries.push(
+                QueryBuilder::insert()
+                    .edges()
+                    .from(edge.source().alias())
+                    .to(edge.target().alias())
+                    .values_uniform(edge)
+                    .query()
+                    .into(),
+            );
+        }
+        if let Err(e) = self.exec_batch(queries) {
+            let copy_full_path = copy_path.full(&self.root_path);
+            if full_path.is_dir() {
+                std::fs::remove_dir_all(&copy_full_path)?;
+            } else if full_path.exists() {
+                std::fs::remove_file(&copy_full_path)?;
+            }
+            return Err(e);
+        }
+
+        let paths: Vec<NodePath> = nodes.iter().map(|copy| copy.path()).collect();
+        self.update_readable_files(&paths);
+        self.open_node(&copy_path)
     }
 
     /// Merges a vector of nodes into the last one.
-    fn merge_nodes(&mut self, nodes: Vec<NodePath>) -> Result<(), Box<dyn Error>> {
+    fn merge_nodes(
+        &mut self,
+        keep: &NodePath,
+        absorb: &NodePath,
+        preference: MergePreference,
+    ) -> Result<(), Box<dyn Error>> {
+        let queries = self.merge_writes(keep, absorb, preference)?;
+        let children: Vec<NodePath> = self
+            .adjacent_edges(absorb, true)?
+            .iter()
+            .filter(|edge| edge.contains())
+            .map(|edge| edge.target().clone())
+            .collect();
+
+        self.exec_batch(queries)?;
+
+        for child in children.iter() {
+            self.move_readable_files(child, &keep.join(&child.name()));
+        }
+        self.update_readable_files(&[absorb.clone()]);
+        // Edges of other nodes were moved too
+        let mut changed = vec![keep.clone()];
+        changed.extend(self.adjacent_edges(keep, false)?.iter().map(|edge| edge.source().clone()));
+        self.update_readable_files(&changed);
         Ok(())
     }
 
@@ -450,7 +1284,7 @@ impl GraphNode for GraphAgdb {
         parent: &NodePath,
         child: &NodePath,
     ) -> Result<(), Box<dyn Error>> {
-        println!("Autoparenting nodes: {:?} and {:?}", parent, child);
+        tracing::trace!("Autoparenting nodes: {:?} and {:?}", parent, child);
         let edge = Edge::new_cont(parent, child);
 
         let edge = self.db.exec_mut(
@@ -464,7 +1298,7 @@ impl GraphNode for GraphAgdb {
 
         let eid = edge.unwrap().ids();
         let eid = eid.first().unwrap();
-        println!("Id of the edge: {:#?}", eid);
+        tracing::trace!("Id of the edge: {:#?}", eid);
 
         let edge = self
             .db
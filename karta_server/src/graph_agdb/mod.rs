@@ -1,6 +1,6 @@
 use std::{error::Error, path::PathBuf};
 
-use agdb::{CountComparison, DbElement, DbError, DbId, DbUserValue, QueryBuilder, QueryError};
+use agdb::{CountComparison, DbElement, DbError, DbId, DbUserValue, DbValue, QueryBuilder, QueryError, QueryType};
 use crate::graph_traits::{Graph, StoragePath};
 
 use crate::{elements, elements::nodetype::NodeType};
@@ -10,6 +10,10 @@ pub (crate) mod graph_core;
 pub (crate) mod graph_ntype;
 pub (crate) mod graph_node;
 pub (crate) mod graph_edge;
+#[deny(warnings)]
+pub (crate) mod graph_bundle;
+#[deny(warnings)]
+pub (crate) mod readable_files;
 
 /// The main graph structure to be interacted with.
 ///
@@ -20,7 +24,7 @@ pub struct GraphAgdb {
 
     /// AGDB database.
     /// Set to public, though direct access to the db is discouraged.
-    db: agdb::Db,
+    db: GraphDb,
 
     /// Path to the root directory of the graph.
     /// All paths are relative to this root.
@@ -34,19 +38,125 @@ pub struct GraphAgdb {
     /// Whether the library should maintain readable files for the nodes
     /// in the graph.
     ///
-    /// If true, there will be a directory at the storage path which
-    /// mirrors the directory structure starting from the root path.
-    /// TODO: Should this be behind a feature flag?
+    /// If true, the mirror directory in the karta folder of the vault
+    /// mirrors the structure of the graph, with a RON file for each node.
+    /// See readable_files.rs.
     maintain_readable_files: bool,
+
+    /// Whether physical files without a known extension are classified
+    /// by their content. Off by default, since it means reading the files.
+    sniff_file_types: bool,
+
+    /// How many children of a directory are indexed at most.
+    dir_node_limit: usize,
 }
 
 
 /// Agdb has multiple implementations. If the size of the database is small enough, it can be stored in memory.
 /// If the database is too large, it can be stored in a file.
-/// TODO: Not in use currently.
-enum GraphDb {
-    Mem(agdb::Db),
-    File(agdb::DbFile),
+pub enum GraphDb {
+    Mem(agdb::DbMemory),
+    File(agdb::Db),
+}
+
+/// Executes each query with the transaction. A macro because the queries
+/// only share a private trait.
+macro_rules! exec_mut_each {
+    ($transaction:expr, $queries:expr) => {{
+        for query in $queries.iter() {
+            match query {
+                QueryType::InsertAlias(query) => $transaction.exec_mut(query),
+                QueryType::InsertEdges(query) => $transaction.exec_mut(query),
+                QueryType::InsertIndex(query) => $transaction.exec_mut(query),
+                QueryType::InsertNodes(query) => $transaction.exec_mut(query),
+                QueryType::InsertValues(query) => $transaction.exec_mut(query),
+                QueryType::Remove(query) => $transaction.exec_mut(query),
+                QueryType::RemoveAliases(query) => $transaction.exec_mut(query),
+                QueryType::RemoveIndex(query) => $transaction.exec_mut(query),
+                QueryType::RemoveValues(query) => $transaction.exec_mut(query),
+                _ => Err(QueryError::from("Only mutating queries can be run together")),
+            }?;
+        }
+        Ok(())
+    }};
+}
+
+/// A write transaction on either kind of db. Handed to the closure of
+/// [`GraphDb::transaction_mut`].
+pub enum GraphTransaction<'a, 'b> {
+    Mem(&'a mut agdb::DbMemoryTransactionMut<'b>),
+    File(&'a mut agdb::DbTransactionMut<'b>),
+}
+
+impl GraphTransaction<'_, '_> {
+    pub fn exec<T: agdb::Query>(&self, query: &T) -> Result<agdb::QueryResult, QueryError> {
+        match self {
+            GraphTransaction::Mem(t) => t.exec(query),
+            GraphTransaction::File(t) => t.exec(query),
+        }
+    }
+
+    pub fn exec_mut<T: agdb::QueryMut>(&mut self, query: &T) -> Result<agdb::QueryResult, QueryError> {
+        match self {
+            GraphTransaction::Mem(t) => t.exec_mut(query),
+            GraphTransaction::File(t) => t.exec_mut(query),
+        }
+    }
+
+    /// Executes the queries in order. Only mutating queries can be run this way.
+    pub fn exec_mut_all(&mut self, queries: &[QueryType]) -> Result<(), QueryError> {
+        exec_mut_each!(self, queries)
+    }
+}
+
+impl GraphDb {
+    pub fn exec<T: agdb::Query>(&self, query: &T) -> Result<agdb::QueryResult, QueryError> {
+        match self {
+            GraphDb::Mem(db) => db.exec(query),
+            GraphDb::File(db) => db.exec(query),
+        }
+    }
+
+    pub fn exec_mut<T: agdb::QueryMut>(&mut self, query: &T) -> Result<agdb::QueryResult, QueryError> {
+        match self {
+            GraphDb::Mem(db) => db.exec_mut(query),
+            GraphDb::File(db) => db.exec_mut(query),
+        }
+    }
+
+    pub fn optimize_storage(&mut self) -> Result<(), DbError> {
+        match self {
+            GraphDb::Mem(db) => db.optimize_storage(),
+            GraphDb::File(db) => db.optimize_storage(),
+        }
+    }
+
+    /// Size of the db in bytes.
+    pub fn size(&self) -> u64 {
+        match self {
+            GraphDb::Mem(db) => db.size(),
+            GraphDb::File(db) => db.size(),
+        }
+    }
+
+    /// Runs the queries in order as one agdb transaction, so that either all of
+    /// them are applied or, if one of them fails, none of them are. Only
+    /// mutating queries can be run this way.
+    pub fn exec_mut_all(&mut self, queries: &[QueryType]) -> Result<(), QueryError> {
+        self.transaction_mut(|t| t.exec_mut_all(queries))
+    }
+
+    /// Runs the closure in one agdb transaction. Its writes are committed if
+    /// it returns Ok and rolled back if it returns Err.
+    pub fn transaction_mut<T, E: From<QueryError>>(
+        &mut self,
+        mut f: impl FnMut(&mut GraphTransaction) -> Result<T, E>,
+    ) -> Result<T, E> {
+        match self {
+            GraphDb::Mem(db) => db.transaction_mut(|t| f(&mut GraphTransaction::Mem(t))),
+            GraphDb::File(db) => db.transaction_mut(|t| f(&mut GraphTransaction::File(t))),
+        }
+    }
 }
 
 impl Graph for GraphAgdb {}
@@ -55,16 +165,49 @@ impl GraphAgdb {
     /// Direct getter for the db. Not recommended to use. If possible, 
     /// use the other implemented functions. They are the intended way
     /// of interacting with the db.
-    pub fn db(&self) -> &agdb::Db {
+    pub fn db(&self) -> &GraphDb {
         &self.db
     }
 
     /// Direct mutable getter for the db. Not recommended to use. If possible,
     /// use the other implemented functions. They are the intended way
     /// of interacting with the db.
-    pub fn db_mut(&mut self) -> &mut agdb::Db {
+    pub fn db_mut(&mut self) -> &mut GraphDb {
         &mut self.db
     }
+
+    /// Runs the closure in one agdb transaction, for changes that have to be
+    /// made as one unit. The writes are committed if the closure returns Ok
+    /// and rolled back if it returns Err, so a failure leaves the db as it was.
+    /// See replace_value for the one kind of write agdb 0.8 can't fully undo.
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T, Box<dyn Error>>
+    where
+        F: FnMut(&mut GraphTransaction) -> Result<T, Box<dyn Error>>,
+    {
+        self.db.transaction_mut(f)
+    }
+
+    /// Applies changes that span several queries as one unit. Callers read what
+    /// they need first and collect the writes, which are then run in a single
+    /// transaction.
+    pub(crate) fn exec_batch(&mut self, queries: Vec<QueryType>) -> Result<(), Box<dyn Error>> {
+        self.transaction(|t| Ok(t.exec_mut_all(&queries)?))
+    }
+}
+
+/// The writes that set `key` to `value` on the given elements as part of a
+/// batch. agdb 0.8 stops undoing a failed transaction at the first value it
+/// has to put back, so the old value is removed before the new one is
+/// inserted. Both of those are undone in full.
+pub(crate) fn replace_value(ids: Vec<DbId>, key: &str, value: DbValue) -> [QueryType; 2] {
+    [
+        QueryBuilder::remove().values(vec![key.into()]).ids(ids.clone()).query().into(),
+        QueryBuilder::insert()
+            .values_uniform(vec![(key, value).into()])
+            .ids(ids)
+            .query()
+            .into(),
+    ]
 }
 
 // ------------------------------------------------------------------
@@ -0,0 +1,175 @@
+// Human-readable mirror of the db. When enabled, every node is written as a
+// RON file into the mirror directory of the vault, in the same directory
+// structure as the graph, so that the graph can be inspected and diffed in
+// git without opening the db. The mirror is written from the db, never read.
+
+use std::{error::Error, path::PathBuf};
+
+use agdb::QueryBuilder;
+
+use crate::{
+    elements::nodetype::NodeType, fs_reader::write_atomically, graph_traits::graph_node::GraphNode,
+    vault::KartaVault,
+};
+
+use super::{attribute::Attribute, node::Node, node_path::NodePath, GraphAgdb};
+
+/// Name of the mirror directory inside the karta folder of the vault.
+pub const MIRROR_DIR_NAME: &str = "mirror";
+
+/// A node as it is written to its mirror file. Ids and times are left out,
+/// since they would change the file without a change to the graph.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MirroredNode {
+    pub path: NodePath,
+    pub ntype: NodeType,
+    pub attributes: Vec<Attribute>,
+    /// Associative edges going out of the node. Contains edges are implied by
+    /// the directory structure of the mirror.
+    pub edges: Vec<MirroredEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MirroredEdge {
+    pub target: NodePath,
+    pub directed: bool,
+    pub attributes: Vec<Attribute>,
+}
+
+impl GraphAgdb {
+    pub fn mirror_dirpath(&self) -> PathBuf {
+        KartaVault::new(self.root_path.clone())
+            .karta_dirpath()
+            .join(MIRROR_DIR_NAME)
+    }
+
+    /// Path of the mirror file of a node. The children of the node are in a
+    /// directory of the same name next to it. The root has no file.
+    pub fn mirror_filepath(&self, path: &NodePath) -> Option<PathBuf> {
+        if *path == NodePath::root() {
+            return None;
+        }
+        let filepath = self.mirror_dirpath().join(path.buf());
+        Some(filepath.with_file_name(format!("{}.ron", path.name())))
+    }
+
+    /// Rewrites the mirror files of the given nodes, and removes the files of
+    /// the ones that no longer exist. Does nothing if the mirror isn't maintained.
+    /// The mirror is secondary to the db, so failures are only logged.
+    pub(crate) fn update_readable_files(&self, paths: &[NodePath]) {
+        if !self.maintain_readable_files {
+            return;
+        }
+        for path in paths {
+            let written = match self.open_node(path) {
+                Ok(node) => self.write_readable_file(&node),
+                Err(_) => self.remove_readable_file(path),
+            };
+            if let Err(e) = written {
+                tracing::warn!("Failed to update readable file of {}: {}", path.alias(), e);
+            }
+        }
+    }
+
+    /// Updates the mirror after a node and its descendants moved to a new path.
+    /// The files under the old path are removed and the ones of the subtree are
+    /// written again, along with the files of the nodes linking into it, since
+    /// their edges name the moved nodes.
+    pub(crate) fn move_readable_files(&self, old_path: &NodePath, new_path: &NodePath) {
+        if !self.maintain_readable_files {
+            return;
+        }
+        if let Err(e) = self.move_readable_subtree(old_path, new_path) {
+            tracing::warn!("Failed to move readable files of {}: {}", old_path.alias(), e);
+        }
+    }
+
+    /// Writes the whole mirror from scratch, such as when it is turned on.
+    pub(crate) fn write_readable_files(&self) {
+        if !self.maintain_readable_files {
+            return;
+        }
+        if let Err(e) = self.write_all_readable_files() {
+            tracing::warn!("Failed to write readable files: {}", e);
+        }
+    }
+
+    fn write_all_readable_files(&self) -> Result<(), Box<dyn Error>> {
+        let mirror = self.mirror_dirpath();
+        if mirror.exists() {
+            std::fs::remove_dir_all(&mirror)?;
+        }
+
+        let nodes = self.db.exec(
+            &QueryBuilder::select()
+                .ids(QueryBuilder::search().from(NodePath::root().alias()).where_().node().query())
+                .query(),
+        )?;
+        for node in nodes.elements.into_iter().filter_map(|elem| Node::try_from(elem).ok()) {
+            self.write_readable_file(&node)?;
+        }
+        Ok(())
+    }
+
+    fn move_readable_subtree(&self, old_path: &NodePath, new_path: &NodePath) -> Result<(), Box<dyn Error>> {
+        self.remove_readable_file(old_path)?;
+        let old_dir = self.mirror_dirpath().join(old_path.buf());
+        if old_dir.exists() {
+            std::fs::remove_dir_all(old_dir)?;
+        }
+
+        let mut changed = vec![new_path.clone()];
+        changed.extend(self.get_all_descendants(new_path)?.iter().map(|node| node.path()));
+        let mut linking: Vec<NodePath> = Vec::new();
+        for path in changed.iter() {
+            for edge in self.adjacent_edges(path, false)? {
+                let source = edge.source();
+                if !edge.contains() && !changed.contains(source) && !linking.contains(source) {
+                    linking.push(source.clone());
+                }
+            }
+        }
+        changed.extend(linking);
+
+        self.update_readable_files(&changed);
+        Ok(())
+    }
+
+    fn write_readable_file(&self, node: &Node) -> Result<(), Box<dyn Error>> {
+        let filepath = match self.mirror_filepath(&node.path()) {
+            Some(filepath) => filepath,
+            None => return Ok(()),
+        };
+
+        let edges = self
+            .adjacent_edges(&node.path(), true)?
+            .into_iter()
+            .filter(|edge| !edge.contains())
+            .map(|edge| MirroredEdge {
+                target: edge.target().clone(),
+                directed: edge.directed(),
+                attributes: edge.attributes().clone(),
+            })
+            .collect();
+        let mirrored = MirroredNode {
+            path: node.path(),
+            ntype: node.ntype_name(),
+            attributes: node.attributes(),
+            edges,
+        };
+
+        let ron = ron::ser::to_string_pretty(&mirrored, ron::ser::PrettyConfig::default())?;
+        std::fs::create_dir_all(filepath.parent().unwrap())?;
+        write_atomically(&filepath, ron)?;
+        Ok(())
+    }
+
+    fn remove_readable_file(&self, path: &NodePath) -> Result<(), Box<dyn Error>> {
+        if let Some(filepath) = self.mirror_filepath(path) {
+            if filepath.exists() {
+                std::fs::remove_file(filepath)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -122,22 +122,70 @@ impl CommandAgdb for CreateNodeByNameCommand {
 pub struct DeleteNodesCommand {
     node_paths: Vec<NodePath>,
     deleted_nodes: Vec<Node>,
+    /// The edges of the deleted nodes, which agdb removes along with them.
+    deleted_edges: Vec<Edge>,
     files: bool,
     dirs: bool,
 }
 
+impl DeleteNodesCommand {
+    pub fn new(node_paths: Vec<NodePath>, files: bool, dirs: bool) -> Self {
+        DeleteNodesCommand {
+            node_paths,
+            deleted_nodes: Vec::new(),
+            deleted_edges: Vec::new(),
+            files,
+            dirs,
+        }
+    }
+}
+
 impl CommandAgdb for DeleteNodesCommand {
     fn command_name(&self) -> String {
         "Delete Nodes".to_string()
     }
     fn apply(&mut self, graph: &mut GraphAgdb) -> Result<CommandResult, Box<dyn Error>> {
-        todo!();
+        // Store the nodes and their edges before deleting them so that they
+        // can be restored as they were on undo.
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut edges: Vec<Edge> = Vec::new();
+        for path in self.node_paths.iter() {
+            nodes.push(graph.open_node(path)?);
+            for outgoing in [true, false] {
+                for edge in graph.adjacent_edges(path, outgoing)? {
+                    // An edge between two deleted nodes is found from both ends
+                    if !edges.iter().any(|found| found.db_id() == edge.db_id()) {
+                        edges.push(edge);
+                    }
+                }
+            }
+        }
+
+        graph.delete_nodes(&self.node_paths, self.files, self.dirs)?;
+        self.deleted_nodes = nodes;
+        self.deleted_edges = edges;
+
+        Ok(CommandResult {
+            msg: format!("Nodes deleted: {:?}", self.node_paths),
+            nodepaths: self.node_paths.clone(),
+            nodes: self.deleted_nodes.clone(),
+            edges: self.deleted_edges.clone(),
+            attributes: vec![],
+        })
     }
     fn undo(&mut self, graph: &mut GraphAgdb) -> Result<CommandResult, Box<dyn Error>> {
-        todo!();
+        graph.restore_nodes(&self.deleted_nodes, &self.deleted_edges)?;
+
+        Ok(CommandResult {
+            msg: format!("Nodes restored: {:?}", self.node_paths),
+            nodepaths: self.node_paths.clone(),
+            nodes: self.deleted_nodes.clone(),
+            edges: self.deleted_edges.clone(),
+            attributes: vec![],
+        })
     }
     fn redo(&mut self, graph: &mut GraphAgdb) -> Result<CommandResult, Box<dyn Error>> {
-        todo!();
+        self.apply(graph)
     }
 }
 
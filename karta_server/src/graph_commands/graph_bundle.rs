@@ -0,0 +1,18 @@
+use std::error::Error;
+
+use crate::prelude::*;
+
+impl GraphBundle for GraphCommands {
+    fn export_subtree(&self, root: &NodePath) -> Result<ExportBundle, Box<dyn Error>> {
+        self.graph.export_subtree(root)
+    }
+
+    fn import_bundle(
+        &mut self,
+        bundle: ExportBundle,
+        target_parent: &NodePath,
+        rename_on_conflict: bool,
+    ) -> Result<ImportResult, Box<dyn Error>> {
+        self.graph.import_bundle(bundle, target_parent, rename_on_conflict)
+    }
+}
@@ -2,19 +2,19 @@ use super::{GraphCommands, GraphCore};
 
 impl GraphCore for GraphCommands {
     fn storage_path(&self) -> super::StoragePath {
-        todo!()
+        self.graph.storage_path()
     }
 
     fn user_root_dirpath(&self) -> std::path::PathBuf {
-        todo!()
+        self.graph.user_root_dirpath()
     }
 
     fn root_nodepath(&self) -> super::NodePath {
-        todo!()
+        self.graph.root_nodepath()
     }
 
     fn root_name(&self) -> String {
-        todo!()
+        self.graph.root_name()
     }
 
     fn new(name: &str, root_path: std::path::PathBuf, custom_storage_path: Option<std::path::PathBuf>) -> Self {
@@ -29,8 +29,8 @@ impl GraphCore for GraphCommands {
         todo!()
     }
 
-    fn index_node_context(&mut self, path: &super::NodePath) {
-        self.graph.index_node_context(path);
+    fn index_node_context(&mut self, path: &super::NodePath) -> crate::graph_traits::graph_core::ContextIndex {
+        self.graph.index_node_context(path)
     }
 
     fn cleanup_dead_nodes(&mut self) {
@@ -38,10 +38,46 @@ impl GraphCore for GraphCommands {
     }
 
     fn maintain_readable_files(&mut self, maintain: bool) {
-        todo!()
+        self.graph.maintain_readable_files(maintain)
+    }
+
+    fn sniff_file_types(&mut self, sniff: bool) {
+        self.graph.sniff_file_types(sniff)
+    }
+
+    fn dir_node_limit(&mut self, limit: usize) {
+        self.graph.dir_node_limit(limit)
     }
 
     fn get_all_aliases(&self) -> Vec<String> {
         self.graph.get_all_aliases()
     }
+
+    fn get_node_count(&self) -> usize {
+        self.graph.get_node_count()
+    }
+
+    fn get_edge_count(&self) -> usize {
+        self.graph.get_edge_count()
+    }
+
+    fn get_node_count_by_type(&self) -> std::collections::HashMap<String, usize> {
+        self.graph.get_node_count_by_type()
+    }
+
+    fn integrity_check(&self) -> Vec<crate::graph_traits::graph_core::IntegrityIssue> {
+        self.graph.integrity_check()
+    }
+
+    fn repair_missing_parents(&mut self) -> Result<Vec<super::NodePath>, Box<dyn std::error::Error>> {
+        self.graph.repair_missing_parents()
+    }
+
+    fn repair_duplicate_edges(&mut self) -> Result<Vec<super::NodePath>, Box<dyn std::error::Error>> {
+        self.graph.repair_duplicate_edges()
+    }
+
+    fn vacuum(&mut self) -> Result<crate::graph_traits::graph_core::VacuumStats, Box<dyn std::error::Error>> {
+        self.graph.vacuum()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+use std::error::Error;
+
+use crate::prelude::*;
+
+impl GraphEdge for GraphCommands {
+    fn get_edge_strict(&self, from: &NodePath, to: &NodePath) -> Result<Edge, Box<dyn Error>> {
+        self.graph.get_edge_strict(from, to)
+    }
+
+    fn create_edge(
+        &mut self,
+        source_path: &NodePath,
+        target_path: &NodePath,
+    ) -> Result<(), Box<dyn Error>> {
+        self.graph.create_edge(source_path, target_path)
+    }
+
+    fn create_typed_edge(
+        &mut self,
+        source_path: &NodePath,
+        target_path: &NodePath,
+        edge_type: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.graph.create_typed_edge(source_path, target_path, edge_type)
+    }
+
+    fn create_undirected_edge(
+        &mut self,
+        source_path: &NodePath,
+        target_path: &NodePath,
+    ) -> Result<(), Box<dyn Error>> {
+        self.graph.create_undirected_edge(source_path, target_path)
+    }
+
+    fn get_edges_by_type(&self, edge_type: &str) -> Result<Vec<Edge>, Box<dyn Error>> {
+        self.graph.get_edges_by_type(edge_type)
+    }
+
+    fn get_edges_between_nodes(&self, paths: &[NodePath]) -> Result<Vec<Edge>, Box<dyn Error>> {
+        self.graph.get_edges_between_nodes(paths)
+    }
+
+    fn get_edges_between_nodes_filtered(
+        &self,
+        paths: &[NodePath],
+        include_contains: bool,
+        edge_type: Option<&str>,
+    ) -> Result<Vec<Edge>, Box<dyn Error>> {
+        self.graph
+            .get_edges_between_nodes_filtered(paths, include_contains, edge_type)
+    }
+
+    fn get_incoming_edges(
+        &self,
+        target: &NodePath,
+        exclude_contains: bool,
+    ) -> Result<Vec<(Node, Edge)>, Box<dyn Error>> {
+        self.graph.get_incoming_edges(target, exclude_contains)
+    }
+
+    fn set_link_edges(
+        &mut self,
+        source: &NodePath,
+        targets: &[NodePath],
+    ) -> Result<(), Box<dyn Error>> {
+        self.graph.set_link_edges(source, targets)
+    }
+
+    fn reparent_node(
+        &mut self,
+        node_path: &NodePath,
+        new_parent_path: &NodePath,
+    ) -> Result<(), Box<dyn Error>> {
+        self.graph.reparent_node(node_path, new_parent_path)
+    }
+
+    fn reconnect_edge(
+        &self,
+        edge: Edge,
+        from: &NodePath,
+        to: &NodePath,
+    ) -> Result<(), Box<dyn Error>> {
+        self.graph.reconnect_edge(edge, from, to)
+    }
+
+    fn insert_edge(&self, edge: Edge) -> Result<(), Box<dyn Error>> {
+        self.graph.insert_edge(edge)
+    }
+
+    fn delete_edge(&mut self, edge: Edge) -> Result<(), Box<dyn Error>> {
+        self.graph.delete_edge(edge)
+    }
+
+    fn insert_edge_attr(&self, edge: Edge, attr: Attribute) -> Result<(), Box<dyn Error>> {
+        self.graph.insert_edge_attr(edge, attr)
+    }
+
+    fn delete_edge_attr(&self, edge: Edge, attr: Attribute) -> Result<(), Box<dyn Error>> {
+        self.graph.delete_edge_attr(edge, attr)
+    }
+}
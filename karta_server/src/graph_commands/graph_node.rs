@@ -1,6 +1,8 @@
 use std::{error::Error, path::PathBuf};
 
-use node::CreateNodeByPathCommand;
+use agdb::DbId;
+
+use node::{CreateNodeByPathCommand, DeleteNodesCommand};
 
 use crate::{elements::attribute::RelativePosition, prelude::*};
 
@@ -9,10 +11,55 @@ impl GraphNode for GraphCommands {
         self.graph.open_node(path)
     }
 
+    fn open_node_by_id(&self, id: DbId) -> Result<Node, Box<dyn std::error::Error>> {
+        self.graph.open_node_by_id(id)
+    }
+
     fn open_node_connections(&self, path: &NodePath) -> Vec<(Node, Edge)> {
         self.graph.open_node_connections(path)
     }
 
+    fn connections_hash(&self, path: &NodePath) -> u64 {
+        self.graph.connections_hash(path)
+    }
+
+    fn get_all_descendants(&self, path: &NodePath) -> Result<Vec<Node>, Box<dyn Error>> {
+        self.graph.get_all_descendants(path)
+    }
+
+    fn is_ancestor_of(&self, ancestor: &NodePath, descendant: &NodePath) -> bool {
+        self.graph.is_ancestor_of(ancestor, descendant)
+    }
+
+    fn get_descendants_to_depth(
+        &self,
+        path: &NodePath,
+        max_depth: usize,
+    ) -> Result<Vec<Node>, Box<dyn Error>> {
+        self.graph.get_descendants_to_depth(path, max_depth)
+    }
+
+    fn search_nodes(
+        &self,
+        query: &str,
+        filter: &Filter,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        self.graph.search_nodes(query, filter, limit)
+    }
+
+    fn recent_nodes(&self, limit: usize) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        self.graph.recent_nodes(limit)
+    }
+
+    fn find_by_attribute(&self, name: &str, value: &AttrValue) -> Result<Vec<Node>, Box<dyn Error>> {
+        self.graph.find_by_attribute(name, value)
+    }
+
+    fn find_by_attribute_range(&self, name: &str, min: f64, max: f64) -> Result<Vec<Node>, Box<dyn Error>> {
+        self.graph.find_by_attribute_range(name, min, max)
+    }
+
     fn create_node_by_path(
         &mut self,
         path: &NodePath,
@@ -23,7 +70,7 @@ impl GraphNode for GraphCommands {
         let result = match self.apply(Box::new(cmd)) {
             Ok(result) => result,
             Err(e) => {
-                println!("Failed to insert node: {}", e);
+                tracing::error!("Failed to insert node: {}", e);
                 return Err(e.into());
             }
         };
@@ -52,7 +99,15 @@ impl GraphNode for GraphCommands {
         files: bool,
         dirs: bool,
     ) -> Result<(), Box<dyn Error>> {
-        todo!()
+        let cmd = DeleteNodesCommand::new(paths.clone(), files, dirs);
+
+        match self.apply(Box::new(cmd)) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::error!("Failed to delete nodes: {}", e);
+                Err(e)
+            }
+        }
     }
 
     fn get_node_attrs(
@@ -67,7 +122,22 @@ impl GraphNode for GraphCommands {
         path: &NodePath,
         attrs: Vec<Attribute>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        todo!()
+        let ntype = self.graph.open_node(path)?.ntype_name();
+        let violations = self.schemas.validate(&ntype, &attrs);
+
+        if !violations.is_empty() {
+            let reasons: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+            match self.schemas.mode() {
+                SchemaMode::Warn => {
+                    tracing::warn!("Schema violations in {}: {}", path.alias(), reasons.join(", "));
+                }
+                SchemaMode::Error => {
+                    return Err(reasons.join(", ").into());
+                }
+            }
+        }
+
+        self.graph.insert_node_attrs(path, attrs)
     }
 
     fn delete_node_attrs(
@@ -78,8 +148,29 @@ impl GraphNode for GraphCommands {
         todo!()
     }
 
-    fn merge_nodes(&mut self, nodes: Vec<NodePath>) -> Result<(), Box<dyn Error>> {
-        todo!()
+    fn set_node_persistent(&mut self, path: &NodePath, persistent: bool) -> Result<(), Box<dyn Error>> {
+        self.graph.set_node_persistent(path, persistent)
+    }
+
+    fn set_node_type(&mut self, path: &NodePath, ntype: NodeType) -> Result<(), Box<dyn Error>> {
+        self.graph.set_node_type(path, ntype)
+    }
+
+    fn duplicate_node(&mut self, path: &NodePath) -> Result<Node, Box<dyn Error>> {
+        self.graph.duplicate_node(path)
+    }
+
+    fn rename_node(&mut self, path: &NodePath, new_name: &str) -> Result<NodePath, Box<dyn Error>> {
+        self.graph.rename_node(path, new_name)
+    }
+
+    fn merge_nodes(
+        &mut self,
+        keep: &NodePath,
+        absorb: &NodePath,
+        preference: MergePreference,
+    ) -> Result<(), Box<dyn Error>> {
+        self.graph.merge_nodes(keep, absorb, preference)
     }
 
     fn autoparent_nodes(
@@ -202,4 +293,97 @@ mod tests {
         let found = ctx.graph.open_node(&npath);
         assert_eq!(found.is_ok(), true, "Node should be found");
     }
+
+    #[test]
+    fn delete_nodes_command_can_be_reverted() {
+        let mut func_name = "delete_nodes_command_can_be_reverted";
+        let mut ctx = TestCommandContext::new(&func_name);
+
+        let npath = NodePath::from("test");
+        let node = ctx.graph.create_node_by_path(&npath, None);
+        assert_eq!(node.is_ok(), true, "Node should be created");
+
+        let deleted = ctx.graph.delete_nodes(&vec![npath.clone()], false, false);
+        assert_eq!(deleted.is_ok(), true, "Node should be deleted");
+
+        let found = ctx.graph.open_node(&npath);
+        assert_eq!(found.is_err(), true, "Node should not be found");
+
+        ctx.graph.undo();
+
+        let found = ctx.graph.open_node(&npath);
+        assert_eq!(found.is_ok(), true, "Node should be restored");
+    }
+
+    #[test]
+    fn reverting_delete_nodes_restores_attributes_and_edges() {
+        let func_name = "reverting_delete_nodes_restores_attributes_and_edges";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let npath = NodePath::from("test");
+        let other = NodePath::from("other");
+        ctx.graph.create_node_by_path(&npath, None).unwrap();
+        ctx.graph.create_node_by_path(&other, None).unwrap();
+        ctx.graph
+            .insert_node_attrs(&npath, vec![Attribute::new_string("tag".to_string(), "draft".to_string())])
+            .unwrap();
+        ctx.graph.set_node_persistent(&npath, true).unwrap();
+        ctx.graph.create_typed_edge(&npath, &other, "references").unwrap();
+        ctx.graph.create_edge(&other, &npath).unwrap();
+        let before = ctx.graph.open_node(&npath).unwrap();
+
+        ctx.graph.delete_nodes(&vec![npath.clone()], false, false).unwrap();
+        assert_eq!(ctx.graph.open_node(&npath).is_err(), true, "Node should be deleted");
+
+        ctx.graph.undo().unwrap();
+
+        let restored = ctx.graph.open_node(&npath).unwrap();
+        assert_eq!(restored.attributes(), before.attributes(), "Attributes should be restored");
+        assert_eq!(restored.persistent(), true, "Persistent flag should be restored");
+        assert_eq!(restored.version(), before.version(), "Version should be restored");
+
+        let edges = ctx.graph.get_edges_between_nodes(&[npath.clone(), other.clone()]).unwrap();
+        let outgoing = edges.iter().find(|edge| edge.source() == &npath && edge.target() == &other);
+        assert_eq!(
+            outgoing.and_then(|edge| edge.edge_type()),
+            Some("references"),
+            "Outgoing edge should be restored with its type"
+        );
+        assert!(
+            edges.iter().any(|edge| edge.source() == &other && edge.target() == &npath),
+            "Incoming edge should be restored"
+        );
+        let parent = ctx.graph.get_edges_between_nodes(&[NodePath::user_root(), npath.clone()]).unwrap();
+        assert!(
+            parent.iter().any(|edge| edge.contains() && edge.target() == &npath),
+            "Contains edge from the parent should be restored"
+        );
+    }
+
+    #[test]
+    fn inserting_attribute_against_schema_fails_in_error_mode() {
+        let func_name = "inserting_attribute_against_schema_fails_in_error_mode";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let path = NodePath::from("task");
+        let ntype = NodeType::new("Task".to_string());
+        ctx.graph.create_node_by_path(&path, Some(ntype.clone())).unwrap();
+
+        ctx.graph
+            .schemas_mut()
+            .register(NodeTypeSchema::new(ntype).with_attribute("due", AttrKind::UInt));
+
+        let wrong = vec![Attribute::new_string("due".to_string(), "tomorrow".to_string())];
+
+        let warned = ctx.graph.insert_node_attrs(&path, wrong.clone());
+        assert_eq!(warned.is_ok(), true, "Violations should only warn by default");
+
+        ctx.graph.schemas_mut().set_mode(SchemaMode::Error);
+        let refused = ctx.graph.insert_node_attrs(&path, wrong);
+        assert_eq!(refused.is_err(), true, "Violations should be refused in error mode");
+
+        let right = vec![Attribute::new_uint("due".to_string(), 1700000000)];
+        let inserted = ctx.graph.insert_node_attrs(&path, right);
+        assert_eq!(inserted.is_ok(), true, "Matching attributes should be inserted");
+    }
 }
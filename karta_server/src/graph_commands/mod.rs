@@ -6,11 +6,17 @@ use crate::prelude::*;
 
 pub mod commands;
 pub mod graph_node;
+#[deny(warnings)]
+pub mod graph_edge;
 pub mod graph_core;
+#[deny(warnings)]
+pub mod graph_bundle;
 
 pub struct GraphCommands {
     command_manager: CommandManager,
     graph: GraphAgdb,
+    /// Attribute schemas of node types, checked when attributes are inserted.
+    schemas: SchemaRegistry,
 }
 
 impl GraphCommands {    
@@ -20,9 +26,23 @@ impl GraphCommands {
         GraphCommands {
             command_manager: action_manager,
             graph,
+            schemas: SchemaRegistry::default(),
         }
     }
 
+    /// Opens the graph of the vault, with the db in its storage directory.
+    pub fn open_vault(name: &str, vault: &KartaVault) -> Self {
+        GraphCommands::new(name, vault.vault_dirpath(), Some(vault.storage_dirpath()))
+    }
+
+    pub fn schemas(&self) -> &SchemaRegistry {
+        &self.schemas
+    }
+
+    pub fn schemas_mut(&mut self) -> &mut SchemaRegistry {
+        &mut self.schemas
+    }
+
     pub fn apply(&mut self, command: Box<dyn CommandAgdb>) -> Result<CommandResult, Box<dyn Error>> {
         self.command_manager.apply(&mut self.graph, command)
     }
@@ -41,6 +61,13 @@ impl GraphCommands {
 pub struct TestCommandContext {
     pub test_name: String,
     pub graph: GraphCommands,
+    dir: TestDir,
+}
+
+/// Removes the directory of a test when dropped. Kept apart from the context so
+/// that it can outlive the context's graph being moved into a server state.
+pub struct TestDir {
+    test_name: String,
 }
 
 impl TestCommandContext {
@@ -50,6 +77,7 @@ impl TestCommandContext {
         Self {
             test_name: name.to_string(),
             graph: TestCommandContext::setup(&name, None),
+            dir: TestDir { test_name: name.to_string() },
         }
     }
 
@@ -59,9 +87,18 @@ impl TestCommandContext {
         Self {
             test_name: name.to_string(),
             graph: TestCommandContext::setup(&name, Some(PathBuf::from("storage"))),
+            dir: TestDir { test_name: name.to_string() },
         }
     }
 
+    /// Server state for calling the handlers in tests. The graph of the context
+    /// is moved into the state, so that there is only ever one handle on the db.
+    /// The returned TestDir takes care of the cleanup and has to be kept until
+    /// the end of the test.
+    pub fn into_app_state(self) -> (AppState, TestDir) {
+        (AppState::new(self.graph), self.dir)
+    }
+
     /// Graph setup function for tests. Always stores the db in the data_dir.
     fn setup(test_name: &str, storage: Option<PathBuf>) -> GraphCommands {
         // let test_name = self.test_name.clone();
@@ -92,7 +129,7 @@ impl TestCommandContext {
     }
 }
 
-impl Drop for TestCommandContext {
+impl Drop for TestDir {
     fn drop(&mut self) {
         // Uncomment this return only if you need to temporarily look at the contents
         // return;
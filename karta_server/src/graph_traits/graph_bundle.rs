@@ -0,0 +1,253 @@
+use std::error::Error;
+
+use crate::context::Context;
+
+use super::{edge::Edge, node::Node, node_path::NodePath};
+
+/// Version of the bundle format. Bump when the layout of ExportBundle changes.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// A self-contained snapshot of a node and all its descendants, for sharing or backup.
+///
+/// Nodes keep their paths, so the bundle can be imported back into the same
+/// structure. Only edges between nodes of the bundle are included.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExportBundle {
+    pub version: u32,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    /// The saved contexts of the nodes of the bundle, with only the view nodes
+    /// that are also in the bundle. Bundles exported before contexts were
+    /// included have none.
+    #[serde(default)]
+    pub contexts: Vec<Context>,
+}
+
+/// The outcome of importing a bundle.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ImportResult {
+    /// The new path of the root node of the bundle.
+    pub root: NodePath,
+    /// Paths of all the imported nodes, root first.
+    pub nodes: Vec<NodePath>,
+}
+
+pub trait GraphBundle {
+    // -------------------------------------------------------------------
+    // Bundles
+
+    /// Collects the node at the path, its descendants, the edges between them
+    /// and their saved contexts.
+    fn export_subtree(&self, root: &NodePath) -> Result<ExportBundle, Box<dyn Error>>;
+
+    /// Inserts the nodes and edges of a bundle under the target parent. The root of
+    /// the bundle keeps its name and the rest of the structure is preserved below it.
+    ///
+    /// Errs if a node already exists at the new path of the root, unless rename_on_conflict
+    /// is set, in which case the root gets a numbered name instead. Physical files
+    /// in the bundle are imported as virtual nodes, since their bytes aren't included.
+    /// The bundle is imported as a whole: if any of its nodes or edges is invalid,
+    /// nothing is inserted. The contexts of the bundle are saved for the imported
+    /// nodes, moved along with them.
+    fn import_bundle(
+        &mut self,
+        bundle: ExportBundle,
+        target_parent: &NodePath,
+        rename_on_conflict: bool,
+    ) -> Result<ImportResult, Box<dyn Error>>;
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use crate::{
+        context::{Context, ContextDb, ViewNode},
+        elements::node_path::NodePath,
+        graph_traits::{graph_bundle::GraphBundle, graph_core::GraphCore, graph_node::GraphNode},
+        elements::nodetype::{NodePhysicality, NodeType},
+        utils::utils::TestContext,
+        vault::KartaVault,
+    };
+
+    use super::BUNDLE_VERSION;
+
+    #[test]
+    fn exporting_subtree_includes_descendants_and_their_edges() {
+        let func_name = "exporting_subtree_includes_descendants_and_their_edges";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = NodePath::from("export");
+        let first = NodePath::from("export/first");
+        let second = NodePath::from("export/second");
+        let nested = NodePath::from("export/second/nested");
+        let outside = NodePath::from("outside");
+
+        for path in [&first, &nested, &outside] {
+            ctx.graph.create_node_by_path(path, None);
+        }
+
+        let bundle = ctx.graph.export_subtree(&root);
+        assert_eq!(bundle.is_ok(), true, "Subtree should be exported");
+        let bundle = bundle.unwrap();
+
+        assert_eq!(bundle.version, BUNDLE_VERSION);
+        assert_eq!(bundle.nodes.len(), 4, "Root and its three descendants should be exported");
+        assert_eq!(
+            bundle.nodes.iter().any(|node| node.path() == outside),
+            false,
+            "Nodes outside the subtree should not be exported"
+        );
+
+        // The contains edge from the parent of the subtree root is left out
+        assert_eq!(bundle.edges.len(), 3);
+        assert_eq!(
+            bundle.edges.iter().any(|edge| *edge.target() == root),
+            false,
+            "Edges entering the subtree should not be exported"
+        );
+    }
+
+    #[test]
+    fn imported_bundle_keeps_structure_under_new_parent() {
+        let func_name = "imported_bundle_keeps_structure_under_new_parent";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = NodePath::from("original");
+        ctx.graph.create_node_by_path(&NodePath::from("original/first"), None);
+        ctx.graph.create_node_by_path(&NodePath::from("original/second/nested"), None);
+
+        let target = NodePath::from("copies");
+        ctx.graph.create_node_by_path(&target, None);
+
+        let bundle = ctx.graph.export_subtree(&root).unwrap();
+
+        let imported = ctx.graph.import_bundle(bundle.clone(), &target, false);
+        assert_eq!(imported.is_ok(), true, "Bundle should be imported");
+        let imported = imported.unwrap();
+
+        assert_eq!(imported.root, NodePath::from("copies/original"));
+        assert_eq!(imported.nodes.len(), 4);
+
+        let nested = NodePath::from("copies/original/second/nested");
+        assert_eq!(ctx.graph.open_node(&nested).is_ok(), true, "Nested node should be imported");
+        assert_eq!(
+            ctx.graph.get_all_descendants(&imported.root).unwrap().len(),
+            3,
+            "Imported root should contain the imported nodes"
+        );
+        assert_eq!(
+            ctx.graph.get_all_descendants(&root).unwrap().len(),
+            3,
+            "Original subtree should be untouched"
+        );
+
+        // Importing again collides with the first import
+        let collision = ctx.graph.import_bundle(bundle.clone(), &target, false);
+        assert_eq!(collision.is_err(), true, "Colliding import should fail");
+
+        let renamed = ctx.graph.import_bundle(bundle, &target, true);
+        assert_eq!(renamed.is_ok(), true, "Colliding import should be renamed");
+        let renamed = renamed.unwrap();
+        assert_eq!(renamed.root, NodePath::from("copies/original_2"));
+        assert_eq!(
+            ctx.graph.get_all_descendants(&renamed.root).unwrap().len(),
+            3
+        );
+    }
+
+    #[test]
+    fn saved_contexts_travel_with_the_bundle() {
+        let func_name = "saved_contexts_travel_with_the_bundle";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = NodePath::from("original");
+        let first = NodePath::from("original/first");
+        let outside = NodePath::from("outside");
+        ctx.graph.create_node_by_path(&first, None);
+        ctx.graph.create_node_by_path(&outside, None);
+
+        let target = NodePath::from("copies");
+        ctx.graph.create_node_by_path(&target, None);
+
+        let db = ContextDb::new(&KartaVault::new(ctx.graph.user_root_dirpath()));
+        let mut context = Context::new(root.clone());
+        context.nodes.push(ViewNode::new(first.clone(), 3.0, -4.0));
+        context.nodes.push(ViewNode::new(outside.clone(), 0.0, 0.0));
+        context.settings.default_zoom = 2.0;
+        db.save_context(&context).unwrap();
+
+        let bundle = ctx.graph.export_subtree(&root).unwrap();
+        assert_eq!(bundle.contexts.len(), 1);
+        assert_eq!(bundle.contexts[0].nodes.len(), 1, "Nodes outside the bundle should be left out");
+
+        let imported = ctx.graph.import_bundle(bundle, &target, false).unwrap();
+
+        let copied = db.get_context(&imported.root).unwrap();
+        assert_eq!(copied.is_some(), true, "Context should be saved for the imported root");
+        let copied = copied.unwrap();
+        assert_eq!(copied.nodes, vec![ViewNode::new(NodePath::from("copies/original/first"), 3.0, -4.0)]);
+        assert_eq!(copied.settings.default_zoom, 2.0);
+        assert_eq!(db.get_context(&root).unwrap(), Some(context), "Original context should be untouched");
+    }
+
+    #[test]
+    fn importing_file_nodes_does_not_touch_file_system() {
+        let func_name = "importing_file_nodes_does_not_touch_file_system";
+        let mut ctx = TestContext::new(func_name);
+
+        let root_dir = ctx.graph.user_root_dirpath();
+        std::fs::create_dir_all(root_dir.join("dir")).unwrap();
+        std::fs::write(root_dir.join("dir").join("file.txt"), "content").unwrap();
+        ctx.graph.index_node_context(&NodePath::from("dir"));
+
+        let bundle = ctx.graph.export_subtree(&NodePath::from("dir")).unwrap();
+
+        let target = NodePath::from("imported");
+        ctx.graph.create_node_by_path(&target, None);
+        let imported = ctx.graph.import_bundle(bundle, &target, false).unwrap();
+
+        let file = ctx.graph.open_node(&NodePath::from("imported/dir/file.txt"));
+        assert_eq!(file.is_ok(), true, "File node should be imported");
+        let file = file.unwrap();
+        assert_eq!(file.ntype_name(), NodeType::text());
+        assert_eq!(file.nphys(), NodePhysicality::Virtual);
+        assert_eq!(
+            root_dir.join("imported").exists(),
+            false,
+            "Nothing should be written to the file system"
+        );
+    }
+
+    #[test]
+    fn broken_bundle_is_refused_without_importing_anything() {
+        let func_name = "broken_bundle_is_refused_without_importing_anything";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = NodePath::from("original");
+        let first = NodePath::from("original/first");
+        ctx.graph.create_node_by_path(&first, None);
+
+        let target = NodePath::from("copies");
+        ctx.graph.create_node_by_path(&target, None);
+
+        let bundle = ctx.graph.export_subtree(&root).unwrap();
+        let imported_root = NodePath::from("copies/original");
+
+        let mut duplicated = bundle.clone();
+        duplicated.nodes.push(duplicated.nodes[1].clone());
+        assert_eq!(ctx.graph.import_bundle(duplicated, &target, false).is_err(), true, "Duplicate paths should be refused");
+
+        let mut misnamed = bundle.clone();
+        misnamed.nodes.push(misnamed.nodes[1].relocated(&NodePath::from("original/.karta")));
+        assert_eq!(ctx.graph.import_bundle(misnamed, &target, false).is_err(), true, "Reserved names should be refused");
+
+        let mut dangling = bundle.clone();
+        let edge = dangling.edges[0].relocated(&first, &NodePath::from("original/missing"));
+        dangling.edges.push(edge);
+        assert_eq!(ctx.graph.import_bundle(dangling, &target, false).is_err(), true, "Edges to nodes outside the bundle should be refused");
+
+        assert_eq!(ctx.graph.open_node(&imported_root).is_err(), true, "Nothing should be imported");
+        assert_eq!(ctx.graph.get_all_descendants(&target).unwrap().len(), 0);
+    }
+}
@@ -1,5 +1,5 @@
 use super::{node::Node, node_path::NodePath, StoragePath};
-use std::{error::Error, path::PathBuf};
+use std::{collections::HashMap, error::Error, path::PathBuf};
 
 pub trait GraphCore {
     fn storage_path(&self) -> StoragePath;
@@ -35,7 +35,8 @@ pub trait GraphCore {
     fn index_single_node(&mut self, path: &NodePath) -> Result<Node, Box<dyn Error>>;
 
     /// Syncs the node's relationships in the db with the file system.
-    fn index_node_context(&mut self, path: &NodePath);
+    /// At most dir_node_limit children of a directory are indexed.
+    fn index_node_context(&mut self, path: &NodePath) -> ContextIndex;
 
     /// Delete all dead nodes from the graph.
     fn cleanup_dead_nodes(&mut self);
@@ -46,11 +47,94 @@ pub trait GraphCore {
     /// Set whether the library should maintain readable files for the nodes in the graph.
     fn maintain_readable_files(&mut self, maintain: bool);
 
+    /// Set whether the content of physical files should be sniffed to determine
+    /// their node type when their extension is missing or unknown.
+    fn sniff_file_types(&mut self, sniff: bool);
+
+    /// Set how many children of a directory are indexed when its context is
+    /// indexed. Defaults to DEFAULT_DIR_NODE_LIMIT.
+    fn dir_node_limit(&mut self, limit: usize);
+
     // fn undo(&mut self, num: usize);
     // fn redo(&mut self, num: usize);
 
     /// For debugging purposes, print all aliases.
     fn get_all_aliases(&self) -> Vec<String>;
+
+    /// Number of nodes in the db, archetypes included.
+    fn get_node_count(&self) -> usize;
+
+    /// Number of edges in the db, contains edges included.
+    fn get_edge_count(&self) -> usize;
+
+    /// Number of nodes of each node type, keyed by the name of the type.
+    fn get_node_count_by_type(&self) -> HashMap<String, usize>;
+
+    /// Removes orphaned nodes, ie. nodes that have no edges and aren't persistent,
+    /// and compacts the storage of the db. Archetype nodes are never removed.
+    /// Associative edges that integrity_check reports as dangling are removed too.
+    /// Dangling contains edges are kept, since removing them would cut their
+    /// nodes off from the tree. reindex_path repairs those.
+    fn vacuum(&mut self) -> Result<VacuumStats, Box<dyn Error>>;
+
+    /// Finds structural problems in the db: nodes without a contains parent,
+    /// nodes with more than one, duplicated contains edges and edges whose
+    /// endpoints don't exist.
+    fn integrity_check(&self) -> Vec<IntegrityIssue>;
+
+    /// Reattaches nodes that are missing a contains parent to the parent implied
+    /// by their path, or to the user_root if that doesn't exist. Returns the
+    /// paths of the reattached nodes.
+    fn repair_missing_parents(&mut self) -> Result<Vec<NodePath>, Box<dyn Error>>;
+
+    /// Removes contains edges that duplicate another one between the same parent
+    /// and child, keeping one of them. Returns the paths of the children.
+    fn repair_duplicate_edges(&mut self) -> Result<Vec<NodePath>, Box<dyn Error>>;
+}
+
+/// How many children of a directory are indexed by default. Directories with
+/// more files than this would otherwise freeze the client.
+pub const DEFAULT_DIR_NODE_LIMIT: usize = 1000;
+
+/// The result of indexing the context of a node.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ContextIndex {
+    /// Number of children of the node in the file system, not counting ignored ones.
+    pub total_children: usize,
+    /// Whether only some of the children were indexed because of the limit.
+    pub truncated: bool,
+}
+
+/// A structural problem in the db, found by an integrity check.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum IntegrityIssue {
+    /// A node other than the root has no contains parent.
+    MissingParent(NodePath),
+    MultipleParents {
+        path: NodePath,
+        parents: Vec<NodePath>,
+    },
+    /// There is more than one contains edge from the same parent to the node.
+    DuplicateEdge {
+        source: NodePath,
+        target: NodePath,
+    },
+    /// The stored source or target of an edge doesn't exist.
+    DanglingEdge {
+        source: NodePath,
+        target: NodePath,
+    },
+}
+
+/// What a vacuum of the graph removed.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VacuumStats {
+    pub removed_nodes: Vec<NodePath>,
+    /// Number of dangling associative edges that were removed.
+    pub removed_edges: usize,
+    /// Size of the db in bytes before and after the vacuum.
+    pub size_before: u64,
+    pub size_after: u64,
 }
 
 #[cfg(test)]
@@ -63,9 +147,11 @@ mod tests {
 
     use crate::{
         elements::{node, node_path::NodePath},
-        graph_agdb::GraphAgdb,
-        graph_traits::{graph_core::GraphCore, graph_edge::GraphEdge, graph_node::GraphNode, StoragePath},
+        elements::attribute::Attribute,
+        graph_agdb::{readable_files::MirroredNode, GraphAgdb},
+        graph_traits::{graph_core::{ContextIndex, GraphCore, IntegrityIssue}, graph_edge::GraphEdge, graph_node::GraphNode, StoragePath},
         utils::utils::TestContext,
+        vault::KartaVault,
     };
 
     /// Add a node to the db, then create a new graph with the same name.
@@ -89,6 +175,72 @@ mod tests {
         assert_eq!(root_node_result.is_ok(), true);
     }
 
+    /// The root contains the archetypes and nothing else. The files of the vault
+    /// live under the user_root.
+    #[test]
+    fn root_contains_only_the_archetypes() {
+        let func_name = "root_contains_only_the_archetypes";
+        let mut first = TestContext::new(func_name);
+
+        let file = NodePath::from("dir/file");
+        first.graph.create_node_by_path(&file, None).unwrap();
+
+        let archetype_children = |graph: &GraphAgdb| {
+            let mut children: Vec<NodePath> = graph
+                .open_node_connections(&NodePath::root())
+                .into_iter()
+                .filter(|(node, edge)| edge.contains() && *edge.source() == NodePath::root())
+                .map(|(node, _)| node.path())
+                .collect();
+            children.sort_by_key(|path| path.alias());
+            children
+        };
+        let mut expected: Vec<NodePath> = crate::elements::nodetype::ARCHETYPES
+            .iter()
+            .map(|atype| NodePath::atype(*atype))
+            .filter(|path| *path != NodePath::root())
+            .collect();
+        expected.sort_by_key(|path| path.alias());
+
+        assert_eq!(archetype_children(&first.graph), expected);
+        assert_eq!(first.graph.is_ancestor_of(&NodePath::user_root(), &file), true);
+        assert_eq!(file.parent(), Some(NodePath::from("dir")), "User files are not under the root directly");
+
+        // A detached archetype is reattached when the db is opened again
+        remove_edges_between(&mut first.graph, &NodePath::root(), &NodePath::atype("settings"));
+        assert_eq!(archetype_children(&first.graph).len(), expected.len() - 1);
+
+        let second = TestContext::new(func_name);
+        assert_eq!(archetype_children(&second.graph), expected);
+    }
+
+    #[test]
+    fn node_and_edge_counts_match_inserted_elements() {
+        let func_name = "node_and_edge_counts_match_inserted_elements";
+        let mut ctx = TestContext::new(func_name);
+
+        let nodes = ctx.graph.get_node_count();
+        let edges = ctx.graph.get_edge_count();
+        assert_eq!(nodes, ctx.graph.get_all_aliases().len());
+        assert_eq!(ctx.graph.get_node_count_by_type().values().sum::<usize>(), nodes);
+
+        let paths: Vec<NodePath> = ["first", "second", "third"].iter().map(|name| NodePath::from(*name)).collect();
+        for path in paths.iter() {
+            ctx.graph.create_node_by_path(path, None).unwrap();
+        }
+        ctx.graph.create_edge(&paths[0], &paths[1]).unwrap();
+        ctx.graph.create_edge(&paths[1], &paths[2]).unwrap();
+
+        assert_eq!(ctx.graph.get_node_count(), nodes + 3);
+        // A contains edge to each new node and the two links
+        assert_eq!(ctx.graph.get_edge_count(), edges + 5);
+
+        let by_type = ctx.graph.get_node_count_by_type();
+        let ntype = ctx.graph.open_node(&paths[0]).unwrap().ntype_name();
+        assert_eq!(by_type.get(ntype.name()).copied().unwrap_or(0) >= 3, true, "{:?}", by_type);
+        assert_eq!(by_type.values().sum::<usize>(), nodes + 3);
+    }
+
     #[test]
     fn create_graph_db_file_in_custom_storage_directory() {
         let func_name = "create_graph_db_file_in_custom_storage_directory";
@@ -125,6 +277,50 @@ mod tests {
         std::fs::remove_dir_all(storage).expect("Failed to remove storage directory");
     }
 
+    #[test]
+    fn graph_db_is_stored_in_karta_folder_of_vault() {
+        let func_name = "graph_db_is_stored_in_karta_folder_of_vault";
+        let ctx = TestContext::new(func_name);
+
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+
+        assert_eq!(vault.karta_dirpath().file_name().unwrap(), ".karta");
+        assert_eq!(vault.karta_dirpath().exists(), true, "Karta folder has not been created");
+        assert_eq!(
+            vault.karta_dirpath().join(format!("{}.agdb", ctx.test_name)).exists(),
+            true,
+            "Db was not created in the karta folder"
+        );
+        assert_eq!(
+            vault.vault_dirpath().join(".kartaVault").exists(),
+            false,
+            "Legacy .kartaVault folder should not be created"
+        );
+    }
+
+    #[test]
+    fn graph_db_is_stored_in_storage_override_of_vault() {
+        let func_name = "graph_db_is_stored_in_storage_override_of_vault";
+        let ctx = TestContext::new(func_name);
+
+        // Both directories live in the test directory, so they are cleaned up with it
+        let vault_dir = ctx.graph.user_root_dirpath().join("vault");
+        let storage_dir = ctx.graph.user_root_dirpath().join("fast_disk");
+        std::fs::create_dir_all(&vault_dir).unwrap();
+
+        let vault = KartaVault::new(vault_dir).with_storage_override(Some(storage_dir.clone()));
+        assert_eq!(vault.storage_dirpath(), storage_dir);
+
+        let graph = crate::prelude::GraphCommands::open_vault("overridden", &vault);
+        assert_eq!(graph.user_root_dirpath(), vault.vault_dirpath());
+        assert_eq!(
+            storage_dir.join(".karta").join("overridden.agdb").exists(),
+            true,
+            "Db was not created in the storage override"
+        );
+        assert_eq!(vault.karta_dirpath().join("overridden.agdb").exists(), false);
+    }
+
 
 
     #[test]
@@ -157,6 +353,414 @@ mod tests {
         });
     }
 
+    #[test]
+    fn indexing_large_directory_is_clamped_to_the_limit() {
+        let func_name = "indexing_large_directory_is_clamped_to_the_limit";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        std::fs::create_dir_all(root.join("big")).unwrap();
+        for i in 0..8 {
+            std::fs::File::create(root.join(format!("big/file_{}.txt", i))).unwrap();
+        }
+
+        ctx.graph.dir_node_limit(5);
+        let dir = NodePath::from("big");
+        let index = ctx.graph.index_node_context(&dir);
+        assert_eq!(index, ContextIndex { total_children: 8, truncated: true });
+
+        let children = ctx
+            .graph
+            .open_node_connections(&dir)
+            .into_iter()
+            .filter(|(_, edge)| edge.contains() && *edge.source() == dir)
+            .count();
+        assert_eq!(children, 5, "Only the limit of children should be indexed");
+
+        ctx.graph.dir_node_limit(10);
+        let index = ctx.graph.index_node_context(&dir);
+        assert_eq!(index, ContextIndex { total_children: 8, truncated: false });
+    }
+
+    #[test]
+    fn reindexing_path_restores_it_from_the_parent_edge() {
+        let func_name = "reindexing_path_restores_it_from_the_parent_edge";
+        let mut ctx = TestContext::new(func_name);
+
+        let path = NodePath::from("dir/nested/note");
+        ctx.graph.create_node_by_path(&path, None).unwrap();
+        let id = ctx.graph.open_node(&path).unwrap().id().unwrap();
+
+        // As if a move to another directory was interrupted halfway
+        let drifted = NodePath::from("elsewhere/note");
+        ctx.graph
+            .db_mut()
+            .exec_mut(
+                &agdb::QueryBuilder::insert()
+                    .values(vec![vec![("path", drifted.clone()).into()]])
+                    .ids(id)
+                    .query(),
+            )
+            .unwrap();
+        assert_eq!(ctx.graph.open_node(&path).unwrap().path(), drifted);
+
+        let reindexed = ctx.graph.reindex_path(id);
+        assert_eq!(reindexed.unwrap(), path);
+        assert_eq!(ctx.graph.open_node(&path).unwrap().path(), path);
+        assert_eq!(
+            ctx.graph.get_edge_strict(&NodePath::from("dir/nested"), &path).is_ok(),
+            true,
+            "Node should still be a child of its parent"
+        );
+        assert_eq!(ctx.graph.integrity_check(), vec![]);
+    }
+
+    #[test]
+    fn readable_files_mirror_nodes_and_their_attributes() {
+        let func_name = "readable_files_mirror_nodes_and_their_attributes";
+        let mut ctx = TestContext::new(func_name);
+
+        let note = NodePath::from("dir/note");
+        let other = NodePath::from("other");
+        ctx.graph.create_node_by_path(&note, None).unwrap();
+        assert_eq!(ctx.graph.mirror_dirpath().exists(), false, "Mirror is opt-in");
+
+        ctx.graph.maintain_readable_files(true);
+        ctx.graph.create_node_by_path(&other, None).unwrap();
+        let status = Attribute::new_string("status".to_string(), "draft".to_string());
+        ctx.graph.insert_node_attrs(&note, vec![status.clone()]).unwrap();
+        ctx.graph.create_edge(&note, &other).unwrap();
+
+        let note_file = ctx.graph.mirror_filepath(&note).unwrap();
+        assert_eq!(note_file.ends_with("user_root/dir/note.ron"), true);
+        assert_eq!(ctx.graph.mirror_filepath(&NodePath::from("dir")).unwrap().exists(), true);
+
+        let mirrored: MirroredNode = ron::from_str(&std::fs::read_to_string(&note_file).unwrap()).unwrap();
+        assert_eq!(mirrored.path, note);
+        assert_eq!(mirrored.attributes.contains(&status), true);
+        assert_eq!(mirrored.edges.len(), 1);
+        assert_eq!(mirrored.edges[0].target, other);
+
+        ctx.graph.delete_nodes(&vec![other.clone()], false, false).unwrap();
+        assert_eq!(ctx.graph.mirror_filepath(&other).unwrap().exists(), false);
+    }
+
+    #[test]
+    fn moving_a_subtree_only_rewrites_its_readable_files() {
+        let func_name = "moving_a_subtree_only_rewrites_its_readable_files";
+        let mut ctx = TestContext::new(func_name);
+
+        let note = NodePath::from("dir/note");
+        let child = NodePath::from("dir/note/child");
+        let linking = NodePath::from("linking");
+        let target = NodePath::from("target");
+        ctx.graph.create_node_by_path(&child, None).unwrap();
+        ctx.graph.create_node_by_path(&linking, None).unwrap();
+        ctx.graph.create_node_by_path(&target, None).unwrap();
+        ctx.graph.maintain_readable_files(true);
+        ctx.graph.create_edge(&linking, &child).unwrap();
+
+        // A file the graph doesn't know about is only kept if the mirror isn't rewritten as a whole
+        let stray = ctx.graph.mirror_dirpath().join("stray.txt");
+        std::fs::write(&stray, "").unwrap();
+
+        ctx.graph.reparent_node(&note, &target).unwrap();
+
+        let moved_child = NodePath::from("target/note/child");
+        assert_eq!(ctx.graph.mirror_filepath(&note).unwrap().exists(), false);
+        assert_eq!(ctx.graph.mirror_filepath(&child).unwrap().exists(), false);
+        assert_eq!(ctx.graph.mirror_filepath(&moved_child).unwrap().exists(), true);
+
+        let linking_file = ctx.graph.mirror_filepath(&linking).unwrap();
+        let mirrored: MirroredNode = ron::from_str(&std::fs::read_to_string(&linking_file).unwrap()).unwrap();
+        assert_eq!(mirrored.edges[0].target, moved_child, "Links into the subtree should be rewritten");
+
+        assert_eq!(stray.exists(), true, "The rest of the mirror should be left alone");
+    }
+
+    #[test]
+    fn memory_backed_graph_writes_nothing_to_disk() {
+        let func_name = "karta_server_test_memory_backed_graph_writes_nothing_to_disk";
+        let root = ProjectDirs::from("com", "karta_server", "karta_server")
+            .unwrap()
+            .data_dir()
+            .join(func_name);
+        if root.exists() {
+            std::fs::remove_dir_all(&root).unwrap();
+        }
+        std::fs::create_dir_all(&root).unwrap();
+
+        let mut graph = GraphAgdb::new_with_storage(func_name, root.clone(), StoragePath::Memory);
+        assert_eq!(graph.storage_path(), StoragePath::Memory);
+
+        let path = NodePath::from("dir/virtual");
+        graph.create_node_by_path(&path, None).unwrap();
+        assert_eq!(graph.open_node(&path).is_ok(), true);
+
+        let written: Vec<_> = std::fs::read_dir(&root).unwrap().collect();
+        assert_eq!(written.len(), 0, "Nothing should be written into the vault");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn fresh_graph_passes_integrity_check() {
+        let func_name = "fresh_graph_passes_integrity_check";
+        let mut ctx = TestContext::new(func_name);
+        ctx.graph.create_node_by_path(&NodePath::from("dir/nested"), None);
+
+        assert_eq!(ctx.graph.integrity_check(), vec![]);
+    }
+
+    #[test]
+    fn integrity_check_finds_and_repairs_missing_parent() {
+        let func_name = "integrity_check_finds_and_repairs_missing_parent";
+        let mut ctx = TestContext::new(func_name);
+
+        let parent = NodePath::from("parent");
+        let child = NodePath::from("parent/child");
+        ctx.graph.create_node_by_path(&child, None);
+        remove_edges_between(&mut ctx.graph, &parent, &child);
+
+        assert_eq!(
+            ctx.graph.integrity_check(),
+            vec![IntegrityIssue::MissingParent(child.clone())]
+        );
+
+        let repaired = ctx.graph.repair_missing_parents();
+        assert_eq!(repaired.unwrap(), vec![child.clone()]);
+        assert_eq!(ctx.graph.integrity_check(), vec![], "Child should be reattached");
+        assert_eq!(ctx.graph.get_edge_strict(&parent, &child).is_ok(), true);
+    }
+
+    #[test]
+    fn integrity_check_finds_multiple_parents() {
+        let func_name = "integrity_check_finds_multiple_parents";
+        let mut ctx = TestContext::new(func_name);
+
+        let child = NodePath::from("first/child");
+        let second = NodePath::from("second");
+        ctx.graph.create_node_by_path(&child, None);
+        ctx.graph.create_node_by_path(&second, None);
+        ctx.graph.autoparent_nodes(&second, &child);
+
+        assert_eq!(
+            ctx.graph.integrity_check(),
+            vec![IntegrityIssue::MultipleParents {
+                path: child,
+                parents: vec![NodePath::from("first"), second],
+            }]
+        );
+    }
+
+    #[test]
+    fn integrity_check_finds_and_repairs_duplicate_edge() {
+        let func_name = "integrity_check_finds_and_repairs_duplicate_edge";
+        let mut ctx = TestContext::new(func_name);
+
+        let parent = NodePath::from("parent");
+        let child = NodePath::from("parent/child");
+        ctx.graph.create_node_by_path(&child, None);
+        ctx.graph.autoparent_nodes(&parent, &child);
+
+        assert_eq!(
+            ctx.graph.integrity_check(),
+            vec![IntegrityIssue::DuplicateEdge {
+                source: parent.clone(),
+                target: child.clone(),
+            }],
+            "The same parent twice is not multiple parents"
+        );
+
+        let repaired = ctx.graph.repair_duplicate_edges();
+        assert_eq!(repaired.unwrap(), vec![child.clone()]);
+        assert_eq!(ctx.graph.integrity_check(), vec![]);
+        assert_eq!(ctx.graph.get_edge_strict(&parent, &child).is_ok(), true, "One edge should be kept");
+    }
+
+    #[test]
+    fn integrity_check_finds_dangling_edge() {
+        let func_name = "integrity_check_finds_dangling_edge";
+        let mut ctx = TestContext::new(func_name);
+
+        let first = NodePath::from("first");
+        let second = NodePath::from("second");
+        let missing = NodePath::from("missing");
+        ctx.graph.create_node_by_path(&first, None);
+        ctx.graph.create_node_by_path(&second, None);
+
+        // An edge whose stored source doesn't match any node, like after an interrupted move
+        let edge = crate::elements::edge::Edge::new(&missing, &second);
+        ctx.graph
+            .db_mut()
+            .exec_mut(
+                &agdb::QueryBuilder::insert()
+                    .edges()
+                    .from(first.alias())
+                    .to(second.alias())
+                    .values_uniform(&edge)
+                    .query(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            ctx.graph.integrity_check(),
+            vec![IntegrityIssue::DanglingEdge {
+                source: missing,
+                target: second,
+            }]
+        );
+    }
+
+    fn remove_edges_between(graph: &mut GraphAgdb, source: &NodePath, target: &NodePath) {
+        graph
+            .db_mut()
+            .exec_mut(
+                &agdb::QueryBuilder::remove()
+                    .ids(
+                        agdb::QueryBuilder::search()
+                            .from(source.alias())
+                            .to(target.alias())
+                            .where_()
+                            .edge()
+                            .query(),
+                    )
+                    .query(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn vacuum_removes_orphaned_nodes_only() {
+        let func_name = "vacuum_removes_orphaned_nodes_only";
+        let mut ctx = TestContext::new(func_name);
+
+        let orphan = NodePath::from("parent/orphan");
+        ctx.graph.create_node_by_path(&orphan, None);
+        ctx.graph.create_node_by_path(&NodePath::from("parent/kept/child"), None);
+        ctx.graph.create_node_by_path(&NodePath::from("pinned"), None);
+        ctx.graph.set_node_persistent(&NodePath::from("pinned"), true);
+
+        // Deleting the parent and the edge to the persistent node leaves them without edges
+        ctx.graph.delete_nodes(&vec![NodePath::from("parent")], false, false);
+        ctx.graph.db_mut().exec_mut(
+            &agdb::QueryBuilder::remove()
+                .ids(
+                    agdb::QueryBuilder::search()
+                        .from(NodePath::user_root().alias())
+                        .to(NodePath::from("pinned").alias())
+                        .where_()
+                        .edge()
+                        .query(),
+                )
+                .query(),
+        ).unwrap();
+
+        let stats = ctx.graph.vacuum();
+        assert_eq!(stats.is_ok(), true, "Vacuum should succeed");
+        let stats = stats.unwrap();
+
+        assert_eq!(stats.removed_nodes, vec![orphan.clone()]);
+        assert_eq!(ctx.graph.open_node(&orphan).is_ok(), false, "Orphan should be removed");
+        assert_eq!(
+            ctx.graph.open_node(&NodePath::from("parent/kept")).is_ok(),
+            true,
+            "Nodes with edges should be kept"
+        );
+        assert_eq!(ctx.graph.open_node(&NodePath::from("pinned")).is_ok(), true);
+        assert_eq!(ctx.graph.open_node(&NodePath::root()).is_ok(), true);
+
+        let again = ctx.graph.vacuum().unwrap();
+        assert_eq!(again.removed_nodes.is_empty(), true, "Nothing should be left to remove");
+    }
+
+    #[test]
+    fn vacuum_removes_dangling_associative_edges() {
+        let func_name = "vacuum_removes_dangling_associative_edges";
+        let mut ctx = TestContext::new(func_name);
+
+        let first = NodePath::from("first");
+        let second = NodePath::from("second");
+        ctx.graph.create_node_by_path(&first, None);
+        ctx.graph.create_node_by_path(&second, None);
+        ctx.graph.create_edge(&first, &second).unwrap();
+
+        // Stored ends that don't match any node, like after an interrupted move
+        let missing = NodePath::from("missing");
+        for edge in [
+            crate::elements::edge::Edge::new(&missing, &second),
+            crate::elements::edge::Edge::new_cont(&missing, &second),
+        ] {
+            ctx.graph
+                .db_mut()
+                .exec_mut(
+                    &agdb::QueryBuilder::insert()
+                        .edges()
+                        .from(first.alias())
+                        .to(second.alias())
+                        .values_uniform(&edge)
+                        .query(),
+                )
+                .unwrap();
+        }
+        assert_eq!(ctx.graph.integrity_check().len(), 3, "{:#?}", ctx.graph.integrity_check());
+
+        let stats = ctx.graph.vacuum().unwrap();
+        assert_eq!(stats.removed_edges, 1, "Only the associative edge should be removed");
+
+        let issues = ctx.graph.integrity_check();
+        let dangling: Vec<_> = issues
+            .iter()
+            .filter(|issue| matches!(issue, IntegrityIssue::DanglingEdge { .. }))
+            .collect();
+        assert_eq!(dangling.len(), 1, "Dangling contains edge should be kept: {:#?}", issues);
+        assert_eq!(ctx.graph.get_edge_strict(&first, &second).is_ok(), true, "Intact edges should be kept");
+    }
+
+    #[test]
+    fn indexing_large_directory_matches_indexing_each_child() {
+        let func_name = "indexing_large_directory_matches_indexing_each_child";
+        let mut batched = TestContext::new(&format!("{}_batched", func_name));
+        let mut single = TestContext::new(&format!("{}_single", func_name));
+
+        let dir = NodePath::from("many");
+        let children: Vec<NodePath> = (0..40)
+            .map(|i| NodePath::from(format!("many/file_{}.txt", i).as_str()))
+            .collect();
+
+        for ctx in [&batched, &single] {
+            let root = ctx.graph.user_root_dirpath();
+            std::fs::create_dir_all(dir.full(&root)).unwrap();
+            for child in children.iter() {
+                std::fs::write(child.full(&root), "").unwrap();
+            }
+        }
+
+        // Half of the children are indexed beforehand
+        batched.graph.index_single_node(&dir);
+        for child in children.iter().step_by(2) {
+            batched.graph.index_single_node(child);
+        }
+        batched.graph.index_node_context(&dir);
+
+        single.graph.index_single_node(&dir);
+        for child in children.iter() {
+            single.graph.index_single_node(child);
+        }
+
+        let descendants = |ctx: &TestContext| -> std::collections::HashSet<NodePath> {
+            ctx.graph
+                .get_all_descendants(&dir)
+                .unwrap()
+                .iter()
+                .map(|node| node.path())
+                .collect()
+        };
+
+        assert_eq!(descendants(&batched).len(), children.len());
+        assert_eq!(descendants(&batched), descendants(&single));
+    }
+
     // /// Test for whether a file gets properly indexed into the db after it is
     // /// added to the file system.
     // #[test]
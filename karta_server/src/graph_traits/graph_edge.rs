@@ -1,6 +1,6 @@
 use std::error::Error;
 
-use super::{attribute::Attribute, edge::Edge, node_path::NodePath};
+use super::{attribute::Attribute, edge::Edge, node::Node, node_path::NodePath};
 
 
 pub trait GraphEdge {
@@ -10,17 +10,68 @@ pub trait GraphEdge {
         to: &NodePath
     ) -> Result<Edge, Box<dyn Error>>;
 
+    /// Creates an associative edge between two existing nodes.
     fn create_edge(
         &mut self,
         source_path: &NodePath,
         target_path: &NodePath,
     ) -> Result<(), Box<dyn Error>>;
 
+    /// Creates an associative edge of the given type between two existing nodes.
+    fn create_typed_edge(
+        &mut self,
+        source_path: &NodePath,
+        target_path: &NodePath,
+        edge_type: &str,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Creates an associative edge that connects the nodes both ways.
+    fn create_undirected_edge(
+        &mut self,
+        source_path: &NodePath,
+        target_path: &NodePath,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Gets all the edges of the given type in the graph.
+    fn get_edges_by_type(&self, edge_type: &str) -> Result<Vec<Edge>, Box<dyn Error>>;
+
+    /// Gets all the edges whose source and target are both in the given set of nodes.
+    fn get_edges_between_nodes(&self, paths: &[NodePath]) -> Result<Vec<Edge>, Box<dyn Error>>;
+
+    /// Like get_edges_between_nodes, but can leave out the contains edges
+    /// and only keep the edges of a given type.
+    fn get_edges_between_nodes_filtered(
+        &self,
+        paths: &[NodePath],
+        include_contains: bool,
+        edge_type: Option<&str>,
+    ) -> Result<Vec<Edge>, Box<dyn Error>>;
+
+    /// Gets the edges pointing at the target, along with the nodes they come from.
+    /// Undirected edges point both ways, so they are included whichever end the
+    /// target is. Errs if the target doesn't exist.
+    fn get_incoming_edges(
+        &self,
+        target: &NodePath,
+        exclude_contains: bool,
+    ) -> Result<Vec<(Node, Edge)>, Box<dyn Error>>;
+
+    /// Replaces the link edges going out of the source with edges to the given targets.
+    /// Link edges that aren't to one of the targets are removed, other edges are kept.
+    /// Targets that aren't in the db are created, along with their missing ancestors.
+    /// Either all of the changes are made or none are.
+    fn set_link_edges(
+        &mut self,
+        source: &NodePath,
+        targets: &[NodePath],
+    ) -> Result<(), Box<dyn Error>>;
+
     /// Changes the parent directory of a node. If the node is physical, it will be moved in the file system.
     /// If the node is virtual, the parent will be changed in the db.
     /// Note that due to the implementation, all children of the node will have to be reindexed, recursively.
+    /// Errs if the new parent is the node itself or one of its descendants.
     fn reparent_node(
-        &self,
+        &mut self,
         node_path: &NodePath,
         new_parent_path: &NodePath,
     ) -> Result<(), Box<dyn Error>>;
@@ -37,8 +88,8 @@ pub trait GraphEdge {
     fn insert_edge(&self, edge: Edge) -> Result<(), Box<dyn Error>>;
 
     /// Delete an edge from the graph. Edges with the attribute "contains" refer to the parent-child relationship
-    /// between nodes and will be ignored. All other attributes will be cleared from them instead.
-    fn delete_edge(&self, edge: Edge) -> Result<(), Box<dyn Error>>;
+    /// between nodes and can't be deleted this way. The edge must have been read from the db.
+    fn delete_edge(&mut self, edge: Edge) -> Result<(), Box<dyn Error>>;
 
     /// Insert attributes to an edge. Ignore reserved attribute names. Update attributes that already exist.
     fn insert_edge_attr(&self, edge: Edge, attr: Attribute) -> Result<(), Box<dyn Error>>;
@@ -52,6 +103,326 @@ pub trait GraphEdge {
 
 #[cfg(test)]
 mod tests {
+    #![allow(warnings)]
+
+    use std::fs::{create_dir_all, File};
+
+    use agdb::QueryBuilder;
+
+    use crate::{
+        elements::node_path::NodePath,
+        graph_traits::{graph_core::GraphCore, graph_edge::GraphEdge, graph_node::GraphNode},
+        utils::utils::TestContext,
+    };
+
+    #[test]
+    fn reparenting_node_under_its_own_grandchild_fails() {
+        let func_name = "reparenting_node_under_its_own_grandchild_fails";
+        let mut ctx = TestContext::new(func_name);
+
+        let top = NodePath::from("top");
+        let grandchild = NodePath::from("top/child/grandchild");
+        ctx.graph.create_node_by_path(&grandchild, None);
+
+        assert_eq!(ctx.graph.is_ancestor_of(&top, &grandchild), true);
+        assert_eq!(ctx.graph.is_ancestor_of(&grandchild, &top), false);
+
+        let result = ctx.graph.reparent_node(&top, &grandchild);
+        assert_eq!(result.is_err(), true, "Reparenting under a descendant should fail");
+
+        let result = ctx.graph.reparent_node(&top, &top);
+        assert_eq!(result.is_err(), true, "Reparenting under itself should fail");
+
+        assert_eq!(ctx.graph.open_node(&top).is_ok(), true, "Tree should be unchanged");
+        assert_eq!(ctx.graph.open_node(&grandchild).is_ok(), true, "Tree should be unchanged");
+    }
+
+    #[test]
+    fn reparented_node_has_a_single_parent() {
+        let func_name = "reparented_node_has_a_single_parent";
+        let mut ctx = TestContext::new(func_name);
+
+        let dir = NodePath::from("dir");
+        let moved = NodePath::from("dir/moved");
+        let target = NodePath::from("target");
+        ctx.graph.create_node_by_path(&moved, None).unwrap();
+        ctx.graph.create_node_by_path(&NodePath::from("dir/sibling"), None).unwrap();
+        ctx.graph.create_node_by_path(&target, None).unwrap();
+        // A leftover duplicate of the contains edge, as older dbs can have
+        ctx.graph.autoparent_nodes(&dir, &moved).unwrap();
+
+        ctx.graph.reparent_node(&moved, &target).unwrap();
+
+        let new_path = NodePath::from("target/moved");
+        assert_eq!(ctx.graph.open_node(&new_path).is_ok(), true);
+        assert_eq!(ctx.graph.get_all_descendants(&dir).unwrap().len(), 1, "Old parent should only keep the sibling");
+        assert_eq!(ctx.graph.integrity_check(), vec![]);
+    }
+
+    #[test]
+    fn reparenting_virtual_node_moves_its_descendants() {
+        let func_name = "reparenting_virtual_node_moves_its_descendants";
+        let mut ctx = TestContext::new(func_name);
+
+        let moved = NodePath::from("moved");
+        let target = NodePath::from("target");
+        ctx.graph.create_node_by_path(&NodePath::from("moved/child"), None);
+        ctx.graph.create_node_by_path(&target, None);
+
+        let result = ctx.graph.reparent_node(&moved, &target);
+        assert_eq!(result.is_ok(), true, "Reparenting should succeed: {:?}", result);
+
+        assert_eq!(ctx.graph.open_node(&moved).is_err(), true, "Old path should be gone");
+
+        let new_path = NodePath::from("target/moved");
+        let node = ctx.graph.open_node(&new_path);
+        assert_eq!(node.is_ok(), true, "Node should be at the new path");
+        assert_eq!(node.unwrap().path(), new_path);
+
+        let child = ctx.graph.open_node(&NodePath::from("target/moved/child"));
+        assert_eq!(child.is_ok(), true, "Descendant should be moved along");
+
+        let edge = ctx.graph.get_edge_strict(&target, &new_path);
+        assert_eq!(edge.is_ok(), true, "New parent should contain the node");
+        let edge = edge.unwrap();
+        assert_eq!(*edge.source(), target);
+        assert_eq!(*edge.target(), new_path);
+
+        assert_eq!(
+            ctx.graph.is_ancestor_of(&NodePath::user_root(), &moved),
+            false,
+            "Old parent should no longer contain the node"
+        );
+    }
+
+    #[test]
+    fn failed_move_in_transaction_leaves_graph_unchanged() {
+        let func_name = "failed_move_in_transaction_leaves_graph_unchanged";
+        let mut ctx = TestContext::new(func_name);
+
+        let moved = NodePath::from("moved");
+        let child = NodePath::from("moved/child");
+        let linked = NodePath::from("linked");
+        ctx.graph.create_node_by_path(&child, None).unwrap();
+        ctx.graph.create_node_by_path(&linked, None).unwrap();
+        ctx.graph.create_edge(&child, &linked).unwrap();
+
+        let new_path = NodePath::from("target/moved");
+        let node = ctx.graph.open_node(&moved).unwrap();
+        let mut queries = ctx.graph.subtree_path_rewrites(node, &moved, &new_path).unwrap();
+        assert_eq!(queries.is_empty(), false, "Move should have writes to run");
+
+        // A failing write after the rewrites makes the whole batch fail
+        queries.push(
+            agdb::QueryBuilder::insert()
+                .edges()
+                .from(NodePath::from("missing").alias())
+                .to(linked.alias())
+                .query()
+                .into(),
+        );
+        let result = ctx.graph.exec_batch(queries);
+        assert_eq!(result.is_err(), true);
+
+        assert_eq!(ctx.graph.open_node(&new_path).is_err(), true, "Move should be rolled back");
+        assert_eq!(ctx.graph.open_node(&moved).unwrap().path(), moved);
+        assert_eq!(ctx.graph.open_node(&child).unwrap().path(), child);
+        assert_eq!(ctx.graph.is_ancestor_of(&moved, &child), true);
+        assert_eq!(ctx.graph.get_edge_strict(&child, &linked).is_ok(), true, "Edges should be unchanged");
+        assert_eq!(
+            ctx.graph.get_edge_strict(&child, &linked).unwrap().source(),
+            &child,
+            "Edge endpoints should be unchanged"
+        );
+    }
+
+    #[test]
+    fn transaction_rolls_back_when_the_closure_fails() {
+        let func_name = "transaction_rolls_back_when_the_closure_fails";
+        let mut ctx = TestContext::new(func_name);
+
+        let moved = NodePath::from("moved");
+        let child = NodePath::from("moved/child");
+        ctx.graph.create_node_by_path(&child, None).unwrap();
+
+        let new_path = NodePath::from("elsewhere");
+        let node = ctx.graph.open_node(&moved).unwrap();
+        let rewrites = ctx.graph.subtree_path_rewrites(node, &moved, &new_path).unwrap();
+
+        let result: Result<(), _> = ctx.graph.transaction(|t| {
+            t.exec_mut_all(&rewrites)?;
+            let renamed = t.exec(&QueryBuilder::select().ids(new_path.alias()).query());
+            assert_eq!(renamed.is_ok(), true, "Writes should be visible inside the transaction");
+            Err("Failure partway through the move".into())
+        });
+        assert_eq!(result.is_err(), true);
+        assert_eq!(ctx.graph.open_node(&new_path).is_err(), true, "Move should be rolled back");
+        assert_eq!(ctx.graph.open_node(&child).unwrap().path(), child);
+
+        let result = ctx.graph.transaction(|t| Ok(t.exec_mut_all(&rewrites)?));
+        assert_eq!(result.is_ok(), true);
+        assert_eq!(ctx.graph.open_node(&new_path).unwrap().path(), new_path, "Move should be committed");
+    }
+
+    #[test]
+    fn reparenting_physical_node_moves_it_in_the_file_system() {
+        let func_name = "reparenting_physical_node_moves_it_in_the_file_system";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        create_dir_all(root.join("dir")).unwrap();
+        create_dir_all(root.join("target")).unwrap();
+        File::create(root.join("dir/file.txt")).unwrap();
+
+        ctx.graph.index_node_context(&NodePath::from("dir"));
+        ctx.graph.index_node_context(&NodePath::user_root());
+
+        let result = ctx.graph.reparent_node(&NodePath::from("dir"), &NodePath::from("target"));
+        assert_eq!(result.is_ok(), true, "Reparenting should succeed: {:?}", result);
+
+        assert_eq!(root.join("dir").exists(), false);
+        assert_eq!(root.join("target/dir/file.txt").exists(), true);
+        assert_eq!(ctx.graph.open_node(&NodePath::from("target/dir/file.txt")).is_ok(), true);
+    }
+
+    #[test]
+    fn incoming_edges_list_every_linking_node() {
+        let func_name = "incoming_edges_list_every_linking_node";
+        let mut ctx = TestContext::new(func_name);
+
+        let target = NodePath::from("dir/target");
+        let sources: Vec<NodePath> = ["first", "second", "dir/third"]
+            .iter()
+            .map(|path| NodePath::from(*path))
+            .collect();
+        ctx.graph.create_node_by_path(&target, None).unwrap();
+        for source in sources.iter() {
+            ctx.graph.create_node_by_path(source, None).unwrap();
+            ctx.graph.create_edge(source, &target).unwrap();
+        }
+
+        let backlinks = ctx.graph.get_incoming_edges(&target, true).unwrap();
+        let mut linking: Vec<NodePath> = backlinks.iter().map(|(node, _)| node.path()).collect();
+        linking.sort_by_key(|path| path.alias());
+        assert_eq!(linking, vec![NodePath::from("dir/third"), sources[0].clone(), sources[1].clone()]);
+        assert_eq!(backlinks.iter().all(|(node, edge)| *edge.source() == node.path()), true);
+
+        let with_parent = ctx.graph.get_incoming_edges(&target, false).unwrap();
+        assert_eq!(with_parent.len(), 4, "Parent should be included through its contains edge");
+        assert_eq!(
+            with_parent.iter().any(|(node, edge)| edge.contains() && node.path() == NodePath::from("dir")),
+            true
+        );
+    }
+
+    #[test]
+    fn undirected_edge_is_found_from_both_ends() {
+        let func_name = "undirected_edge_is_found_from_both_ends";
+        let mut ctx = TestContext::new(func_name);
+
+        let first = NodePath::from("first");
+        let second = NodePath::from("second");
+        let third = NodePath::from("third");
+        for path in [&first, &second, &third] {
+            ctx.graph.create_node_by_path(path, None).unwrap();
+        }
+
+        ctx.graph.create_undirected_edge(&first, &second).unwrap();
+        ctx.graph.create_edge(&first, &third).unwrap();
+
+        let edge = ctx.graph.get_edge_strict(&first, &second).unwrap();
+        assert_eq!(edge.directed(), false);
+        assert_eq!(ctx.graph.get_edge_strict(&first, &third).unwrap().directed(), true);
+
+        for (from, to) in [(&first, &second), (&second, &first)] {
+            let connected = ctx
+                .graph
+                .open_node_connections(from)
+                .iter()
+                .any(|(node, _)| node.path() == *to);
+            assert_eq!(connected, true, "{} should be connected to {}", from.alias(), to.alias());
+
+            let incoming = ctx.graph.get_incoming_edges(from, true).unwrap();
+            assert_eq!(incoming.len(), 1, "Only the undirected edge points at {}", from.alias());
+            assert_eq!(incoming[0].0.path(), *to);
+        }
+    }
+
+    #[test]
+    fn typed_edges_are_found_by_type() {
+        let func_name = "typed_edges_are_found_by_type";
+        let mut ctx = TestContext::new(func_name);
+
+        let first = NodePath::from("first");
+        let second = NodePath::from("second");
+        let third = NodePath::from("third");
+        for path in [&first, &second, &third] {
+            ctx.graph.create_node_by_path(path, None).unwrap();
+        }
+
+        ctx.graph.create_typed_edge(&first, &second, "references").unwrap();
+        ctx.graph.create_typed_edge(&third, &second, "references").unwrap();
+        ctx.graph.create_typed_edge(&first, &third, "depends_on").unwrap();
+        ctx.graph.create_edge(&second, &third).unwrap();
+
+        let edge = ctx.graph.get_edge_strict(&first, &third).unwrap();
+        assert_eq!(edge.edge_type(), Some("depends_on"), "Type should be stored with the edge");
+
+        let mut references = ctx.graph.get_edges_by_type("references").unwrap();
+        references.sort_by_key(|edge| edge.source().alias());
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[0].source(), &first);
+        assert_eq!(references[1].source(), &third);
+        assert_eq!(references.iter().all(|edge| edge.target() == &second), true);
+
+        assert_eq!(ctx.graph.get_edges_by_type("depends_on").unwrap().len(), 1);
+        assert_eq!(ctx.graph.get_edges_by_type("unused").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn filtering_edges_between_nodes_by_contains_and_type() {
+        let func_name = "filtering_edges_between_nodes_by_contains_and_type";
+        let mut ctx = TestContext::new(func_name);
+
+        let parent = NodePath::from("parent");
+        let first = NodePath::from("parent/first");
+        let second = NodePath::from("parent/second");
+        ctx.graph.create_node_by_path(&first, None).unwrap();
+        ctx.graph.create_node_by_path(&second, None).unwrap();
+
+        ctx.graph.create_edge(&first, &second).unwrap();
+        ctx.graph.create_edge(&second, &first).unwrap();
+
+        // Give the first associative edge a type
+        let typed = ctx.graph.get_edge_strict(&first, &second).unwrap();
+        ctx.graph
+            .db_mut()
+            .exec_mut(
+                &QueryBuilder::insert()
+                    .values(vec![vec![("type", "references").into()]])
+                    .ids(typed.db_id().unwrap())
+                    .query(),
+            )
+            .unwrap();
+
+        let paths = vec![parent.clone(), first.clone(), second.clone()];
+
+        let all = ctx.graph.get_edges_between_nodes(&paths).unwrap();
+        assert_eq!(all.len(), 4, "Two contains edges and two associative edges");
+
+        let associative = ctx.graph.get_edges_between_nodes_filtered(&paths, false, None).unwrap();
+        assert_eq!(associative.len(), 2);
+        assert_eq!(associative.iter().any(|edge| edge.contains()), false);
+
+        let references = ctx
+            .graph
+            .get_edges_between_nodes_filtered(&paths, true, Some("references"))
+            .unwrap();
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].source(), &first);
+        assert_eq!(references[0].target(), &second);
+    }
+
 //     #![allow(warnings)]
 
 //     use crate::{
@@ -1,8 +1,30 @@
 use std::{error::Error, path::PathBuf};
 
-use crate::elements::nodetype::NodeType;
+use agdb::DbId;
 
-use super::{attribute::{Attribute, RelativePosition}, edge::Edge, node::Node, node_path::NodePath};
+use crate::{elements::{nodetype::NodeType, SysTime}, filter::Filter};
+
+use super::{attribute::{AttrValue, Attribute, RelativePosition}, edge::Edge, node::Node, node_path::NodePath};
+
+/// A single match from a node search. Files and directories that haven't been
+/// indexed yet are included too, with their data read from the file system.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SearchResult {
+    pub path: NodePath,
+    pub ntype: NodeType,
+    pub indexed: bool,
+    pub created_time: SysTime,
+    pub modified_time: SysTime,
+}
+
+/// Whose value is kept when both nodes of a merge have the same attribute.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergePreference {
+    #[default]
+    Keep,
+    Absorbed,
+}
 
 pub trait GraphNode {
     // -------------------------------------------------------------------
@@ -15,6 +37,10 @@ pub trait GraphNode {
     /// awkward and tech debt.
     fn open_node(&self, path: &NodePath) -> Result<Node, Box<dyn Error>>;
 
+    /// Retrieves a node by its id in the database, for clients that have held
+    /// on to the id of a node rather than its path.
+    fn open_node_by_id(&self, id: DbId) -> Result<Node, Box<dyn Error>>;
+
     // Retrieves the edges of a particular node.
     // fn get_node_edges(&self, path: &NodePath) -> Vec<Edge>;
 
@@ -28,6 +54,51 @@ pub trait GraphNode {
     /// Then filters could just be wrappers around agdb's QueryConditions...
     fn open_node_connections(&self, path: &NodePath) -> Vec<(Node, Edge)>;
 
+    /// A hash of the nodes and edges that open_node_connections would return,
+    /// values included, computed from the db without building them. Changes
+    /// whenever one of them is added, removed or written to.
+    fn connections_hash(&self, path: &NodePath) -> u64;
+
+    /// Retrieves all the descendants of a node by following its "contains" edges.
+    /// The node itself is not included.
+    fn get_all_descendants(&self, path: &NodePath) -> Result<Vec<Node>, Box<dyn Error>>;
+
+    /// Whether the descendant can be reached from the ancestor by following "contains" edges.
+    fn is_ancestor_of(&self, ancestor: &NodePath, descendant: &NodePath) -> bool;
+
+    /// Retrieves a node and its descendants up to the given depth by following
+    /// "contains" edges. Depth 1 means direct children, and depth 0 returns just the node.
+    /// The node itself is always the first element.
+    fn get_descendants_to_depth(
+        &self,
+        path: &NodePath,
+        max_depth: usize,
+    ) -> Result<Vec<Node>, Box<dyn Error>>;
+
+    /// Searches for nodes whose path contains the query, case insensitively.
+    /// Also searches the file system under the user_root for files and directories
+    /// that haven't been indexed. Results are sorted by path and truncated to the limit.
+    fn search_nodes(
+        &self,
+        query: &str,
+        filter: &Filter,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>, Box<dyn Error>>;
+
+    /// The most recently modified nodes, newest first, up to the limit. Like search,
+    /// includes files and directories that haven't been indexed, with their times
+    /// read from the file system. Nodes modified in the same second are sorted by path.
+    fn recent_nodes(&self, limit: usize) -> Result<Vec<SearchResult>, Box<dyn Error>>;
+
+    /// Finds the indexed nodes that have an attribute of the given name and value.
+    /// The value must also be of the same type, so a float doesn't match a uint.
+    /// Results are sorted by path.
+    fn find_by_attribute(&self, name: &str, value: &AttrValue) -> Result<Vec<Node>, Box<dyn Error>>;
+
+    /// Finds the indexed nodes that have a float or uint attribute of the given
+    /// name between min and max. Both bounds are inclusive. Results are sorted by path.
+    fn find_by_attribute_range(&self, name: &str, min: f64, max: f64) -> Result<Vec<Node>, Box<dyn Error>>;
+
     /// Creates a node from the given path. Inserts it into the graph.
     /// Insert the relative path from the root, not including the root dir.
     ///
@@ -84,8 +155,37 @@ pub trait GraphNode {
         attr_name: Vec<&str>,
     ) -> Result<(), Box<dyn Error>>;
 
-    /// Merges a vector of nodes into the last one.
-    fn merge_nodes(&mut self, nodes: Vec<NodePath>) -> Result<(), Box<dyn Error>>;
+    /// Sets whether a node is persistent. A persistent virtual node stays in the
+    /// database even if it has no edges and isn't in any context.
+    fn set_node_persistent(&mut self, path: &NodePath, persistent: bool) -> Result<(), Box<dyn Error>>;
+
+    /// Changes the type of a node. Errs if the node is physical and the type
+    /// contradicts it being a file or a directory.
+    fn set_node_type(&mut self, path: &NodePath, ntype: NodeType) -> Result<(), Box<dyn Error>>;
+
+    /// Changes the name of a node without changing its parent. If the node is physical,
+    /// it is renamed in the file system too. If the name is taken, a unique name is
+    /// generated from it. Errs if the name is invalid, so a name with a slash can't
+    /// move the node under another parent. Returns the new path of the node.
+    fn rename_node(&mut self, path: &NodePath, new_name: &str) -> Result<NodePath, Box<dyn Error>>;
+
+    /// Copies a node next to itself under a unique name, such as "notes_2.txt" for
+    /// "notes.txt". The file or directory of a physical node is copied on disk.
+    /// Descendants and attributes are copied along, edges other than contains
+    /// edges aren't. Returns the copy.
+    fn duplicate_node(&mut self, path: &NodePath) -> Result<Node, Box<dyn Error>>;
+
+    /// Merges the absorbed node into the kept one and deletes it. The edges and
+    /// children of the absorbed node are moved onto the kept node, except for
+    /// edges the kept node already has. Attributes of both nodes are combined,
+    /// and the preference decides which value wins when both have the same one.
+    /// Only virtual nodes can be merged, since physical ones are separate files.
+    fn merge_nodes(
+        &mut self,
+        keep: &NodePath,
+        absorb: &NodePath,
+        preference: MergePreference,
+    ) -> Result<(), Box<dyn Error>>;
 
     // pub fn set_relative_positions
 
@@ -124,10 +224,11 @@ mod tests {
 
     use crate::{
         elements::{
-            self, attribute::{Attribute, RESERVED_NODE_ATTRS}, node, node_path::NodePath, nodetype::ARCHETYPES
+            self, attribute::{AttrValue, Attribute, RESERVED_NODE_ATTRS}, node, node_path::NodePath, nodetype::{NodeType, ARCHETYPES}
         },
         graph_agdb::GraphAgdb,
         graph_traits::graph_edge::GraphEdge,
+        filter::Filter,
         utils::utils::TestContext,
     };
     use agdb::QueryBuilder;
@@ -138,7 +239,28 @@ mod tests {
         vec,
     };
 
-    use crate::graph_traits::{graph_core::GraphCore, graph_node::GraphNode};
+    use crate::graph_traits::{graph_core::GraphCore, graph_node::{GraphNode, MergePreference}};
+
+    #[test]
+    fn creating_a_child_keeps_its_existing_parent() {
+        let func_name = "creating_a_child_keeps_its_existing_parent";
+        let mut ctx = TestContext::new(func_name);
+
+        let parent = NodePath::from("parent");
+        ctx.graph.create_node_by_path(&parent, None).unwrap();
+        let status = Attribute::new_string("status".to_string(), "draft".to_string());
+        ctx.graph.insert_node_attrs(&parent, vec![status.clone()]).unwrap();
+        let before = ctx.graph.open_node(&parent).unwrap();
+        assert_eq!(before.version() > 0, true, "Inserting attributes should bump the version");
+
+        ctx.graph.create_node_by_path(&NodePath::from("parent/first"), None).unwrap();
+        ctx.graph.create_node_by_path(&NodePath::from("parent/second"), None).unwrap();
+
+        let after = ctx.graph.open_node(&parent).unwrap();
+        assert_eq!(after.version(), before.version(), "Parent should not be reset");
+        assert_eq!(after.attributes().contains(&status), true);
+        assert_eq!(ctx.graph.integrity_check(), vec![], "Parent should have a single contains edge");
+    }
 
     #[test]
     fn create_node_and_open_it() {
@@ -587,6 +709,449 @@ mod tests {
     /// Test converting NodePath to and from DbValue
     /// Test converting NodePhysicality to and from DbValue
     /// Test converting NodeType to and from DbValue
+    #[test]
+    fn getting_descendants_follows_contains_edges() {
+        let func_name = "getting_descendants_follows_contains_edges";
+        let mut ctx = TestContext::new(func_name);
+
+        let dir = NodePath::from("dir");
+        let deep = NodePath::from("dir/sub/deep");
+        let other = NodePath::from("other");
+
+        ctx.graph.create_node_by_path(&deep, None);
+        ctx.graph.create_node_by_path(&other, None);
+
+        let descendants = ctx.graph.get_all_descendants(&dir);
+        assert_eq!(descendants.is_ok(), true, "Descendants should be found");
+
+        let paths: Vec<NodePath> = descendants.unwrap().iter().map(|n| n.path()).collect();
+
+        assert_eq!(paths.len(), 2, "Expected 2 descendants, got {:?}", paths);
+        assert!(paths.contains(&NodePath::from("dir/sub")));
+        assert!(paths.contains(&deep));
+        assert!(!paths.contains(&dir), "Node itself should not be included");
+        assert!(!paths.contains(&other), "Unrelated nodes should not be included");
+    }
+
+    #[test]
+    fn persistent_orphan_node_survives_context_reload() {
+        let func_name = "persistent_orphan_node_survives_context_reload";
+        let mut ctx = TestContext::new(func_name);
+
+        let path = NodePath::from("orphan");
+        ctx.graph.create_node_by_path(&path, None);
+
+        let node = ctx.graph.open_node(&path).unwrap();
+        assert_eq!(node.persistent(), false, "Nodes should not be persistent by default");
+
+        let set = ctx.graph.set_node_persistent(&path, true);
+        assert_eq!(set.is_ok(), true, "Persistency should be set");
+
+        ctx.graph.index_node_context(&NodePath::user_root());
+
+        let reloaded = TestContext::new(func_name);
+        let node = reloaded.graph.open_node(&path);
+        assert_eq!(node.is_ok(), true, "Persistent node should survive a reload");
+
+        let node = node.unwrap();
+        assert_eq!(node.persistent(), true, "Node should still be persistent");
+        assert_eq!(
+            node.attributes().iter().any(|attr| attr.name == "persistent"),
+            false,
+            "Persistency should not show up as a regular attribute"
+        );
+    }
+
+    #[test]
+    fn setting_persistency_of_nonexisting_node_fails() {
+        let func_name = "setting_persistency_of_nonexisting_node_fails";
+        let mut ctx = TestContext::new(func_name);
+
+        let set = ctx.graph.set_node_persistent(&NodePath::from("nothing"), true);
+        assert_eq!(set.is_err(), true, "Persistency of a missing node should not be set");
+    }
+
+    #[test]
+    fn nodes_are_found_by_attribute_value() {
+        let func_name = "nodes_are_found_by_attribute_value";
+        let mut ctx = TestContext::new(func_name);
+
+        let tasks = [("tasks/write", "done"), ("tasks/test", "todo"), ("tasks/ship", "done")];
+        for (path, status) in tasks {
+            let path = NodePath::from(path);
+            ctx.graph.create_node_by_path(&path, None).unwrap();
+            let attrs = vec![
+                Attribute::new_string("status".to_string(), status.to_string()),
+                Attribute::new_uint("priority".to_string(), 2),
+            ];
+            ctx.graph.insert_node_attrs(&path, attrs).unwrap();
+        }
+
+        let done = ctx
+            .graph
+            .find_by_attribute("status", &AttrValue::String("done".to_string()))
+            .unwrap();
+        let done: Vec<NodePath> = done.into_iter().map(|node| node.path()).collect();
+        assert_eq!(done, vec![NodePath::from("tasks/ship"), NodePath::from("tasks/write")]);
+
+        let prioritised = ctx.graph.find_by_attribute("priority", &AttrValue::UInt(2)).unwrap();
+        assert_eq!(prioritised.len(), 3);
+        let float = ctx.graph.find_by_attribute("priority", &AttrValue::Float(2.0)).unwrap();
+        assert_eq!(float.len(), 0, "Values of another type should not match");
+        let missing = ctx.graph.find_by_attribute("owner", &AttrValue::String("done".to_string())).unwrap();
+        assert_eq!(missing.len(), 0);
+    }
+
+    #[test]
+    fn nodes_are_found_by_attribute_range() {
+        let func_name = "nodes_are_found_by_attribute_range";
+        let mut ctx = TestContext::new(func_name);
+
+        let sizes = [
+            ("files/tiny", AttrValue::UInt(5)),
+            ("files/low", AttrValue::UInt(10)),
+            ("files/mid", AttrValue::Float(15.5)),
+            ("files/high", AttrValue::UInt(20)),
+            ("files/huge", AttrValue::Float(20.5)),
+            ("files/named", AttrValue::String("15".to_string())),
+        ];
+        for (path, size) in sizes {
+            let path = NodePath::from(path);
+            ctx.graph.create_node_by_path(&path, None).unwrap();
+            let attr = Attribute { name: "size".to_string(), value: size };
+            ctx.graph.insert_node_attrs(&path, vec![attr]).unwrap();
+        }
+
+        let names = |nodes: Vec<node::Node>| -> Vec<String> {
+            nodes.into_iter().map(|node| node.path().name()).collect()
+        };
+
+        // Both bounds are inclusive, for uints and floats alike
+        let in_range = ctx.graph.find_by_attribute_range("size", 10.0, 20.0).unwrap();
+        assert_eq!(names(in_range), vec!["high", "low", "mid"]);
+
+        let fractional = ctx.graph.find_by_attribute_range("size", 15.5, 20.5).unwrap();
+        assert_eq!(names(fractional), vec!["high", "huge", "mid"]);
+
+        let empty = ctx.graph.find_by_attribute_range("size", 30.0, 10.0).unwrap();
+        assert_eq!(empty.len(), 0, "A range with min above max should match nothing");
+
+        assert_eq!(ctx.graph.find_by_attribute_range("size", f64::NAN, 1.0).is_err(), true);
+    }
+
+    #[test]
+    fn recent_nodes_come_newest_first() {
+        let func_name = "recent_nodes_come_newest_first";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        let now = std::time::SystemTime::now();
+        let day = std::time::Duration::from_secs(60 * 60 * 24);
+
+        // Indexed and unindexed files alike, touched days apart
+        for (name, days_ago) in [("oldest.txt", 3), ("older.txt", 2), ("old.txt", 1)] {
+            File::create(root.join(name)).unwrap().set_modified(now - day * days_ago).unwrap();
+        }
+        ctx.graph.create_node_by_path(&NodePath::from("older.txt"), None).unwrap();
+        ctx.graph.create_node_by_path(&NodePath::from("fresh_virtual"), None).unwrap();
+
+        let ours = ["fresh_virtual", "old.txt", "older.txt", "oldest.txt"];
+        let all: Vec<String> = ctx
+            .graph
+            .recent_nodes(100)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.path.alias())
+            .collect();
+        let order: Vec<String> = all
+            .iter()
+            .filter(|alias| ours.iter().any(|name| alias.ends_with(name)))
+            .cloned()
+            .collect();
+        let expected: Vec<String> = ours.iter().map(|name| NodePath::from(*name).alias()).collect();
+        assert_eq!(order, expected);
+
+        let limited = ctx.graph.recent_nodes(2).unwrap();
+        assert_eq!(limited.len(), 2);
+        assert_eq!(limited.iter().map(|r| r.path.alias()).collect::<Vec<_>>(), all[..2].to_vec());
+    }
+
+    #[test]
+    fn search_filters_nodes_by_modified_time_window() {
+        let func_name = "search_filters_nodes_by_modified_time_window";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        let now = std::time::SystemTime::now();
+        let day = std::time::Duration::from_secs(60 * 60 * 24);
+
+        File::create(root.join("report_new.txt")).unwrap();
+        File::create(root.join("report_old.txt"))
+            .unwrap()
+            .set_modified(now - day * 10)
+            .unwrap();
+        ctx.graph.create_node_by_path(&NodePath::from("report_virtual"), None);
+
+        let week_ago = (now - day * 7)
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let all = ctx.graph.search_nodes("report", &Filter::default(), 100).unwrap();
+        assert_eq!(all.len(), 3, "All reports should be found: {:#?}", all);
+
+        let filter = Filter {
+            modified_after: Some(week_ago),
+            ..Default::default()
+        };
+        let recent: Vec<NodePath> = ctx
+            .graph
+            .search_nodes("report", &filter, 100)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.path)
+            .collect();
+
+        assert_eq!(recent.len(), 2, "Only recent reports should be found: {:?}", recent);
+        assert!(recent.contains(&NodePath::from("report_new.txt")));
+        assert!(recent.contains(&NodePath::from("report_virtual")));
+
+        let filter = Filter {
+            modified_before: Some(week_ago),
+            ..Default::default()
+        };
+        let old = ctx.graph.search_nodes("report", &filter, 100).unwrap();
+        assert_eq!(old.len(), 1);
+        assert_eq!(old[0].path, NodePath::from("report_old.txt"));
+        assert_eq!(old[0].indexed, false, "File on disk should not be indexed");
+    }
+
+    #[test]
+    fn search_filtered_to_directories_excludes_files() {
+        let func_name = "search_filtered_to_directories_excludes_files";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        create_dir(root.join("report_dir")).unwrap();
+        File::create(root.join("report.txt")).unwrap();
+        File::create(root.join("report_dir/inner_report.txt")).unwrap();
+
+        let all = ctx.graph.search_nodes("report", &Filter::default(), 100).unwrap();
+        assert_eq!(all.len(), 3, "All reports should be found: {:#?}", all);
+
+        let filter = Filter {
+            ntypes: vec![NodeType::dir().name().to_string()],
+            ..Default::default()
+        };
+        // A limit of 1 would be taken up by a file if the limit was applied before the filter
+        let dirs = ctx.graph.search_nodes("report", &filter, 1).unwrap();
+
+        assert_eq!(dirs.len(), 1, "Only the directory should be found: {:#?}", dirs);
+        assert_eq!(dirs[0].path, NodePath::from("report_dir"));
+        assert_eq!(dirs[0].ntype, NodeType::dir());
+    }
+
+    #[test]
+    fn merged_node_gets_the_union_of_edges() {
+        let func_name = "merged_node_gets_the_union_of_edges";
+        let mut ctx = TestContext::new(func_name);
+
+        let keep = NodePath::from("keep");
+        let absorb = NodePath::from("absorb");
+        let shared = NodePath::from("shared");
+        let only_absorbed = NodePath::from("only_absorbed");
+        for path in [&keep, &absorb, &shared, &only_absorbed] {
+            ctx.graph.create_node_by_path(path, None).unwrap();
+        }
+        ctx.graph.create_node_by_path(&NodePath::from("absorb/child"), None).unwrap();
+
+        ctx.graph.create_edge(&keep, &shared).unwrap();
+        ctx.graph.create_edge(&absorb, &shared).unwrap();
+        ctx.graph.create_edge(&only_absorbed, &absorb).unwrap();
+        ctx.graph.create_edge(&absorb, &keep).unwrap();
+
+        let attr = |name: &str, value: &str| Attribute::new_string(name.to_string(), value.to_string());
+        ctx.graph.insert_node_attrs(&keep, vec![attr("status", "draft")]).unwrap();
+        ctx.graph
+            .insert_node_attrs(&absorb, vec![attr("status", "final"), attr("note", "merged")])
+            .unwrap();
+
+        ctx.graph.merge_nodes(&keep, &absorb, MergePreference::Keep).unwrap();
+
+        assert_eq!(ctx.graph.open_node(&absorb).is_err(), true, "Absorbed node should be deleted");
+        assert_eq!(ctx.graph.get_edge_strict(&keep, &shared).is_ok(), true);
+        assert_eq!(ctx.graph.get_edge_strict(&only_absorbed, &keep).is_ok(), true);
+        assert_eq!(
+            ctx.graph.is_ancestor_of(&keep, &NodePath::from("keep/child")),
+            true,
+            "Children should move to the kept node"
+        );
+
+        let associative: Vec<_> = ctx
+            .graph
+            .open_node_connections(&keep)
+            .into_iter()
+            .filter(|(_, edge)| !edge.contains())
+            .collect();
+        assert_eq!(associative.len(), 2, "Shared edge should not be duplicated");
+
+        let attrs = ctx.graph.open_node(&keep).unwrap().attributes();
+        assert_eq!(attrs.contains(&attr("status", "draft")), true, "Kept value should win");
+        assert_eq!(attrs.contains(&attr("note", "merged")), true);
+    }
+
+    #[test]
+    fn failed_merge_leaves_graph_unchanged() {
+        let func_name = "failed_merge_leaves_graph_unchanged";
+        let mut ctx = TestContext::new(func_name);
+
+        let keep = NodePath::from("keep");
+        let absorb = NodePath::from("absorb");
+        let child = NodePath::from("absorb/child");
+        let linked = NodePath::from("linked");
+        for path in [&keep, &child, &linked] {
+            ctx.graph.create_node_by_path(path, None).unwrap();
+        }
+        ctx.graph.create_edge(&absorb, &linked).unwrap();
+        ctx.graph
+            .insert_node_attrs(&absorb, vec![Attribute::new_string("note".to_string(), "merged".to_string())])
+            .unwrap();
+
+        let mut queries = ctx.graph.merge_writes(&keep, &absorb, MergePreference::Keep).unwrap();
+        assert_eq!(queries.is_empty(), false, "Merge should have writes to run");
+
+        // A failing write at the end makes the whole merge fail
+        queries.push(
+            agdb::QueryBuilder::insert()
+                .edges()
+                .from(NodePath::from("missing").alias())
+                .to(linked.alias())
+                .query()
+                .into(),
+        );
+        let result = ctx.graph.exec_batch(queries);
+        assert_eq!(result.is_err(), true);
+
+        assert_eq!(ctx.graph.open_node(&absorb).is_ok(), true, "Absorbed node should still exist");
+        assert_eq!(ctx.graph.open_node(&child).unwrap().path(), child, "Children should not move");
+        assert_eq!(ctx.graph.open_node(&NodePath::from("keep/child")).is_err(), true);
+        assert_eq!(ctx.graph.get_edge_strict(&absorb, &linked).is_ok(), true);
+        let copied = ctx.graph.get_edges_between_nodes(&[keep.clone(), linked.clone()]).unwrap();
+        assert_eq!(copied.is_empty(), true, "Edges should not be copied");
+        let attrs = ctx.graph.open_node(&keep).unwrap().attributes();
+        assert_eq!(attrs.iter().any(|attr| attr.name == "note"), false, "Attributes should not be merged");
+    }
+
+    #[test]
+    fn physical_nodes_cannot_be_merged() {
+        let func_name = "physical_nodes_cannot_be_merged";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        File::create(root.join("file.txt")).unwrap();
+        ctx.graph.index_node_context(&NodePath::user_root());
+        ctx.graph.create_node_by_path(&NodePath::from("virtual"), None).unwrap();
+
+        let merged = ctx.graph.merge_nodes(
+            &NodePath::from("virtual"),
+            &NodePath::from("file.txt"),
+            MergePreference::Keep,
+        );
+        assert_eq!(merged.is_err(), true);
+        assert_eq!(ctx.graph.open_node(&NodePath::from("file.txt")).is_ok(), true);
+    }
+
+    #[test]
+    fn getting_descendants_to_depth_stops_at_the_depth() {
+        let func_name = "getting_descendants_to_depth_stops_at_the_depth";
+        let mut ctx = TestContext::new(func_name);
+
+        let top = NodePath::from("top");
+        let child = NodePath::from("top/child");
+        let grandchild = NodePath::from("top/child/grandchild");
+        let great_grandchild = NodePath::from("top/child/grandchild/great");
+
+        ctx.graph.create_node_by_path(&great_grandchild, None);
+
+        let only_self = ctx.graph.get_descendants_to_depth(&top, 0).unwrap();
+        assert_eq!(only_self.len(), 1);
+        assert_eq!(only_self[0].path(), top, "Depth 0 should return just the node");
+
+        let two_levels: Vec<NodePath> = ctx
+            .graph
+            .get_descendants_to_depth(&top, 2)
+            .unwrap()
+            .iter()
+            .map(|n| n.path())
+            .collect();
+
+        assert_eq!(two_levels.len(), 3, "{:?}", two_levels);
+        assert_eq!(two_levels[0], top, "Node itself should be first");
+        assert!(two_levels.contains(&child));
+        assert!(two_levels.contains(&grandchild));
+        assert!(!two_levels.contains(&great_grandchild));
+    }
+
+    #[test]
+    fn duplicated_node_is_copied_next_to_itself() {
+        let func_name = "duplicated_node_is_copied_next_to_itself";
+        let mut ctx = TestContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        std::fs::write(root.join("notes.txt"), "hello").unwrap();
+        ctx.graph.index_node_context(&NodePath::user_root());
+
+        let original = NodePath::from("notes.txt");
+        let attr = Attribute::new_string("status".to_string(), "draft".to_string());
+        ctx.graph.insert_node_attrs(&original, vec![attr.clone()]).unwrap();
+
+        let copy = ctx.graph.duplicate_node(&original).unwrap();
+        assert_eq!(copy.path(), NodePath::from("notes_2.txt"));
+        assert_eq!(copy.id() != ctx.graph.open_node(&original).unwrap().id(), true);
+        assert_eq!(copy.attributes().contains(&attr), true, "Attributes should be copied");
+        assert_eq!(
+            std::fs::read_to_string(root.join("notes_2.txt")).unwrap(),
+            "hello",
+            "File should be copied on disk"
+        );
+
+        ctx.graph.create_node_by_path(&NodePath::from("topic/child"), None).unwrap();
+        let copy = ctx.graph.duplicate_node(&NodePath::from("topic")).unwrap();
+        assert_eq!(copy.path(), NodePath::from("topic_2"));
+        assert_eq!(
+            ctx.graph.is_ancestor_of(&copy.path(), &NodePath::from("topic_2/child")),
+            true,
+            "Children should be copied along with the node"
+        );
+        assert_eq!(ctx.graph.open_node(&NodePath::from("topic/child")).is_ok(), true);
+    }
+
+    #[test]
+    fn nodes_are_opened_by_paths_in_one_call() {
+        let func_name = "nodes_are_opened_by_paths_in_one_call";
+        let mut ctx = TestContext::new(func_name);
+
+        let paths: Vec<NodePath> = ["one", "two", "dir/three"].iter().map(|p| NodePath::from(*p)).collect();
+        for path in paths.iter() {
+            ctx.graph.create_node_by_path(path, None).unwrap();
+        }
+
+        let found = ctx.graph.get_nodes_by_paths(&paths).unwrap();
+        assert_eq!(found.len(), 3);
+        for path in paths.iter() {
+            assert_eq!(found.get(path).map(|node| node.path()), Some(path.clone()));
+        }
+
+        let missing = NodePath::from("missing");
+        let mut with_missing = paths.clone();
+        with_missing.push(missing.clone());
+        let found = ctx.graph.get_nodes_by_paths(&with_missing).unwrap();
+        assert_eq!(found.len(), 3, "Missing nodes should be left out");
+        assert_eq!(found.contains_key(&missing), false);
+
+        assert_eq!(ctx.graph.get_nodes_by_paths(&[]).unwrap().is_empty(), true);
+    }
+
     #[test]
     fn todo_tests() {
         assert_eq!(2 + 2, 4);
@@ -3,6 +3,7 @@ use std::{error::Error, path::PathBuf};
 use crate::elements;
 use elements::*;
 
+use graph_bundle::GraphBundle;
 use graph_core::GraphCore;
 use graph_edge::GraphEdge;
 use graph_node::GraphNode;
@@ -12,11 +13,16 @@ pub(crate) mod graph_core;
 pub(crate) mod graph_ntype;
 pub(crate) mod graph_node;
 pub(crate) mod graph_edge;
+#[deny(warnings)]
+pub(crate) mod graph_bundle;
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum StoragePath {
     Default,
     Custom(PathBuf),
+    /// The db is kept in memory and never written to disk. For tests and
+    /// sessions that don't need to be saved.
+    Memory,
 }
 
 impl StoragePath {
@@ -26,12 +32,12 @@ impl StoragePath {
 
     pub fn strg_path(&self) -> Option<PathBuf> {
         match self {
-            Self::Default => None,
+            Self::Default | Self::Memory => None,
             Self::Custom(path) => Some(path.clone()),
         }
     }
 }
 
 /// The main graph trait.
-pub(crate) trait Graph: GraphCore + GraphNtype + GraphNode + GraphEdge {}
+pub(crate) trait Graph: GraphCore + GraphNtype + GraphNode + GraphEdge + GraphBundle {}
 
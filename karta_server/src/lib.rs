@@ -1,28 +1,53 @@
 #![allow(warnings)]
 
+// The allow above is for the older modules. Newer ones, here and in the
+// submodules, deny warnings so that clippy checks them.
+#[deny(warnings)]
+mod cli;
+#[deny(warnings)]
+mod context;
 mod elements;
+mod filter;
+#[deny(warnings)]
+mod fs_reader;
 mod graph_traits;
 mod graph_agdb;
 mod graph_commands;
 
 mod server;
 
+#[deny(warnings)]
+mod vault;
+
 mod utils;
 
 pub mod prelude {
+    pub use crate::cli::{
+        init_logging, resolve_vault_path, select_vault_source, ServerArgs, VaultSource, USAGE,
+        VAULT_ENV_VAR,
+    };
+
+    pub use crate::context::{Context, ContextDb, ContextSettings, ViewNode};
+
     pub use crate::elements::{
-        attribute::Attribute,
-        edge::Edge,
+        attribute::{AttrValue, Attribute, NODE_COLOR_ATTR},
+        edge::{Edge, CONTEXT_EDGE_TYPE, LINK_EDGE_TYPE},
         node::Node,
-        node_path::NodePath,
-        nodetype::NodeType,
+        node_path::{NameError, NodePath},
+        nodetype::{NodeType, NodeTypeError},
+        schema::{AttrKind, NodeTypeSchema, SchemaMode, SchemaRegistry, SchemaViolation},
         SysTime,
     };
 
+    pub use crate::filter::Filter;
+
+    pub use crate::fs_reader::write_atomically;
+
     pub use crate::graph_traits::{
-        graph_core::GraphCore,
+        graph_bundle::{ExportBundle, GraphBundle, ImportResult, BUNDLE_VERSION},
+        graph_core::{ContextIndex, GraphCore, IntegrityIssue, VacuumStats, DEFAULT_DIR_NODE_LIMIT},
         graph_edge::GraphEdge,
-        graph_node::GraphNode,
+        graph_node::{GraphNode, MergePreference, SearchResult},
         StoragePath,
     };
 
@@ -34,4 +59,6 @@ pub mod prelude {
     };
 
     pub use crate::server::*;
+
+    pub use crate::vault::{KartaVault, KARTA_DIR_NAME};
 }
\ No newline at end of file
@@ -1,6 +1,46 @@
-use karta_server::prelude::run_server;
+use std::io::IsTerminal;
+
+use karta_server::prelude::*;
 
 #[tokio::main]
 async fn main() {
-    run_server().await;
+    let args = match ServerArgs::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    init_logging(args.verbosity);
+
+    let interactive = !args.headless && std::io::stdin().is_terminal();
+    let source = select_vault_source(
+        args.vault.as_ref(),
+        std::env::var(VAULT_ENV_VAR).ok(),
+        interactive,
+    );
+
+    let root_path = match source {
+        Ok(VaultSource::Path(vault)) => match resolve_vault_path(&vault) {
+            Ok(root_path) => root_path,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Ok(VaultSource::Prompt) => match prompt_vault_path() {
+            Some(root_path) => root_path,
+            None => return,
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let vault = KartaVault::new(root_path).with_storage_override(args.storage.clone());
+    if let Err(e) = run_server_with_shutdown(vault, &args.addr(), shutdown_signal()).await {
+        eprintln!("Server failed on {}: {}", args.addr(), e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,167 @@
+use std::error::Error;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::prelude::{NameError, NodeTypeError};
+
+/// Machine-readable kind of an error, serialized as e.g. "NOT_FOUND".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    NotFound,
+    /// A node already exists at the path the request would create.
+    NameCollision,
+    /// The request itself is invalid, such as a node name with a slash.
+    Validation,
+    /// The request refers to nodes that don't exist.
+    UnknownNodes,
+    /// The node has changed since the version the client expected.
+    VersionConflict,
+    DbError,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::NameCollision => StatusCode::CONFLICT,
+            ErrorCode::Validation => StatusCode::BAD_REQUEST,
+            ErrorCode::UnknownNodes => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::VersionConflict => StatusCode::CONFLICT,
+            ErrorCode::DbError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Error response of the write endpoints. Responds with the status of the code
+/// and a body of the form { code, message, details }.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Anything the client needs to act on the error, such as the unknown
+    /// paths of an UNKNOWN_NODES error. Null if there is nothing to add.
+    #[serde(default)]
+    pub details: serde_json::Value,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ApiError {
+            code,
+            message: message.into(),
+            details: serde_json::Value::Null,
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn name_collision(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::NameCollision, message)
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::Validation, message)
+    }
+
+    pub fn db_error(message: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::DbError, message)
+    }
+
+    pub fn with_details(mut self, details: impl serde::Serialize) -> Self {
+        self.details = serde_json::to_value(details).unwrap_or_default();
+        self
+    }
+}
+
+/// Most errors from the graph don't tell what went wrong, so they are treated
+/// as db errors. Handlers check for the other kinds before calling the graph.
+impl From<Box<dyn Error>> for ApiError {
+    fn from(error: Box<dyn Error>) -> Self {
+        if error.is::<NameError>() || error.is::<NodeTypeError>() {
+            return ApiError::validation(error.to_string());
+        }
+        ApiError::db_error(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(error: std::io::Error) -> Self {
+        ApiError::db_error(error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.code.status(), Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use axum::{extract::Path, Extension};
+
+    use crate::{graph_commands::TestCommandContext, prelude::*};
+
+    use super::{
+        super::{create_node, text_nodes::{update_text_content, UpdateTextContentPayload}, AppState},
+        *,
+    };
+
+    async fn response_json(response: Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn not_found_error_has_code_message_and_details() {
+        let func_name = "not_found_error_has_code_message_and_details";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let payload = UpdateTextContentPayload {
+            content: "content".to_string(),
+        };
+        let updated = update_text_content(
+            Extension(state.clone()),
+            Path(NodePath::from("missing").alias()),
+            Json(payload),
+        )
+        .await;
+
+        let response = updated.unwrap_err().into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let json = response_json(response).await;
+        assert_eq!(json["code"], "NOT_FOUND");
+        assert_eq!(json["message"].is_string(), true);
+        assert_eq!(json["details"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn validation_error_has_code_message_and_details() {
+        let func_name = "validation_error_has_code_message_and_details";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let created = create_node(Extension(state.clone()), Path("dir/.karta".to_string())).await;
+
+        let response = created.unwrap_err().into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let json = response_json(response).await;
+        assert_eq!(json["code"], "VALIDATION");
+        assert_eq!(json["message"], NameError::Reserved(".karta".to_string()).to_string());
+        assert_eq!(json.get("details").is_some(), true);
+    }
+}
@@ -0,0 +1,576 @@
+use std::{
+    io::{Read, SeekFrom},
+    path::{Component, Path as FsPath, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use axum::{
+    body::Body,
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::{fs_reader::mime_type_for, prelude::*, vault::KartaVault};
+
+use super::{text_nodes::text_backing_file, AppState};
+
+/// Serves a file from the vault. The path is relative to the user_root.
+/// Text nodes are served from their backing file.
+/// Supports single byte range requests so that large media can be streamed.
+pub(super) async fn get_asset(
+    Extension(state): Extension<AppState>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let (vault, node_path) = match vault_node_path(&state, path) {
+        Some(found) => found,
+        None => return StatusCode::BAD_REQUEST.into_response(),
+    };
+    let full_path = node_file_path(&state, &vault, &node_path);
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+
+    asset_response(&full_path, range).await
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct ThumbnailParams {
+    #[serde(default = "default_thumbnail_size")]
+    size: u32,
+}
+
+fn default_thumbnail_size() -> u32 {
+    256
+}
+
+/// Upper limit for the size of a thumbnail, so that clients can't make the
+/// server decode and cache images at full resolution.
+pub(super) const MAX_THUMBNAIL_SIZE: u32 = 1024;
+
+/// Serves a downscaled png of an image file in the vault. Thumbnails are cached
+/// in the vault's karta folder and regenerated when the source file is newer.
+pub(super) async fn get_thumbnail(
+    Extension(state): Extension<AppState>,
+    Path(path): Path<String>,
+    Query(params): Query<ThumbnailParams>,
+) -> Response {
+    let (vault, node_path) = match vault_node_path(&state, path) {
+        Some(found) => found,
+        None => return StatusCode::BAD_REQUEST.into_response(),
+    };
+
+    // Decoding and resizing images is slow, so it's kept off the async runtime
+    let size = params.size.min(MAX_THUMBNAIL_SIZE);
+    let thumbnail =
+        tokio::task::spawn_blocking(move || ensure_thumbnail(&vault, &node_path, size)).await;
+
+    match thumbnail {
+        Ok(Ok(thumbnail)) => asset_response(&thumbnail, None).await,
+        Ok(Err(status)) => status.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct PreviewParams {
+    #[serde(default = "default_preview_bytes")]
+    bytes: u64,
+}
+
+fn default_preview_bytes() -> u64 {
+    1024
+}
+
+/// Upper limit for the bytes of a preview, so that clients can't read whole files through it.
+pub(super) const MAX_PREVIEW_BYTES: u64 = 64 * 1024;
+
+/// The beginning of a text file. Binary files are flagged instead of being
+/// sent as garbled text.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TextPreview {
+    pub text: String,
+    /// True if the file continues past the preview.
+    pub truncated: bool,
+    pub is_binary: bool,
+}
+
+/// Serves up to the requested number of bytes from the start of a file as UTF-8 text.
+/// Text nodes are previewed from their backing file.
+pub(super) async fn get_text_preview(
+    Extension(state): Extension<AppState>,
+    Path(path): Path<String>,
+    Query(params): Query<PreviewParams>,
+) -> Response {
+    let (vault, node_path) = match vault_node_path(&state, path) {
+        Some(found) => found,
+        None => return StatusCode::BAD_REQUEST.into_response(),
+    };
+    let full_path = node_file_path(&state, &vault, &node_path);
+
+    match read_text_preview(&full_path, params.bytes) {
+        Ok(preview) => Json(preview).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+/// Reads the start of a file, trimming back to the last complete character so that
+/// multibyte characters aren't split. Errs with the status code to respond with.
+/// Also used by clients that read the vault directly, so that previews look the
+/// same either way.
+pub fn read_text_preview(full_path: &FsPath, max_bytes: u64) -> Result<TextPreview, StatusCode> {
+    let file = std::fs::File::open(full_path).map_err(|_| StatusCode::NOT_FOUND)?;
+    let len = match file.metadata() {
+        Ok(meta) if meta.is_file() => meta.len(),
+        _ => return Err(StatusCode::NOT_FOUND),
+    };
+
+    let max_bytes = max_bytes.min(MAX_PREVIEW_BYTES);
+    let mut bytes = Vec::new();
+    file.take(max_bytes)
+        .read_to_end(&mut bytes)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let binary = TextPreview {
+        text: String::new(),
+        truncated: false,
+        is_binary: true,
+    };
+    if bytes.contains(&0) {
+        return Ok(binary);
+    }
+
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        // Only an incomplete character at the end is allowed, anything else isn't text
+        Err(e) if e.utf8_error().error_len().is_none() => {
+            let valid = e.utf8_error().valid_up_to();
+            let mut bytes = e.into_bytes();
+            bytes.truncate(valid);
+            String::from_utf8(bytes).unwrap()
+        }
+        Err(_) => return Ok(binary),
+    };
+
+    Ok(TextPreview {
+        truncated: (text.len() as u64) < len,
+        text,
+        is_binary: false,
+    })
+}
+
+/// Resolves a path relative to the user_root into the vault and a NodePath.
+/// Returns None if the path would escape the vault or reach into a karta folder,
+/// where the db and the saved contexts are.
+fn vault_node_path(state: &AppState, path: String) -> Option<(KartaVault, NodePath)> {
+    let relative = PathBuf::from(path);
+
+    let escapes = relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(part) if part != KARTA_DIR_NAME));
+    if escapes {
+        return None;
+    }
+
+    let graph = state.graph_commands.read().unwrap();
+    let vault = KartaVault::new(graph.user_root_dirpath());
+    Some((vault, NodePath::new(relative)))
+}
+
+/// The file on disk that holds the content of a node. Text nodes that aren't
+/// files in the vault are read from their backing file.
+fn node_file_path(state: &AppState, vault: &KartaVault, node_path: &NodePath) -> PathBuf {
+    let full_path = node_path.full(&vault.vault_dirpath());
+    if full_path.exists() {
+        return full_path;
+    }
+    let graph = state.graph_commands.read().unwrap();
+    text_backing_file(&graph, vault, node_path).unwrap_or(full_path)
+}
+
+pub(super) fn thumbnail_dirpath(vault: &KartaVault) -> PathBuf {
+    vault.karta_dirpath().join("thumbnails")
+}
+
+/// Thumbnails are named by a hash of the node's alias and the size. The hash is
+/// stable, so thumbnails cached by an earlier build are still found and removed.
+fn thumbnail_prefix(path: &NodePath) -> String {
+    format!("{:016x}_", path.stable_hash())
+}
+
+/// Counts the thumbnails written, so that the temporary files of concurrent writes
+/// have different names.
+static THUMBNAIL_WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the path to the cached thumbnail of the node, generating it first if
+/// it is missing or older than the source file. Errs with the status code to respond with.
+pub(super) fn ensure_thumbnail(
+    vault: &KartaVault,
+    path: &NodePath,
+    size: u32,
+) -> Result<PathBuf, StatusCode> {
+    let source = path.full(&vault.vault_dirpath());
+    if !source.is_file() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mime = mime_type_for(&source);
+    if !mime.starts_with("image/") || mime == "image/svg+xml" {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    let thumbnail = thumbnail_dirpath(vault).join(format!("{}{}.png", thumbnail_prefix(path), size));
+
+    let modified = |p: &FsPath| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    if let (Some(thumb_time), Some(source_time)) = (modified(&thumbnail), modified(&source)) {
+        if thumb_time >= source_time {
+            return Ok(thumbnail);
+        }
+    }
+
+    let image = image::open(&source).map_err(|_| StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+
+    std::fs::create_dir_all(thumbnail_dirpath(vault))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Encoded into a temporary file of this request's own and renamed into place,
+    // so that a concurrent request never serves a half written thumbnail
+    let temp = thumbnail_dirpath(vault).join(format!(
+        ".{}{}.{}.{}.tmp",
+        thumbnail_prefix(path),
+        size,
+        std::process::id(),
+        THUMBNAIL_WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = image
+        .thumbnail(size, size)
+        .save_with_format(&temp, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())
+        .and_then(|_| std::fs::rename(&temp, &thumbnail).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        tracing::warn!("Failed to write thumbnail {}: {}", thumbnail.display(), e);
+        let _ = std::fs::remove_file(&temp);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    Ok(thumbnail)
+}
+
+/// Removes all cached thumbnails of a node, of every size.
+pub(super) fn remove_thumbnails(vault: &KartaVault, path: &NodePath) {
+    let prefix = thumbnail_prefix(path);
+    let entries = match std::fs::read_dir(thumbnail_dirpath(vault)) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Builds the response for a file on disk, with the content type inferred from
+/// the extension. If a range is given, responds with 206 and only that part of the file.
+/// The file is streamed, so large media isn't read into memory.
+pub(super) async fn asset_response(full_path: &FsPath, range: Option<&str>) -> Response {
+    let mut file = match tokio::fs::File::open(full_path).await {
+        Ok(file) => file,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let len = match file.metadata().await {
+        Ok(meta) if meta.is_file() => meta.len(),
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let mime = mime_type_for(full_path);
+
+    let (start, end) = match range {
+        None => (0, len.saturating_sub(1)),
+        Some(range) => match parse_range(range, len) {
+            Some(bounds) => bounds,
+            None => {
+                return (
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+                )
+                    .into_response()
+            }
+        },
+    };
+
+    let content_length = if len > 0 { end - start + 1 } else { 0 };
+    if file.seek(SeekFrom::Start(start)).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    let body = Body::from_stream(ReaderStream::new(file.take(content_length)));
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, mime)
+        .header(header::CONTENT_LENGTH, content_length)
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    response = match range {
+        Some(_) => response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len)),
+        None => response.status(StatusCode::OK),
+    };
+
+    response.body(body).unwrap()
+}
+
+/// Parses a single "bytes=" range into inclusive start and end offsets.
+/// Returns None if the range is malformed or can't be satisfied.
+fn parse_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+
+    if len == 0 {
+        return None;
+    }
+
+    let (start, end) = if start.is_empty() {
+        // Suffix range, ie. the last n bytes
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end: u64 = match end {
+            "" => len - 1,
+            end => end.parse::<u64>().ok()?.min(len - 1),
+        };
+        (start, end)
+    };
+
+    if start > end || start >= len {
+        return None;
+    }
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use std::{fs::File, io::Write};
+
+    use crate::graph_commands::TestCommandContext;
+    use crate::server::text_nodes::{create_text_node, CreateTextNodePayload};
+
+    use super::*;
+
+    const PNG_BYTES: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    #[tokio::test]
+    async fn asset_response_sets_content_type_and_serves_ranges() {
+        let func_name = "asset_response_sets_content_type_and_serves_ranges";
+        let ctx = TestCommandContext::new(func_name);
+
+        let path = ctx.graph.user_root_dirpath().join("image.png");
+        File::create(&path).unwrap().write_all(&PNG_BYTES).unwrap();
+
+        let full = asset_response(&path, None).await;
+        assert_eq!(full.status(), StatusCode::OK);
+        assert_eq!(full.headers()[header::CONTENT_TYPE], "image/png");
+        assert_eq!(full.headers()[header::CONTENT_LENGTH], "8");
+
+        let partial = asset_response(&path, Some("bytes=1-3")).await;
+        assert_eq!(partial.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(partial.headers()[header::CONTENT_RANGE], "bytes 1-3/8");
+
+        let body = axum::body::to_bytes(partial.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), b"PNG");
+
+        let unsatisfiable = asset_response(&path, Some("bytes=20-")).await;
+        assert_eq!(unsatisfiable.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+
+    #[tokio::test]
+    async fn karta_folder_is_not_served_as_an_asset() {
+        let func_name = "karta_folder_is_not_served_as_an_asset";
+        let ctx = TestCommandContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        let vault = KartaVault::new(root.clone());
+        std::fs::create_dir_all(vault.karta_dirpath()).unwrap();
+        std::fs::write(vault.karta_dirpath().join("secret.txt"), "private").unwrap();
+        std::fs::write(root.join("public.txt"), "public").unwrap();
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let public = get_asset(Extension(state.clone()), Path("public.txt".to_string()), HeaderMap::new()).await;
+        assert_eq!(public.status(), StatusCode::OK);
+
+        let hidden = format!("{}/secret.txt", KARTA_DIR_NAME);
+        let refused = get_asset(Extension(state.clone()), Path(hidden), HeaderMap::new()).await;
+        assert_eq!(refused.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn thumbnail_is_generated_once_and_then_served_from_cache() {
+        let func_name = "thumbnail_is_generated_once_and_then_served_from_cache";
+        let ctx = TestCommandContext::new(func_name);
+
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+        let image_path = NodePath::from("big.png");
+        image::RgbImage::new(512, 300)
+            .save(image_path.full(&vault.vault_dirpath()))
+            .unwrap();
+
+        let first = ensure_thumbnail(&vault, &image_path, 256);
+        assert_eq!(first.is_ok(), true, "Thumbnail should be generated");
+        let first = first.unwrap();
+        assert_eq!(first.starts_with(thumbnail_dirpath(&vault)), true);
+
+        let thumb = image::open(&first).unwrap();
+        assert_eq!(thumb.width() <= 256 && thumb.height() <= 256, true, "Thumbnail should be downscaled");
+
+        let first_time = std::fs::metadata(&first).unwrap().modified().unwrap();
+
+        let second = ensure_thumbnail(&vault, &image_path, 256).unwrap();
+        let second_time = std::fs::metadata(&second).unwrap().modified().unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first_time, second_time, "Second request should be served from cache");
+
+        let leftovers = std::fs::read_dir(thumbnail_dirpath(&vault))
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .count();
+        assert_eq!(leftovers, 0, "Temporary files should be renamed into place");
+
+        remove_thumbnails(&vault, &image_path);
+        assert_eq!(first.exists(), false, "Thumbnail should be removed");
+    }
+
+    #[tokio::test]
+    async fn thumbnail_size_is_clamped() {
+        let func_name = "thumbnail_size_is_clamped";
+        let ctx = TestCommandContext::new(func_name);
+
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+        image::RgbImage::new(2048, 16)
+            .save(NodePath::from("wide.png").full(&vault.vault_dirpath()))
+            .unwrap();
+
+        let (state, _dir) = ctx.into_app_state();
+        let response = get_thumbnail(
+            Extension(state),
+            Path("wide.png".to_string()),
+            Query(ThumbnailParams { size: 100_000 }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let thumb = image::load_from_memory(&body).unwrap();
+        assert_eq!(thumb.width(), MAX_THUMBNAIL_SIZE, "Thumbnail should be no larger than the maximum");
+    }
+
+    #[test]
+    fn text_preview_of_ascii_file_is_capped() {
+        let func_name = "text_preview_of_ascii_file_is_capped";
+        let ctx = TestCommandContext::new(func_name);
+
+        let path = ctx.graph.user_root_dirpath().join("notes.txt");
+        std::fs::write(&path, "hello world").unwrap();
+
+        let short = read_text_preview(&path, 5).unwrap();
+        assert_eq!(short.text, "hello");
+        assert_eq!(short.truncated, true);
+        assert_eq!(short.is_binary, false);
+
+        let whole = read_text_preview(&path, 1024).unwrap();
+        assert_eq!(whole.text, "hello world");
+        assert_eq!(whole.truncated, false);
+
+        let missing = read_text_preview(&ctx.graph.user_root_dirpath().join("missing.txt"), 10);
+        assert_eq!(missing, Err(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn text_preview_of_virtual_text_node_reads_its_backing_file() {
+        let func_name = "text_preview_of_virtual_text_node_reads_its_backing_file";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+        let payload = CreateTextNodePayload {
+            parent_path: NodePath::user_root().alias(),
+            name: "note".to_string(),
+            content: "# Draft".to_string(),
+        };
+        create_text_node(Extension(state.clone()), Json(payload)).await.unwrap();
+
+        let response = get_text_preview(
+            Extension(state.clone()),
+            Path("note".to_string()),
+            Query(PreviewParams { bytes: 1024 }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let preview: TextPreview = serde_json::from_slice(&body).unwrap();
+        assert_eq!(preview.text, "# Draft");
+    }
+
+    #[test]
+    fn text_preview_does_not_split_multibyte_characters() {
+        let func_name = "text_preview_does_not_split_multibyte_characters";
+        let ctx = TestCommandContext::new(func_name);
+
+        // The emoji takes four bytes, starting at byte 3
+        let path = ctx.graph.user_root_dirpath().join("emoji.md");
+        std::fs::write(&path, "abc🦀def").unwrap();
+
+        for cutoff in 4..7 {
+            let preview = read_text_preview(&path, cutoff).unwrap();
+            assert_eq!(preview.text, "abc", "Partial emoji should be trimmed at {} bytes", cutoff);
+            assert_eq!(preview.is_binary, false);
+            assert_eq!(preview.truncated, true);
+        }
+
+        let preview = read_text_preview(&path, 7).unwrap();
+        assert_eq!(preview.text, "abc🦀");
+    }
+
+    #[test]
+    fn text_preview_of_binary_file_is_flagged() {
+        let func_name = "text_preview_of_binary_file_is_flagged";
+        let ctx = TestCommandContext::new(func_name);
+
+        let path = ctx.graph.user_root_dirpath().join("image.png");
+        File::create(&path).unwrap().write_all(&PNG_BYTES).unwrap();
+        let preview = read_text_preview(&path, 1024).unwrap();
+        assert_eq!(preview.is_binary, true, "Invalid UTF-8 should be flagged as binary");
+        assert_eq!(preview.text, "");
+
+        let nulls = ctx.graph.user_root_dirpath().join("nulls.txt");
+        std::fs::write(&nulls, b"text\0more").unwrap();
+        let preview = read_text_preview(&nulls, 1024).unwrap();
+        assert_eq!(preview.is_binary, true, "Null bytes should be flagged as binary");
+    }
+
+    #[test]
+    fn thumbnail_of_non_image_is_unsupported() {
+        let func_name = "thumbnail_of_non_image_is_unsupported";
+        let ctx = TestCommandContext::new(func_name);
+
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+        let text_path = NodePath::from("notes.txt");
+        File::create(text_path.full(&vault.vault_dirpath())).unwrap();
+
+        let result = ensure_thumbnail(&vault, &text_path, 256);
+        assert_eq!(result, Err(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+    }
+}
@@ -0,0 +1,623 @@
+use axum::{
+    extract::{Path, Query},
+    Extension, Json,
+};
+
+use crate::{prelude::*, vault::KartaVault};
+
+use super::{events, ApiError, AppState, ErrorCode, GraphEvent};
+
+#[derive(serde::Deserialize)]
+pub(super) struct SaveContextParams {
+    /// Drop view nodes that don't exist in the graph instead of rejecting the context.
+    #[serde(default)]
+    drop_unknown: bool,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SaveContextResponse {
+    /// The context as it was saved.
+    pub context: Context,
+    pub warnings: Vec<String>,
+}
+
+/// The state of a context after an operation changed it, so that a client can
+/// update its view without refetching.
+#[derive(Debug, serde::Serialize)]
+pub struct AffectedContext {
+    pub focal: NodePath,
+    /// The saved layout of the context, without the nodes that no longer exist.
+    pub saved: Option<Context>,
+    /// The nodes connected to the focal node, with the connecting edges.
+    pub nodes: Vec<(Node, Edge)>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AffectedContextsParams {
+    /// Include the new state of the contexts the operation changed in the response.
+    #[serde(default)]
+    pub include_contexts: bool,
+}
+
+/// Opens the current state of the context of the focal node.
+#[tracing::instrument(level = "debug", skip_all, fields(focal = %focal.alias()))]
+pub(super) fn open_context_from_path(
+    graph: &GraphCommands,
+    focal: &NodePath,
+) -> Result<AffectedContext, Box<dyn std::error::Error>> {
+    let db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+    let saved = db.get_context(focal)?.map(|mut context| {
+        context.nodes.retain(|node| graph.open_node(&node.path).is_ok());
+        context
+    });
+
+    Ok(AffectedContext {
+        focal: focal.clone(),
+        saved,
+        nodes: graph.open_node_connections(focal),
+    })
+}
+
+/// Opens the contexts of the focal nodes that still exist, each once.
+pub(super) fn open_affected_contexts(graph: &GraphCommands, focals: &[NodePath]) -> Vec<AffectedContext> {
+    let mut opened: Vec<AffectedContext> = Vec::new();
+    for focal in focals {
+        if opened.iter().any(|context| context.focal == *focal) || graph.open_node(focal).is_err() {
+            continue;
+        }
+        if let Ok(context) = open_context_from_path(graph, focal) {
+            opened.push(context);
+        }
+    }
+    opened
+}
+
+/// An UNKNOWN_NODES error with the unknown paths as its details.
+fn unknown_nodes_error(unknown: Vec<NodePath>) -> ApiError {
+    ApiError::new(ErrorCode::UnknownNodes, "Context refers to nodes that don't exist")
+        .with_details(unknown)
+}
+
+/// Saves the context of the focal node. Responds with UNKNOWN_NODES if the focal
+/// node or any of the view nodes don't exist, unless drop_unknown is set.
+pub(super) async fn save_context(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<SaveContextParams>,
+    Json(mut context): Json<Context>,
+) -> Result<Json<SaveContextResponse>, ApiError> {
+    // Write lock, so that two saves of the same context never interleave
+    let graph = state.graph_commands.write().unwrap();
+    context.focal = NodePath::from_alias(&id);
+
+    let warnings =
+        validate_context(&graph, &mut context, params.drop_unknown).map_err(unknown_nodes_error)?;
+
+    let db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+    db.save_context(&context)?;
+
+    events::publish(&state, GraphEvent::new("save_context", vec![context.focal.clone()]));
+    Ok(Json(SaveContextResponse { context, warnings }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct AddToContextPayload {
+    /// Alias of the node to add.
+    pub node: String,
+    /// Position of the node relative to the focal node.
+    #[serde(default)]
+    pub x: f32,
+    #[serde(default)]
+    pub y: f32,
+    /// Connect the node to the focal node with a context edge, unless they are
+    /// already connected.
+    #[serde(default)]
+    pub connect: bool,
+}
+
+/// Adds an existing node to the saved context of the focal node, without moving
+/// it anywhere in the graph or the file system. Adding a node that is already in
+/// the context changes nothing. Responds with the context as it now is.
+pub(super) async fn add_to_context(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<AddToContextPayload>,
+) -> Result<Json<AffectedContext>, ApiError> {
+    let mut graph = state.graph_commands.write().unwrap();
+    let focal = NodePath::from_alias(&id);
+
+    let context = execute_add_to_context(&mut graph, &focal, &payload)?;
+
+    events::publish(&state, GraphEvent::new("save_context", vec![focal]));
+    Ok(Json(context))
+}
+
+fn execute_add_to_context(
+    graph: &mut GraphCommands,
+    focal: &NodePath,
+    payload: &AddToContextPayload,
+) -> Result<AffectedContext, ApiError> {
+    let path = NodePath::from_alias(&payload.node);
+    for node in [focal, &path] {
+        if graph.open_node(node).is_err() {
+            return Err(ApiError::not_found(format!("Node {} does not exist", node.alias())));
+        }
+    }
+    if path == *focal {
+        return Err(ApiError::validation("A node can't be added to its own context"));
+    }
+
+    let db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+    let mut context = db.get_context(focal)?.unwrap_or_else(|| Context::new(focal.clone()));
+    if !context.nodes.iter().any(|node| node.path == path) {
+        context.nodes.push(ViewNode::new(path.clone(), payload.x, payload.y));
+        db.save_context(&context)?;
+    }
+
+    let pair = [focal.clone(), path.clone()];
+    if payload.connect && graph.get_edges_between_nodes(&pair)?.is_empty() {
+        graph.create_typed_edge(focal, &path, CONTEXT_EDGE_TYPE)?;
+    }
+
+    Ok(open_context_from_path(graph, focal)?)
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFromContextParams {
+    /// Alias of the node to remove.
+    pub node: String,
+    /// Also remove the context edge between the node and the focal node, if there is one.
+    #[serde(default)]
+    pub remove_edge: bool,
+}
+
+/// Removes a node from the saved context of the focal node. The node itself is
+/// kept. Children of the focal node are always part of its context, so they
+/// can't be removed from it. Responds with the context as it now is.
+pub(super) async fn remove_from_context(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<RemoveFromContextParams>,
+) -> Result<Json<AffectedContext>, ApiError> {
+    let mut graph = state.graph_commands.write().unwrap();
+    let focal = NodePath::from_alias(&id);
+
+    let context = execute_remove_from_context(&mut graph, &focal, &params)?;
+
+    events::publish(&state, GraphEvent::new("save_context", vec![focal]));
+    Ok(Json(context))
+}
+
+fn execute_remove_from_context(
+    graph: &mut GraphCommands,
+    focal: &NodePath,
+    params: &RemoveFromContextParams,
+) -> Result<AffectedContext, ApiError> {
+    let path = NodePath::from_alias(&params.node);
+    let edges = graph.get_edges_between_nodes(&[focal.clone(), path.clone()])?;
+    if edges.iter().any(|edge| edge.contains() && edge.source() == focal) {
+        return Err(ApiError::validation(format!(
+            "Node {} is a child of {} and can't be removed from its context",
+            path.alias(),
+            focal.alias()
+        )));
+    }
+
+    let db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+    let mut context = db
+        .get_context(focal)?
+        .filter(|context| context.nodes.iter().any(|node| node.path == path))
+        .ok_or_else(|| {
+            ApiError::not_found(format!("Node {} is not in the context of {}", path.alias(), focal.alias()))
+        })?;
+    context.nodes.retain(|node| node.path != path);
+    db.save_context(&context)?;
+
+    if params.remove_edge {
+        for edge in edges.into_iter().filter(|edge| edge.edge_type() == Some(CONTEXT_EDGE_TYPE)) {
+            graph.delete_edge(edge)?;
+        }
+    }
+
+    Ok(open_context_from_path(graph, focal)?)
+}
+
+#[derive(serde::Deserialize)]
+pub struct ReorderPayload {
+    /// Aliases of the nodes in the order they should be stacked, front to back.
+    pub order: Vec<String>,
+}
+
+/// Changes the stacking order of nodes in the saved context of the focal node.
+/// Responds with NOT_FOUND if the focal node has no saved context and with
+/// UNKNOWN_NODES if some of the nodes aren't in it.
+pub(super) async fn reorder_context(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<ReorderPayload>,
+) -> Result<Json<Context>, ApiError> {
+    // The context is read, changed and saved, so the write lock keeps another
+    // edit of it from being lost in between
+    let graph = state.graph_commands.write().unwrap();
+    let db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+    let focal = NodePath::from_alias(&id);
+
+    let mut context = db.get_context(&focal)?.ok_or_else(|| {
+        ApiError::not_found(format!("Node {} has no saved context", focal.alias()))
+    })?;
+
+    let order: Vec<NodePath> = payload.order.iter().map(|alias| NodePath::from_alias(alias)).collect();
+    context.reorder(&order).map_err(unknown_nodes_error)?;
+
+    db.save_context(&context)?;
+
+    events::publish(&state, GraphEvent::new("save_context", vec![focal]));
+    Ok(Json(context))
+}
+
+/// Responds with the focal nodes of the saved contexts the node is placed in,
+/// so that a client can tell where a node is used before deleting it.
+pub(super) async fn get_context_list(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<NodePath>>, ApiError> {
+    let graph = state.graph_commands.read().unwrap();
+    let path = NodePath::from_alias(&id);
+    if graph.open_node(&path).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+    }
+
+    let db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+    Ok(Json(db.contexts_containing(&path)?))
+}
+
+/// Settings to change. Settings that aren't given are kept as they are.
+#[derive(serde::Deserialize)]
+pub struct ContextSettingsPayload {
+    #[serde(default)]
+    pub background_color: Option<[f32; 4]>,
+    #[serde(default)]
+    pub default_zoom: Option<f32>,
+    #[serde(default)]
+    pub camera_position: Option<[f32; 2]>,
+}
+
+/// Updates the settings of the saved context of the focal node. A context is
+/// saved for the node if it has none yet. Responds with the saved context.
+pub(super) async fn update_context_settings(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<ContextSettingsPayload>,
+) -> Result<Json<Context>, ApiError> {
+    if payload.default_zoom.is_some_and(|zoom| !zoom.is_finite() || zoom <= 0.0) {
+        return Err(ApiError::validation("Default zoom must be a positive number"));
+    }
+
+    // Write lock for the same reason as in reorder_context
+    let graph = state.graph_commands.write().unwrap();
+    let focal = NodePath::from_alias(&id);
+    if graph.open_node(&focal).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", focal.alias())));
+    }
+
+    let db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+    let mut context = db.get_context(&focal)?.unwrap_or_else(|| Context::new(focal.clone()));
+
+    if let Some(color) = payload.background_color {
+        context.settings.background_color = Some(color);
+    }
+    if let Some(zoom) = payload.default_zoom {
+        context.settings.default_zoom = zoom;
+    }
+    if let Some(position) = payload.camera_position {
+        context.settings.camera_position = position;
+    }
+
+    db.save_context(&context)?;
+
+    events::publish(&state, GraphEvent::new("save_context", vec![focal]));
+    Ok(Json(context))
+}
+
+/// Checks that the nodes of the context exist. Unknown view nodes are either
+/// dropped with a warning or returned as an error. An unknown focal node is
+/// always an error.
+fn validate_context(
+    graph: &GraphCommands,
+    context: &mut Context,
+    drop_unknown: bool,
+) -> Result<Vec<String>, Vec<NodePath>> {
+    if graph.open_node(&context.focal).is_err() {
+        return Err(vec![context.focal.clone()]);
+    }
+
+    let (known, unknown): (Vec<ViewNode>, Vec<ViewNode>) = context
+        .nodes
+        .drain(..)
+        .partition(|node| graph.open_node(&node.path).is_ok());
+    context.nodes = known;
+
+    let unknown: Vec<NodePath> = unknown.into_iter().map(|node| node.path).collect();
+    if unknown.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !drop_unknown {
+        return Err(unknown);
+    }
+
+    Ok(unknown
+        .iter()
+        .map(|path| format!("Dropped unknown node {}", path.alias()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use axum::http::StatusCode;
+
+    use crate::graph_commands::TestCommandContext;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn context_with_unknown_node_is_rejected_or_pruned() {
+        let func_name = "context_with_unknown_node_is_rejected_or_pruned";
+        let mut ctx = TestCommandContext::new(func_name);
+        ctx.graph.create_node_by_path(&NodePath::from("focal/child"), None);
+
+        let root = ctx.graph.user_root_dirpath();
+        let (state, _dir) = ctx.into_app_state();
+        let db = ContextDb::new(&KartaVault::new(root));
+
+        let focal = NodePath::from("focal");
+        let bogus = NodePath::from("focal/bogus");
+        let mut context = Context::new(focal.clone());
+        context.nodes.push(ViewNode::new(NodePath::from("focal/child"), 0.0, 0.0));
+        context.nodes.push(ViewNode::new(bogus.clone(), 50.0, 0.0));
+
+        let rejected = save_context(
+            Extension(state.clone()),
+            Path(focal.alias()),
+            Query(SaveContextParams { drop_unknown: false }),
+            Json(context.clone()),
+        )
+        .await;
+        let rejected = rejected.unwrap_err();
+        assert_eq!(rejected.code.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let unknown: Vec<NodePath> = serde_json::from_value(rejected.details).unwrap();
+        assert_eq!(unknown, vec![bogus.clone()]);
+        assert_eq!(db.get_context(&focal).unwrap(), None, "Rejected context should not be saved");
+
+        let pruned = save_context(
+            Extension(state.clone()),
+            Path(focal.alias()),
+            Query(SaveContextParams { drop_unknown: true }),
+            Json(context),
+        )
+        .await;
+        assert_eq!(pruned.is_ok(), true);
+        let body = pruned.unwrap().0;
+        assert_eq!(body.warnings.len(), 1);
+        assert_eq!(body.context.nodes.len(), 1);
+        assert_eq!(db.get_context(&focal).unwrap(), Some(body.context));
+    }
+
+    #[tokio::test]
+    async fn reordering_saved_context_stores_new_z_indices() {
+        let func_name = "reordering_saved_context_stores_new_z_indices";
+        let ctx = TestCommandContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        let (state, _dir) = ctx.into_app_state();
+        let db = ContextDb::new(&KartaVault::new(root));
+
+        let focal = NodePath::from("focal");
+        let paths: Vec<NodePath> = ["a", "b", "c"]
+            .iter()
+            .map(|name| NodePath::from(format!("focal/{}", name).as_str()))
+            .collect();
+        let mut context = Context::new(focal.clone());
+        for path in paths.iter() {
+            context.nodes.push(ViewNode::new(path.clone(), 0.0, 0.0));
+        }
+        db.save_context(&context).unwrap();
+
+        let payload = ReorderPayload {
+            order: vec![paths[1].alias(), paths[2].alias(), paths[0].alias()],
+        };
+        let response = reorder_context(Extension(state.clone()), Path(focal.alias()), Json(payload)).await;
+        assert_eq!(response.is_ok(), true);
+
+        let saved = db.get_context(&focal).unwrap().unwrap();
+        let z = |path: &NodePath| saved.nodes.iter().find(|n| n.path == *path).unwrap().z_index;
+        assert_eq!(z(&paths[1]) > z(&paths[2]), true);
+        assert_eq!(z(&paths[2]) > z(&paths[0]), true);
+
+        let missing = reorder_context(
+            Extension(state.clone()),
+            Path(NodePath::from("no_context").alias()),
+            Json(ReorderPayload { order: vec![] }),
+        )
+        .await;
+        assert_eq!(missing.unwrap_err().code.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn context_settings_survive_reopening() {
+        let func_name = "context_settings_survive_reopening";
+        let mut ctx = TestCommandContext::new(func_name);
+        let focal = NodePath::from("focal");
+        ctx.graph.create_node_by_path(&NodePath::from("focal/child"), None);
+
+        let root = ctx.graph.user_root_dirpath();
+        let (state, _dir) = ctx.into_app_state();
+        let db = ContextDb::new(&KartaVault::new(root));
+
+        let mut context = Context::new(focal.clone());
+        context.nodes.push(ViewNode::new(NodePath::from("focal/child"), 5.0, 5.0));
+        db.save_context(&context).unwrap();
+
+        let payload = ContextSettingsPayload {
+            background_color: Some([0.1, 0.2, 0.3, 1.0]),
+            default_zoom: Some(2.0),
+            camera_position: None,
+        };
+        let updated = update_context_settings(Extension(state.clone()), Path(focal.alias()), Json(payload)).await;
+        assert_eq!(updated.is_ok(), true);
+
+        let reopened = db.get_context(&focal).unwrap().unwrap();
+        assert_eq!(reopened.settings.background_color, Some([0.1, 0.2, 0.3, 1.0]));
+        assert_eq!(reopened.settings.default_zoom, 2.0);
+        assert_eq!(reopened.settings.camera_position, [0.0, 0.0], "Unset settings should be kept");
+        assert_eq!(reopened.nodes, context.nodes, "Nodes should be untouched");
+
+        let opened = open_context_from_path(&state.graph_commands.read().unwrap(), &focal).unwrap();
+        assert_eq!(opened.saved.unwrap().settings, reopened.settings);
+
+        let invalid = ContextSettingsPayload {
+            background_color: None,
+            default_zoom: Some(0.0),
+            camera_position: None,
+        };
+        let rejected = update_context_settings(Extension(state.clone()), Path(focal.alias()), Json(invalid)).await;
+        assert_eq!(rejected.unwrap_err().code.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn pinned_view_nodes_stay_pinned_after_saving() {
+        let func_name = "pinned_view_nodes_stay_pinned_after_saving";
+        let mut ctx = TestCommandContext::new(func_name);
+        let focal = NodePath::from("focal");
+        let pinned = NodePath::from("focal/pinned");
+        let loose = NodePath::from("focal/loose");
+        ctx.graph.create_node_by_path(&pinned, None).unwrap();
+        ctx.graph.create_node_by_path(&loose, None).unwrap();
+
+        let root = ctx.graph.user_root_dirpath();
+        let (state, _dir) = ctx.into_app_state();
+        let db = ContextDb::new(&KartaVault::new(root));
+
+        let mut context = Context::new(focal.clone());
+        let mut pinned_node = ViewNode::new(pinned.clone(), 30.0, -15.0);
+        pinned_node.pinned = true;
+        context.nodes.push(pinned_node);
+        context.nodes.push(ViewNode::new(loose.clone(), 0.0, 0.0));
+
+        // Sent as JSON, like a client would
+        let payload: Context = serde_json::from_str(&serde_json::to_string(&context).unwrap()).unwrap();
+        let saved = save_context(
+            Extension(state.clone()),
+            Path(focal.alias()),
+            Query(SaveContextParams { drop_unknown: false }),
+            Json(payload),
+        )
+        .await;
+        assert_eq!(saved.is_ok(), true);
+
+        let reopened = db.get_context(&focal).unwrap().unwrap();
+        assert_eq!(reopened.nodes, context.nodes);
+        let pinned_paths: Vec<NodePath> = reopened
+            .nodes
+            .iter()
+            .filter(|node| node.pinned)
+            .map(|node| node.path.clone())
+            .collect();
+        assert_eq!(pinned_paths, vec![pinned]);
+
+        let mut without_pins = serde_json::to_value(&context).unwrap();
+        for node in without_pins["nodes"].as_array_mut().unwrap() {
+            node.as_object_mut().unwrap().remove("pinned");
+        }
+        let older: Context = serde_json::from_value(without_pins).unwrap();
+        assert_eq!(older.nodes.iter().any(|node| node.pinned), false, "Nodes should be unpinned by default");
+    }
+
+    #[test]
+    fn adding_node_to_context_leaves_the_file_system_alone() {
+        let func_name = "adding_node_to_context_leaves_the_file_system_alone";
+        let mut ctx = TestCommandContext::new(func_name);
+        let root = ctx.graph.user_root_dirpath();
+
+        let focal = NodePath::from("board");
+        let node = NodePath::from("notes/idea.txt");
+        ctx.graph.create_node_by_path(&focal, None).unwrap();
+        std::fs::create_dir_all(root.join("notes")).unwrap();
+        std::fs::File::create(node.full(&root)).unwrap();
+        ctx.graph.index_node_context(&NodePath::from("notes"));
+        assert_eq!(ctx.graph.open_node(&node).is_ok(), true);
+
+        let payload = AddToContextPayload { node: node.alias(), x: 40.0, y: -20.0, connect: true };
+        let opened = execute_add_to_context(&mut ctx.graph, &focal, &payload).unwrap();
+
+        let saved = opened.saved.expect("Context should be saved");
+        assert_eq!(saved.nodes, vec![ViewNode::new(node.clone(), 40.0, -20.0)]);
+        let connected = opened.nodes.iter().find(|(n, _)| n.path() == node);
+        assert_eq!(connected.map(|(_, edge)| edge.edge_type()), Some(Some(CONTEXT_EDGE_TYPE)));
+
+        assert_eq!(node.full(&root).exists(), true, "Node should stay where it is");
+        assert_eq!(focal.full(&root).exists(), false, "Nothing should be written under the focal node");
+        assert_eq!(ctx.graph.open_node(&node).is_ok(), true);
+
+        let moved = AddToContextPayload { node: node.alias(), x: 0.0, y: 0.0, connect: true };
+        let again = execute_add_to_context(&mut ctx.graph, &focal, &moved).unwrap();
+        assert_eq!(again.saved.unwrap().nodes, saved.nodes, "Adding twice should change nothing");
+        let edges = ctx.graph.get_edges_between_nodes(&[focal.clone(), node.clone()]).unwrap();
+        assert_eq!(edges.len(), 1);
+
+        let missing = AddToContextPayload { node: "notes/missing".to_string(), x: 0.0, y: 0.0, connect: false };
+        let rejected = execute_add_to_context(&mut ctx.graph, &focal, &missing);
+        assert_eq!(rejected.unwrap_err().code.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn removing_node_from_context_keeps_the_node() {
+        let func_name = "removing_node_from_context_keeps_the_node";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let focal = NodePath::from("board");
+        let child = NodePath::from("board/child");
+        let node = NodePath::from("elsewhere/node");
+        ctx.graph.create_node_by_path(&child, None).unwrap();
+        ctx.graph.create_node_by_path(&node, None).unwrap();
+
+        let add = |node: &NodePath| AddToContextPayload { node: node.alias(), x: 0.0, y: 0.0, connect: true };
+        execute_add_to_context(&mut ctx.graph, &focal, &add(&node)).unwrap();
+        execute_add_to_context(&mut ctx.graph, &focal, &add(&child)).unwrap();
+
+        let params = RemoveFromContextParams { node: node.alias(), remove_edge: true };
+        let opened = execute_remove_from_context(&mut ctx.graph, &focal, &params).unwrap();
+
+        let saved = opened.saved.unwrap();
+        assert_eq!(saved.nodes.iter().any(|n| n.path == node), false, "Node should be gone from the context");
+        assert_eq!(saved.nodes.iter().any(|n| n.path == child), true, "Other nodes should stay");
+        assert_eq!(opened.nodes.iter().any(|(n, _)| n.path() == node), false, "Context edge should be removed");
+        assert_eq!(ctx.graph.open_node(&node).is_ok(), true, "Node should still exist");
+
+        let again = execute_remove_from_context(&mut ctx.graph, &focal, &params);
+        assert_eq!(again.unwrap_err().code.status(), StatusCode::NOT_FOUND);
+
+        let params = RemoveFromContextParams { node: child.alias(), remove_edge: false };
+        let rejected = execute_remove_from_context(&mut ctx.graph, &focal, &params);
+        assert_eq!(rejected.unwrap_err().code.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn context_of_unknown_focal_node_is_rejected() {
+        let func_name = "context_of_unknown_focal_node_is_rejected";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let focal = NodePath::from("missing");
+        let response = save_context(
+            Extension(state.clone()),
+            Path(focal.alias()),
+            Query(SaveContextParams { drop_unknown: true }),
+            Json(Context::new(focal.clone())),
+        )
+        .await;
+        assert_eq!(response.unwrap_err().code.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
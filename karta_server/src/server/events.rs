@@ -0,0 +1,65 @@
+use axum::{
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    response::Response,
+    Extension,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::prelude::*;
+
+use super::AppState;
+
+/// Describes a change to the graph. Sent to all connected clients so that
+/// they can stay in sync.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GraphEvent {
+    pub operation: String,
+    pub paths: Vec<NodePath>,
+}
+
+impl GraphEvent {
+    pub fn new(operation: &str, paths: Vec<NodePath>) -> Self {
+        GraphEvent {
+            operation: operation.to_string(),
+            paths,
+        }
+    }
+
+    /// Sent to a client that fell behind and missed events. The client
+    /// should reload whatever it is displaying.
+    pub fn resync() -> Self {
+        GraphEvent::new("resync", vec![])
+    }
+}
+
+/// Publishes an event to all subscribers. Having no subscribers is not an error.
+pub(super) fn publish(state: &AppState, event: GraphEvent) {
+    if let Ok(json) = serde_json::to_string(&event) {
+        let _ = state.tx.send(json);
+    }
+}
+
+pub(super) async fn subscribe_to_events(
+    Extension(state): Extension<AppState>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| forward_events(socket, state))
+}
+
+async fn forward_events(mut socket: WebSocket, state: AppState) {
+    let mut rx = state.tx.subscribe();
+
+    loop {
+        let json = match rx.recv().await {
+            Ok(json) => json,
+            // Lagging receivers skip the missed events and tell the client to resync
+            Err(RecvError::Lagged(_)) => serde_json::to_string(&GraphEvent::resync()).unwrap(),
+            Err(RecvError::Closed) => break,
+        };
+
+        if socket.send(Message::Text(json)).await.is_err() {
+            // Client disconnected
+            break;
+        }
+    }
+}
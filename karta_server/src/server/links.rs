@@ -0,0 +1,224 @@
+use axum::{
+    extract::{Path, Query},
+    Extension, Json,
+};
+
+use crate::{prelude::*, vault::KartaVault};
+
+use super::{events, text_nodes::text_backing_file, ApiError, AppState, GraphEvent};
+
+/// Link targets of the [[wiki links]] in the content, in order of appearance
+/// and without duplicates. Aliases and headings, as in [[Note|alias]] and
+/// [[Note#Heading]], are left out of the target.
+pub fn extract_wiki_links(content: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let end = match rest.find("]]") {
+            Some(end) => end,
+            None => break,
+        };
+
+        let link = &rest[..end];
+        rest = &rest[end + 2..];
+
+        let target = link.split(['|', '#']).next().unwrap_or("").trim();
+        if !target.is_empty() && !links.iter().any(|l| l == target) {
+            links.push(target.to_string());
+        }
+    }
+
+    links
+}
+
+/// Resolves a link target to a node. Targets with slashes are paths from the
+/// user root, others are names in the directory of the linking node. A name
+/// without an extension also matches a markdown file of that name. Targets
+/// that don't match any node resolve to the path they name, which is checked
+/// to be valid so that a node can be created there.
+fn resolve_link(
+    graph: &GraphCommands,
+    source: &NodePath,
+    target: &str,
+) -> Result<NodePath, ApiError> {
+    let path = if target.contains('/') {
+        NodePath::from(target)
+    } else {
+        source
+            .parent()
+            .unwrap_or_else(NodePath::user_root)
+            .join(target)
+    };
+
+    if graph.open_node(&path).is_ok() {
+        return Ok(path);
+    }
+    let markdown = NodePath::from_alias(&format!("{}.md", path.alias()));
+    if graph.open_node(&markdown).is_ok() {
+        return Ok(markdown);
+    }
+
+    for component in path.buf().components() {
+        let name = component.as_os_str().to_string_lossy();
+        NodePath::validate_name(&name).map_err(|e| ApiError::validation(e.to_string()))?;
+    }
+    Ok(path)
+}
+
+/// Reads the content of the node, creates link edges to the targets of its
+/// wiki links and removes the link edges whose links are gone. All the links
+/// are resolved before anything is written, and targets without a node are
+/// created as virtual nodes along with the edges. Returns the paths of the
+/// linked nodes.
+pub(super) fn index_links(
+    graph: &mut GraphCommands,
+    vault: &KartaVault,
+    path: &NodePath,
+) -> Result<Vec<NodePath>, ApiError> {
+    if graph.open_node(path).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+    }
+
+    let file = text_backing_file(graph, vault, path)
+        .or_else(|| Some(path.full(&graph.user_root_dirpath())).filter(|full| full.is_file()))
+        .ok_or_else(|| ApiError::validation(format!("Node {} has no content", path.alias())))?;
+    let content = std::fs::read_to_string(file)
+        .map_err(|_| ApiError::validation(format!("Content of {} is not text", path.alias())))?;
+
+    let mut targets: Vec<NodePath> = Vec::new();
+    for link in extract_wiki_links(&content) {
+        let target = resolve_link(graph, path, &link)?;
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+
+    graph.set_link_edges(path, &targets)?;
+    Ok(targets)
+}
+
+pub(super) async fn index_node_links(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<NodePath>>, ApiError> {
+    let mut graph = state.graph_commands.write().unwrap();
+    let vault = KartaVault::new(graph.user_root_dirpath());
+    let path = NodePath::from_alias(&id);
+
+    let targets = index_links(&mut graph, &vault, &path)?;
+
+    events::publish(&state, GraphEvent::new("index_links", vec![path]));
+    Ok(Json(targets))
+}
+
+#[derive(serde::Deserialize)]
+pub(super) struct BacklinksParams {
+    /// Also list the parent of the node, which points at it with a contains edge.
+    #[serde(default)]
+    include_contains: bool,
+}
+
+/// The nodes that have an edge to the given node, along with the edges.
+pub(super) async fn get_backlinks(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<BacklinksParams>,
+) -> Result<Json<Vec<(Node, Edge)>>, ApiError> {
+    let graph = state.graph_commands.read().unwrap();
+    let path = NodePath::from_alias(&id);
+    if graph.open_node(&path).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+    }
+
+    let backlinks = graph.get_incoming_edges(&path, !params.include_contains)?;
+    Ok(Json(backlinks))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use crate::graph_commands::TestCommandContext;
+
+    use super::*;
+
+    #[test]
+    fn wiki_links_are_extracted_without_aliases_and_headings() {
+        let content = "See [[First]] and [[dir/Second|the second]], [[First#Intro]] and [[ ]] [[unclosed";
+        assert_eq!(
+            extract_wiki_links(content),
+            vec!["First".to_string(), "dir/Second".to_string()]
+        );
+    }
+
+    #[test]
+    fn reindexing_links_removes_stale_link_edges() {
+        let func_name = "reindexing_links_removes_stale_link_edges";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        let vault = KartaVault::new(root.clone());
+        std::fs::write(root.join("note.md"), "Links to [[first]] and [[second]].").unwrap();
+        std::fs::write(root.join("first.md"), "").unwrap();
+        ctx.graph.index_node_context(&NodePath::user_root());
+
+        let note = NodePath::from("note.md");
+        let first = NodePath::from("first.md");
+        // Doesn't exist yet, so it is created as a virtual node
+        let second = NodePath::from("second");
+
+        let linked = index_links(&mut ctx.graph, &vault, &note);
+        assert_eq!(linked.unwrap(), vec![first.clone(), second.clone()]);
+
+        let paths = vec![note.clone(), first.clone(), second.clone()];
+        let links = ctx
+            .graph
+            .get_edges_between_nodes_filtered(&paths, false, Some(LINK_EDGE_TYPE))
+            .unwrap();
+        assert_eq!(links.len(), 2, "Both links should become edges");
+        assert_eq!(links.iter().all(|edge| *edge.source() == note), true);
+
+        std::fs::write(root.join("note.md"), "Only links to [[first]] now.").unwrap();
+        index_links(&mut ctx.graph, &vault, &note).unwrap();
+
+        let links = ctx
+            .graph
+            .get_edges_between_nodes_filtered(&paths, false, Some(LINK_EDGE_TYPE))
+            .unwrap();
+        assert_eq!(links.len(), 1, "Removed link should lose its edge");
+        assert_eq!(*links[0].target(), first);
+    }
+
+    #[test]
+    fn invalid_link_leaves_the_graph_unchanged() {
+        let func_name = "invalid_link_leaves_the_graph_unchanged";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        let vault = KartaVault::new(root.clone());
+        std::fs::write(root.join("note.md"), "Links to [[ideas/new]] and [[.karta]].").unwrap();
+        ctx.graph.index_node_context(&NodePath::user_root());
+
+        let note = NodePath::from("note.md");
+        let ideas = NodePath::from("ideas");
+        let new = NodePath::from("ideas/new");
+
+        let linked = index_links(&mut ctx.graph, &vault, &note);
+        assert_eq!(linked.is_err(), true, "Reserved name should be refused");
+        assert_eq!(ctx.graph.open_node(&ideas).is_err(), true, "Nothing should be created");
+
+        std::fs::write(root.join("note.md"), "Links to [[ideas/new]].").unwrap();
+        let linked = index_links(&mut ctx.graph, &vault, &note).unwrap();
+        assert_eq!(linked, vec![new.clone()]);
+        assert_eq!(ctx.graph.open_node(&new).is_ok(), true);
+
+        let contains = ctx
+            .graph
+            .get_edges_between_nodes_filtered(&[ideas.clone(), new.clone()], true, None)
+            .unwrap();
+        assert_eq!(contains.len(), 1, "Missing ancestor should be created as the parent");
+        assert_eq!(contains[0].contains(), true);
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+    Extension, Json,
+};
+
+use crate::{prelude::*, vault::KartaVault};
+
+use super::AppState;
+
+/// How long the measured size of the karta folder is reused for. Walking
+/// the folder of a big vault is too slow to do on every request.
+const STORAGE_SIZE_CACHE_DURATION: Duration = Duration::from_secs(60);
+
+/// Request counters of the server. Shared between all the clones of the app state.
+pub(super) struct Metrics {
+    started: Instant,
+    total_requests: AtomicU64,
+    endpoint_requests: Mutex<HashMap<String, u64>>,
+    storage_size: Mutex<Option<(Instant, u64)>>,
+}
+
+impl Metrics {
+    pub(super) fn new() -> Self {
+        Metrics {
+            started: Instant::now(),
+            total_requests: AtomicU64::new(0),
+            endpoint_requests: Mutex::new(HashMap::new()),
+            storage_size: Mutex::new(None),
+        }
+    }
+
+    fn record(&self, endpoint: &str) {
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+        let mut endpoints = self.endpoint_requests.lock().unwrap();
+        *endpoints.entry(endpoint.to_string()).or_insert(0) += 1;
+    }
+
+    /// Size of the karta folders of the vault in bytes, measured at most once per
+    /// cache duration. A db stored outside the vault is measured where it is.
+    fn storage_size(&self, vault: &KartaVault) -> u64 {
+        let mut cached = self.storage_size.lock().unwrap();
+        if let Some((measured, size)) = *cached {
+            if measured.elapsed() < STORAGE_SIZE_CACHE_DURATION {
+                return size;
+            }
+        }
+
+        let karta_dirpath = vault.karta_dirpath();
+        let db_dirpath = vault.storage_dirpath().join(KARTA_DIR_NAME);
+        let mut size = dir_size(&karta_dirpath);
+        if db_dirpath != karta_dirpath {
+            size += dir_size(&db_dirpath);
+        }
+        *cached = Some((Instant::now(), size));
+        size
+    }
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MetricsResponse {
+    pub uptime_secs: u64,
+    pub total_requests: u64,
+    /// Request counts keyed by route, such as "/nodes/*id".
+    pub endpoint_requests: HashMap<String, u64>,
+    pub indexed_nodes: usize,
+    pub indexed_edges: usize,
+    /// Size of the karta folder of the vault in bytes, along with the db if it
+    /// is stored elsewhere.
+    pub storage_size: u64,
+}
+
+/// Counts the requests to each route. Added as a route layer, so requests
+/// that don't match any route aren't counted.
+pub(super) async fn count_requests(
+    Extension(state): Extension<AppState>,
+    matched: MatchedPath,
+    request: Request,
+    next: Next,
+) -> Response {
+    state.metrics.record(matched.as_str());
+    next.run(request).await
+}
+
+pub(super) async fn get_metrics(Extension(state): Extension<AppState>) -> Json<MetricsResponse> {
+    let graph = state.graph_commands.read().unwrap();
+    let vault = KartaVault::new(graph.user_root_dirpath())
+        .with_storage_override(graph.storage_path().strg_path());
+    let metrics = &state.metrics;
+
+    Json(MetricsResponse {
+        uptime_secs: metrics.started.elapsed().as_secs(),
+        total_requests: metrics.total_requests.load(Ordering::Relaxed),
+        endpoint_requests: metrics.endpoint_requests.lock().unwrap().clone(),
+        indexed_nodes: graph.get_node_count(),
+        indexed_edges: graph.get_edge_count(),
+        storage_size: metrics.storage_size(&vault),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use crate::graph_commands::TestCommandContext;
+
+    use super::{super::create_router, *};
+
+    #[tokio::test]
+    async fn metrics_count_requests_per_endpoint() {
+        let func_name = "metrics_count_requests_per_endpoint";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+        let router = create_router(state.clone());
+
+        for uri in ["/nodes", "/nodes", "/nodes/", "/not_a_route"] {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            router.clone().oneshot(request).await.unwrap();
+        }
+
+        let request = Request::builder().uri("/metrics").body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let metrics: MetricsResponse = serde_json::from_slice(&body).unwrap();
+
+        // The request to the metrics itself is counted too
+        assert_eq!(metrics.total_requests, 4);
+        assert_eq!(metrics.endpoint_requests.get("/nodes"), Some(&2));
+        assert_eq!(metrics.endpoint_requests.get("/nodes/"), Some(&1));
+        assert_eq!(metrics.endpoint_requests.get("/metrics"), Some(&1));
+        assert_eq!(metrics.indexed_nodes > 0, true);
+        assert_eq!(metrics.indexed_edges > 0, true, "Archetypes are contained by the root");
+        assert_eq!(metrics.storage_size > 0, true, "The db should take up some space");
+    }
+
+    #[tokio::test]
+    async fn storage_size_includes_db_stored_outside_the_vault() {
+        let func_name = "storage_size_includes_db_stored_outside_the_vault";
+        let ctx = TestCommandContext::custom_storage(func_name);
+
+        let storage = ctx.graph.storage_path().strg_path().unwrap();
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+        assert_ne!(storage.join(KARTA_DIR_NAME), vault.karta_dirpath(), "Db should be outside the karta folder");
+
+        let (state, _dir) = ctx.into_app_state();
+        let metrics = get_metrics(Extension(state)).await.0;
+        assert_eq!(metrics.storage_size >= dir_size(&storage.join(KARTA_DIR_NAME)), true);
+        assert_eq!(metrics.storage_size > 0, true, "The db should be measured where it is");
+    }
+}
@@ -1,18 +1,62 @@
+use crate::elements::attribute::RESERVED_NODE_ATTRS;
 use crate::prelude::*;
 use axum::{
-    extract::{Path, State},
-    routing::{get, post},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, patch, post, put},
     Extension, Json, Router,
 };
-use std::{io::{self, Write}, sync::RwLock};
+use std::{collections::HashSet, io::{self, Write}, sync::RwLock};
 use std::path::PathBuf;
-use std::{error::Error, sync::Arc};
+use std::{error::Error, future::Future, sync::Arc};
 use tokio::sync::broadcast;
 
+#[deny(warnings)]
+mod api_error;
+#[deny(warnings)]
+mod asset_endpoints;
+#[deny(warnings)]
+mod context_endpoints;
+#[deny(warnings)]
+mod events;
+#[deny(warnings)]
+mod links;
+#[deny(warnings)]
+mod metrics;
+#[deny(warnings)]
+mod move_nodes;
+#[deny(warnings)]
+mod text_nodes;
+#[deny(warnings)]
+mod trash;
+
+pub use api_error::{ApiError, ErrorCode};
+pub use asset_endpoints::{read_text_preview, TextPreview};
+pub use context_endpoints::{AffectedContext, SaveContextResponse};
+pub use events::GraphEvent;
+pub use links::extract_wiki_links;
+pub use metrics::MetricsResponse;
+pub use move_nodes::{MoveNodesResponse, MovedNode};
+pub use text_nodes::TEXT_FILE_ATTR;
+pub use trash::{list_trash, purge_trash, TrashEntry};
+
 #[derive(Clone)]
 pub struct AppState {
     graph_commands: Arc<RwLock<GraphCommands>>,
     tx: broadcast::Sender<String>,
+    metrics: Arc<metrics::Metrics>,
+}
+
+impl AppState {
+    pub fn new(graph_commands: GraphCommands) -> Self {
+        let (tx, _rx) = broadcast::channel(100);
+        AppState {
+            graph_commands: Arc::new(RwLock::new(graph_commands)),
+            tx,
+            metrics: Arc::new(metrics::Metrics::new()),
+        }
+    }
 }
 
 pub fn create_router(state: AppState) -> Router {
@@ -21,23 +65,67 @@ pub fn create_router(state: AppState) -> Router {
 
         .route("/idx/*id", post(index_node_connections))
 
-        .route("/nodes", get(get_all_aliases))
+        .route("/nodes", get(get_all_aliases).delete(delete_nodes))
 
         .route("/nodes/", get(get_root_node))
-        .route("/nodes/*id", get(get_node))
+        .route("/nodes/*id", get(get_node).post(create_node))
+        .route("/text-nodes", post(text_nodes::create_text_node))
+        .route("/content/*id", put(text_nodes::update_text_content))
+        .route("/rename/*id", put(rename_node))
+        .route("/duplicate/*id", post(duplicate_node))
+        .route("/type/*id", put(set_node_type))
+        .route("/attrs/*id", put(update_node_attrs))
+        .route("/reveal/*id", get(reveal_node))
+        .route("/move", post(move_nodes::move_nodes))
+        .route("/ops/merge", post(merge_nodes))
+        .route("/index-links/*id", post(links::index_node_links))
+        .route("/backlinks/*id", get(links::get_backlinks))
+
+        .route("/ctx/*id", get(get_node_context).put(context_endpoints::save_context).post(context_endpoints::add_to_context).delete(context_endpoints::remove_from_context))
+        .route("/reorder/*id", patch(context_endpoints::reorder_context))
+        .route("/ctx-settings/*id", patch(context_endpoints::update_context_settings))
+        .route("/context-list/*id", get(context_endpoints::get_context_list))
+
+        .route("/search", get(search_nodes))
+        .route("/query/by-attribute", get(find_nodes_by_attribute))
+        .route("/query/by-attribute-range", get(find_nodes_by_attribute_range))
+        .route("/query/recent", get(get_recent_nodes))
+        .route("/query/resolve", post(resolve_nodes))
+
+        .route("/export/*path", get(export_subtree))
+        .route("/import", post(import_bundle))
+
+        .route("/asset/*path", get(asset_endpoints::get_asset))
+        .route("/thumbnail/*path", get(asset_endpoints::get_thumbnail))
+        .route("/preview/*path", get(asset_endpoints::get_text_preview))
+
+        .route("/maintenance/vacuum", post(vacuum))
 
-        .route("/ctx/*id", get(get_node_context))
+        .route("/trash", get(trash::get_trash))
+        .route("/trash/purge", post(trash::purge_trash_log))
+
+        .route("/events", get(events::subscribe_to_events))
+        .route("/metrics", get(metrics::get_metrics))
+        .route_layer(axum::middleware::from_fn(metrics::count_requests))
         // .with_state(state)
         .layer(Extension(state));
     router
 }
 
-async fn index_node_connections(Extension(state): Extension<AppState>, Path(id): Path<String>) {
+/// Responds with the number of children of the node and whether only some of
+/// them were indexed.
+async fn index_node_connections(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+) -> Json<ContextIndex> {
     let nodepath = NodePath::from_alias(&id);
 
     let mut graph = state.graph_commands.write().unwrap();
 
-    graph.index_node_context(&nodepath);
+    let index = graph.index_node_context(&nodepath);
+
+    events::publish(&state, GraphEvent::new("index", vec![nodepath]));
+    Json(index)
 }
 
 async fn root() -> &'static str {
@@ -66,29 +154,719 @@ async fn get_node(
 ) -> Json<Result<Node, String>> {
     let graph = &state.graph_commands.read().unwrap();
 
-    println!("Requested node with id: {}", id);
+    tracing::debug!("Requested node with id: {}", id);
     let node_path = NodePath::from_alias(&id);
-    println!("Resulting node_path: {:#?}", node_path);
-    println!("Resulting alias: {}", node_path.alias());
+    tracing::debug!("Resulting node_path: {:#?}", node_path);
+    tracing::debug!("Resulting alias: {}", node_path.alias());
     let result = graph
         .open_node(&node_path)
         .map_err(|e| e.to_string());
     Json(result)
 }
 
+/// A node as a client refers to it, either by its id in the database or by its path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeHandle {
+    Id(agdb::DbId),
+    Path(NodePath),
+}
+
+/// Parses a handle. Handles made up of only digits are ids, anything else is a
+/// path. Aliases start with a slash, so a node named with just a number is still
+/// found by its alias.
+pub fn parse_node_handle(handle: &str) -> NodeHandle {
+    let trimmed = handle.trim();
+    if !trimmed.is_empty() && trimmed.chars().all(|c| c.is_ascii_digit()) {
+        if let Ok(id) = trimmed.parse::<i64>() {
+            return NodeHandle::Id(agdb::DbId(id));
+        }
+    }
+    NodeHandle::Path(NodePath::from_alias(trimmed))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResolveNodesPayload {
+    pub handles: Vec<String>,
+}
+
+/// A handle along with the node it refers to, or None if there is no such node.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ResolvedHandle {
+    pub handle: String,
+    pub node: Option<Node>,
+}
+
+/// Opens many nodes at once, such as when restoring a saved selection. Results
+/// are in the order of the handles.
+async fn resolve_nodes(
+    Extension(state): Extension<AppState>,
+    Json(payload): Json<ResolveNodesPayload>,
+) -> Json<Vec<ResolvedHandle>> {
+    let graph = state.graph_commands.read().unwrap();
+    Json(execute_resolve_nodes(&graph, payload))
+}
+
+fn execute_resolve_nodes(graph: &GraphCommands, payload: ResolveNodesPayload) -> Vec<ResolvedHandle> {
+    payload
+        .handles
+        .into_iter()
+        .map(|handle| {
+            let node = match parse_node_handle(&handle) {
+                NodeHandle::Id(id) => graph.open_node_by_id(id),
+                NodeHandle::Path(path) => graph.open_node(&path),
+            };
+            ResolvedHandle { handle, node: node.ok() }
+        })
+        .collect()
+}
+
+/// Operation id returned for dry runs, so that clients can tell a preview
+/// apart from an actual deletion.
+pub const DRY_RUN_OPERATION_ID: &str = "dry_run";
+
+/// Paths of the nodes to delete, given as aliases.
+#[derive(serde::Deserialize)]
+pub struct DeleteNodesPayload {
+    pub paths: Vec<String>,
+    /// If true, only validate the deletion and collect warnings. Nothing is deleted.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteNodesParams {
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Include the new state of the parents' contexts in the response.
+    #[serde(default)]
+    pub include_contexts: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct FailedDeletion {
+    pub path: NodePath,
+    pub error: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DeleteNodesResponse {
+    /// The nodes that were deleted, or would be deleted in a dry run.
+    /// Includes the descendants of the requested nodes.
+    pub deleted: Vec<NodePath>,
+    pub failed_deletions: Vec<FailedDeletion>,
+    pub warnings: Vec<String>,
+    pub operation_id: String,
+    /// Focal nodes of the saved contexts the deleted nodes were removed from.
+    pub updated_contexts: Vec<NodePath>,
+    /// The contexts of the parents of the deleted nodes after the deletion.
+    /// Only included if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contexts: Option<Vec<AffectedContext>>,
+}
+
+/// Deletes the given nodes. Responds with 200 even if some of the deletions fail,
+/// the failures are listed in the response. Deleting a directory produces a warning
+/// about its descendants, which a client can show before confirming with a
+/// non-dry-run request.
+async fn delete_nodes(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<DeleteNodesParams>,
+    Json(mut payload): Json<DeleteNodesPayload>,
+) -> Json<DeleteNodesResponse> {
+    let mut graph = state.graph_commands.write().unwrap();
+
+    payload.dry_run = payload.dry_run || params.dry_run;
+
+    let mut response = execute_delete_nodes(&mut graph, payload);
+
+    if params.include_contexts && response.operation_id != DRY_RUN_OPERATION_ID {
+        let parents: Vec<NodePath> = response.deleted.iter().filter_map(|path| path.parent()).collect();
+        response.contexts = Some(context_endpoints::open_affected_contexts(&graph, &parents));
+    }
+
+    if response.operation_id != DRY_RUN_OPERATION_ID && !response.deleted.is_empty() {
+        events::publish(&state, GraphEvent::new("delete", response.deleted.clone()));
+    }
+    Json(response)
+}
+
+/// Deletes nodes in two phases. The first validates the requested paths and
+/// collects their descendants, the second deletes them all. Dry runs stop
+/// after the first phase.
+#[tracing::instrument(level = "debug", skip_all, fields(count = payload.paths.len(), dry_run = payload.dry_run))]
+fn execute_delete_nodes(
+    graph: &mut GraphCommands,
+    payload: DeleteNodesPayload,
+) -> DeleteNodesResponse {
+    let mut to_delete: Vec<NodePath> = Vec::new();
+    let mut queued: HashSet<NodePath> = HashSet::new();
+    let mut failed_deletions: Vec<FailedDeletion> = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+
+    // Phase 1: validation and descendant collection
+    for alias in payload.paths.iter() {
+        let path = NodePath::from_alias(alias);
+
+        if path.is_atype() {
+            failed_deletions.push(FailedDeletion {
+                path,
+                error: "Archetype nodes cannot be deleted".to_string(),
+            });
+            continue;
+        }
+
+        let node = match graph.open_node(&path) {
+            Ok(node) => node,
+            Err(e) => {
+                failed_deletions.push(FailedDeletion {
+                    path,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let descendants = graph.get_all_descendants(&path).unwrap_or_default();
+
+        if node.ntype_name() == NodeType::dir() || !descendants.is_empty() {
+            warnings.push(format!(
+                "{} is a directory, its {} descendants will also be deleted",
+                path.alias(),
+                descendants.len()
+            ));
+        }
+
+        for node_path in std::iter::once(path).chain(descendants.iter().map(|n| n.path())) {
+            if queued.insert(node_path.clone()) {
+                to_delete.push(node_path);
+            }
+        }
+    }
+
+    if payload.dry_run {
+        return DeleteNodesResponse {
+            deleted: to_delete,
+            failed_deletions,
+            warnings,
+            operation_id: DRY_RUN_OPERATION_ID.to_string(),
+            updated_contexts: Vec::new(),
+            contexts: None,
+        };
+    }
+
+    // Phase 2: deletion
+    if !to_delete.is_empty() {
+        if let Err(e) = graph.delete_nodes(&to_delete, false, false) {
+            failed_deletions.extend(to_delete.drain(..).map(|path| FailedDeletion {
+                path,
+                error: e.to_string(),
+            }));
+        }
+    }
+
+    let vault = KartaVault::new(graph.user_root_dirpath());
+    for path in to_delete.iter() {
+        asset_endpoints::remove_thumbnails(&vault, path);
+    }
+
+    // Deleted nodes are removed from the saved contexts, so that the contexts
+    // don't refer to nodes that no longer exist. The contexts of deleted focal
+    // nodes are deleted along with them.
+    let context_db = ContextDb::new(&vault);
+    let mut updated_contexts: Vec<NodePath> = Vec::new();
+    let deleted: HashSet<&NodePath> = to_delete.iter().collect();
+    let mut updated: HashSet<NodePath> = HashSet::new();
+    for path in to_delete.iter() {
+        if let Err(e) = context_db.delete_context(path) {
+            warnings.push(format!("Saved context of {} could not be deleted: {}", path.alias(), e));
+        }
+    }
+    for path in to_delete.iter() {
+        match context_db.remove_node_from_contexts(path) {
+            Ok(focals) => {
+                for focal in focals {
+                    if !deleted.contains(&focal) && updated.insert(focal.clone()) {
+                        updated_contexts.push(focal);
+                    }
+                }
+            }
+            Err(e) => warnings.push(format!(
+                "{} could not be removed from the saved contexts: {}",
+                path.alias(),
+                e
+            )),
+        }
+    }
+
+    let operation_id = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos()
+        .to_string();
+
+    if !to_delete.is_empty() {
+        let entry = TrashEntry::new(operation_id.clone(), to_delete.clone());
+        if let Err(e) = trash::write_to_trash_log(&vault, &entry) {
+            warnings.push(format!("Deletion could not be written to the trash log: {}", e));
+        }
+    }
+
+    DeleteNodesResponse {
+        deleted: to_delete,
+        failed_deletions,
+        warnings,
+        operation_id,
+        updated_contexts,
+        contexts: None,
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SearchParams {
+    pub q: String,
+    #[serde(default = "default_search_limit")]
+    pub limit: usize,
+    pub modified_after: Option<u64>,
+    pub modified_before: Option<u64>,
+    pub created_after: Option<u64>,
+    /// Comma separated names of the allowed node types.
+    pub ntype: Option<String>,
+}
+
+fn default_search_limit() -> usize {
+    100
+}
+
+async fn search_nodes(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<Vec<SearchResult>>, ApiError> {
+    let graph = &state.graph_commands.read().unwrap();
+
+    let filter = Filter {
+        modified_after: params.modified_after,
+        modified_before: params.modified_before,
+        created_after: params.created_after,
+        ntypes: params
+            .ntype
+            .map(|ntypes| ntypes.split(',').map(|n| n.trim().to_string()).collect())
+            .unwrap_or_default(),
+    };
+
+    Ok(Json(graph.search_nodes(&params.q, &filter, params.limit)?))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RecentNodesParams {
+    #[serde(default = "default_recent_limit")]
+    pub limit: usize,
+}
+
+fn default_recent_limit() -> usize {
+    20
+}
+
+/// Responds with the most recently modified nodes, newest first.
+async fn get_recent_nodes(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<RecentNodesParams>,
+) -> Result<Json<Vec<SearchResult>>, ApiError> {
+    let graph = state.graph_commands.read().unwrap();
+    Ok(Json(graph.recent_nodes(params.limit)?))
+}
+
+#[derive(serde::Deserialize)]
+pub struct AttributeSearchParams {
+    pub name: String,
+    pub value: String,
+    /// Type of the value, one of "string", "float" or "uint". Defaults to string.
+    #[serde(default)]
+    pub kind: Option<String>,
+}
+
+impl AttributeSearchParams {
+    fn attr_value(&self) -> Result<AttrValue, ApiError> {
+        let invalid = |kind: &str| {
+            ApiError::validation(format!("Value {} is not a valid {}", self.value, kind))
+        };
+        match self.kind.as_deref().unwrap_or("string") {
+            "string" => Ok(AttrValue::String(self.value.clone())),
+            "float" => self.value.trim().parse().map(AttrValue::Float).map_err(|_| invalid("float")),
+            "uint" => self.value.trim().parse().map(AttrValue::UInt).map_err(|_| invalid("uint")),
+            other => Err(ApiError::validation(format!("Unknown attribute type {}", other))),
+        }
+    }
+}
+
+/// Responds with the nodes whose attribute of the given name has the given value.
+async fn find_nodes_by_attribute(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<AttributeSearchParams>,
+) -> Result<Json<Vec<Node>>, ApiError> {
+    let value = params.attr_value()?;
+    let graph = state.graph_commands.read().unwrap();
+    Ok(Json(graph.find_by_attribute(&params.name, &value)?))
+}
+
+#[derive(serde::Deserialize)]
+pub struct AttributeRangeParams {
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Responds with the nodes whose numeric attribute of the given name is
+/// between min and max, both inclusive.
+async fn find_nodes_by_attribute_range(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<AttributeRangeParams>,
+) -> Result<Json<Vec<Node>>, ApiError> {
+    if params.min.is_nan() || params.max.is_nan() {
+        return Err(ApiError::validation("Range bounds must be numbers"));
+    }
+    let graph = state.graph_commands.read().unwrap();
+    Ok(Json(graph.find_by_attribute_range(&params.name, params.min, params.max)?))
+}
+
+/// Exports the node and its descendants as a single bundle.
+async fn export_subtree(
+    Extension(state): Extension<AppState>,
+    Path(path): Path<String>,
+) -> Result<Json<ExportBundle>, ApiError> {
+    let graph = &state.graph_commands.read().unwrap();
+    let path = NodePath::from_alias(&path);
+    if graph.open_node(&path).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+    }
+
+    Ok(Json(graph.export_subtree(&path)?))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ImportBundlePayload {
+    pub bundle: ExportBundle,
+    /// Alias of the node to import the bundle under.
+    pub target_parent: String,
+    #[serde(default)]
+    pub rename_on_conflict: bool,
+}
+
+/// Imports a previously exported bundle under the target parent.
+async fn import_bundle(
+    Extension(state): Extension<AppState>,
+    Json(payload): Json<ImportBundlePayload>,
+) -> Result<Json<ImportResult>, ApiError> {
+    let mut graph = state.graph_commands.write().unwrap();
+
+    let target_parent = NodePath::from_alias(&payload.target_parent);
+    if graph.open_node(&target_parent).is_err() {
+        return Err(ApiError::not_found(format!(
+            "Target parent {} does not exist",
+            target_parent.alias()
+        )));
+    }
+
+    // The backing files of text nodes aren't part of the bundle. Left in, the
+    // imported nodes would share the files of the nodes they were exported from.
+    let mut bundle = payload.bundle;
+    for node in bundle.nodes.iter_mut() {
+        node.remove_attributes(&[TEXT_FILE_ATTR]);
+    }
+
+    let imported = graph.import_bundle(bundle, &target_parent, payload.rename_on_conflict)?;
+
+    events::publish(&state, GraphEvent::new("import", imported.nodes.clone()));
+    Ok(Json(imported))
+}
+
+/// Removes orphaned nodes and compacts the db.
+async fn vacuum(Extension(state): Extension<AppState>) -> Result<Json<VacuumStats>, ApiError> {
+    let mut graph = state.graph_commands.write().unwrap();
+
+    let stats = graph.vacuum()?;
+
+    if !stats.removed_nodes.is_empty() {
+        events::publish(&state, GraphEvent::new("delete", stats.removed_nodes.clone()));
+    }
+    Ok(Json(stats))
+}
+
+/// Creates a virtual node at the given path, along with any missing ancestors.
+/// Responds with a validation error if any name along the path is invalid.
+async fn create_node(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Node>, ApiError> {
+    let node_path = NodePath::from_alias(&id);
+    for component in node_path.buf().components() {
+        let name = component.as_os_str().to_string_lossy();
+        NodePath::validate_name(&name).map_err(|e| ApiError::validation(e.to_string()))?;
+    }
+
+    let mut graph = state.graph_commands.write().unwrap();
+
+    let node = graph.create_node_by_path(&node_path, None)?;
+
+    events::publish(&state, GraphEvent::new("create", vec![node_path]));
+    Ok(Json(node))
+}
+
+/// Copies a node next to itself under a unique name. Text nodes in the copy get
+/// copies of the backing files of the originals. Responds with the copy.
+async fn duplicate_node(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Node>, ApiError> {
+    let mut graph = state.graph_commands.write().unwrap();
+    let path = NodePath::from_alias(&id);
+    if graph.open_node(&path).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+    }
+
+    let copy = graph.duplicate_node(&path)?;
+    let vault = KartaVault::new(graph.user_root_dirpath());
+    text_nodes::copy_text_files(&mut graph, &vault, &path, &copy.path())?;
+    let copy = graph.open_node(&copy.path())?;
+
+    events::publish(&state, GraphEvent::new("create", vec![copy.path()]));
+    Ok(Json(copy))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RenameNodePayload {
+    pub new_name: String,
+}
+
+/// Renames a node within its parent and in the saved contexts. A taken name
+/// gets a number added to it, so the response holds the path the node actually
+/// ended up at. Moving a node
+/// under another parent is done by reparenting instead, so names with slashes
+/// are rejected.
+async fn rename_node(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<RenameNodePayload>,
+) -> Result<Json<NodePath>, ApiError> {
+    NodePath::validate_name(&payload.new_name).map_err(|e| ApiError::validation(e.to_string()))?;
+
+    let mut graph = state.graph_commands.write().unwrap();
+    let path = NodePath::from_alias(&id);
+    if graph.open_node(&path).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+    }
+
+    let new_path = graph.rename_node(&path, &payload.new_name)?;
+
+    // The node has been renamed either way, as when moving nodes
+    let db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+    if let Err(e) = db.rename_path(&path, &new_path) {
+        tracing::warn!("Saved contexts did not follow {}: {}", path.alias(), e);
+    }
+
+    events::publish(&state, GraphEvent::new("rename", vec![path, new_path.clone()]));
+    Ok(Json(new_path))
+}
+
+#[derive(serde::Deserialize)]
+pub struct MergeNodesPayload {
+    pub keep: String,
+    pub absorb: String,
+    #[serde(default)]
+    pub prefer: MergePreference,
+}
+
+/// Merges one virtual node into another and removes the absorbed node from
+/// the saved contexts. Responds with the kept node.
+async fn merge_nodes(
+    Extension(state): Extension<AppState>,
+    Json(payload): Json<MergeNodesPayload>,
+) -> Result<Json<Node>, ApiError> {
+    let mut graph = state.graph_commands.write().unwrap();
+    let keep = NodePath::from_alias(&payload.keep);
+    let absorb = NodePath::from_alias(&payload.absorb);
+    for path in [&keep, &absorb] {
+        if graph.open_node(path).is_err() {
+            return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+        }
+    }
+    if keep == absorb {
+        return Err(ApiError::validation("Cannot merge a node into itself"));
+    }
+    if absorb.full(&graph.user_root_dirpath()).exists() || keep.full(&graph.user_root_dirpath()).exists() {
+        return Err(ApiError::validation("Physical nodes cannot be merged"));
+    }
+
+    graph.merge_nodes(&keep, &absorb, payload.prefer)?;
+
+    let db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+    db.remove_node_from_contexts(&absorb)?;
+
+    let node = graph.open_node(&keep)?;
+    events::publish(&state, GraphEvent::new("merge", vec![keep, absorb]));
+    Ok(Json(node))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SetNodeTypePayload {
+    pub ntype: String,
+}
+
+/// Changes the type of a node. Physical directories can't be given a file type
+/// and physical files can't be made directories.
+async fn set_node_type(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetNodeTypePayload>,
+) -> Result<Json<Node>, ApiError> {
+    if payload.ntype.trim().is_empty() {
+        return Err(ApiError::validation("Node type cannot be empty"));
+    }
+
+    let mut graph = state.graph_commands.write().unwrap();
+    let path = NodePath::from_alias(&id);
+    if graph.open_node(&path).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+    }
+
+    graph.set_node_type(&path, NodeType::new(payload.ntype))?;
+
+    events::publish(&state, GraphEvent::new("update", vec![path.clone()]));
+    Ok(Json(graph.open_node(&path)?))
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateNodeAttrsPayload {
+    pub attributes: Vec<Attribute>,
+    /// The version of the node the client last saw. If given and the node has
+    /// changed since, nothing is updated.
+    #[serde(default)]
+    pub expected_version: Option<u64>,
+}
+
+/// Inserts or updates attributes of a node. Responds with VERSION_CONFLICT and
+/// the current node as details if the node has changed since the expected
+/// version, so that the client can merge its edits and retry.
+async fn update_node_attrs(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateNodeAttrsPayload>,
+) -> Result<Json<Node>, ApiError> {
+    if payload.attributes.is_empty() {
+        return Err(ApiError::validation("No attributes to update"));
+    }
+    if let Some(reserved) = payload
+        .attributes
+        .iter()
+        .find(|attr| RESERVED_NODE_ATTRS.contains(&attr.name.as_str()))
+    {
+        return Err(ApiError::validation(format!("Attribute name {} is reserved", reserved.name)));
+    }
+    // The backing file of a text node is only ever set by the server
+    if payload.attributes.iter().any(|attr| attr.name == TEXT_FILE_ATTR) {
+        return Err(ApiError::validation(format!("Attribute {} cannot be set", TEXT_FILE_ATTR)));
+    }
+
+    let mut graph = state.graph_commands.write().unwrap();
+    let path = NodePath::from_alias(&id);
+    let node = graph
+        .open_node(&path)
+        .map_err(|_| ApiError::not_found(format!("Node {} does not exist", path.alias())))?;
+
+    if let Some(expected) = payload.expected_version {
+        if expected != node.version() {
+            return Err(ApiError::new(
+                ErrorCode::VersionConflict,
+                format!(
+                    "Node {} is at version {}, not {}",
+                    path.alias(),
+                    node.version(),
+                    expected
+                ),
+            )
+            .with_details(node));
+        }
+    }
+
+    graph.insert_node_attrs(&path, payload.attributes)?;
+
+    events::publish(&state, GraphEvent::new("update", vec![path.clone()]));
+    Ok(Json(graph.open_node(&path)?))
+}
+
+/// Responds with the canonical absolute path of the file or directory of a node,
+/// so that it can be shown in the file manager of the OS. Virtual nodes have
+/// no such path and respond with NOT_FOUND.
+async fn reveal_node(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<PathBuf>, ApiError> {
+    let graph = state.graph_commands.read().unwrap();
+    let path = NodePath::from_alias(&id);
+    if graph.open_node(&path).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+    }
+
+    let full = path
+        .full(&graph.user_root_dirpath())
+        .canonicalize()
+        .map_err(|_| ApiError::not_found(format!("Node {} is not in the file system", path.alias())))?;
+    Ok(Json(full))
+}
+
+/// Responds with the connections of the node and an ETag. If the client sends
+/// a matching If-None-Match, responds with 304 and no body instead.
 async fn get_node_context(
     Extension(state): Extension<AppState>,
     Path(id): Path<String>,
-) -> Json<Vec<(Node, Edge)>> {
+    headers: HeaderMap,
+) -> Response {
     let graph = &state.graph_commands.read().unwrap();
     let node_path = NodePath::from_alias(&id);
+
+    // Checked before the connections are built, so that a cached context
+    // costs only the hash
+    let etag = format!("\"{:016x}\"", graph.connections_hash(&node_path));
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value == etag);
+    if cached {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
     let result = graph.open_node_connections(&node_path);
-    Json(result)
+    ([(header::ETAG, etag)], Json(result)).into_response()
 }
 
+/// Prompts for the vault and serves it on the default address.
 pub async fn run_server() {
-    let name = "karta_server";
-    let root_path = loop {
+    init_logging(0);
+
+    let root_path = match prompt_vault_path() {
+        Some(root_path) => root_path,
+        None => return,
+    };
+
+    let addr = ServerArgs::default().addr();
+    run_server_with_shutdown(KartaVault::new(root_path), &addr, shutdown_signal())
+        .await
+        .unwrap();
+}
+
+/// Completes when the process is asked to stop with Ctrl+C, so that the
+/// server can finish its requests and close the db cleanly.
+pub async fn shutdown_signal() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        // Without the signal there is nothing to wait for but the process being killed
+        tracing::error!("Failed to listen for Ctrl+C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Asks for the path of the vault until a directory is given. None if the
+/// user exits by entering nothing.
+pub fn prompt_vault_path() -> Option<PathBuf> {
+    loop {
         print!("Enter the path for the server (or press Enter to exit): ");
         io::stdout().flush().unwrap();
         let mut input = String::new();
@@ -97,28 +875,683 @@ pub async fn run_server() {
 
         if input.is_empty() {
             println!("Exiting server.");
-            return;
+            return None;
         }
 
         let path = PathBuf::from(input);
         if path.is_dir() {
-            break path;
+            return Some(path);
         } else {
             println!("Invalid path. Please enter a valid directory path.");
         }
-    };
+    }
+}
 
-    let graph_commands = Arc::new(RwLock::new(GraphCommands::new(
-        name,
-        root_path.clone(),
-        Some(root_path.clone()),
-    )));
-    let (tx, _rx) = broadcast::channel(100);
+/// Serves the vault on the address until the shutdown future completes.
+pub async fn run_server_with_shutdown<F>(vault: KartaVault, addr: &str, shutdown: F) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let name = "karta_server";
+    let state = AppState::new(GraphCommands::open_vault(name, &vault));
 
-    let state = AppState { graph_commands, tx };
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!(
+        "Serving vault {} on {}, with the db in {}",
+        vault.vault_dirpath().display(),
+        addr,
+        vault.storage_dirpath().display()
+    );
+    serve(listener, state, shutdown).await
+}
 
+/// Serves requests from the listener until the shutdown future completes. Requests
+/// that are being handled when it does are finished before returning.
+pub async fn serve<F>(listener: tokio::net::TcpListener, state: AppState, shutdown: F) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
     let app = create_router(state);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use std::fs::{create_dir_all, File};
+
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use crate::graph_commands::TestCommandContext;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn creating_node_broadcasts_event_to_subscribers() {
+        let func_name = "creating_node_broadcasts_event_to_subscribers";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let mut rx = state.tx.subscribe();
+
+        let path = NodePath::from("created");
+        let created = create_node(Extension(state.clone()), Path(path.alias())).await;
+        assert_eq!(created.is_ok(), true, "Node should be created");
+
+        let event = rx.try_recv();
+        assert_eq!(event.is_ok(), true, "Event should be received");
+
+        let event: GraphEvent = serde_json::from_str(&event.unwrap()).unwrap();
+        assert_eq!(event, GraphEvent::new("create", vec![path]));
+    }
+
+    #[tokio::test]
+    async fn creating_existing_node_keeps_its_version_and_persistence() {
+        let func_name = "creating_existing_node_keeps_its_version_and_persistence";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let path = NodePath::from("existing");
+        create_node(Extension(state.clone()), Path(path.alias())).await.unwrap();
+        {
+            let mut graph = state.graph_commands.write().unwrap();
+            let tag = Attribute::new_string("tag".to_string(), "kept".to_string());
+            graph.insert_node_attrs(&path, vec![tag]).unwrap();
+            graph.set_node_persistent(&path, true).unwrap();
+        }
+        let before = state.graph_commands.read().unwrap().open_node(&path).unwrap();
+        assert_eq!(before.version() > 0, true);
+
+        let created = create_node(Extension(state.clone()), Path(path.alias())).await;
+        assert_eq!(created.unwrap().0, before, "Existing node should be returned as it is");
+
+        let after = state.graph_commands.read().unwrap().open_node(&path).unwrap();
+        assert_eq!(after.version(), before.version(), "Version should survive");
+        assert_eq!(after.persistent(), true, "Persistent flag should survive");
+    }
+
+    #[tokio::test]
+    async fn nodes_named_like_other_routes_are_still_served() {
+        let func_name = "nodes_named_like_other_routes_are_still_served";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        // Directly under the root, so that their aliases are the bare names
+        let paths: Vec<NodePath> = ["by-attribute", "text", "merge", "by-attribute-range", "recent", "resolve"]
+            .iter()
+            .map(|name| NodePath::root().join(name))
+            .collect();
+        for path in paths.iter() {
+            ctx.graph.create_node_by_path(path, None).unwrap();
+        }
+        let (state, _dir) = ctx.into_app_state();
+        let router = create_router(state);
+
+        for path in paths {
+            let request = Request::builder().uri(format!("/nodes{}", path.alias())).body(Body::empty()).unwrap();
+            let response = router.clone().oneshot(request).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let node: Result<Node, String> = serde_json::from_slice(&body).unwrap();
+            assert_eq!(node.map(|node| node.path()), Ok(path.clone()), "{} should be served as a node", path.alias());
+        }
+    }
+
+    #[tokio::test]
+    async fn creating_node_with_reserved_name_is_a_bad_request() {
+        let func_name = "creating_node_with_reserved_name_is_a_bad_request";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let path = NodePath::from("dir/.karta");
+        let created = create_node(Extension(state.clone()), Path(path.alias())).await;
+
+        let error = created.unwrap_err();
+        assert_eq!(error.code.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.message, NameError::Reserved(".karta".to_string()).to_string());
+        assert_eq!(
+            state.graph_commands.read().unwrap().open_node(&NodePath::from("dir")).is_ok(),
+            false,
+            "Nothing should be created"
+        );
+    }
+
+    #[tokio::test]
+    async fn unchanged_context_responds_not_modified_to_matching_etag() {
+        let func_name = "unchanged_context_responds_not_modified_to_matching_etag";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let first = get_node_context(
+            Extension(state.clone()),
+            Path(NodePath::user_root().alias()),
+            HeaderMap::new(),
+        )
+        .await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = first.headers()[header::ETAG].clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+
+        let second = get_node_context(
+            Extension(state.clone()),
+            Path(NodePath::user_root().alias()),
+            headers.clone(),
+        )
+        .await;
+        assert_eq!(second.status(), StatusCode::NOT_MODIFIED);
+
+        // Changing the context changes the tag
+        state
+            .graph_commands
+            .write()
+            .unwrap()
+            .create_node_by_path(&NodePath::from("new"), None);
+
+        let third = get_node_context(
+            Extension(state.clone()),
+            Path(NodePath::user_root().alias()),
+            headers,
+        )
+        .await;
+        assert_eq!(third.status(), StatusCode::OK, "Changed context should not be cached");
+    }
+
+    #[tokio::test]
+    async fn attribute_edit_changes_context_etag() {
+        let func_name = "attribute_edit_changes_context_etag";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let node = NodePath::from("node");
+        state.graph_commands.write().unwrap().create_node_by_path(&node, None).unwrap();
+
+        let first = get_node_context(
+            Extension(state.clone()),
+            Path(NodePath::user_root().alias()),
+            HeaderMap::new(),
+        )
+        .await;
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, first.headers()[header::ETAG].clone());
+
+        // Neither the paths nor the structure of the context change
+        state
+            .graph_commands
+            .write()
+            .unwrap()
+            .insert_node_attrs(&node, vec![Attribute::new_float("x".into(), 12.0)])
+            .unwrap();
+
+        let second = get_node_context(
+            Extension(state.clone()),
+            Path(NodePath::user_root().alias()),
+            headers,
+        )
+        .await;
+        assert_eq!(second.status(), StatusCode::OK, "Edited attributes should not be cached");
+    }
+
+    #[tokio::test]
+    async fn renaming_file_keeps_it_in_the_same_directory() {
+        let func_name = "renaming_file_keeps_it_in_the_same_directory";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        create_dir_all(root.join("dir")).unwrap();
+        File::create(root.join("dir/old.txt")).unwrap();
+        File::create(root.join("dir/taken.txt")).unwrap();
+        ctx.graph.index_node_context(&NodePath::from("dir"));
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let renamed = rename_node(
+            Extension(state.clone()),
+            Path(NodePath::from("dir/old.txt").alias()),
+            Json(RenameNodePayload { new_name: "new.txt".to_string() }),
+        )
+        .await;
+        assert_eq!(renamed.unwrap().0, NodePath::from("dir/new.txt"));
+        assert_eq!(root.join("dir/old.txt").exists(), false);
+        assert_eq!(root.join("dir/new.txt").exists(), true);
+
+        let contained = state
+            .graph_commands
+            .read()
+            .unwrap()
+            .is_ancestor_of(&NodePath::from("dir"), &NodePath::from("dir/new.txt"));
+        assert_eq!(contained, true, "Parent should still contain the renamed node");
+
+        // A taken name gets a number added to it
+        let renamed = rename_node(
+            Extension(state.clone()),
+            Path(NodePath::from("dir/new.txt").alias()),
+            Json(RenameNodePayload { new_name: "taken.txt".to_string() }),
+        )
+        .await;
+        assert_eq!(renamed.unwrap().0, NodePath::from("dir/taken_2.txt"));
+        assert_eq!(root.join("dir/taken_2.txt").exists(), true);
+    }
+
+    #[tokio::test]
+    async fn renamed_node_keeps_its_saved_contexts() {
+        let func_name = "renamed_node_keeps_its_saved_contexts";
+        let mut ctx = TestCommandContext::new(func_name);
+        ctx.graph.create_node_by_path(&NodePath::from("old/child"), None).unwrap();
+
+        let root = ctx.graph.user_root_dirpath();
+        let db = ContextDb::new(&KartaVault::new(root.clone()));
+        let mut context = Context::new(NodePath::from("old"));
+        context.nodes.push(ViewNode::new(NodePath::from("old/child"), 7.0, 8.0));
+        db.save_context(&context).unwrap();
+
+        let (state, _dir) = ctx.into_app_state();
+        let renamed = rename_node(
+            Extension(state.clone()),
+            Path(NodePath::from("old").alias()),
+            Json(RenameNodePayload { new_name: "new".to_string() }),
+        )
+        .await;
+        assert_eq!(renamed.unwrap().0, NodePath::from("new"));
+
+        assert_eq!(db.get_context(&NodePath::from("old")).unwrap(), None);
+        let context = db.get_context(&NodePath::from("new")).unwrap().expect("Context should follow the node");
+        assert_eq!(context.nodes[0].path, NodePath::from("new/child"));
+        assert_eq!(context.nodes[0].x, 7.0);
+    }
+
+    #[tokio::test]
+    async fn renaming_with_a_slash_is_rejected() {
+        let func_name = "renaming_with_a_slash_is_rejected";
+        let mut ctx = TestCommandContext::new(func_name);
+        ctx.graph.create_node_by_path(&NodePath::from("dir/node"), None);
+        ctx.graph.create_node_by_path(&NodePath::from("other"), None);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let renamed = rename_node(
+            Extension(state.clone()),
+            Path(NodePath::from("dir/node").alias()),
+            Json(RenameNodePayload { new_name: "../other/node".to_string() }),
+        )
+        .await;
+        assert_eq!(renamed.unwrap_err().code, ErrorCode::Validation);
+        assert_eq!(
+            state.graph_commands.read().unwrap().open_node(&NodePath::from("dir/node")).is_ok(),
+            true,
+            "Node should stay where it was"
+        );
+    }
+
+    #[tokio::test]
+    async fn virtual_node_can_be_retyped() {
+        let func_name = "virtual_node_can_be_retyped";
+        let mut ctx = TestCommandContext::new(func_name);
+        ctx.graph.create_node_by_path(&NodePath::from("note"), None);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let retyped = set_node_type(
+            Extension(state.clone()),
+            Path(NodePath::from("note").alias()),
+            Json(SetNodeTypePayload { ntype: NodeType::text().name().to_string() }),
+        )
+        .await;
+        assert_eq!(retyped.unwrap().ntype_name(), NodeType::text());
+
+        let node = state.graph_commands.read().unwrap().open_node(&NodePath::from("note"));
+        assert_eq!(node.unwrap().ntype_name(), NodeType::text(), "Type should be stored");
+    }
+
+    #[tokio::test]
+    async fn physical_directory_cannot_be_given_a_file_type() {
+        let func_name = "physical_directory_cannot_be_given_a_file_type";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        create_dir_all(root.join("dir")).unwrap();
+        ctx.graph.index_node_context(&NodePath::user_root());
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let retyped = set_node_type(
+            Extension(state.clone()),
+            Path(NodePath::from("dir").alias()),
+            Json(SetNodeTypePayload { ntype: NodeType::file().name().to_string() }),
+        )
+        .await;
+        assert_eq!(retyped.unwrap_err().code, ErrorCode::Validation);
+
+        let node = state.graph_commands.read().unwrap().open_node(&NodePath::from("dir"));
+        assert_eq!(node.unwrap().ntype_name(), NodeType::dir(), "Type should not change");
+    }
+
+    #[tokio::test]
+    async fn stale_attribute_update_is_a_conflict() {
+        let func_name = "stale_attribute_update_is_a_conflict";
+        let mut ctx = TestCommandContext::new(func_name);
+        let path = NodePath::from("note");
+        ctx.graph.create_node_by_path(&path, None);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let status = |value: &str| vec![Attribute::new_string("status".to_string(), value.to_string())];
+        let initial = state.graph_commands.read().unwrap().open_node(&path).unwrap().version();
+
+        let updated = update_node_attrs(
+            Extension(state.clone()),
+            Path(path.alias()),
+            Json(UpdateNodeAttrsPayload { attributes: status("draft"), expected_version: Some(initial) }),
+        )
+        .await;
+        let updated = updated.unwrap().0;
+        assert_eq!(updated.version(), initial + 1, "Update should bump the version");
+
+        let stale = update_node_attrs(
+            Extension(state.clone()),
+            Path(path.alias()),
+            Json(UpdateNodeAttrsPayload { attributes: status("final"), expected_version: Some(initial) }),
+        )
+        .await;
+        let error = stale.unwrap_err();
+        assert_eq!(error.code.status(), StatusCode::CONFLICT);
+        let current: Node = serde_json::from_value(error.details).unwrap();
+        assert_eq!(current, updated, "Conflict should carry the current node");
+
+        let node = state.graph_commands.read().unwrap().open_node(&path).unwrap();
+        assert_eq!(node.attributes().contains(&status("draft")[0]), true, "Stale update should not be applied");
+    }
+
+    #[tokio::test]
+    async fn node_color_can_be_set_through_the_attributes() {
+        let func_name = "node_color_can_be_set_through_the_attributes";
+        let mut ctx = TestCommandContext::new(func_name);
+        let path = NodePath::from("node");
+        ctx.graph.create_node_by_path(&path, None);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let color = vec![Attribute::new_string(NODE_COLOR_ATTR.to_string(), "#ff8800".to_string())];
+        let updated = update_node_attrs(
+            Extension(state.clone()),
+            Path(path.alias()),
+            Json(UpdateNodeAttrsPayload { attributes: color.clone(), expected_version: None }),
+        )
+        .await;
+        assert_eq!(updated.unwrap().0.attributes().contains(&color[0]), true);
+
+        let node = state.graph_commands.read().unwrap().open_node(&path).unwrap();
+        assert_eq!(node.attributes().contains(&color[0]), true, "Color should be saved with the node");
+    }
+
+    #[tokio::test]
+    async fn revealing_node_gives_its_full_path_if_it_is_physical() {
+        let func_name = "revealing_node_gives_its_full_path_if_it_is_physical";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        create_dir_all(root.join("dir")).unwrap();
+        File::create(root.join("dir/file.txt")).unwrap();
+        ctx.graph.index_node_context(&NodePath::from("dir"));
+        ctx.graph.create_node_by_path(&NodePath::from("dir/virtual"), None);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let revealed = reveal_node(Extension(state.clone()), Path(NodePath::from("dir/file.txt").alias())).await;
+        let revealed = revealed.unwrap().0;
+        assert_eq!(revealed.is_absolute(), true);
+        assert_eq!(revealed, root.join("dir/file.txt").canonicalize().unwrap());
+
+        let virtual_node = reveal_node(Extension(state.clone()), Path(NodePath::from("dir/virtual").alias())).await;
+        assert_eq!(virtual_node.unwrap_err().code.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn server_stops_when_shutdown_is_signalled() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let func_name = "server_stops_when_shutdown_is_signalled";
+        let ctx = TestCommandContext::new(func_name);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, state, async {
+            let _ = shutdown_rx.await;
+        }));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert_eq!(response.starts_with("HTTP/1.1 200"), true, "{}", response);
+
+        shutdown_tx.send(()).unwrap();
+        let stopped = tokio::time::timeout(std::time::Duration::from_secs(5), server).await;
+        assert_eq!(stopped.is_ok(), true, "Server should stop after the signal");
+        assert_eq!(stopped.unwrap().unwrap().is_ok(), true);
+    }
+
+    #[test]
+    fn dry_run_directory_deletion_lists_descendants_and_deletes_nothing() {
+        let func_name = "dry_run_directory_deletion_lists_descendants_and_deletes_nothing";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        create_dir_all(root.join("dir/sub")).unwrap();
+        File::create(root.join("dir/file.txt")).unwrap();
+
+        let dir = NodePath::from("dir");
+        ctx.graph.index_node_context(&dir);
+
+        let response = execute_delete_nodes(
+            &mut ctx.graph,
+            DeleteNodesPayload {
+                paths: vec![dir.alias()],
+                dry_run: true,
+            },
+        );
+
+        assert_eq!(response.operation_id, DRY_RUN_OPERATION_ID);
+        assert_eq!(response.failed_deletions.len(), 0, "{:?}", response.failed_deletions);
+        assert_eq!(response.warnings.len(), 1, "Directory deletion should warn");
+        assert_eq!(response.deleted.len(), 3, "{:?}", response.deleted);
+        assert!(response.deleted.contains(&dir));
+        assert!(response.deleted.contains(&NodePath::from("dir/sub")));
+        assert!(response.deleted.contains(&NodePath::from("dir/file.txt")));
+
+        for path in response.deleted.iter() {
+            assert_eq!(path.full(&root).exists(), true, "{:?} should still exist on disk", path);
+            assert_eq!(ctx.graph.open_node(path).is_ok(), true, "{:?} should still be in the db", path);
+        }
+    }
+
+    #[test]
+    fn deleted_node_is_removed_from_saved_contexts() {
+        let func_name = "deleted_node_is_removed_from_saved_contexts";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let focal = NodePath::from("focal");
+        let deleted = NodePath::from("focal/deleted");
+        let kept = NodePath::from("focal/kept");
+        ctx.graph.create_node_by_path(&deleted, None).unwrap();
+        ctx.graph.create_node_by_path(&kept, None).unwrap();
+
+        let db = ContextDb::new(&KartaVault::new(ctx.graph.user_root_dirpath()));
+        let mut context = Context::new(focal.clone());
+        context.nodes.push(ViewNode::new(deleted.clone(), 0.0, 0.0));
+        context.nodes.push(ViewNode::new(kept.clone(), 10.0, 0.0));
+        db.save_context(&context).unwrap();
+
+        let response = execute_delete_nodes(
+            &mut ctx.graph,
+            DeleteNodesPayload {
+                paths: vec![deleted.alias()],
+                dry_run: false,
+            },
+        );
+        assert_eq!(response.deleted, vec![deleted.clone()]);
+        assert_eq!(response.updated_contexts, vec![focal.clone()]);
+
+        let saved = db.get_context(&focal).unwrap().unwrap();
+        let paths: Vec<NodePath> = saved.nodes.iter().map(|node| node.path.clone()).collect();
+        assert_eq!(paths, vec![kept], "Only the deleted node should be removed");
+        assert_eq!(db.contexts_containing(&deleted).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn resolving_handles_mixes_ids_and_paths() {
+        let func_name = "resolving_handles_mixes_ids_and_paths";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        for path in ["first", "second", "dir/third"] {
+            ctx.graph.create_node_by_path(&NodePath::from(path), None).unwrap();
+        }
+        let first = ctx.graph.open_node(&NodePath::from("first")).unwrap();
+        let second = ctx.graph.open_node(&NodePath::from("second")).unwrap();
+
+        let handles = vec![
+            first.id().unwrap().0.to_string(),
+            second.id().unwrap().0.to_string(),
+            NodePath::from("dir/third").alias(),
+            "missing".to_string(),
+        ];
+        let resolved = execute_resolve_nodes(&ctx.graph, ResolveNodesPayload { handles: handles.clone() });
+
+        assert_eq!(resolved.len(), 4);
+        let paths: Vec<Option<NodePath>> = resolved.iter().map(|r| r.node.as_ref().map(|n| n.path())).collect();
+        assert_eq!(paths[0], Some(NodePath::from("first")));
+        assert_eq!(paths[1], Some(NodePath::from("second")));
+        assert_eq!(paths[2], Some(NodePath::from("dir/third")));
+        assert_eq!(paths[3], None, "Missing node should be marked as not found");
+        assert_eq!(resolved.iter().map(|r| r.handle.clone()).collect::<Vec<_>>(), handles);
+
+        let numbered = NodePath::from("2024");
+        assert_eq!(parse_node_handle(&numbered.alias()), NodeHandle::Path(numbered));
+    }
+
+    #[test]
+    fn deleting_focal_node_deletes_its_saved_context() {
+        let func_name = "deleting_focal_node_deletes_its_saved_context";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let focal = NodePath::from("focal");
+        let other = NodePath::from("other");
+        ctx.graph.create_node_by_path(&focal, None).unwrap();
+        ctx.graph.create_node_by_path(&other, None).unwrap();
+
+        let db = ContextDb::new(&KartaVault::new(ctx.graph.user_root_dirpath()));
+        db.save_context(&Context::new(focal.clone())).unwrap();
+        db.save_context(&Context::new(other.clone())).unwrap();
+        assert_eq!(db.context_file_path(&focal).exists(), true);
+
+        let response = execute_delete_nodes(
+            &mut ctx.graph,
+            DeleteNodesPayload {
+                paths: vec![focal.alias()],
+                dry_run: false,
+            },
+        );
+        assert_eq!(response.failed_deletions.len(), 0, "{:?}", response.failed_deletions);
+
+        assert_eq!(db.context_file_path(&focal).exists(), false, "Context file should be removed");
+        assert_eq!(db.get_context(&other).unwrap().is_some(), true, "Other contexts should be kept");
+    }
+
+    #[tokio::test]
+    async fn exporting_missing_node_responds_not_found() {
+        let func_name = "exporting_missing_node_responds_not_found";
+        let mut ctx = TestCommandContext::new(func_name);
+        ctx.graph.create_node_by_path(&NodePath::from("present"), None).unwrap();
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let exported = export_subtree(Extension(state.clone()), Path(NodePath::from("present").alias())).await;
+        assert_eq!(exported.is_ok(), true, "Existing node should be exported");
+
+        let missing = export_subtree(Extension(state.clone()), Path(NodePath::from("missing").alias())).await;
+        let error = missing.unwrap_err();
+        assert_eq!(error.code, ErrorCode::NotFound);
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn imported_nodes_do_not_share_text_files() {
+        let func_name = "imported_nodes_do_not_share_text_files";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let note = NodePath::from("original/note");
+        ctx.graph.create_node_by_path(&note, None).unwrap();
+        ctx.graph.create_node_by_path(&NodePath::from("copies"), None).unwrap();
+        ctx.graph
+            .insert_node_attrs(&note, vec![Attribute::new_string(TEXT_FILE_ATTR.to_string(), "text/1.md".to_string())])
+            .unwrap();
+        let bundle = ctx.graph.export_subtree(&NodePath::from("original")).unwrap();
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let payload = ImportBundlePayload {
+            bundle,
+            target_parent: NodePath::from("copies").alias(),
+            rename_on_conflict: false,
+        };
+        let imported = import_bundle(Extension(state.clone()), Json(payload)).await;
+        assert_eq!(imported.is_ok(), true, "Bundle should be imported");
+
+        let graph = state.graph_commands.read().unwrap();
+        let copy = graph.open_node(&NodePath::from("copies/original/note")).unwrap();
+        assert_eq!(
+            copy.attributes().iter().any(|attr| attr.name == TEXT_FILE_ATTR),
+            false,
+            "Imported node should not point to the text file of the original"
+        );
+    }
+
+    #[tokio::test]
+    async fn duplicated_nodes_do_not_share_text_files() {
+        let func_name = "duplicated_nodes_do_not_share_text_files";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let note = NodePath::from("original/note");
+        ctx.graph.create_node_by_path(&note, None).unwrap();
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+        let text_dir = vault.karta_dirpath().join("text");
+        std::fs::create_dir_all(&text_dir).unwrap();
+        std::fs::write(text_dir.join("1.md"), "hello").unwrap();
+        ctx.graph
+            .insert_node_attrs(&note, vec![Attribute::new_string(TEXT_FILE_ATTR.to_string(), "text/1.md".to_string())])
+            .unwrap();
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let copy = duplicate_node(Extension(state.clone()), Path(NodePath::from("original").alias())).await;
+        assert_eq!(copy.unwrap().0.path(), NodePath::from("original_2"));
+
+        let graph = state.graph_commands.read().unwrap();
+        let copied_note = NodePath::from("original_2/note");
+        let file = text_nodes::text_backing_file(&graph, &vault, &copied_note)
+            .expect("Copied note should have a text file of its own");
+        assert_ne!(file, text_dir.join("1.md"), "Copy should not point to the text file of the original");
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "hello", "Content should be copied");
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+        std::fs::write(&file, "changed").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(text_dir.join("1.md")).unwrap(),
+            "hello",
+            "Editing the copy should not change the original"
+        );
+    }
 }
@@ -0,0 +1,181 @@
+use axum::{extract::Query, Extension, Json};
+
+use crate::prelude::*;
+
+use super::{
+    context_endpoints::{open_affected_contexts, AffectedContext, AffectedContextsParams},
+    events, ApiError, AppState, GraphEvent,
+};
+
+#[derive(serde::Deserialize)]
+pub struct MoveNodesPayload {
+    /// Aliases of the nodes to move.
+    pub paths: Vec<String>,
+    /// Alias of the node to move them under.
+    pub target_parent: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct MovedNode {
+    pub from: NodePath,
+    pub to: NodePath,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct FailedMove {
+    pub path: NodePath,
+    pub error: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct MoveNodesResponse {
+    pub moved: Vec<MovedNode>,
+    pub failed_moves: Vec<FailedMove>,
+    /// The contexts of the old parents and the target parent after the move.
+    /// Only included if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contexts: Option<Vec<AffectedContext>>,
+}
+
+/// Moves the given nodes under the target parent. Responds with 200 even if
+/// some of the moves fail, the failures are listed in the response.
+pub(super) async fn move_nodes(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<AffectedContextsParams>,
+    Json(payload): Json<MoveNodesPayload>,
+) -> Result<Json<MoveNodesResponse>, ApiError> {
+    let mut graph = state.graph_commands.write().unwrap();
+
+    let target_parent = NodePath::from_alias(&payload.target_parent);
+    if graph.open_node(&target_parent).is_err() {
+        return Err(ApiError::not_found(format!(
+            "Target parent {} does not exist",
+            target_parent.alias()
+        )));
+    }
+
+    let response = execute_move_nodes(&mut graph, &payload.paths, &target_parent, params.include_contexts);
+
+    if !response.moved.is_empty() {
+        let paths = response
+            .moved
+            .iter()
+            .flat_map(|moved| [moved.from.clone(), moved.to.clone()])
+            .collect();
+        events::publish(&state, GraphEvent::new("move", paths));
+    }
+    Ok(Json(response))
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(count = aliases.len(), target = %target_parent.alias()))]
+fn execute_move_nodes(
+    graph: &mut GraphCommands,
+    aliases: &[String],
+    target_parent: &NodePath,
+    include_contexts: bool,
+) -> MoveNodesResponse {
+    let mut moved: Vec<MovedNode> = Vec::new();
+    let mut failed_moves: Vec<FailedMove> = Vec::new();
+    let context_db = ContextDb::new(&KartaVault::new(graph.user_root_dirpath()));
+
+    for alias in aliases {
+        let path = NodePath::from_alias(alias);
+        match graph.reparent_node(&path, target_parent) {
+            Ok(()) => {
+                let to = target_parent.join(&path.name());
+                // The node has moved either way, so a context that can't be
+                // rewritten doesn't fail the move
+                if let Err(e) = context_db.rename_path(&path, &to) {
+                    tracing::warn!("Saved contexts did not follow {}: {}", path.alias(), e);
+                }
+                moved.push(MovedNode { from: path, to });
+            }
+            Err(e) => failed_moves.push(FailedMove {
+                path,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    let contexts = include_contexts.then(|| {
+        let focals: Vec<NodePath> = moved
+            .iter()
+            .filter_map(|moved| moved.from.parent())
+            .chain(std::iter::once(target_parent.clone()))
+            .collect();
+        open_affected_contexts(graph, &focals)
+    });
+
+    MoveNodesResponse {
+        moved,
+        failed_moves,
+        contexts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use crate::graph_commands::TestCommandContext;
+
+    use super::*;
+
+    #[test]
+    fn moved_node_appears_only_in_the_destination_context() {
+        let func_name = "moved_node_appears_only_in_the_destination_context";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let source = NodePath::from("source");
+        let target = NodePath::from("target");
+        let node = NodePath::from("source/node");
+        ctx.graph.create_node_by_path(&node, None).unwrap();
+        ctx.graph.create_node_by_path(&target, None).unwrap();
+
+        let response = execute_move_nodes(&mut ctx.graph, &[node.alias()], &target, true);
+        assert_eq!(response.failed_moves.len(), 0, "{:?}", response.failed_moves);
+        assert_eq!(response.moved[0].to, NodePath::from("target/node"));
+
+        let contexts = response.contexts.expect("Contexts should be included");
+        let focals: Vec<NodePath> = contexts.iter().map(|context| context.focal.clone()).collect();
+        assert_eq!(focals, vec![source, target]);
+
+        let contains_moved = |context: &AffectedContext| {
+            context
+                .nodes
+                .iter()
+                .any(|(node, _)| node.path().name() == "node")
+        };
+        assert_eq!(contains_moved(&contexts[0]), false, "Source should no longer have the node");
+        assert_eq!(contains_moved(&contexts[1]), true, "Destination should have the node");
+
+        let moved_back = [NodePath::from("target/node").alias()];
+        let response = execute_move_nodes(&mut ctx.graph, &moved_back, &NodePath::user_root(), false);
+        assert_eq!(response.contexts.is_none(), true, "Contexts are only included on request");
+    }
+
+    #[test]
+    fn moved_node_keeps_its_saved_contexts() {
+        let func_name = "moved_node_keeps_its_saved_contexts";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let node = NodePath::from("source/node");
+        let target = NodePath::from("target");
+        ctx.graph.create_node_by_path(&node.join("child"), None).unwrap();
+        ctx.graph.create_node_by_path(&target, None).unwrap();
+
+        let db = ContextDb::new(&KartaVault::new(ctx.graph.user_root_dirpath()));
+        let mut context = Context::new(node.clone());
+        context.nodes.push(ViewNode::new(node.join("child"), 1.0, 1.0));
+        db.save_context(&context).unwrap();
+
+        let response = execute_move_nodes(&mut ctx.graph, &[node.alias()], &target, false);
+        assert_eq!(response.failed_moves.len(), 0, "{:?}", response.failed_moves);
+
+        let moved = NodePath::from("target/node");
+        assert_eq!(db.get_context(&node).unwrap(), None);
+        let context = db.get_context(&moved).unwrap().expect("Context should follow the node");
+        assert_eq!(context.focal, moved);
+        assert_eq!(context.nodes[0].path, moved.join("child"));
+    }
+}
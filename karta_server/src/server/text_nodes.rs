@@ -0,0 +1,306 @@
+use std::path::{Component, PathBuf};
+
+use axum::{extract::Path, Extension, Json};
+
+use crate::{prelude::*, vault::KartaVault};
+
+use super::{events, ApiError, AppState, GraphEvent};
+
+pub use crate::elements::attribute::TEXT_FILE_ATTR;
+
+pub(super) fn text_dirpath(vault: &KartaVault) -> PathBuf {
+    vault.karta_dirpath().join("text")
+}
+
+/// Whether the value of a text file attribute points to a file in the text
+/// folder. Anything else, such as a path with "..", could reach outside the vault.
+pub(super) fn is_valid_text_file(file: &str) -> bool {
+    let mut components = std::path::Path::new(file).components();
+    components.next() == Some(Component::Normal("text".as_ref()))
+        && components.clone().next().is_some()
+        && components.all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Full path to the file holding the content of a text node. None if the node
+/// isn't a text node with a backing file, or if its backing file isn't in the
+/// text folder.
+pub(super) fn text_backing_file(
+    graph: &GraphCommands,
+    vault: &KartaVault,
+    path: &NodePath,
+) -> Option<PathBuf> {
+    let node = graph.open_node(path).ok()?;
+    node.attributes().into_iter().find_map(|attr| match attr.value {
+        AttrValue::String(file) if attr.name == TEXT_FILE_ATTR => {
+            if !is_valid_text_file(&file) {
+                tracing::warn!("Ignoring text file {} of {} outside the text folder", file, path.alias());
+                return None;
+            }
+            Some(vault.karta_dirpath().join(file))
+        }
+        _ => None,
+    })
+}
+
+/// Gives the text nodes of a duplicated subtree backing files of their own,
+/// with the content of the originals. Copies are made without the attribute,
+/// so that editing a copy doesn't change the original.
+pub(super) fn copy_text_files(
+    graph: &mut GraphCommands,
+    vault: &KartaVault,
+    original: &NodePath,
+    copy: &NodePath,
+) -> Result<(), ApiError> {
+    let mut originals = vec![original.clone()];
+    originals.extend(graph.get_all_descendants(original)?.iter().map(|node| node.path()));
+
+    for path in originals {
+        let file = match text_backing_file(graph, vault, &path) {
+            Some(file) => file,
+            None => continue,
+        };
+        if !file.exists() {
+            tracing::warn!("Text file of {} is missing, not copying it", path.alias());
+            continue;
+        }
+        let relative = match path.relative_to(original) {
+            Some(relative) => relative,
+            None => continue,
+        };
+        let copied = if relative.buf().as_os_str().is_empty() {
+            copy.clone()
+        } else {
+            copy.join(&relative.buf().to_string_lossy())
+        };
+        let id = graph
+            .open_node(&copied)?
+            .id()
+            .ok_or_else(|| ApiError::db_error("Copied node has no id"))?;
+
+        let file_name = format!("{}.md", id.0);
+        std::fs::create_dir_all(text_dirpath(vault))?;
+        std::fs::copy(&file, text_dirpath(vault).join(&file_name))?;
+        graph.insert_node_attrs(
+            &copied,
+            vec![Attribute::new_string(TEXT_FILE_ATTR.to_string(), format!("text/{}", file_name))],
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+pub struct CreateTextNodePayload {
+    /// Alias of the node to create the text node under.
+    pub parent_path: String,
+    pub name: String,
+    pub content: String,
+}
+
+/// Creates a virtual text node and writes its content into a backing file
+/// in the karta folder.
+pub(super) async fn create_text_node(
+    Extension(state): Extension<AppState>,
+    Json(payload): Json<CreateTextNodePayload>,
+) -> Result<Json<Node>, ApiError> {
+    NodePath::validate_name(&payload.name).map_err(|e| ApiError::validation(e.to_string()))?;
+
+    let mut graph = state.graph_commands.write().unwrap();
+    let vault = KartaVault::new(graph.user_root_dirpath());
+
+    let parent = NodePath::from_alias(&payload.parent_path);
+    let path = parent.join(&payload.name);
+
+    let node = write_new_text_node(&mut graph, &vault, &parent, &path, &payload.content)?;
+
+    events::publish(&state, GraphEvent::new("create", vec![path]));
+    Ok(Json(node))
+}
+
+fn write_new_text_node(
+    graph: &mut GraphCommands,
+    vault: &KartaVault,
+    parent: &NodePath,
+    path: &NodePath,
+    content: &str,
+) -> Result<Node, ApiError> {
+    graph
+        .open_node(parent)
+        .map_err(|_| ApiError::not_found(format!("Parent {} does not exist", parent.alias())))?;
+    if graph.open_node(path).is_ok() {
+        return Err(ApiError::name_collision(format!("Node {} already exists", path.alias())));
+    }
+
+    graph.create_node_by_path(path, Some(NodeType::text()))?;
+    let node = graph.open_node(path)?;
+    let id = node.id().ok_or_else(|| ApiError::db_error("Created node has no id"))?;
+
+    // Named by the db id, so that the file doesn't need to move with the node
+    let file_name = format!("{}.md", id.0);
+    std::fs::create_dir_all(text_dirpath(vault))?;
+    std::fs::write(text_dirpath(vault).join(&file_name), content)?;
+
+    let file_attr = format!("text/{}", file_name);
+    graph.insert_node_attrs(
+        path,
+        vec![Attribute::new_string(TEXT_FILE_ATTR.to_string(), file_attr)],
+    )?;
+
+    Ok(graph.open_node(path)?)
+}
+
+#[derive(serde::Deserialize)]
+pub struct UpdateTextContentPayload {
+    pub content: String,
+}
+
+/// Replaces the content of a text node.
+pub(super) async fn update_text_content(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateTextContentPayload>,
+) -> Result<Json<()>, ApiError> {
+    let graph = state.graph_commands.read().unwrap();
+    let vault = KartaVault::new(graph.user_root_dirpath());
+    let path = NodePath::from_alias(&id);
+
+    if graph.open_node(&path).is_err() {
+        return Err(ApiError::not_found(format!("Node {} does not exist", path.alias())));
+    }
+    let file = text_backing_file(&graph, &vault, &path)
+        .ok_or_else(|| ApiError::validation(format!("Node {} is not a text node", path.alias())))?;
+    std::fs::write(file, payload.content)?;
+
+    events::publish(&state, GraphEvent::new("update", vec![path]));
+    Ok(Json(()))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use crate::graph_commands::TestCommandContext;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn creating_text_node_leaves_its_parent_unchanged() {
+        let func_name = "creating_text_node_leaves_its_parent_unchanged";
+        let mut ctx = TestCommandContext::new(func_name);
+
+        let parent = NodePath::from("notes");
+        ctx.graph.create_node_by_path(&parent, None).unwrap();
+        ctx.graph.set_node_type(&parent, NodeType::new("Journal".to_string())).unwrap();
+        ctx.graph.set_node_persistent(&parent, true).unwrap();
+        let before = ctx.graph.open_node(&parent).unwrap();
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let payload = CreateTextNodePayload {
+            parent_path: parent.alias(),
+            name: "entry".to_string(),
+            content: "".to_string(),
+        };
+        let created = create_text_node(Extension(state.clone()), Json(payload)).await;
+        assert_eq!(created.is_ok(), true, "Text node should be created");
+
+        let after = state.graph_commands.read().unwrap().open_node(&parent).unwrap();
+        assert_eq!(after.persistent(), true, "Parent should stay persistent");
+        assert_eq!(after.ntype_name(), NodeType::new("Journal".to_string()), "Parent should keep its type");
+        assert_eq!(after.version(), before.version());
+    }
+
+    #[tokio::test]
+    async fn text_node_is_created_with_content_and_updated() {
+        let func_name = "text_node_is_created_with_content_and_updated";
+        let ctx = TestCommandContext::new(func_name);
+
+        let root = ctx.graph.user_root_dirpath();
+        let (state, _dir) = ctx.into_app_state();
+        let vault = KartaVault::new(root);
+
+        let payload = CreateTextNodePayload {
+            parent_path: NodePath::user_root().alias(),
+            name: "note".to_string(),
+            content: "# First draft".to_string(),
+        };
+        let created = create_text_node(Extension(state.clone()), Json(payload)).await;
+        assert_eq!(created.is_ok(), true, "Text node should be created");
+        assert_eq!(created.unwrap().ntype_name(), NodeType::text());
+
+        let path = NodePath::from("note");
+        let file = {
+            let graph = state.graph_commands.read().unwrap();
+            text_backing_file(&graph, &vault, &path)
+        };
+        assert_eq!(file.is_some(), true, "Backing file should be stored as an attribute");
+        let file = file.unwrap();
+        assert_eq!(file.starts_with(text_dirpath(&vault)), true);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "# First draft");
+
+        let payload = UpdateTextContentPayload {
+            content: "# Second draft".to_string(),
+        };
+        let updated = update_text_content(Extension(state.clone()), Path(path.alias()), Json(payload)).await;
+        assert_eq!(updated.is_ok(), true, "Content should be updated");
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "# Second draft");
+
+        // The asset endpoint serves the backing file
+        let asset = super::super::asset_endpoints::get_asset(
+            Extension(state.clone()),
+            Path("note".to_string()),
+            axum::http::HeaderMap::new(),
+        )
+        .await;
+        let body = axum::body::to_bytes(asset.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), b"# Second draft");
+    }
+
+    #[tokio::test]
+    async fn updating_content_of_non_text_node_fails() {
+        let func_name = "updating_content_of_non_text_node_fails";
+        let mut ctx = TestCommandContext::new(func_name);
+        ctx.graph.create_node_by_path(&NodePath::from("plain"), None);
+
+        let (state, _dir) = ctx.into_app_state();
+
+        let payload = UpdateTextContentPayload {
+            content: "content".to_string(),
+        };
+        let updated = update_text_content(
+            Extension(state.clone()),
+            Path(NodePath::from("plain").alias()),
+            Json(payload),
+        )
+        .await;
+        assert_eq!(updated.unwrap_err().code, super::super::ErrorCode::Validation);
+    }
+
+    #[tokio::test]
+    async fn text_file_outside_the_text_folder_is_refused() {
+        let func_name = "text_file_outside_the_text_folder_is_refused";
+        let mut ctx = TestCommandContext::new(func_name);
+        let path = NodePath::from("plain");
+        ctx.graph.create_node_by_path(&path, None).unwrap();
+
+        let root = ctx.graph.user_root_dirpath();
+        let (state, _dir) = ctx.into_app_state();
+
+        let escape = Attribute::new_string(TEXT_FILE_ATTR.to_string(), "../../../etc/x".to_string());
+        let payload = super::super::UpdateNodeAttrsPayload {
+            attributes: vec![escape.clone()],
+            expected_version: None,
+        };
+        let updated = super::super::update_node_attrs(Extension(state.clone()), Path(path.alias()), Json(payload)).await;
+        assert_eq!(updated.unwrap_err().code, super::super::ErrorCode::Validation);
+
+        // Even if the attribute got into the db some other way, it isn't followed
+        state.graph_commands.write().unwrap().insert_node_attrs(&path, vec![escape]).unwrap();
+        let graph = state.graph_commands.read().unwrap();
+        assert_eq!(text_backing_file(&graph, &KartaVault::new(root), &path), None);
+
+        assert_eq!(is_valid_text_file("text/12.md"), true);
+        for invalid in ["text/../../x.md", "/etc/passwd", "other/12.md", "text", "./text/12.md"] {
+            assert_eq!(is_valid_text_file(invalid), false, "{} should be refused", invalid);
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    error::Error,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Extension, Json};
+
+use crate::{prelude::*, vault::KartaVault};
+
+use super::{ApiError, AppState};
+
+/// A single deletion operation, as recorded in the trash log.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TrashEntry {
+    pub operation_id: String,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    pub paths: Vec<NodePath>,
+}
+
+impl TrashEntry {
+    pub fn new(operation_id: String, paths: Vec<NodePath>) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        TrashEntry {
+            operation_id,
+            timestamp,
+            paths,
+        }
+    }
+}
+
+/// Serializes access to the trash log, so that concurrent writes and purges
+/// can't interleave and corrupt entries.
+static TRASH_LOG_LOCK: Mutex<()> = Mutex::new(());
+
+/// The trash log has one RON encoded entry per line.
+pub(super) fn trash_log_path(vault: &KartaVault) -> PathBuf {
+    vault.karta_dirpath().join("trash").join("trash_log.ron")
+}
+
+pub(super) fn write_to_trash_log(vault: &KartaVault, entry: &TrashEntry) -> Result<(), Box<dyn Error>> {
+    let line = ron::to_string(entry)?;
+    let log_path = trash_log_path(vault);
+
+    let _lock = TRASH_LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    std::fs::create_dir_all(log_path.parent().unwrap())?;
+
+    let mut file = OpenOptions::new().create(true).append(true).open(log_path)?;
+    // The entry goes out in a single write
+    file.write_all(format!("{}\n", line).as_bytes())?;
+    Ok(())
+}
+
+/// Reads the entries of the trash log, newest first. Malformed lines are skipped.
+pub fn list_trash(vault: &KartaVault) -> Result<Vec<TrashEntry>, Box<dyn Error>> {
+    let log_path = trash_log_path(vault);
+    if !log_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let contents = {
+        let _lock = TRASH_LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        std::fs::read_to_string(&log_path)?
+    };
+    let mut entries: Vec<TrashEntry> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match ron::from_str::<TrashEntry>(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!("Skipping malformed trash log line: {}", e);
+                None
+            }
+        })
+        .collect();
+
+    // Operation ids are increasing, so they break ties within the same second
+    entries.sort_by(|a, b| {
+        (b.timestamp, b.operation_id.len(), &b.operation_id)
+            .cmp(&(a.timestamp, a.operation_id.len(), &a.operation_id))
+    });
+    Ok(entries)
+}
+
+/// Removes the entries older than the given age from the trash log and returns how many
+/// were removed. Only the log is purged, nothing is restored or removed from the file system.
+/// Lines that can't be parsed are kept as they are.
+pub fn purge_trash(vault: &KartaVault, older_than_secs: u64) -> Result<usize, Box<dyn Error>> {
+    let log_path = trash_log_path(vault);
+    if !log_path.exists() {
+        return Ok(0);
+    }
+
+    let _lock = TRASH_LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let cutoff = now.saturating_sub(older_than_secs);
+
+    let contents = std::fs::read_to_string(&log_path)?;
+    let mut remaining = String::new();
+    let mut purged = 0;
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match ron::from_str::<TrashEntry>(line) {
+            Ok(entry) if entry.timestamp < cutoff => purged += 1,
+            _ => {
+                remaining.push_str(line);
+                remaining.push('\n');
+            }
+        }
+    }
+
+    // Rewrite the whole log rather than appending. Atomically, so that a crash
+    // midway doesn't lose the entries that were kept.
+    write_atomically(&log_path, remaining)?;
+    Ok(purged)
+}
+
+pub(super) async fn get_trash(
+    Extension(state): Extension<AppState>,
+) -> Result<Json<Vec<TrashEntry>>, ApiError> {
+    let vault = KartaVault::new(state.graph_commands.read().unwrap().user_root_dirpath());
+
+    Ok(Json(list_trash(&vault)?))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PurgeTrashPayload {
+    pub older_than_secs: u64,
+}
+
+pub(super) async fn purge_trash_log(
+    Extension(state): Extension<AppState>,
+    Json(payload): Json<PurgeTrashPayload>,
+) -> Result<Json<usize>, ApiError> {
+    let vault = KartaVault::new(state.graph_commands.read().unwrap().user_root_dirpath());
+
+    let purged = purge_trash(&vault, payload.older_than_secs)?;
+    Ok(Json(purged))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(warnings)]
+
+    use crate::{graph_commands::TestCommandContext, server::execute_delete_nodes};
+
+    use super::*;
+
+    #[test]
+    fn purging_trash_removes_only_old_entries() {
+        let func_name = "purging_trash_removes_only_old_entries";
+        let ctx = TestCommandContext::new(func_name);
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+
+        let mut old = TrashEntry::new("old".to_string(), vec![NodePath::from("old")]);
+        old.timestamp -= 1000;
+        let new = TrashEntry::new("new".to_string(), vec![NodePath::from("new")]);
+
+        write_to_trash_log(&vault, &old).unwrap();
+        write_to_trash_log(&vault, &new).unwrap();
+
+        let purged = purge_trash(&vault, 500);
+        assert_eq!(purged.is_ok(), true, "Purge should succeed");
+        assert_eq!(purged.unwrap(), 1, "Only the old entry should be purged");
+
+        let contents = std::fs::read_to_string(trash_log_path(&vault)).unwrap();
+        let remaining: Vec<TrashEntry> = contents
+            .lines()
+            .map(|line| ron::from_str(line).unwrap())
+            .collect();
+        assert_eq!(remaining, vec![new]);
+    }
+
+    #[test]
+    fn listing_trash_shows_deletions_newest_first() {
+        let func_name = "listing_trash_shows_deletions_newest_first";
+        let mut ctx = TestCommandContext::new(func_name);
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+
+        let first = NodePath::from("first");
+        let second = NodePath::from("second");
+        ctx.graph.create_node_by_path(&first, None).unwrap();
+        ctx.graph.create_node_by_path(&second, None).unwrap();
+
+        for path in [&first, &second] {
+            let payload = DeleteNodesPayload {
+                paths: vec![path.alias()],
+                dry_run: false,
+            };
+            execute_delete_nodes(&mut ctx.graph, payload);
+        }
+
+        // Malformed lines don't break the listing
+        let mut log = OpenOptions::new().append(true).open(trash_log_path(&vault)).unwrap();
+        writeln!(log, "not an entry").unwrap();
+
+        let entries = list_trash(&vault);
+        assert_eq!(entries.is_ok(), true, "Trash should be listed");
+        let entries = entries.unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].paths, vec![second]);
+        assert_eq!(entries[1].paths, vec![first]);
+    }
+
+    #[test]
+    fn concurrent_trash_log_writes_stay_parseable() {
+        let func_name = "concurrent_trash_log_writes_stay_parseable";
+        let ctx = TestCommandContext::new(func_name);
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let vault = vault.clone();
+                std::thread::spawn(move || {
+                    for i in 0..20 {
+                        let paths = (0..10)
+                            .map(|n| NodePath::from(format!("thread_{}/node_{}_{}", thread, i, n)))
+                            .collect();
+                        let entry = TrashEntry::new(format!("{}_{}", thread, i), paths);
+                        write_to_trash_log(&vault, &entry).unwrap();
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        let contents = std::fs::read_to_string(trash_log_path(&vault)).unwrap();
+        let parsed = contents
+            .lines()
+            .filter(|line| ron::from_str::<TrashEntry>(line).is_ok())
+            .count();
+        assert_eq!(contents.lines().count(), 160);
+        assert_eq!(parsed, 160, "Every line should be a whole entry");
+    }
+
+    #[test]
+    fn deleting_node_writes_one_trash_log_line() {
+        let func_name = "deleting_node_writes_one_trash_log_line";
+        let mut ctx = TestCommandContext::new(func_name);
+        let vault = KartaVault::new(ctx.graph.user_root_dirpath());
+
+        let path = NodePath::from("deleted");
+        ctx.graph.create_node_by_path(&path, None).unwrap();
+
+        let payload = DeleteNodesPayload {
+            paths: vec![path.alias()],
+            dry_run: false,
+        };
+        let response = execute_delete_nodes(&mut ctx.graph, payload);
+
+        let contents = std::fs::read_to_string(trash_log_path(&vault)).unwrap();
+        assert_eq!(contents.lines().count(), 1, "Deletion should be logged exactly once");
+
+        let entry: TrashEntry = ron::from_str(contents.lines().next().unwrap()).unwrap();
+        assert_eq!(entry.operation_id, response.operation_id);
+        assert_eq!(entry.paths, vec![path]);
+    }
+}
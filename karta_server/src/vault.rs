@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+/// Name of the hidden folder inside a vault where Karta stores its db
+/// and other data.
+pub const KARTA_DIR_NAME: &str = ".karta";
+
+/// A Karta vault. The directory the user has chosen as the root of their graph.
+///
+/// This is the single definition of a vault shared by the server and the clients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KartaVault {
+    path: PathBuf,
+    /// Directory to keep the db in instead of the vault, such as one on a
+    /// faster disk.
+    storage_override: Option<PathBuf>,
+}
+
+impl KartaVault {
+    pub fn new(path: PathBuf) -> Self {
+        KartaVault { path, storage_override: None }
+    }
+
+    pub fn with_storage_override(mut self, storage: Option<PathBuf>) -> Self {
+        self.storage_override = storage;
+        self
+    }
+
+    pub fn storage_override(&self) -> Option<&PathBuf> {
+        self.storage_override.as_ref()
+    }
+
+    /// Directory the db is stored under. The vault itself, unless overridden.
+    /// Contexts and other files of the vault are kept in the vault regardless.
+    pub fn storage_dirpath(&self) -> PathBuf {
+        self.storage_override.clone().unwrap_or_else(|| self.path.clone())
+    }
+
+    /// Path to the root directory of the vault.
+    pub fn vault_dirpath(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Path to the folder inside the vault where Karta keeps its data.
+    pub fn karta_dirpath(&self) -> PathBuf {
+        self.path.join(KARTA_DIR_NAME)
+    }
+}